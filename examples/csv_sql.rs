@@ -38,7 +38,8 @@ fn main() {
     ]));
 
     // register csv file with the execution context
-    let csv_datasource = CsvDataSource::new("test/data/uk_cities.csv", schema.clone(), 1024);
+    let csv_datasource =
+        CsvDataSource::new("test/data/uk_cities.csv", schema.clone(), false, 1024).unwrap();
     ctx.register_datasource("cities", Rc::new(RefCell::new(csv_datasource)));
 
     // simple projection and selection
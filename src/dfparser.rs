@@ -57,6 +57,8 @@ pub enum DFASTNode {
 /// SQL Parser
 pub struct DFParser {
     parser: Parser,
+    /// The queries nested in parentheses, which are parsed separately
+    queries: Vec<ASTNode>,
 }
 
 impl DFParser {
@@ -64,9 +66,19 @@ impl DFParser {
     pub fn new(sql: String) -> Result<Self, ParserError> {
         let dialect = GenericSqlDialect {};
         let mut tokenizer = Tokenizer::new(&dialect, &sql);
-        let tokens = tokenizer.tokenize()?;
+        let tokens = rewrite_subscripts(rewrite_temporal_syntax(tokenizer.tokenize()?));
+        let tokens = rewrite_with_clauses(rewrite_subqueries(tokens));
+        let tokens = rewrite_set_operations(tokens);
+        let tokens = rewrite_within_group(rewrite_aggregate_orderings(tokens));
+        let tokens = rewrite_aggregate_filters(tokens);
+        let tokens = rewrite_grouping_sets(rewrite_window_functions(tokens));
+        let tokens = rewrite_simple_case(rewrite_list_predicates(tokens));
+        let tokens = rewrite_unary_operators(&rewrite_table_aliases(tokens));
+        let mut queries = vec![];
+        let tokens = extract_queries(tokens, &mut queries)?;
         Ok(DFParser {
             parser: Parser::new(tokens),
+            queries,
         })
     }
 
@@ -78,7 +90,10 @@ impl DFParser {
 
     /// Parse a new expression
     pub fn parse(&mut self) -> Result<DFASTNode, ParserError> {
-        self.parse_expr(0)
+        match self.parse_expr(0)? {
+            DFASTNode::ANSI(ast) => Ok(DFASTNode::ANSI(replace_placeholders(ast, &self.queries))),
+            other => Ok(other),
+        }
     }
 
     /// Parse tokens until the precedence changes
@@ -220,3 +235,1685 @@ impl DFParser {
         unimplemented!()
     }
 }
+
+/// The prefix of the placeholders for nested queries. It cannot start an identifier in SQL.
+const QUERY_PLACEHOLDER: &str = "$query";
+
+/// The sqlparser crate expects a query to run to the end of the statement, so each query nested
+/// in parentheses is parsed on its own, after the queries nested in it, and replaced with a
+/// placeholder identifier that `replace_placeholders` swaps for the parsed query.
+fn extract_queries(
+    tokens: Vec<Token>,
+    queries: &mut Vec<ASTNode>,
+) -> Result<Vec<Token>, ParserError> {
+    let mut out: Vec<Token> = Vec::with_capacity(tokens.len());
+    let mut i = 0;
+    while i < tokens.len() {
+        if tokens[i] == Token::LParen {
+            let query = next_non_whitespace(&tokens, i + 1)
+                .filter(|&j| is_word(&tokens[j], "SELECT"))
+                .and_then(|j| matching_paren(&tokens, i).map(|end| (j, end)));
+            if let Some((start, end)) = query {
+                let query_tokens = extract_queries(tokens[start..end].to_vec(), queries)?;
+                let query = replace_placeholders(Parser::new(query_tokens).parse()?, queries);
+                out.push(Token::LParen);
+                out.push(Token::Identifier(format!(
+                    "{}{}",
+                    QUERY_PLACEHOLDER,
+                    queries.len()
+                )));
+                out.push(Token::RParen);
+                queries.push(query);
+                i = end + 1;
+                continue;
+            }
+        }
+        out.push(tokens[i].clone());
+        i += 1;
+    }
+    Ok(out)
+}
+
+/// Replace the placeholders for nested queries with the parsed queries, and the placeholder
+/// calls for unary operators with the operators
+fn replace_placeholders(ast: ASTNode, queries: &[ASTNode]) -> ASTNode {
+    let replace = |ast: ASTNode| replace_placeholders(ast, queries);
+    let replace_box = |ast: Box<ASTNode>| Box::new(replace_placeholders(*ast, queries));
+    match ast {
+        ASTNode::SQLIdentifier(ref id) if id.starts_with(QUERY_PLACEHOLDER) => {
+            match id[QUERY_PLACEHOLDER.len()..].parse::<usize>() {
+                Ok(index) if index < queries.len() => queries[index].clone(),
+                _ => ast,
+            }
+        }
+        ASTNode::SQLFunction { ref id, ref args }
+            if (id == NEGATIVE || id == POSITIVE) && args.len() == 1 =>
+        {
+            ASTNode::SQLUnary {
+                operator: if id == NEGATIVE {
+                    SQLOperator::Minus
+                } else {
+                    SQLOperator::Plus
+                },
+                expr: Box::new(replace(args[0].clone())),
+            }
+        }
+        ASTNode::SQLAliasedExpr(expr, alias) => ASTNode::SQLAliasedExpr(replace_box(expr), alias),
+        ASTNode::SQLIsNull(expr) => ASTNode::SQLIsNull(replace_box(expr)),
+        ASTNode::SQLIsNotNull(expr) => ASTNode::SQLIsNotNull(replace_box(expr)),
+        ASTNode::SQLBinaryExpr { left, op, right } => ASTNode::SQLBinaryExpr {
+            left: replace_box(left),
+            op,
+            right: replace_box(right),
+        },
+        ASTNode::SQLCast { expr, data_type } => ASTNode::SQLCast {
+            expr: replace_box(expr),
+            data_type,
+        },
+        ASTNode::SQLNested(expr) => ASTNode::SQLNested(replace_box(expr)),
+        ASTNode::SQLUnary { operator, expr } => ASTNode::SQLUnary {
+            operator,
+            expr: replace_box(expr),
+        },
+        ASTNode::SQLFunction { id, args } => ASTNode::SQLFunction {
+            id,
+            args: args.into_iter().map(replace).collect(),
+        },
+        ASTNode::SQLCase {
+            conditions,
+            results,
+            else_result,
+        } => ASTNode::SQLCase {
+            conditions: conditions.into_iter().map(replace).collect(),
+            results: results.into_iter().map(replace).collect(),
+            else_result: else_result.map(replace_box),
+        },
+        ASTNode::SQLSelect {
+            projection,
+            relation,
+            joins,
+            selection,
+            order_by,
+            group_by,
+            having,
+            limit,
+        } => ASTNode::SQLSelect {
+            projection: projection.into_iter().map(replace).collect(),
+            relation: relation.map(replace_box),
+            joins: joins
+                .into_iter()
+                .map(|join| Join {
+                    relation: replace(join.relation),
+                    join_operator: match join.join_operator {
+                        JoinOperator::Inner(c) => {
+                            JoinOperator::Inner(replace_constraint(c, queries))
+                        }
+                        JoinOperator::LeftOuter(c) => {
+                            JoinOperator::LeftOuter(replace_constraint(c, queries))
+                        }
+                        JoinOperator::RightOuter(c) => {
+                            JoinOperator::RightOuter(replace_constraint(c, queries))
+                        }
+                        JoinOperator::FullOuter(c) => {
+                            JoinOperator::FullOuter(replace_constraint(c, queries))
+                        }
+                        other => other,
+                    },
+                })
+                .collect(),
+            selection: selection.map(replace_box),
+            order_by: order_by.map(|order_by| {
+                order_by
+                    .into_iter()
+                    .map(|o| SQLOrderByExpr::new(replace_box(o.expr), o.asc, o.nulls_first))
+                    .collect()
+            }),
+            group_by: group_by.map(|group_by| group_by.into_iter().map(replace).collect()),
+            having: having.map(replace_box),
+            limit: limit.map(replace_box),
+        },
+        other => other,
+    }
+}
+
+fn replace_constraint(constraint: JoinConstraint, queries: &[ASTNode]) -> JoinConstraint {
+    match constraint {
+        JoinConstraint::On(expr) => JoinConstraint::On(replace_placeholders(expr, queries)),
+        other => other,
+    }
+}
+
+/// The placeholder functions for the unary minus and plus operators
+const NEGATIVE: &str = "$negative";
+const POSITIVE: &str = "$positive";
+
+/// The sqlparser crate has no prefix parser for unary minus and plus, or keywords for the boolean
+/// literals. So `-x` and `+x` are rewritten to placeholder calls around the operand, which
+/// `replace_placeholders` turns into unary operators, and TRUE and FALSE are made keywords.
+fn rewrite_unary_operators(tokens: &[Token]) -> Vec<Token> {
+    let mut out: Vec<Token> = Vec::with_capacity(tokens.len());
+    let mut i = 0;
+    while i < tokens.len() {
+        if is_word(&tokens[i], "TRUE") || is_word(&tokens[i], "FALSE") {
+            out.push(Token::Keyword(token_text(&tokens[i..=i])));
+            i += 1;
+            continue;
+        }
+        let prefix = match tokens[i] {
+            Token::Minus => Some(NEGATIVE),
+            Token::Plus => Some(POSITIVE),
+            _ => None,
+        };
+        if let Some(name) = prefix.filter(|_| starts_operand(&out)) {
+            let operand = next_non_whitespace(tokens, i + 1)
+                .and_then(|j| operand_end(tokens, j).map(|end| (j, end)));
+            if let Some((start, end)) = operand {
+                out.push(Token::Identifier(name.to_string()));
+                out.push(Token::LParen);
+                out.extend(rewrite_unary_operators(&tokens[start..=end]));
+                out.push(Token::RParen);
+                i = end + 1;
+                continue;
+            }
+        }
+        out.push(tokens[i].clone());
+        i += 1;
+    }
+    out
+}
+
+/// Whether the next token starts an operand rather than following one, which is the case at the
+/// start of the statement and after an operator, a separator or a keyword other than one that
+/// ends an expression
+fn starts_operand(tokens: &[Token]) -> bool {
+    match tokens.iter().rev().find(|t| !is_whitespace(t)) {
+        None => true,
+        Some(Token::Keyword(k)) => !["END", "NULL", "TRUE", "FALSE"]
+            .iter()
+            .any(|w| k.eq_ignore_ascii_case(w)),
+        Some(Token::Comma) | Some(Token::LParen) | Some(Token::Eq) | Some(Token::Neq)
+        | Some(Token::Lt) | Some(Token::Gt) | Some(Token::LtEq) | Some(Token::GtEq)
+        | Some(Token::Plus) | Some(Token::Minus) | Some(Token::Mult) | Some(Token::Div)
+        | Some(Token::Mod) => true,
+        _ => false,
+    }
+}
+
+/// Find the index of the last token of the operand that starts at `start`, which is a literal, an
+/// identifier or function call with any field accesses, a parenthesized expression, a CASE
+/// expression or another unary operation
+fn operand_end(tokens: &[Token], start: usize) -> Option<usize> {
+    match &tokens[start] {
+        Token::Minus | Token::Plus => operand_end(tokens, next_non_whitespace(tokens, start + 1)?),
+        Token::Number(_) | Token::SingleQuotedString(_) | Token::DoubleQuotedString(_) => {
+            Some(start)
+        }
+        Token::LParen => matching_paren(tokens, start),
+        Token::Keyword(k) if k.eq_ignore_ascii_case("CASE") => matching_end(tokens, start),
+        Token::Keyword(k)
+            if ["NULL", "TRUE", "FALSE"]
+                .iter()
+                .any(|w| k.eq_ignore_ascii_case(w)) =>
+        {
+            Some(start)
+        }
+        Token::Identifier(_) => {
+            let mut end = start;
+            loop {
+                match next_non_whitespace(tokens, end + 1).map(|i| (i, &tokens[i])) {
+                    Some((i, Token::LParen)) => end = matching_paren(tokens, i)?,
+                    Some((i, Token::Period)) => match next_non_whitespace(tokens, i + 1) {
+                        Some(j) if is_identifier(&tokens[j]) => end = j,
+                        _ => return Some(end),
+                    },
+                    _ => return Some(end),
+                }
+            }
+        }
+        _ => None,
+    }
+}
+
+fn is_identifier(token: &Token) -> bool {
+    match token {
+        Token::Identifier(_) => true,
+        _ => false,
+    }
+}
+
+/// The sqlparser crate does not support `EXTRACT(field FROM value)` or `INTERVAL '...'`
+/// literals, so they are rewritten to `date_part('field', value)` and `to_interval('...')`
+/// before parsing. ZONE is not a keyword of the generic dialect, so it is turned into one after
+/// TIME for the parser to accept `TIMESTAMP WITH TIME ZONE`. The parser reads a bare TIME type
+/// as TIMESTAMP, so it is spelled out as `TIME WITHOUT TIME ZONE`.
+fn rewrite_temporal_syntax(tokens: Vec<Token>) -> Vec<Token> {
+    let mut out: Vec<Token> = Vec::with_capacity(tokens.len());
+    let mut i = 0;
+    while i < tokens.len() {
+        if is_word(&tokens[i], "EXTRACT") {
+            if let Some((field, from)) = extract_field(&tokens, i + 1) {
+                out.push(Token::Identifier("date_part".to_string()));
+                out.push(Token::LParen);
+                out.push(Token::SingleQuotedString(field));
+                out.push(Token::Comma);
+                // the value and the closing parenthesis follow FROM
+                i = from + 1;
+                continue;
+            }
+        }
+        if is_word(&tokens[i], "INTERVAL") {
+            let literal = next_non_whitespace(&tokens, i + 1).filter(|&j| match tokens[j] {
+                Token::SingleQuotedString(_) => true,
+                _ => false,
+            });
+            if let Some(j) = literal {
+                out.push(Token::Identifier("to_interval".to_string()));
+                out.push(Token::LParen);
+                out.push(tokens[j].clone());
+                out.push(Token::RParen);
+                i = j + 1;
+                continue;
+            }
+        }
+        if is_word(&tokens[i], "TIME") && is_bare_time(&tokens, i, &out) {
+            out.push(tokens[i].clone());
+            for keyword in &["WITHOUT", "TIME", "ZONE"] {
+                out.push(Token::Keyword(keyword.to_string()));
+            }
+            i += 1;
+            continue;
+        }
+        if is_word(&tokens[i], "ZONE")
+            && out
+                .iter()
+                .rev()
+                .find(|t| !is_whitespace(t))
+                .map_or(false, |t| is_word(t, "TIME"))
+        {
+            out.push(Token::Keyword("ZONE".to_string()));
+            i += 1;
+            continue;
+        }
+        out.push(tokens[i].clone());
+        i += 1;
+    }
+    out
+}
+
+/// Whether the TIME at `i` is a data type without a time zone clause, rather than part of one or
+/// the type of a literal. `out` holds the rewritten tokens before it.
+fn is_bare_time(tokens: &[Token], i: usize, out: &[Token]) -> bool {
+    let in_zone_clause = out
+        .iter()
+        .rev()
+        .find(|t| !is_whitespace(t))
+        .map_or(false, |t| is_word(t, "WITH") || is_word(t, "WITHOUT"));
+    let followed_by_zone_or_literal =
+        next_non_whitespace(tokens, i + 1).map_or(false, |j| match tokens[j] {
+            Token::SingleQuotedString(_) => true,
+            ref t => is_word(t, "WITH") || is_word(t, "WITHOUT"),
+        });
+    !in_zone_clause && !followed_by_zone_or_literal
+}
+
+/// Find the field of an `EXTRACT(field FROM value)` expression whose parenthesis is at or after
+/// `start`, along with the index of the FROM keyword
+fn extract_field(tokens: &[Token], start: usize) -> Option<(String, usize)> {
+    let mut non_whitespace = (start..tokens.len()).filter(|&i| !is_whitespace(&tokens[i]));
+    let lparen = non_whitespace.next()?;
+    let field = non_whitespace.next()?;
+    let from = non_whitespace.next()?;
+    if tokens[lparen] != Token::LParen || !is_word(&tokens[from], "FROM") {
+        return None;
+    }
+    match &tokens[field] {
+        Token::Keyword(s) | Token::Identifier(s) => Some((s.to_lowercase(), from)),
+        _ => None,
+    }
+}
+
+/// Array subscripts are not supported by the sqlparser crate, so `list[i]` is rewritten to
+/// `array_element(list, i)` before parsing. The list can be a column, a struct field, a function
+/// call or an expression in parentheses.
+fn rewrite_subscripts(tokens: Vec<Token>) -> Vec<Token> {
+    let mut out: Vec<Token> = Vec::with_capacity(tokens.len());
+    let mut i = 0;
+    while i < tokens.len() {
+        if tokens[i] == Token::LBracket {
+            if let (Some(start), Some(end)) =
+                (subscript_operand_start(&out), matching_bracket(&tokens, i))
+            {
+                let list = out.split_off(start);
+                out.push(Token::Identifier("array_element".to_string()));
+                out.push(Token::LParen);
+                out.extend(list);
+                out.push(Token::Comma);
+                out.extend(rewrite_subscripts(tokens[i + 1..end].to_vec()));
+                out.push(Token::RParen);
+                i = end + 1;
+                continue;
+            }
+        }
+        out.push(tokens[i].clone());
+        i += 1;
+    }
+    out
+}
+
+/// Find the start of the expression that ends with the last token and is subscripted, which is
+/// a possibly compound identifier, a function call or an expression in parentheses
+fn subscript_operand_start(tokens: &[Token]) -> Option<usize> {
+    let last = tokens.iter().rposition(|t| !is_whitespace(t))?;
+    match tokens[last] {
+        Token::RParen => function_call_start(tokens).or_else(|| {
+            (0..last)
+                .rev()
+                .find(|&i| tokens[i] == Token::LParen && matching_paren(tokens, i) == Some(last))
+        }),
+        Token::Identifier(_) => {
+            let mut start = last;
+            while start >= 2
+                && tokens[start - 1] == Token::Period
+                && is_identifier(&tokens[start - 2])
+            {
+                start -= 2;
+            }
+            Some(start)
+        }
+        _ => None,
+    }
+}
+
+/// Find the index of the bracket that closes the one at `lbracket`
+fn matching_bracket(tokens: &[Token], lbracket: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (i, token) in tokens.iter().enumerate().skip(lbracket) {
+        match token {
+            Token::LBracket => depth += 1,
+            Token::RBracket => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// The sqlparser crate cannot parse the `WITHIN GROUP (ORDER BY x)` clause of the ordered-set
+/// aggregates, so `percentile_cont(p) WITHIN GROUP (ORDER BY x)` is rewritten to
+/// `percentile_cont(x, p)` and `mode() WITHIN GROUP (ORDER BY x)` to `mode(x)` before parsing.
+/// The ordering of `listagg(x, sep) WITHIN GROUP (ORDER BY y)` becomes its last argument, as
+/// for `rewrite_aggregate_orderings`. A descending key is rewritten by `sort_item`, which the
+/// planner rejects.
+fn rewrite_within_group(tokens: Vec<Token>) -> Vec<Token> {
+    let mut out: Vec<Token> = Vec::with_capacity(tokens.len());
+    let mut i = 0;
+    while i < tokens.len() {
+        if is_word(&tokens[i], "WITHIN") {
+            if let (Some(start), Some((key_start, key_end))) =
+                (function_call_start(&out), within_group_key(&tokens, i + 1))
+            {
+                let name = match out[start] {
+                    Token::Identifier(ref name) => name.to_lowercase(),
+                    _ => String::new(),
+                };
+                let mut key = sort_item(&tokens[key_start..key_end]);
+                if name == "percentile_cont" || name == "percentile_disc" || name == "mode" {
+                    let mut call = out.split_off(start);
+                    let lparen = call.iter().position(|t| *t == Token::LParen).unwrap();
+                    let has_args = next_non_whitespace(&call, lparen + 1)
+                        .map_or(false, |j| call[j] != Token::RParen);
+                    if has_args {
+                        key.push(Token::Comma);
+                    }
+                    call.splice(lparen + 1..lparen + 1, key);
+                    out.extend(call);
+                    // skip past the closing parenthesis of the WITHIN GROUP clause
+                    i = key_end + 1;
+                    continue;
+                } else if name == "listagg" || name == "string_agg" || name == "array_agg" {
+                    let rparen = out.iter().rposition(|t| *t == Token::RParen).unwrap();
+                    let mut args = vec![Token::Comma];
+                    args.extend(key);
+                    out.splice(rparen..rparen, args);
+                    // skip past the closing parenthesis of the WITHIN GROUP clause
+                    i = key_end + 1;
+                    continue;
+                }
+            }
+        }
+        out.push(tokens[i].clone());
+        i += 1;
+    }
+    out
+}
+
+/// `array_agg(x ORDER BY y)` and `string_agg(x, sep ORDER BY y)` cannot be parsed by the
+/// sqlparser crate, so the ordering is rewritten to the last argument, as in `array_agg(x, y)`
+/// and `string_agg(x, sep, y)`, before parsing.
+fn rewrite_aggregate_orderings(tokens: Vec<Token>) -> Vec<Token> {
+    let mut out: Vec<Token> = Vec::with_capacity(tokens.len());
+    let mut i = 0;
+    while i < tokens.len() {
+        let is_ordered_aggregate = match tokens[i] {
+            Token::Identifier(ref name) => {
+                let name = name.to_lowercase();
+                name == "array_agg" || name == "string_agg" || name == "listagg"
+            }
+            _ => false,
+        };
+        let lparen = next_non_whitespace(&tokens, i + 1).filter(|&j| tokens[j] == Token::LParen);
+        if let (true, Some(lparen)) = (is_ordered_aggregate, lparen) {
+            if let Some(rparen) = matching_paren(&tokens, lparen) {
+                let args = &tokens[lparen + 1..rparen];
+                if let Some(order) = top_level_order_by(args) {
+                    out.extend_from_slice(&tokens[i..=lparen]);
+                    out.extend(rewrite_aggregate_orderings(args[..order].to_vec()));
+                    let by = next_non_whitespace(args, order + 1).unwrap();
+                    for item in split_top_level(&args[by + 1..], |t| *t == Token::Comma) {
+                        out.push(Token::Comma);
+                        out.extend(sort_item(&rewrite_aggregate_orderings(item.to_vec())));
+                    }
+                    out.push(Token::RParen);
+                    i = rparen + 1;
+                    continue;
+                }
+            }
+        }
+        out.push(tokens[i].clone());
+        i += 1;
+    }
+    out
+}
+
+/// Find the index of an ORDER BY that is not nested in parentheses
+fn top_level_order_by(tokens: &[Token]) -> Option<usize> {
+    let mut depth = 0;
+    for (i, token) in tokens.iter().enumerate() {
+        match token {
+            Token::LParen => depth += 1,
+            Token::RParen => depth -= 1,
+            _ if depth == 0 && is_word(token, "ORDER") => {
+                let by = next_non_whitespace(tokens, i + 1)?;
+                if is_word(&tokens[by], "BY") {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Find the ordering of a `WITHIN GROUP (ORDER BY ...)` clause that follows the WITHIN keyword,
+/// returning the start of the ordering and the index of the closing parenthesis
+fn within_group_key(tokens: &[Token], start: usize) -> Option<(usize, usize)> {
+    let mut non_whitespace = (start..tokens.len()).filter(|&i| !is_whitespace(&tokens[i]));
+    let group_keyword = non_whitespace.next()?;
+    let lparen = non_whitespace.next()?;
+    let order_keyword = non_whitespace.next()?;
+    let by_keyword = non_whitespace.next()?;
+    if !is_word(&tokens[group_keyword], "GROUP")
+        || tokens[lparen] != Token::LParen
+        || !is_word(&tokens[order_keyword], "ORDER")
+        || !is_word(&tokens[by_keyword], "BY")
+    {
+        return None;
+    }
+    Some((by_keyword + 1, matching_paren(tokens, lparen)?))
+}
+
+/// The ANSI `agg(...) FILTER (WHERE cond)` syntax is not supported by the sqlparser crate, so it
+/// is rewritten to `$aggregate_filter(agg(...), cond)` before parsing and the query planner
+/// applies the condition to the aggregate.
+fn rewrite_aggregate_filters(tokens: Vec<Token>) -> Vec<Token> {
+    let mut out: Vec<Token> = Vec::with_capacity(tokens.len());
+    let mut i = 0;
+    while i < tokens.len() {
+        if is_word(&tokens[i], "FILTER") {
+            if let (Some(start), Some((cond_start, cond_end))) =
+                (function_call_start(&out), filter_condition(&tokens, i + 1))
+            {
+                let call = out.split_off(start);
+                out.push(Token::Identifier("$aggregate_filter".to_string()));
+                out.push(Token::LParen);
+                out.extend(call);
+                out.push(Token::Comma);
+                out.extend_from_slice(&tokens[cond_start..cond_end]);
+                out.push(Token::RParen);
+                // skip past the closing parenthesis of the FILTER clause
+                i = cond_end + 1;
+                continue;
+            }
+        }
+        out.push(tokens[i].clone());
+        i += 1;
+    }
+    out
+}
+
+/// Window functions are not supported by the sqlparser crate, so
+/// `f(...) OVER (PARTITION BY p ORDER BY o DESC ROWS BETWEEN 1 PRECEDING AND CURRENT ROW)` is
+/// rewritten to `$window_over(f(...), $window_partition(p), $window_order($sort_desc(o)),
+/// $window_frame('ROWS', '1 PRECEDING', 'CURRENT ROW'))` before parsing, omitting the clauses
+/// that are not present.
+fn rewrite_window_functions(tokens: Vec<Token>) -> Vec<Token> {
+    let mut out: Vec<Token> = Vec::with_capacity(tokens.len());
+    let mut i = 0;
+    while i < tokens.len() {
+        if is_word(&tokens[i], "OVER") {
+            let lparen =
+                next_non_whitespace(&tokens, i + 1).filter(|&j| tokens[j] == Token::LParen);
+            if let (Some(start), Some(lparen)) = (function_call_start(&out), lparen) {
+                if let Some(rparen) = matching_paren(&tokens, lparen) {
+                    let call = out.split_off(start);
+                    out.push(Token::Identifier("$window_over".to_string()));
+                    out.push(Token::LParen);
+                    out.extend(call);
+                    out.extend(window_spec(&tokens[lparen + 1..rparen]));
+                    out.push(Token::RParen);
+                    i = rparen + 1;
+                    continue;
+                }
+            }
+        }
+        out.push(tokens[i].clone());
+        i += 1;
+    }
+    out
+}
+
+/// Rewrite the clauses of a window specification as arguments to `$window_over`
+fn window_spec(tokens: &[Token]) -> Vec<Token> {
+    // find the start of each clause at the top level of the specification
+    let mut clauses: Vec<(&str, usize)> = vec![];
+    let mut depth = 0;
+    for (i, token) in tokens.iter().enumerate() {
+        match token {
+            Token::LParen => depth += 1,
+            Token::RParen => depth -= 1,
+            _ if depth > 0 => {}
+            _ if is_word(token, "PARTITION") => clauses.push(("PARTITION", i)),
+            _ if is_word(token, "ORDER") => clauses.push(("ORDER", i)),
+            _ if is_word(token, "ROWS") => clauses.push(("ROWS", i)),
+            _ if is_word(token, "RANGE") => clauses.push(("RANGE", i)),
+            _ => {}
+        }
+    }
+
+    let mut out = vec![];
+    for (n, (clause, start)) in clauses.iter().enumerate() {
+        let end = clauses.get(n + 1).map_or(tokens.len(), |(_, end)| *end);
+        let body = &tokens[start + 1..end];
+        out.push(Token::Comma);
+        match *clause {
+            "PARTITION" | "ORDER" => {
+                // skip the BY keyword
+                let body = match next_non_whitespace(body, 0) {
+                    Some(by) if is_word(&body[by], "BY") => &body[by + 1..],
+                    _ => body,
+                };
+                if *clause == "PARTITION" {
+                    out.push(Token::Identifier("$window_partition".to_string()));
+                    out.push(Token::LParen);
+                    out.extend_from_slice(body);
+                } else {
+                    out.push(Token::Identifier("$window_order".to_string()));
+                    out.push(Token::LParen);
+                    for (k, item) in split_top_level(body, |t| *t == Token::Comma)
+                        .iter()
+                        .enumerate()
+                    {
+                        if k > 0 {
+                            out.push(Token::Comma);
+                        }
+                        out.extend(sort_item(item));
+                    }
+                }
+                out.push(Token::RParen);
+            }
+            units => {
+                // a frame with only a start bound ends at the current row
+                let bounds: Vec<String> = split_top_level(body, |t| is_word(t, "AND"))
+                    .iter()
+                    .map(|b| token_text(b))
+                    .collect();
+                let (start_bound, end_bound) = match bounds.as_slice() {
+                    [start, end] if start.starts_with("BETWEEN ") => {
+                        (start["BETWEEN ".len()..].to_string(), end.clone())
+                    }
+                    _ => (token_text(body), "CURRENT ROW".to_string()),
+                };
+                out.push(Token::Identifier("$window_frame".to_string()));
+                out.push(Token::LParen);
+                out.push(Token::SingleQuotedString(units.to_string()));
+                out.push(Token::Comma);
+                out.push(Token::SingleQuotedString(start_bound));
+                out.push(Token::Comma);
+                out.push(Token::SingleQuotedString(end_bound));
+                out.push(Token::RParen);
+            }
+        }
+    }
+    out
+}
+
+/// Rewrite an ORDER BY item to `$sort_desc(expr)` when it is descending, dropping ASC
+fn sort_item(tokens: &[Token]) -> Vec<Token> {
+    match tokens.iter().rposition(|t| !is_whitespace(t)) {
+        Some(last) if is_word(&tokens[last], "DESC") => {
+            let mut out = vec![Token::Identifier("$sort_desc".to_string()), Token::LParen];
+            out.extend_from_slice(&tokens[..last]);
+            out.push(Token::RParen);
+            out
+        }
+        Some(last) if is_word(&tokens[last], "ASC") => tokens[..last].to_vec(),
+        _ => tokens.to_vec(),
+    }
+}
+
+/// Split tokens at the separators that are not nested in parentheses
+fn split_top_level<F>(tokens: &[Token], is_separator: F) -> Vec<&[Token]>
+where
+    F: Fn(&Token) -> bool,
+{
+    let mut parts = vec![];
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, token) in tokens.iter().enumerate() {
+        match token {
+            Token::LParen => depth += 1,
+            Token::RParen => depth -= 1,
+            _ if depth == 0 && is_separator(token) => {
+                parts.push(&tokens[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&tokens[start..]);
+    parts
+}
+
+/// The words of a frame bound such as `UNBOUNDED PRECEDING` or `3 FOLLOWING`
+fn token_text(tokens: &[Token]) -> String {
+    tokens
+        .iter()
+        .filter_map(|t| match t {
+            Token::Keyword(s) | Token::Identifier(s) => Some(s.to_uppercase()),
+            Token::Number(s) => Some(s.clone()),
+            _ => None,
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// Find the index of the parenthesis that closes the one at `lparen`
+fn matching_paren(tokens: &[Token], lparen: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (i, token) in tokens.iter().enumerate().skip(lparen) {
+        match token {
+            Token::LParen => depth += 1,
+            Token::RParen => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Find the index of the END keyword that closes the CASE keyword at `case`
+fn matching_end(tokens: &[Token], case: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (i, token) in tokens.iter().enumerate().skip(case) {
+        if is_word(token, "CASE") {
+            depth += 1;
+        } else if is_word(token, "END") {
+            depth -= 1;
+            if depth == 0 {
+                return Some(i);
+            }
+        }
+    }
+    None
+}
+
+/// Find the index of the first token at or after `start` that is outside any parentheses or
+/// CASE expressions and matches `predicate`, or of the parenthesis that closes an enclosing one
+fn find_top_level<F>(tokens: &[Token], start: usize, predicate: F) -> Option<usize>
+where
+    F: Fn(&Token) -> bool,
+{
+    let mut i = start;
+    while i < tokens.len() {
+        match &tokens[i] {
+            Token::LParen => i = matching_paren(tokens, i)?,
+            Token::RParen => return Some(i),
+            token if is_word(token, "CASE") => i = matching_end(tokens, i)?,
+            token if predicate(token) => return Some(i),
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+/// The sqlparser crate cannot parse `x [NOT] IN (a, b)` or `x [NOT] BETWEEN low AND high`, so
+/// they are rewritten to `x = $in_list(a, b)` and `x = $between(low, high)` before parsing, with
+/// `$not_in_list` and `$not_between` for the negated forms, and the planner turns them into
+/// IN and BETWEEN expressions. IN subqueries have already been rewritten by `rewrite_subqueries`.
+fn rewrite_list_predicates(tokens: Vec<Token>) -> Vec<Token> {
+    let mut out: Vec<Token> = Vec::with_capacity(tokens.len());
+    let mut i = 0;
+    while i < tokens.len() {
+        let is_in = is_word(&tokens[i], "IN");
+        if is_in || is_word(&tokens[i], "BETWEEN") {
+            let negated = match out.iter().rposition(|t| !is_whitespace(t)) {
+                Some(not) if is_word(&out[not], "NOT") => Some(not),
+                _ => None,
+            };
+            let name = match (is_in, negated.is_some()) {
+                (true, false) => "$in_list",
+                (true, true) => "$not_in_list",
+                (false, false) => "$between",
+                (false, true) => "$not_between",
+            };
+            if is_in {
+                let list =
+                    next_non_whitespace(&tokens, i + 1).filter(|&j| tokens[j] == Token::LParen);
+                if let Some(lparen) = list {
+                    if let Some(not) = negated {
+                        out.truncate(not);
+                    }
+                    out.push(Token::Eq);
+                    out.push(Token::Identifier(name.to_string()));
+                    // the list stays in its parentheses and is rewritten as the scan goes on
+                    i = lparen;
+                    continue;
+                }
+            } else {
+                let and = find_top_level(&tokens, i + 1, |t| is_word(t, "AND"))
+                    .filter(|&and| is_word(&tokens[and], "AND"));
+                if let Some(and) = and {
+                    // the upper bound ends at the next boolean operator, comparison or keyword
+                    // other than the name of a function call such as CAST(...)
+                    let mut end = and + 1;
+                    let end = loop {
+                        let next = find_top_level(&tokens, end, |t| match t {
+                            Token::Keyword(k) => !["NULL", "TRUE", "FALSE"]
+                                .iter()
+                                .any(|w| k.eq_ignore_ascii_case(w)),
+                            Token::Comma
+                            | Token::Eq
+                            | Token::Neq
+                            | Token::Lt
+                            | Token::Gt
+                            | Token::LtEq
+                            | Token::GtEq => true,
+                            _ => false,
+                        });
+                        match next {
+                            Some(k) if starts_function_call(&tokens, k) => end = k + 1,
+                            Some(k) => break k,
+                            None => break tokens.len(),
+                        }
+                    };
+                    if let Some(not) = negated {
+                        out.truncate(not);
+                    }
+                    out.push(Token::Eq);
+                    out.push(Token::Identifier(name.to_string()));
+                    out.push(Token::LParen);
+                    out.extend(rewrite_list_predicates(tokens[i + 1..and].to_vec()));
+                    out.push(Token::Comma);
+                    out.extend(rewrite_list_predicates(tokens[and + 1..end].to_vec()));
+                    out.push(Token::RParen);
+                    i = end;
+                    continue;
+                }
+            }
+        }
+        out.push(tokens[i].clone());
+        i += 1;
+    }
+    out
+}
+
+/// Whether the token at `i` is followed by a parenthesis, as the name of a function call
+fn starts_function_call(tokens: &[Token], i: usize) -> bool {
+    next_non_whitespace(tokens, i + 1).map_or(false, |j| tokens[j] == Token::LParen)
+}
+
+/// The sqlparser crate only parses searched CASE expressions, so a simple
+/// `CASE x WHEN a THEN ... END` is rewritten to `$simple_case(x, CASE WHEN a THEN ... END)`
+/// before parsing and the planner compares the operand with each WHEN value.
+fn rewrite_simple_case(tokens: Vec<Token>) -> Vec<Token> {
+    let mut out: Vec<Token> = Vec::with_capacity(tokens.len());
+    let mut i = 0;
+    while i < tokens.len() {
+        if is_word(&tokens[i], "CASE") {
+            let simple = next_non_whitespace(&tokens, i + 1)
+                .filter(|&j| !is_word(&tokens[j], "WHEN"))
+                .and_then(|_| {
+                    let when = find_top_level(&tokens, i + 1, |t| is_word(t, "WHEN"))?;
+                    let end = matching_end(&tokens, i)?;
+                    Some((when, end)).filter(|_| is_word(&tokens[when], "WHEN") && when < end)
+                });
+            if let Some((when, end)) = simple {
+                out.push(Token::Identifier("$simple_case".to_string()));
+                out.push(Token::LParen);
+                out.extend(rewrite_simple_case(tokens[i + 1..when].to_vec()));
+                out.push(Token::Comma);
+                out.push(tokens[i].clone());
+                out.extend(rewrite_simple_case(tokens[when..=end].to_vec()));
+                out.push(Token::RParen);
+                i = end + 1;
+                continue;
+            }
+        }
+        out.push(tokens[i].clone());
+        i += 1;
+    }
+    out
+}
+
+/// `GROUPING SETS ((a, b), c, ())` is not supported by the sqlparser crate, so it is rewritten
+/// to `$grouping_sets($grouping_set(a, b), c, $grouping_set())` before parsing. ROLLUP, CUBE and
+/// GROUPING calls are always parsed as functions.
+fn rewrite_grouping_sets(tokens: Vec<Token>) -> Vec<Token> {
+    let mut out: Vec<Token> = Vec::with_capacity(tokens.len());
+    let mut i = 0;
+    while i < tokens.len() {
+        let next = next_non_whitespace(&tokens, i + 1);
+        if is_word(&tokens[i], "GROUPING") && next.map_or(false, |j| is_word(&tokens[j], "SETS")) {
+            let sets = next.unwrap();
+            if let Some(lparen) = next_non_whitespace(&tokens, sets + 1) {
+                if tokens[lparen] == Token::LParen {
+                    out.push(Token::Identifier("$grouping_sets".to_string()));
+                    out.push(Token::LParen);
+                    // a parenthesized element at the top level of the list is a grouping set
+                    let mut depth = 0;
+                    let mut element_start = true;
+                    let mut j = lparen + 1;
+                    while j < tokens.len() {
+                        let token = &tokens[j];
+                        match token {
+                            Token::LParen if depth == 0 && element_start => {
+                                out.push(Token::Identifier("$grouping_set".to_string()));
+                                depth += 1;
+                            }
+                            Token::LParen => depth += 1,
+                            Token::RParen if depth == 0 => break,
+                            Token::RParen => depth -= 1,
+                            _ => {}
+                        }
+                        if !is_whitespace(token) {
+                            element_start = depth == 0 && *token == Token::Comma;
+                        }
+                        out.push(token.clone());
+                        j += 1;
+                    }
+                    out.push(Token::RParen);
+                    // skip past the closing parenthesis of the list
+                    i = j + 1;
+                    continue;
+                }
+            }
+        }
+        match tokens[i] {
+            Token::Keyword(ref k)
+                if (is_word(&tokens[i], "ROLLUP")
+                    || is_word(&tokens[i], "CUBE")
+                    || is_word(&tokens[i], "GROUPING"))
+                    && next.map_or(false, |j| tokens[j] == Token::LParen) =>
+            {
+                out.push(Token::Identifier(k.clone()))
+            }
+            ref token => out.push(token.clone()),
+        }
+        i += 1;
+    }
+    out
+}
+
+/// The keywords that end a FROM clause
+const FROM_CLAUSE_END: [&str; 8] = [
+    "WHERE",
+    "GROUP",
+    "HAVING",
+    "ORDER",
+    "LIMIT",
+    "UNION",
+    "INTERSECT",
+    "EXCEPT",
+];
+
+/// Table aliases are not supported by the sqlparser crate, so
+/// `FROM (SELECT ...) AS t (a, b)` is rewritten to `FROM $table_alias((SELECT ...), t, a, b)`
+/// before parsing. The relation can be a table name or a parenthesized query, `AS` is optional
+/// and so is the list of column names. Every relation of a FROM clause is rewritten, whether it
+/// follows FROM, a comma or JOIN.
+fn rewrite_table_aliases(tokens: Vec<Token>) -> Vec<Token> {
+    let mut out: Vec<Token> = Vec::with_capacity(tokens.len());
+    // whether the tokens at each level of parentheses are in a FROM clause
+    let mut in_from = vec![false];
+    let mut i = 0;
+    while i < tokens.len() {
+        out.push(tokens[i].clone());
+        let starts_relation = match &tokens[i] {
+            Token::LParen => {
+                in_from.push(false);
+                false
+            }
+            Token::RParen => {
+                in_from.pop();
+                false
+            }
+            Token::Comma => in_from.last() == Some(&true),
+            token if is_word(token, "FROM") => {
+                in_from.pop();
+                in_from.push(true);
+                true
+            }
+            token if is_word(token, "JOIN") => true,
+            token if FROM_CLAUSE_END.iter().any(|w| is_word(token, w)) => {
+                in_from.pop();
+                in_from.push(false);
+                false
+            }
+            _ => false,
+        };
+        if starts_relation {
+            let relation =
+                next_non_whitespace(&tokens, i + 1).and_then(|start| match tokens[start] {
+                    Token::LParen => matching_paren(&tokens, start).map(|end| (start, end + 1)),
+                    Token::Identifier(_) => Some((start, start + 1)),
+                    _ => None,
+                });
+            if let Some((start, end)) = relation {
+                if let Some((alias, columns, next)) = table_alias(&tokens, end) {
+                    out.push(Token::Identifier("$table_alias".to_string()));
+                    out.push(Token::LParen);
+                    // the relation may itself contain aliased relations
+                    out.extend(rewrite_table_aliases(tokens[start..end].to_vec()));
+                    for name in std::iter::once(alias).chain(columns) {
+                        out.push(Token::Comma);
+                        out.push(name);
+                    }
+                    out.push(Token::RParen);
+                    i = next;
+                    continue;
+                }
+            }
+        }
+        i += 1;
+    }
+    out
+}
+
+/// Find the alias and the optional column names that follow a relation ending at `start`,
+/// returning them along with the index of the next token
+fn table_alias(tokens: &[Token], start: usize) -> Option<(Token, Vec<Token>, usize)> {
+    let mut alias = next_non_whitespace(tokens, start)?;
+    if is_word(&tokens[alias], "AS") {
+        alias = next_non_whitespace(tokens, alias + 1)?;
+    }
+    match tokens[alias] {
+        Token::Identifier(_) => {}
+        _ => return None,
+    }
+
+    let lparen = next_non_whitespace(tokens, alias + 1).filter(|&j| tokens[j] == Token::LParen);
+    if let Some(lparen) = lparen {
+        let rparen = matching_paren(tokens, lparen)?;
+        let columns = column_names(&tokens[lparen + 1..rparen])?;
+        Some((tokens[alias].clone(), columns, rparen + 1))
+    } else {
+        Some((tokens[alias].clone(), vec![], alias + 1))
+    }
+}
+
+/// Parse a comma separated list of column names
+fn column_names(tokens: &[Token]) -> Option<Vec<Token>> {
+    split_top_level(tokens, |t| *t == Token::Comma)
+        .iter()
+        .map(|column| {
+            let names: Vec<&Token> = column.iter().filter(|t| !is_whitespace(t)).collect();
+            match names.as_slice() {
+                [Token::Identifier(name)] => Some(Token::Identifier(name.clone())),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Common table expressions are not supported by the sqlparser crate, so
+/// `WITH t (a, b) AS (SELECT ...) SELECT ...` is rewritten to
+/// `$with_query($cte(t, $cte_columns(a, b), (SELECT ...)), (SELECT ...))` before parsing. In a
+/// `WITH RECURSIVE` clause the query of a CTE is split at its last top-level UNION into
+/// `$cte(t, $cte_columns(a, b), (static term), (recursive term), 'ALL')`, with 'DISTINCT' in place
+/// of 'ALL' for a UNION without ALL.
+fn rewrite_with_clauses(tokens: Vec<Token>) -> Vec<Token> {
+    let mut out: Vec<Token> = Vec::with_capacity(tokens.len());
+    let mut i = 0;
+    while i < tokens.len() {
+        // a WITH clause can start a statement or a parenthesized query
+        let query_start = out
+            .iter()
+            .rposition(|t| !is_whitespace(t))
+            .map_or(true, |j| out[j] == Token::LParen);
+        if query_start && is_word(&tokens[i], "WITH") {
+            if let Some((query, next)) = with_query(&tokens, i) {
+                out.extend(query);
+                i = next;
+                continue;
+            }
+        }
+        out.push(tokens[i].clone());
+        i += 1;
+    }
+    out
+}
+
+/// Rewrite the query with a WITH clause starting at `start`, returning the rewritten tokens
+/// along with the index of the token after the end of the query
+fn with_query(tokens: &[Token], start: usize) -> Option<(Vec<Token>, usize)> {
+    let mut i = next_non_whitespace(tokens, start + 1)?;
+    let recursive = is_word(&tokens[i], "RECURSIVE");
+    if recursive {
+        i = next_non_whitespace(tokens, i + 1)?;
+    }
+
+    let mut out = vec![Token::Identifier("$with_query".to_string()), Token::LParen];
+    loop {
+        let name = match tokens[i] {
+            Token::Identifier(_) => tokens[i].clone(),
+            _ => return None,
+        };
+        i = next_non_whitespace(tokens, i + 1)?;
+        let mut columns = vec![];
+        if tokens[i] == Token::LParen {
+            let rparen = matching_paren(tokens, i)?;
+            columns = column_names(&tokens[i + 1..rparen])?;
+            i = next_non_whitespace(tokens, rparen + 1)?;
+        }
+        if !is_word(&tokens[i], "AS") {
+            return None;
+        }
+        let lparen = next_non_whitespace(tokens, i + 1).filter(|&j| tokens[j] == Token::LParen)?;
+        let rparen = matching_paren(tokens, lparen)?;
+
+        out.push(Token::Identifier("$cte".to_string()));
+        out.push(Token::LParen);
+        out.push(name);
+        out.push(Token::Comma);
+        out.push(Token::Identifier("$cte_columns".to_string()));
+        out.push(Token::LParen);
+        for (k, column) in columns.into_iter().enumerate() {
+            if k > 0 {
+                out.push(Token::Comma);
+            }
+            out.push(column);
+        }
+        out.push(Token::RParen);
+        let body = &tokens[lparen + 1..rparen];
+        let union = if recursive {
+            recursive_union(body)
+        } else {
+            None
+        };
+        match union {
+            Some((static_end, recursive_start, all)) => {
+                for term in &[&body[..static_end], &body[recursive_start..]] {
+                    out.push(Token::Comma);
+                    out.push(Token::LParen);
+                    out.extend(rewrite_with_clauses(term.to_vec()));
+                    out.push(Token::RParen);
+                }
+                out.push(Token::Comma);
+                let kind = if all { "ALL" } else { "DISTINCT" };
+                out.push(Token::SingleQuotedString(kind.to_string()));
+            }
+            None => {
+                out.push(Token::Comma);
+                out.push(Token::LParen);
+                out.extend(rewrite_with_clauses(body.to_vec()));
+                out.push(Token::RParen);
+            }
+        }
+        out.push(Token::RParen);
+        out.push(Token::Comma);
+
+        i = next_non_whitespace(tokens, rparen + 1)?;
+        if tokens[i] == Token::Comma {
+            i = next_non_whitespace(tokens, i + 1)?;
+        } else {
+            break;
+        }
+    }
+
+    // the query runs to the end of the enclosing parentheses or the statement
+    let mut depth = 0;
+    let mut end = tokens.len();
+    for (j, token) in tokens.iter().enumerate().skip(i) {
+        match token {
+            Token::LParen => depth += 1,
+            Token::RParen if depth == 0 => {
+                end = j;
+                break;
+            }
+            Token::RParen => depth -= 1,
+            Token::SemiColon if depth == 0 => {
+                end = j;
+                break;
+            }
+            _ => {}
+        }
+    }
+    out.push(Token::LParen);
+    out.extend(rewrite_with_clauses(tokens[i..end].to_vec()));
+    out.push(Token::RParen);
+    out.push(Token::RParen);
+    Some((out, end))
+}
+
+/// Find the last UNION at the top level of the query of a recursive CTE, returning the end of
+/// the static term, the start of the recursive term and whether it is a UNION ALL
+fn recursive_union(tokens: &[Token]) -> Option<(usize, usize, bool)> {
+    let mut depth = 0;
+    let mut union = None;
+    for (i, token) in tokens.iter().enumerate() {
+        match token {
+            Token::LParen => depth += 1,
+            Token::RParen => depth -= 1,
+            _ if depth == 0 && is_word(token, "UNION") => union = Some(i),
+            _ => {}
+        }
+    }
+    let union = union?;
+    let next = next_non_whitespace(tokens, union + 1)?;
+    if is_word(&tokens[next], "ALL") {
+        Some((union, next + 1, true))
+    } else if is_word(&tokens[next], "DISTINCT") {
+        Some((union, next + 1, false))
+    } else {
+        Some((union, union + 1, false))
+    }
+}
+
+/// Subquery predicates are not supported by the sqlparser crate, so `x IN (SELECT ...)` is
+/// rewritten to `x = $in_subquery((SELECT ...))` before parsing, `x NOT IN (SELECT ...)` to
+/// `x = $not_in_subquery((SELECT ...))` and `EXISTS (SELECT ...)` to
+/// `$exists_subquery((SELECT ...))`. The planner turns these into semi and anti joins.
+fn rewrite_subqueries(tokens: Vec<Token>) -> Vec<Token> {
+    let mut out: Vec<Token> = Vec::with_capacity(tokens.len());
+    let mut i = 0;
+    while i < tokens.len() {
+        let is_in = is_word(&tokens[i], "IN");
+        if is_in || is_word(&tokens[i], "EXISTS") {
+            let query = next_non_whitespace(&tokens, i + 1)
+                .filter(|&j| tokens[j] == Token::LParen)
+                .filter(|&j| {
+                    next_non_whitespace(&tokens, j + 1).map_or(false, |k| {
+                        is_word(&tokens[k], "SELECT") || is_word(&tokens[k], "WITH")
+                    })
+                })
+                .and_then(|j| matching_paren(&tokens, j).map(|end| (j, end)));
+            if let Some((start, end)) = query {
+                let name = if is_in {
+                    let negated = out
+                        .iter()
+                        .rposition(|t| !is_whitespace(t))
+                        .filter(|&j| is_word(&out[j], "NOT"));
+                    let name = match negated {
+                        Some(not) => {
+                            out.truncate(not);
+                            "$not_in_subquery"
+                        }
+                        None => "$in_subquery",
+                    };
+                    out.push(Token::Eq);
+                    name
+                } else {
+                    "$exists_subquery"
+                };
+                out.push(Token::Identifier(name.to_string()));
+                out.push(Token::LParen);
+                // the subquery may itself contain subqueries
+                out.extend(rewrite_subqueries(tokens[start..=end].to_vec()));
+                out.push(Token::RParen);
+                i = end + 1;
+                continue;
+            }
+        }
+        out.push(tokens[i].clone());
+        i += 1;
+    }
+    out
+}
+
+/// Set operations are not supported by the sqlparser crate, so
+/// `SELECT ... UNION ALL SELECT ...` is rewritten to
+/// `$set_operation('UNION', 'ALL', (SELECT ...), (SELECT ...))` before parsing, with 'DISTINCT'
+/// in place of 'ALL' when ALL is not given. INTERSECT binds more tightly than UNION and EXCEPT,
+/// which apply from left to right. An ORDER BY or LIMIT after the last term applies to the
+/// result, so the query is rewritten to `SELECT * FROM $set_operation(...) ORDER BY ...`.
+fn rewrite_set_operations(tokens: Vec<Token>) -> Vec<Token> {
+    // the queries nested in parentheses are rewritten first
+    let mut out: Vec<Token> = Vec::with_capacity(tokens.len());
+    let mut i = 0;
+    while i < tokens.len() {
+        if tokens[i] == Token::LParen {
+            if let Some(end) = matching_paren(&tokens, i) {
+                out.push(Token::LParen);
+                out.extend(rewrite_set_operations(tokens[i + 1..end].to_vec()));
+                out.push(Token::RParen);
+                i = end + 1;
+                continue;
+            }
+        }
+        out.push(tokens[i].clone());
+        i += 1;
+    }
+    set_operation(&out).unwrap_or(out)
+}
+
+/// Rewrite the set operations at the top level of a query, returning `None` when there are none
+fn set_operation(tokens: &[Token]) -> Option<Vec<Token>> {
+    // find the operators and the terms between them, up to the end of the statement
+    let mut operators: Vec<(&str, bool)> = vec![];
+    let mut terms: Vec<&[Token]> = vec![];
+    let mut depth = 0;
+    let mut start = 0;
+    let mut end = tokens.len();
+    let mut i = 0;
+    while i < tokens.len() {
+        match tokens[i] {
+            Token::LParen => depth += 1,
+            Token::RParen => depth -= 1,
+            Token::SemiColon if depth == 0 => {
+                end = i;
+                break;
+            }
+            ref token if depth == 0 => {
+                let operator = ["UNION", "INTERSECT", "EXCEPT"]
+                    .iter()
+                    .find(|op| is_word(token, op))
+                    .cloned();
+                if let Some(operator) = operator {
+                    terms.push(&tokens[start..i]);
+                    start = i + 1;
+                    let quantifier = next_non_whitespace(tokens, i + 1)
+                        .filter(|&j| is_word(&tokens[j], "ALL") || is_word(&tokens[j], "DISTINCT"));
+                    if let Some(j) = quantifier {
+                        start = j + 1;
+                    }
+                    let all = quantifier.map_or(false, |j| is_word(&tokens[j], "ALL"));
+                    operators.push((operator, all));
+                    i = start;
+                    continue;
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    if operators.is_empty() {
+        return None;
+    }
+    // an ORDER BY or LIMIT after the last term applies to the result of the set operation
+    let last = &tokens[start..end];
+    let trailing = split_top_level(last, |t| is_word(t, "ORDER") || is_word(t, "LIMIT"))[0].len();
+    terms.push(&last[..trailing]);
+
+    // each INTERSECT is combined with the term before it, then the rest from left to right
+    let mut operands: Vec<Vec<Token>> = vec![terms[0].to_vec()];
+    let mut rest: Vec<(&str, bool)> = vec![];
+    for ((operator, all), term) in operators.into_iter().zip(terms.into_iter().skip(1)) {
+        if operator == "INTERSECT" {
+            let left = operands.pop().unwrap();
+            operands.push(set_operation_call(operator, all, &left, term));
+        } else {
+            rest.push((operator, all));
+            operands.push(term.to_vec());
+        }
+    }
+    let mut operands = operands.into_iter();
+    let mut out = operands.next().unwrap();
+    for ((operator, all), right) in rest.into_iter().zip(operands) {
+        out = set_operation_call(operator, all, &out, &right);
+    }
+    if trailing < last.len() {
+        let mut query = vec![
+            Token::Keyword("SELECT".to_string()),
+            Token::Mult,
+            Token::Keyword("FROM".to_string()),
+        ];
+        query.extend(out);
+        query.extend_from_slice(&last[trailing..]);
+        out = query;
+    }
+    out.extend_from_slice(&tokens[end..]);
+    Some(out)
+}
+
+/// The tokens of a `$set_operation` call combining two queries
+fn set_operation_call(operator: &str, all: bool, left: &[Token], right: &[Token]) -> Vec<Token> {
+    let quantifier = if all { "ALL" } else { "DISTINCT" };
+    let mut out = vec![
+        Token::Identifier("$set_operation".to_string()),
+        Token::LParen,
+        Token::SingleQuotedString(operator.to_string()),
+        Token::Comma,
+        Token::SingleQuotedString(quantifier.to_string()),
+    ];
+    for query in &[left, right] {
+        out.push(Token::Comma);
+        out.push(Token::LParen);
+        out.extend_from_slice(query);
+        out.push(Token::RParen);
+    }
+    out.push(Token::RParen);
+    out
+}
+
+fn next_non_whitespace(tokens: &[Token], start: usize) -> Option<usize> {
+    (start..tokens.len()).find(|&i| !is_whitespace(&tokens[i]))
+}
+
+fn is_word(token: &Token, word: &str) -> bool {
+    match token {
+        Token::Keyword(s) | Token::Identifier(s) => s.eq_ignore_ascii_case(word),
+        _ => false,
+    }
+}
+
+fn is_whitespace(token: &Token) -> bool {
+    match token {
+        Token::Whitespace(_) => true,
+        _ => false,
+    }
+}
+
+/// Find the index of the function name for a call that ends with the last token
+fn function_call_start(tokens: &[Token]) -> Option<usize> {
+    let mut i = tokens.iter().rposition(|t| !is_whitespace(t))?;
+    if tokens[i] != Token::RParen {
+        return None;
+    }
+    let mut depth = 0;
+    loop {
+        match tokens[i] {
+            Token::RParen => depth += 1,
+            Token::LParen => {
+                depth -= 1;
+                if depth == 0 {
+                    break;
+                }
+            }
+            _ => {}
+        }
+        if i == 0 {
+            return None;
+        }
+        i -= 1;
+    }
+    let name = tokens[..i].iter().rposition(|t| !is_whitespace(t))?;
+    match tokens[name] {
+        Token::Identifier(_) => Some(name),
+        _ => None,
+    }
+}
+
+/// Find the range of the condition in a `(WHERE cond)` clause starting at `start`
+fn filter_condition(tokens: &[Token], start: usize) -> Option<(usize, usize)> {
+    let mut non_whitespace = (start..tokens.len()).filter(|&i| !is_whitespace(&tokens[i]));
+    let lparen = non_whitespace.next()?;
+    let where_keyword = non_whitespace.next()?;
+    if tokens[lparen] != Token::LParen || !is_word(&tokens[where_keyword], "WHERE") {
+        return None;
+    }
+    let mut depth = 1;
+    for (i, token) in tokens.iter().enumerate().skip(where_keyword + 1) {
+        match token {
+            Token::LParen => depth += 1,
+            Token::RParen => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some((where_keyword + 1, i));
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parse a query with the DFParser, including its rewrites
+    fn parse(sql: &str) -> ASTNode {
+        match DFParser::parse_sql(sql.to_string()).unwrap() {
+            DFASTNode::ANSI(ast) => ast,
+            other => panic!("expected a query but got {:?}", other),
+        }
+    }
+
+    /// The data types of the CAST expressions in the SELECT list of a query
+    fn cast_types(sql: &str) -> Vec<SQLType> {
+        match parse(sql) {
+            ASTNode::SQLSelect { projection, .. } => projection
+                .into_iter()
+                .map(|e| match e {
+                    ASTNode::SQLCast { data_type, .. } => data_type,
+                    other => panic!("expected a CAST but got {:?}", other),
+                })
+                .collect(),
+            other => panic!("expected a SELECT but got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn list_predicates() {
+        let sql = "SELECT a FROM t WHERE a IN (1, 2) AND b NOT IN ('x') \
+                   AND c BETWEEN 1 AND CAST(d AS INT) AND e NOT BETWEEN f + 1 AND 10 \
+                   AND g BETWEEN 0 AND CASE WHEN h THEN 1 ELSE 2 END";
+        assert_eq!(
+            "SELECT a FROM t WHERE a = $in_list(1, 2) AND b = $not_in_list('x') \
+             AND c = $between(1, CAST(d AS int)) AND e = $not_between(f + 1, 10) \
+             AND g = $between(0, CASE WHEN h THEN 1 ELSE 2 END)",
+            parse(sql).to_string()
+        );
+    }
+
+    #[test]
+    fn simple_case() {
+        let sql = "SELECT CASE a WHEN 1 THEN 'one' WHEN 2 THEN 'two' END FROM t";
+        assert_eq!(
+            "SELECT $simple_case(a, CASE WHEN 1 THEN 'one' WHEN 2 THEN 'two' END) FROM t",
+            parse(sql).to_string()
+        );
+    }
+
+    #[test]
+    fn unary_operators() {
+        let sql = "SELECT -a, +t.b, - -1, 2 * -f(x), a - -b, -CASE WHEN c THEN 1 END, \
+                   -CAST(a AS INT), TRUE FROM t WHERE -a < 0 AND NOT FALSE";
+        assert_eq!(
+            "SELECT - a, + t.b, - - 1, 2 * - f(x), a - - b, - CASE WHEN c THEN 1 END, \
+             - CAST(a AS int), true FROM t WHERE - a < 0 AND NOT false",
+            parse(sql).to_string()
+        );
+    }
+
+    #[test]
+    fn aggregate_filters() {
+        let sql = "SELECT SUM(a) FILTER (WHERE b > 1 AND c), COUNT(*) filter (where d), \
+                   filter FROM t GROUP BY e";
+        assert_eq!(
+            "SELECT $aggregate_filter(SUM(a), b > 1 AND c), $aggregate_filter(COUNT(*), d), \
+             filter FROM t GROUP BY e",
+            parse(sql).to_string()
+        );
+    }
+
+    #[test]
+    fn grouping_sets() {
+        let sql = "SELECT a, b, SUM(c) FROM t GROUP BY GROUPING SETS ((a, b), a, ()), ROLLUP (a)";
+        assert_eq!(
+            "SELECT a, b, SUM(c) FROM t \
+             GROUP BY $grouping_sets($grouping_set(a, b), a, $grouping_set()), ROLLUP(a)",
+            parse(sql).to_string()
+        );
+    }
+
+    #[test]
+    fn window_functions() {
+        let sql = "SELECT rank() OVER (PARTITION BY a, b ORDER BY c DESC, d), \
+                   SUM(e) OVER (ORDER BY c ROWS BETWEEN 2 PRECEDING AND CURRENT ROW), \
+                   COUNT(*) OVER (), AVG(e) OVER (RANGE UNBOUNDED PRECEDING) FROM t";
+        assert_eq!(
+            "SELECT $window_over(rank(), $window_partition(a, b), \
+             $window_order($sort_desc(c), d)), \
+             $window_over(SUM(e), $window_order(c), \
+             $window_frame('ROWS', '2 PRECEDING', 'CURRENT ROW')), \
+             $window_over(COUNT(*)), \
+             $window_over(AVG(e), $window_frame('RANGE', 'UNBOUNDED PRECEDING', 'CURRENT ROW')) \
+             FROM t",
+            parse(sql).to_string()
+        );
+    }
+
+    #[test]
+    fn table_aliases() {
+        let sql = "SELECT a FROM t AS x (a, b), (SELECT c FROM u v) w, y \
+                   JOIN z AS z2 ON x.a = z2.a WHERE a = f(b, c) GROUP BY a, b";
+        assert_eq!(
+            "SELECT a FROM $table_alias(t, x, a, b), \
+             $table_alias(SELECT c FROM $table_alias(u, v), w), \
+             y JOIN $table_alias(z, z2) ON x.a = z2.a WHERE a = f(b, c) GROUP BY a, b",
+            parse(sql).to_string()
+        );
+    }
+
+    #[test]
+    fn with_clauses() {
+        let sql = "WITH t (a) AS (SELECT 1), u AS (SELECT a FROM t) SELECT a FROM u";
+        assert_eq!(
+            "$with_query($cte(t, $cte_columns(a), SELECT 1), \
+             $cte(u, $cte_columns(), SELECT a FROM t), SELECT a FROM u)",
+            parse(sql).to_string()
+        );
+        let sql = "WITH RECURSIVE t (n) AS (SELECT 1 UNION ALL SELECT n + 1 FROM t WHERE n < 3) \
+                   SELECT n FROM t";
+        assert_eq!(
+            "$with_query($cte(t, $cte_columns(n), SELECT 1, SELECT n + 1 FROM t WHERE n < 3, 'ALL'), \
+             SELECT n FROM t)",
+            parse(sql).to_string()
+        );
+    }
+
+    #[test]
+    fn subqueries() {
+        let sql = "SELECT a FROM t WHERE a IN (SELECT b FROM u) AND NOT EXISTS (SELECT c FROM v) \
+                   AND a NOT IN (SELECT d FROM w WHERE d > (SELECT MAX(e) FROM x))";
+        assert_eq!(
+            "SELECT a FROM t WHERE a = $in_subquery(SELECT b FROM u) \
+             AND NOT $exists_subquery(SELECT c FROM v) \
+             AND a = $not_in_subquery(SELECT d FROM w WHERE d > SELECT MAX(e) FROM x)",
+            parse(sql).to_string()
+        );
+    }
+
+    #[test]
+    fn scalar_subqueries() {
+        let sql = "SELECT a, (SELECT MAX(b) FROM u WHERE u.c = t.c) FROM t \
+                   WHERE a > (SELECT AVG(a) FROM t)";
+        // the parsed queries are not printed in parentheses
+        assert_eq!(
+            "SELECT a, SELECT MAX(b) FROM u WHERE u.c = t.c FROM t \
+             WHERE a > SELECT AVG(a) FROM t",
+            parse(sql).to_string()
+        );
+    }
+
+    #[test]
+    fn set_operations() {
+        let sql = "SELECT a FROM t UNION ALL SELECT b FROM u INTERSECT SELECT c FROM v \
+                   EXCEPT SELECT d FROM w ORDER BY a LIMIT 2";
+        // INTERSECT binds more tightly than UNION and EXCEPT
+        assert_eq!(
+            "SELECT * FROM $set_operation('EXCEPT', 'DISTINCT', \
+             $set_operation('UNION', 'ALL', SELECT a FROM t, \
+             $set_operation('INTERSECT', 'DISTINCT', SELECT b FROM u, SELECT c FROM v)), \
+             SELECT d FROM w) ORDER BY a ASC LIMIT 2",
+            parse(sql).to_string()
+        );
+    }
+
+    #[test]
+    fn subscripts() {
+        let sql = "SELECT tags[1], address.tags[i + 1], f(x)[2][3], (tags)[n[1]] FROM t";
+        let expected = "SELECT array_element(tags, 1), array_element(address.tags, i + 1), \
+                        array_element(array_element(f(x), 2), 3), \
+                        array_element((tags), array_element(n, 1)) FROM t";
+        assert_eq!(parse(expected), parse(sql));
+    }
+
+    #[test]
+    fn within_group() {
+        let sql = "SELECT percentile_cont(0.5) WITHIN GROUP (ORDER BY a), \
+                   percentile_disc(0.25) WITHIN GROUP (ORDER BY a + 1 ASC), \
+                   mode() WITHIN GROUP (ORDER BY b) FILTER (WHERE a > 1) FROM t";
+        let expected = "SELECT percentile_cont(a, 0.5), percentile_disc(a + 1, 0.25), \
+                        mode(b) FILTER (WHERE a > 1) FROM t";
+        assert_eq!(parse(expected), parse(sql));
+    }
+
+    #[test]
+    fn aggregate_orderings() {
+        let sql = "SELECT array_agg(a ORDER BY b), string_agg(c, ',' ORDER BY f(b, a) ASC), \
+                   listagg(c, '|') WITHIN GROUP (ORDER BY a) FROM t";
+        let expected = "SELECT array_agg(a, b), string_agg(c, ',', f(b, a)), \
+                        listagg(c, '|', a) FROM t";
+        assert_eq!(parse(expected), parse(sql));
+    }
+
+    #[test]
+    fn bare_time_type() {
+        let sql = "SELECT CAST(a AS TIME), CAST(a AS TIMESTAMP), CAST(a AS TIME WITH TIME ZONE), \
+                   CAST(a AS TIMESTAMP WITH TIME ZONE) FROM t";
+        assert_eq!(
+            vec![
+                SQLType::Time,
+                SQLType::Timestamp,
+                SQLType::Time,
+                SQLType::Timestamp
+            ],
+            cast_types(sql)
+        );
+
+        let sql = "CREATE EXTERNAL TABLE t (a TIME, b TIMESTAMP, c TIME NOT NULL) \
+                   STORED AS CSV LOCATION 'x.csv'";
+        match DFParser::parse_sql(sql.to_string()).unwrap() {
+            DFASTNode::CreateExternalTable { columns, .. } => {
+                let types: Vec<SQLType> = columns.into_iter().map(|c| c.data_type).collect();
+                assert_eq!(
+                    vec![SQLType::Time, SQLType::Timestamp, SQLType::Time],
+                    types
+                );
+            }
+            other => panic!("expected CREATE EXTERNAL TABLE but got {:?}", other),
+        }
+    }
+
+}
@@ -12,10 +12,13 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-//! Execution of a simple aggregate relation containing MIN, MAX, COUNT, SUM aggregate functions
-//! with optional GROUP BY columns
+//! Execution of an aggregate relation containing MIN, MAX, COUNT, SUM, AVG, BOOL_AND, BOOL_OR,
+//! ARRAY_AGG, STRING_AGG, statistical, ordered-set and approximate aggregate functions with optional
+//! GROUP BY columns
 
 use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::hash::{Hash, Hasher};
 use std::rc::Rc;
 use std::str;
 use std::sync::Arc;
@@ -27,11 +30,17 @@ use arrow::datatypes::{DataType, Schema};
 use arrow::record_batch::RecordBatch;
 
 use super::error::{ExecutionError, Result};
-use super::expression::{AggregateType, RuntimeExpr};
+use super::expression::{AggregateType, CompiledExpr, RuntimeExpr};
+use super::filter;
 use super::relation::Relation;
+use super::sketch::{HyperLogLog, TDigest};
+use super::temporal::{
+    to_integer_array, with_data_type, DateArray, TimeArray, TimestampArray, DATE_TYPE,
+    TIMESTAMP_TYPE, TIME_TYPE,
+};
 use crate::logicalplan::ScalarValue;
 
-use fnv::FnvHashMap;
+use fnv::{FnvHashMap, FnvHasher};
 
 /// An aggregate relation is made up of zero or more grouping expressions and one
 /// or more aggregate expressions
@@ -39,6 +48,8 @@ pub struct AggregateRelation {
     schema: Arc<Schema>,
     input: Rc<RefCell<Relation>>,
     group_expr: Vec<RuntimeExpr>,
+    /// Optional grouping sets as indices into `group_expr`
+    grouping_sets: Option<Vec<Vec<usize>>>,
     aggr_expr: Vec<RuntimeExpr>,
     end_of_results: bool,
 }
@@ -48,22 +59,26 @@ impl AggregateRelation {
         schema: Arc<Schema>,
         input: Rc<RefCell<Relation>>,
         group_expr: Vec<RuntimeExpr>,
+        grouping_sets: Option<Vec<Vec<usize>>>,
         aggr_expr: Vec<RuntimeExpr>,
     ) -> Self {
         AggregateRelation {
             schema,
             input,
             group_expr,
+            grouping_sets,
             aggr_expr,
             end_of_results: false,
         }
     }
 }
 
-/// Enumeration of types that can be used in a GROUP BY expression (all primitives except for
-/// floating point numerics)
+/// Enumeration of values that can be used in a GROUP BY expression. NULL values form a group of
+/// their own.
 #[derive(Debug, PartialEq, Eq, Hash, Clone)]
 enum GroupByScalar {
+    Null,
+    Boolean(bool),
     UInt8(u8),
     UInt16(u16),
     UInt32(u32),
@@ -72,7 +87,66 @@ enum GroupByScalar {
     Int16(i16),
     Int32(i32),
     Int64(i64),
-    Utf8(String),
+    /// Bits of a canonicalised `f32` (see `canonical_f32`)
+    Float32(u32),
+    /// Bits of a canonicalised `f64` (see `canonical_f64`)
+    Float64(u64),
+    Date32(i32),
+    Time64(i64),
+    Timestamp(i64),
+    /// Key of the string in the aggregate's `StringDictionary`
+    Utf8(u32),
+}
+
+/// Get the bits of an `f32` group key, mapping every NaN to the same value and -0.0 to 0.0 so
+/// that they each form a single group
+fn canonical_f32(n: f32) -> u32 {
+    if n.is_nan() {
+        std::f32::NAN.to_bits()
+    } else if n == 0.0 {
+        0
+    } else {
+        n.to_bits()
+    }
+}
+
+/// Get the bits of an `f64` group key, mapping every NaN to the same value and -0.0 to 0.0 so
+/// that they each form a single group
+fn canonical_f64(n: f64) -> u64 {
+    if n.is_nan() {
+        std::f64::NAN.to_bits()
+    } else if n == 0.0 {
+        0
+    } else {
+        n.to_bits()
+    }
+}
+
+/// Dictionary of the distinct strings seen in GROUP BY columns, so that group keys can hold a
+/// small integer key instead of allocating a `String` for every row
+#[derive(Debug, Default)]
+struct StringDictionary {
+    keys: FnvHashMap<Vec<u8>, u32>,
+    values: Vec<String>,
+}
+
+impl StringDictionary {
+    /// Get the key for a value, adding the value to the dictionary if it is new
+    fn key(&mut self, value: &[u8]) -> u32 {
+        if let Some(key) = self.keys.get(value) {
+            return *key;
+        }
+        let key = self.values.len() as u32;
+        self.keys.insert(value.to_vec(), key);
+        self.values
+            .push(String::from(str::from_utf8(value).unwrap()));
+        key
+    }
+
+    /// Get the value for a key
+    fn value(&self, key: u32) -> &str {
+        &self.values[key as usize]
+    }
 }
 
 /// Common trait for all aggregation functions
@@ -80,7 +154,13 @@ trait AggregateFunction {
     /// Get the function name (used for debugging)
     fn name(&self) -> &str;
     fn accumulate_scalar(&mut self, value: &Option<ScalarValue>);
-    fn result(&self) -> &Option<ScalarValue>;
+    /// Accumulate the values of all arguments for one row. Only aggregate functions with more
+    /// than one argument need to override this.
+    fn accumulate_row(&mut self, values: &[Option<ScalarValue>]) {
+        self.accumulate_scalar(&values[0])
+    }
+    /// Get the result of the aggregation so far
+    fn result(&self) -> Option<ScalarValue>;
     fn data_type(&self) -> &DataType;
 }
 
@@ -139,13 +219,28 @@ impl AggregateFunction for MinFunction {
                 (Some(ScalarValue::Float64(a)), Some(ScalarValue::Float64(b))) => {
                     Some(ScalarValue::Float64(a.min(*b)))
                 }
+                (Some(ScalarValue::Boolean(a)), Some(ScalarValue::Boolean(b))) => {
+                    Some(ScalarValue::Boolean(*a && *b))
+                }
+                (Some(ScalarValue::Utf8(a)), Some(ScalarValue::Utf8(b))) => {
+                    Some(ScalarValue::Utf8(if b < a { b.clone() } else { a.clone() }))
+                }
+                (Some(ScalarValue::Date32(a)), Some(ScalarValue::Date32(b))) => {
+                    Some(ScalarValue::Date32(*a.min(b)))
+                }
+                (Some(ScalarValue::Time64(a)), Some(ScalarValue::Time64(b))) => {
+                    Some(ScalarValue::Time64(*a.min(b)))
+                }
+                (Some(ScalarValue::Timestamp(a)), Some(ScalarValue::Timestamp(b))) => {
+                    Some(ScalarValue::Timestamp(*a.min(b)))
+                }
                 _ => panic!("unsupported data type for MIN"),
             }
         }
     }
 
-    fn result(&self) -> &Option<ScalarValue> {
-        &self.value
+    fn result(&self) -> Option<ScalarValue> {
+        self.value.clone()
     }
 
     fn data_type(&self) -> &DataType {
@@ -208,13 +303,28 @@ impl AggregateFunction for MaxFunction {
                 (Some(ScalarValue::Float64(a)), Some(ScalarValue::Float64(b))) => {
                     Some(ScalarValue::Float64(a.max(*b)))
                 }
+                (Some(ScalarValue::Boolean(a)), Some(ScalarValue::Boolean(b))) => {
+                    Some(ScalarValue::Boolean(*a || *b))
+                }
+                (Some(ScalarValue::Utf8(a)), Some(ScalarValue::Utf8(b))) => {
+                    Some(ScalarValue::Utf8(if b > a { b.clone() } else { a.clone() }))
+                }
+                (Some(ScalarValue::Date32(a)), Some(ScalarValue::Date32(b))) => {
+                    Some(ScalarValue::Date32(*a.max(b)))
+                }
+                (Some(ScalarValue::Time64(a)), Some(ScalarValue::Time64(b))) => {
+                    Some(ScalarValue::Time64(*a.max(b)))
+                }
+                (Some(ScalarValue::Timestamp(a)), Some(ScalarValue::Timestamp(b))) => {
+                    Some(ScalarValue::Timestamp(*a.max(b)))
+                }
                 _ => panic!("unsupported data type for MAX"),
             }
         }
     }
 
-    fn result(&self) -> &Option<ScalarValue> {
-        &self.value
+    fn result(&self) -> Option<ScalarValue> {
+        self.value.clone()
     }
 
     fn data_type(&self) -> &DataType {
@@ -274,18 +384,672 @@ impl AggregateFunction for SumFunction {
                 (Some(ScalarValue::Float32(a)), Some(ScalarValue::Float32(b))) => {
                     Some(ScalarValue::Float32(a + *b))
                 }
-                (Some(ScalarValue::Float64(a)), Some(ScalarValue::Float64(b))) => {
-                    Some(ScalarValue::Float64(a + *b))
+                (Some(ScalarValue::Float64(a)), Some(ScalarValue::Float64(b))) => {
+                    Some(ScalarValue::Float64(a + *b))
+                }
+                _ => panic!("unsupported data type for SUM"),
+            }
+        }
+    }
+
+    fn result(&self) -> Option<ScalarValue> {
+        self.value.clone()
+    }
+
+    fn data_type(&self) -> &DataType {
+        &self.data_type
+    }
+}
+
+/// AVG of a Float64 argument, ignoring NULL values. The average of no values is NULL.
+#[derive(Debug)]
+struct AvgFunction {
+    sum: f64,
+    count: u64,
+    data_type: DataType,
+}
+
+impl AvgFunction {
+    fn new() -> Self {
+        Self {
+            sum: 0.0,
+            count: 0,
+            data_type: DataType::Float64,
+        }
+    }
+}
+
+impl AggregateFunction for AvgFunction {
+    fn name(&self) -> &str {
+        "avg"
+    }
+
+    fn accumulate_scalar(&mut self, value: &Option<ScalarValue>) {
+        match value {
+            Some(ScalarValue::Float64(x)) => {
+                self.sum += x;
+                self.count += 1;
+            }
+            None => {}
+            _ => panic!("unexpected argument for avg"),
+        }
+    }
+
+    fn result(&self) -> Option<ScalarValue> {
+        if self.count == 0 {
+            None
+        } else {
+            Some(ScalarValue::Float64(self.sum / self.count as f64))
+        }
+    }
+
+    fn data_type(&self) -> &DataType {
+        &self.data_type
+    }
+}
+
+/// COUNT is implemented as the sum of the number of non-null values seen, so that the same
+/// accumulator can be used for per-row (grouped) and per-batch (ungrouped) accumulation
+#[derive(Debug)]
+struct CountFunction {
+    data_type: DataType,
+    value: Option<ScalarValue>,
+}
+
+impl CountFunction {
+    fn new() -> Self {
+        Self {
+            data_type: DataType::UInt64,
+            value: Some(ScalarValue::UInt64(0)),
+        }
+    }
+}
+
+impl AggregateFunction for CountFunction {
+    fn name(&self) -> &str {
+        "count"
+    }
+
+    fn accumulate_scalar(&mut self, value: &Option<ScalarValue>) {
+        self.value = match (&self.value, value) {
+            (Some(ScalarValue::UInt64(a)), Some(ScalarValue::UInt64(b))) => {
+                Some(ScalarValue::UInt64(*a + b))
+            }
+            (_, None) => self.value.clone(),
+            _ => panic!("unexpected partial count value"),
+        }
+    }
+
+    fn result(&self) -> Option<ScalarValue> {
+        self.value.clone()
+    }
+
+    fn data_type(&self) -> &DataType {
+        &self.data_type
+    }
+}
+
+/// GROUPING() has the same value for every row of a group, which is determined by the grouping
+/// set that the group belongs to
+#[derive(Debug)]
+struct GroupingFunction {
+    data_type: DataType,
+    value: Option<ScalarValue>,
+}
+
+impl GroupingFunction {
+    fn new() -> Self {
+        Self {
+            data_type: DataType::UInt32,
+            value: None,
+        }
+    }
+}
+
+impl AggregateFunction for GroupingFunction {
+    fn name(&self) -> &str {
+        "grouping"
+    }
+
+    fn accumulate_scalar(&mut self, value: &Option<ScalarValue>) {
+        self.value = value.clone();
+    }
+
+    fn result(&self) -> Option<ScalarValue> {
+        self.value.clone()
+    }
+
+    fn data_type(&self) -> &DataType {
+        &self.data_type
+    }
+}
+
+/// Get the value of GROUPING() for the grouping set, where each bit is set if the corresponding
+/// grouping expression is not part of the set
+fn grouping_id(indices: &[usize], grouping_set: &[usize]) -> u32 {
+    indices.iter().fold(0, |id, i| {
+        (id << 1) | if grouping_set.contains(i) { 0 } else { 1 }
+    })
+}
+
+/// Sort values by their ORDER BY keys, keeping the input order of values with equal keys and
+/// placing NULL keys last
+fn sort_by_keys<T>(items: &mut Vec<(Option<ScalarValue>, T)>) {
+    items.sort_by(|(a, _), (b, _)| match (a, b) {
+        (Some(a), Some(b)) => compare_scalars(a, b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    });
+}
+
+/// ARRAY_AGG collects every input value, including NULLs, into a list, optionally ordered by a
+/// second argument. The result is NULL when there are no input rows.
+#[derive(Debug)]
+struct ArrayAggFunction {
+    data_type: DataType,
+    /// ORDER BY key (if any) and value of each input row
+    items: Vec<(Option<ScalarValue>, ScalarValue)>,
+}
+
+impl ArrayAggFunction {
+    fn new(data_type: &DataType) -> Self {
+        Self {
+            data_type: data_type.clone(),
+            items: vec![],
+        }
+    }
+}
+
+impl AggregateFunction for ArrayAggFunction {
+    fn name(&self) -> &str {
+        "array_agg"
+    }
+
+    fn accumulate_scalar(&mut self, value: &Option<ScalarValue>) {
+        self.accumulate_row(&[value.clone()])
+    }
+
+    fn accumulate_row(&mut self, values: &[Option<ScalarValue>]) {
+        let item = values[0].clone().unwrap_or(ScalarValue::Null);
+        let key = values.get(1).cloned().unwrap_or(None);
+        self.items.push((key, item));
+    }
+
+    fn result(&self) -> Option<ScalarValue> {
+        if self.items.is_empty() {
+            return None;
+        }
+        let element_type = match self.data_type {
+            DataType::List(ref t) => t.as_ref().clone(),
+            _ => panic!("array_agg must have a list data type"),
+        };
+        let mut items = self.items.clone();
+        sort_by_keys(&mut items);
+        Some(ScalarValue::List(
+            items.into_iter().map(|(_, v)| v).collect(),
+            element_type,
+        ))
+    }
+
+    fn data_type(&self) -> &DataType {
+        &self.data_type
+    }
+}
+
+/// STRING_AGG concatenates the non-null input strings with a separator, optionally ordered by a
+/// second argument. The result is NULL when there are no non-null input values.
+#[derive(Debug)]
+struct StringAggFunction {
+    separator: String,
+    data_type: DataType,
+    /// ORDER BY key (if any) and value of each non-null input value
+    items: Vec<(Option<ScalarValue>, Rc<String>)>,
+}
+
+impl StringAggFunction {
+    fn new(separator: &str) -> Self {
+        Self {
+            separator: separator.to_string(),
+            data_type: DataType::Utf8,
+            items: vec![],
+        }
+    }
+}
+
+impl AggregateFunction for StringAggFunction {
+    fn name(&self) -> &str {
+        "string_agg"
+    }
+
+    fn accumulate_scalar(&mut self, value: &Option<ScalarValue>) {
+        self.accumulate_row(&[value.clone()])
+    }
+
+    fn accumulate_row(&mut self, values: &[Option<ScalarValue>]) {
+        match &values[0] {
+            Some(ScalarValue::Utf8(s)) => {
+                let key = values.get(1).cloned().unwrap_or(None);
+                self.items.push((key, s.clone()));
+            }
+            None => {}
+            _ => panic!("unexpected argument for {}", self.name()),
+        }
+    }
+
+    fn result(&self) -> Option<ScalarValue> {
+        if self.items.is_empty() {
+            return None;
+        }
+        let mut items = self.items.clone();
+        sort_by_keys(&mut items);
+        let strings: Vec<&str> = items.iter().map(|(_, s)| s.as_str()).collect();
+        Some(ScalarValue::Utf8(Rc::new(strings.join(&self.separator))))
+    }
+
+    fn data_type(&self) -> &DataType {
+        &self.data_type
+    }
+}
+
+/// Statistics computed by `VarianceFunction`
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Variance {
+    Sample,
+    Population,
+    SampleStddev,
+    PopulationStddev,
+}
+
+/// VAR_SAMP, VAR_POP, STDDEV_SAMP and STDDEV_POP of a Float64 argument, accumulated with
+/// Welford's algorithm to avoid the loss of precision of summing squares
+#[derive(Debug)]
+struct VarianceFunction {
+    statistic: Variance,
+    count: u64,
+    mean: f64,
+    m2: f64,
+    data_type: DataType,
+}
+
+impl VarianceFunction {
+    fn new(statistic: Variance) -> Self {
+        Self {
+            statistic,
+            count: 0,
+            mean: 0.0,
+            m2: 0.0,
+            data_type: DataType::Float64,
+        }
+    }
+}
+
+impl AggregateFunction for VarianceFunction {
+    fn name(&self) -> &str {
+        match self.statistic {
+            Variance::Sample => "var_samp",
+            Variance::Population => "var_pop",
+            Variance::SampleStddev => "stddev_samp",
+            Variance::PopulationStddev => "stddev_pop",
+        }
+    }
+
+    fn accumulate_scalar(&mut self, value: &Option<ScalarValue>) {
+        let x = match value {
+            Some(ScalarValue::Float64(x)) => *x,
+            None => return,
+            _ => panic!("unexpected argument for {}", self.name()),
+        };
+        self.count += 1;
+        let delta = x - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (x - self.mean);
+    }
+
+    fn result(&self) -> Option<ScalarValue> {
+        let divisor = match self.statistic {
+            Variance::Sample | Variance::SampleStddev => self.count.checked_sub(1)?,
+            Variance::Population | Variance::PopulationStddev => self.count,
+        };
+        if divisor == 0 {
+            return None;
+        }
+        let variance = self.m2 / divisor as f64;
+        Some(ScalarValue::Float64(match self.statistic {
+            Variance::SampleStddev | Variance::PopulationStddev => variance.sqrt(),
+            _ => variance,
+        }))
+    }
+
+    fn data_type(&self) -> &DataType {
+        &self.data_type
+    }
+}
+
+/// Statistics computed by `CovarianceFunction`
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Covariance {
+    Sample,
+    Population,
+    Correlation,
+    RegressionSlope,
+    RegressionIntercept,
+}
+
+/// COVAR_SAMP, COVAR_POP, CORR, REGR_SLOPE and REGR_INTERCEPT of a pair of Float64 arguments
+/// `(y, x)`, accumulated with the co-moment form of Welford's algorithm. Rows where either
+/// argument is NULL are ignored.
+#[derive(Debug)]
+struct CovarianceFunction {
+    statistic: Covariance,
+    count: u64,
+    mean_x: f64,
+    mean_y: f64,
+    /// sum of the products of the differences of x and y from their means
+    c: f64,
+    /// sum of the squared differences of x from its mean
+    m2_x: f64,
+    /// sum of the squared differences of y from its mean
+    m2_y: f64,
+    data_type: DataType,
+}
+
+impl CovarianceFunction {
+    fn new(statistic: Covariance) -> Self {
+        Self {
+            statistic,
+            count: 0,
+            mean_x: 0.0,
+            mean_y: 0.0,
+            c: 0.0,
+            m2_x: 0.0,
+            m2_y: 0.0,
+            data_type: DataType::Float64,
+        }
+    }
+}
+
+impl AggregateFunction for CovarianceFunction {
+    fn name(&self) -> &str {
+        match self.statistic {
+            Covariance::Sample => "covar_samp",
+            Covariance::Population => "covar_pop",
+            Covariance::Correlation => "corr",
+            Covariance::RegressionSlope => "regr_slope",
+            Covariance::RegressionIntercept => "regr_intercept",
+        }
+    }
+
+    fn accumulate_scalar(&mut self, _value: &Option<ScalarValue>) {
+        panic!("{} requires two arguments", self.name())
+    }
+
+    fn accumulate_row(&mut self, values: &[Option<ScalarValue>]) {
+        let (y, x) = match (&values[0], &values[1]) {
+            (Some(ScalarValue::Float64(y)), Some(ScalarValue::Float64(x))) => (*y, *x),
+            (None, _) | (_, None) => return,
+            _ => panic!("unexpected arguments for {}", self.name()),
+        };
+        self.count += 1;
+        let n = self.count as f64;
+        let dx = x - self.mean_x;
+        let dy = y - self.mean_y;
+        self.mean_x += dx / n;
+        self.mean_y += dy / n;
+        self.c += dx * (y - self.mean_y);
+        self.m2_x += dx * (x - self.mean_x);
+        self.m2_y += dy * (y - self.mean_y);
+    }
+
+    fn result(&self) -> Option<ScalarValue> {
+        let n = self.count as f64;
+        let value = match self.statistic {
+            Covariance::Sample if self.count > 1 => Some(self.c / (n - 1.0)),
+            Covariance::Population if self.count > 0 => Some(self.c / n),
+            Covariance::Correlation if self.m2_x * self.m2_y > 0.0 => {
+                Some(self.c / (self.m2_x * self.m2_y).sqrt())
+            }
+            Covariance::RegressionSlope if self.m2_x > 0.0 => Some(self.c / self.m2_x),
+            Covariance::RegressionIntercept if self.m2_x > 0.0 => {
+                Some(self.mean_y - self.c / self.m2_x * self.mean_x)
+            }
+            _ => None,
+        };
+        value.map(ScalarValue::Float64)
+    }
+
+    fn data_type(&self) -> &DataType {
+        &self.data_type
+    }
+}
+
+/// Hash a value for a HyperLogLog with 64-bit FNV-1a, whose output is specified, so that
+/// sketches built separately, or by another version of Rust, can be merged. The sketch indexes
+/// its registers with the high bits, which FNV barely changes for short inputs such as
+/// booleans, so the hash is mixed with the finalizer of MurmurHash3.
+fn hash_scalar(value: &ScalarValue) -> u64 {
+    let mut hasher = FnvHasher::default();
+    // the bytes are written in little-endian order so that the hash does not depend on the
+    // platform
+    match value {
+        ScalarValue::Boolean(b) => hasher.write_u8(*b as u8),
+        ScalarValue::UInt8(n) => hasher.write_u8(*n),
+        ScalarValue::UInt16(n) => hasher.write(&n.to_le_bytes()),
+        ScalarValue::UInt32(n) => hasher.write(&n.to_le_bytes()),
+        ScalarValue::UInt64(n) => hasher.write(&n.to_le_bytes()),
+        ScalarValue::Int8(n) => hasher.write(&n.to_le_bytes()),
+        ScalarValue::Int16(n) => hasher.write(&n.to_le_bytes()),
+        ScalarValue::Int32(n) | ScalarValue::Date32(n) => hasher.write(&n.to_le_bytes()),
+        ScalarValue::Int64(n)
+        | ScalarValue::Time64(n)
+        | ScalarValue::Timestamp(n)
+        | ScalarValue::Interval(n) => hasher.write(&n.to_le_bytes()),
+        ScalarValue::Float32(n) => hasher.write(&canonical_f32(*n).to_le_bytes()),
+        ScalarValue::Float64(n) => hasher.write(&canonical_f64(*n).to_le_bytes()),
+        ScalarValue::Utf8(s) => hasher.write(s.as_bytes()),
+        other => panic!("cannot hash {:?}", other),
+    }
+    let mut h = hasher.finish();
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    h ^ (h >> 33)
+}
+
+/// APPROX_COUNT_DISTINCT estimates the number of distinct non-null values with a HyperLogLog
+#[derive(Debug)]
+struct ApproxCountDistinctFunction {
+    data_type: DataType,
+    sketch: HyperLogLog,
+}
+
+impl ApproxCountDistinctFunction {
+    fn new() -> Self {
+        Self {
+            data_type: DataType::UInt64,
+            sketch: HyperLogLog::new(),
+        }
+    }
+}
+
+impl AggregateFunction for ApproxCountDistinctFunction {
+    fn name(&self) -> &str {
+        "approx_count_distinct"
+    }
+
+    fn accumulate_scalar(&mut self, value: &Option<ScalarValue>) {
+        if let Some(v) = value {
+            self.sketch.add_hash(hash_scalar(v));
+        }
+    }
+
+    fn result(&self) -> Option<ScalarValue> {
+        Some(ScalarValue::UInt64(self.sketch.count()))
+    }
+
+    fn data_type(&self) -> &DataType {
+        &self.data_type
+    }
+}
+
+/// APPROX_PERCENTILE_CONT and APPROX_MEDIAN estimate a percentile of a Float64 argument with a
+/// t-digest
+#[derive(Debug)]
+struct ApproxPercentileFunction {
+    percentile: f64,
+    data_type: DataType,
+    sketch: TDigest,
+}
+
+impl ApproxPercentileFunction {
+    fn new(percentile: f64) -> Self {
+        Self {
+            percentile,
+            data_type: DataType::Float64,
+            sketch: TDigest::new(),
+        }
+    }
+}
+
+impl AggregateFunction for ApproxPercentileFunction {
+    fn name(&self) -> &str {
+        "approx_percentile_cont"
+    }
+
+    fn accumulate_scalar(&mut self, value: &Option<ScalarValue>) {
+        match value {
+            Some(ScalarValue::Float64(n)) => self.sketch.add(*n),
+            None => {}
+            _ => panic!("unexpected argument for {}", self.name()),
+        }
+    }
+
+    fn result(&self) -> Option<ScalarValue> {
+        self.sketch
+            .quantile(self.percentile)
+            .map(ScalarValue::Float64)
+    }
+
+    fn data_type(&self) -> &DataType {
+        &self.data_type
+    }
+}
+
+/// Compare two non-null values of the same type. NaN is ordered after all other floats.
+pub fn compare_scalars(a: &ScalarValue, b: &ScalarValue) -> Ordering {
+    let compare_floats = |a: f64, b: f64| {
+        a.partial_cmp(&b)
+            .unwrap_or_else(|| a.is_nan().cmp(&b.is_nan()))
+    };
+    match (a, b) {
+        (ScalarValue::Boolean(a), ScalarValue::Boolean(b)) => a.cmp(b),
+        (ScalarValue::UInt8(a), ScalarValue::UInt8(b)) => a.cmp(b),
+        (ScalarValue::UInt16(a), ScalarValue::UInt16(b)) => a.cmp(b),
+        (ScalarValue::UInt32(a), ScalarValue::UInt32(b)) => a.cmp(b),
+        (ScalarValue::UInt64(a), ScalarValue::UInt64(b)) => a.cmp(b),
+        (ScalarValue::Int8(a), ScalarValue::Int8(b)) => a.cmp(b),
+        (ScalarValue::Int16(a), ScalarValue::Int16(b)) => a.cmp(b),
+        (ScalarValue::Int32(a), ScalarValue::Int32(b))
+        | (ScalarValue::Date32(a), ScalarValue::Date32(b)) => a.cmp(b),
+        (ScalarValue::Int64(a), ScalarValue::Int64(b))
+        | (ScalarValue::Time64(a), ScalarValue::Time64(b))
+        | (ScalarValue::Timestamp(a), ScalarValue::Timestamp(b))
+        | (ScalarValue::Interval(a), ScalarValue::Interval(b)) => a.cmp(b),
+        (ScalarValue::Float32(a), ScalarValue::Float32(b)) => compare_floats(*a as f64, *b as f64),
+        (ScalarValue::Float64(a), ScalarValue::Float64(b)) => compare_floats(*a, *b),
+        (ScalarValue::Utf8(a), ScalarValue::Utf8(b)) => a.cmp(b),
+        _ => panic!("cannot compare {:?} with {:?}", a, b),
+    }
+}
+
+/// Statistics computed by `OrderedSetFunction`
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum OrderedSet {
+    PercentileCont(f64),
+    PercentileDisc(f64),
+    Mode,
+}
+
+/// MEDIAN, PERCENTILE_CONT, PERCENTILE_DISC and MODE, which buffer every non-null value and sort
+/// them when the result is requested. PERCENTILE_CONT expects Float64 values.
+#[derive(Debug)]
+struct OrderedSetFunction {
+    statistic: OrderedSet,
+    data_type: DataType,
+    values: Vec<ScalarValue>,
+}
+
+impl OrderedSetFunction {
+    fn new(statistic: OrderedSet, data_type: &DataType) -> Self {
+        Self {
+            statistic,
+            data_type: data_type.clone(),
+            values: vec![],
+        }
+    }
+}
+
+impl AggregateFunction for OrderedSetFunction {
+    fn name(&self) -> &str {
+        match self.statistic {
+            OrderedSet::PercentileCont(_) => "percentile_cont",
+            OrderedSet::PercentileDisc(_) => "percentile_disc",
+            OrderedSet::Mode => "mode",
+        }
+    }
+
+    fn accumulate_scalar(&mut self, value: &Option<ScalarValue>) {
+        if let Some(v) = value {
+            self.values.push(v.clone());
+        }
+    }
+
+    fn result(&self) -> Option<ScalarValue> {
+        if self.values.is_empty() {
+            return None;
+        }
+        let mut values = self.values.clone();
+        values.sort_by(compare_scalars);
+        let n = values.len();
+
+        match self.statistic {
+            OrderedSet::PercentileCont(p) => {
+                // interpolate between the values either side of the percentile
+                let position = p * (n - 1) as f64;
+                let lower = position.floor() as usize;
+                let upper = position.ceil() as usize;
+                match (&values[lower], &values[upper]) {
+                    (ScalarValue::Float64(a), ScalarValue::Float64(b)) => Some(
+                        ScalarValue::Float64(a + (b - a) * (position - lower as f64)),
+                    ),
+                    _ => panic!("unexpected argument for {}", self.name()),
+                }
+            }
+            OrderedSet::PercentileDisc(p) => {
+                // the first value whose position in the ordering is at or after the percentile
+                let index = ((p * n as f64).ceil() as usize).max(1) - 1;
+                Some(values[index].clone())
+            }
+            OrderedSet::Mode => {
+                // the most frequent value, choosing the smallest when there is a tie
+                let mut best = 0;
+                let mut best_count = 0;
+                let mut start = 0;
+                for i in 1..=n {
+                    if i == n || compare_scalars(&values[start], &values[i]) != Ordering::Equal {
+                        if i - start > best_count {
+                            best = start;
+                            best_count = i - start;
+                        }
+                        start = i;
+                    }
                 }
-                _ => panic!("unsupported data type for SUM"),
+                Some(values[best].clone())
             }
         }
     }
 
-    fn result(&self) -> &Option<ScalarValue> {
-        &self.value
-    }
-
     fn data_type(&self) -> &DataType {
         &self.data_type
     }
@@ -301,10 +1065,15 @@ impl AccumulatorSet {
         accumulator.accumulate_scalar(&value);
     }
 
+    fn accumulate_row(&mut self, i: usize, values: &[Option<ScalarValue>]) {
+        let mut accumulator = self.aggr_values[i].borrow_mut();
+        accumulator.accumulate_row(values);
+    }
+
     fn values(&self) -> Vec<Option<ScalarValue>> {
         self.aggr_values
             .iter()
-            .map(|x| x.borrow().result().clone())
+            .map(|x| x.borrow().result())
             .collect()
     }
 }
@@ -315,32 +1084,175 @@ struct MapEntry {
     v: Vec<Option<ScalarValue>>,
 }
 
+fn variance(statistic: Variance) -> Result<Rc<RefCell<AggregateFunction>>> {
+    Ok(Rc::new(RefCell::new(VarianceFunction::new(statistic))))
+}
+
+fn covariance(statistic: Covariance) -> Result<Rc<RefCell<AggregateFunction>>> {
+    Ok(Rc::new(RefCell::new(CovarianceFunction::new(statistic))))
+}
+
+fn ordered_set(
+    statistic: OrderedSet,
+    data_type: &DataType,
+) -> Result<Rc<RefCell<AggregateFunction>>> {
+    Ok(Rc::new(RefCell::new(OrderedSetFunction::new(
+        statistic, data_type,
+    ))))
+}
+
+/// Create the accumulator for an aggregate expression
+fn create_accumulator(aggr_expr: &RuntimeExpr) -> Result<Rc<RefCell<AggregateFunction>>> {
+    match aggr_expr {
+        RuntimeExpr::AggregateFunction { ref f, ref t, .. } => match f {
+            AggregateType::Min => {
+                Ok(Rc::new(RefCell::new(MinFunction::new(t))) as Rc<RefCell<AggregateFunction>>)
+            }
+            AggregateType::Max => {
+                Ok(Rc::new(RefCell::new(MaxFunction::new(t))) as Rc<RefCell<AggregateFunction>>)
+            }
+            AggregateType::Sum => {
+                Ok(Rc::new(RefCell::new(SumFunction::new(t))) as Rc<RefCell<AggregateFunction>>)
+            }
+            AggregateType::Count => {
+                Ok(Rc::new(RefCell::new(CountFunction::new())) as Rc<RefCell<AggregateFunction>>)
+            }
+            AggregateType::Avg => {
+                Ok(Rc::new(RefCell::new(AvgFunction::new())) as Rc<RefCell<AggregateFunction>>)
+            }
+            AggregateType::ArrayAgg => {
+                Ok(Rc::new(RefCell::new(ArrayAggFunction::new(t)))
+                    as Rc<RefCell<AggregateFunction>>)
+            }
+            AggregateType::VarSamp => variance(Variance::Sample),
+            AggregateType::VarPop => variance(Variance::Population),
+            AggregateType::StddevSamp => variance(Variance::SampleStddev),
+            AggregateType::StddevPop => variance(Variance::PopulationStddev),
+            AggregateType::CovarSamp => covariance(Covariance::Sample),
+            AggregateType::CovarPop => covariance(Covariance::Population),
+            AggregateType::Corr => covariance(Covariance::Correlation),
+            AggregateType::RegrSlope => covariance(Covariance::RegressionSlope),
+            AggregateType::RegrIntercept => covariance(Covariance::RegressionIntercept),
+            AggregateType::ApproxCountDistinct => {
+                Ok(Rc::new(RefCell::new(ApproxCountDistinctFunction::new()))
+                    as Rc<RefCell<AggregateFunction>>)
+            }
+            AggregateType::ApproxPercentile(p) => {
+                Ok(Rc::new(RefCell::new(ApproxPercentileFunction::new(*p)))
+                    as Rc<RefCell<AggregateFunction>>)
+            }
+            AggregateType::PercentileCont(p) => ordered_set(OrderedSet::PercentileCont(*p), t),
+            AggregateType::PercentileDisc(p) => ordered_set(OrderedSet::PercentileDisc(*p), t),
+            AggregateType::Mode => ordered_set(OrderedSet::Mode, t),
+            AggregateType::StringAgg(separator) => {
+                Ok(Rc::new(RefCell::new(StringAggFunction::new(separator)))
+                    as Rc<RefCell<AggregateFunction>>)
+            }
+            AggregateType::Grouping(_) => {
+                Ok(Rc::new(RefCell::new(GroupingFunction::new()))
+                    as Rc<RefCell<AggregateFunction>>)
+            }
+            _ => Err(ExecutionError::ExecutionError(
+                "unsupported aggregate function".to_string(),
+            )),
+        },
+        _ => Err(ExecutionError::ExecutionError(
+            "invalid aggregate expression".to_string(),
+        )),
+    }
+}
+
 /// Create an initial aggregate entry
 fn create_accumulators(aggr_expr: &Vec<RuntimeExpr>) -> Result<AccumulatorSet> {
-    let aggr_values: Vec<Rc<RefCell<AggregateFunction>>> =
-        aggr_expr
-            .iter()
-            .map(|e| match e {
-                RuntimeExpr::AggregateFunction { ref f, ref t, .. } => match f {
-                    AggregateType::Min => Ok(Rc::new(RefCell::new(MinFunction::new(t)))
-                        as Rc<RefCell<AggregateFunction>>),
-                    AggregateType::Max => Ok(Rc::new(RefCell::new(MaxFunction::new(t)))
-                        as Rc<RefCell<AggregateFunction>>),
-                    AggregateType::Sum => Ok(Rc::new(RefCell::new(SumFunction::new(t)))
-                        as Rc<RefCell<AggregateFunction>>),
-                    _ => Err(ExecutionError::ExecutionError(
-                        "unsupported aggregate function".to_string(),
-                    )),
-                },
-                _ => Err(ExecutionError::ExecutionError(
-                    "invalid aggregate expression".to_string(),
-                )),
-            })
-            .collect::<Result<Vec<Rc<RefCell<AggregateFunction>>>>>()?;
+    let aggr_values = aggr_expr
+        .iter()
+        .map(create_accumulator)
+        .collect::<Result<Vec<Rc<RefCell<AggregateFunction>>>>>()?;
 
     Ok(AccumulatorSet { aggr_values })
 }
 
+/// Accumulates the rows of a single aggregate expression one at a time, which is used to
+/// evaluate aggregate functions over window frames
+pub struct Accumulator {
+    accumulator: Rc<RefCell<AggregateFunction>>,
+    count: bool,
+}
+
+impl Accumulator {
+    pub fn new(aggr_expr: &RuntimeExpr) -> Result<Self> {
+        let count = match aggr_expr {
+            RuntimeExpr::AggregateFunction {
+                f: AggregateType::Count,
+                ..
+            } => true,
+            _ => false,
+        };
+        Ok(Self {
+            accumulator: create_accumulator(aggr_expr)?,
+            count,
+        })
+    }
+
+    /// Accumulate the values of the arguments to the aggregate function for one row
+    pub fn accumulate(&mut self, values: &[Option<ScalarValue>]) {
+        if self.count {
+            // COUNT accumulates the number of non-null values
+            let value = values[0].as_ref().map(|_| ScalarValue::UInt64(1));
+            self.accumulator.borrow_mut().accumulate_scalar(&value);
+        } else {
+            self.accumulator.borrow_mut().accumulate_row(values);
+        }
+    }
+
+    pub fn result(&self) -> Option<ScalarValue> {
+        self.accumulator.borrow().result()
+    }
+}
+
+/// Get the MIN (logical AND) or MAX (logical OR) of the non-null values in a boolean array
+fn boolean_min_max(array: &ArrayRef, min: bool) -> Option<ScalarValue> {
+    let array = array.as_any().downcast_ref::<BooleanArray>().unwrap();
+    let mut result = None;
+    for i in 0..array.len() {
+        if array.is_valid(i) {
+            let value = array.value(i);
+            result = Some(match result {
+                Some(r) if min => r && value,
+                Some(r) => r || value,
+                None => value,
+            });
+        }
+    }
+    result.map(ScalarValue::Boolean)
+}
+
+/// Get the lexicographically first (`Ordering::Less`) or last (`Ordering::Greater`) non-null
+/// value in a string array
+fn utf8_min_max(array: &ArrayRef, ordering: Ordering) -> Option<ScalarValue> {
+    let array = array.as_any().downcast_ref::<BinaryArray>().unwrap();
+    let mut result: Option<usize> = None;
+    for i in 0..array.len() {
+        if array.is_valid(i) {
+            result = match result {
+                Some(r) if array.value(i).cmp(array.value(r)) != ordering => Some(r),
+                _ => Some(i),
+            };
+        }
+    }
+    result.map(|i| ScalarValue::Utf8(Rc::new(array.get_string(i))))
+}
+
+/// Give the MIN or MAX of the integers that store dates, times or timestamps the temporal type
+fn temporal_scalar(value: ScalarValue, dt: &DataType) -> ScalarValue {
+    match (value, dt) {
+        (ScalarValue::Int32(n), DataType::Date(_)) => ScalarValue::Date32(n),
+        (ScalarValue::Int64(n), DataType::Time64(_)) => ScalarValue::Time64(n),
+        (ScalarValue::Int64(n), DataType::Timestamp(_)) => ScalarValue::Timestamp(n),
+        (value, _) => value,
+    }
+}
+
 fn array_min(array: ArrayRef, dt: &DataType) -> Result<Option<ScalarValue>> {
     match dt {
         DataType::UInt8 => {
@@ -403,6 +1315,13 @@ fn array_min(array: ArrayRef, dt: &DataType) -> Result<Option<ScalarValue>> {
                 None => Ok(None),
             }
         }
+        DataType::Boolean => Ok(boolean_min_max(&array, true)),
+        DataType::Utf8 => Ok(utf8_min_max(&array, Ordering::Less)),
+        DataType::Date(_) | DataType::Time64(_) | DataType::Timestamp(_) => {
+            let values = to_integer_array(&array)?;
+            let value = array_min(values.clone(), values.data_type())?;
+            Ok(value.map(|v| temporal_scalar(v, dt)))
+        }
         _ => Err(ExecutionError::ExecutionError(
             "Unsupported data type for MIN".to_string(),
         )),
@@ -471,6 +1390,13 @@ fn array_max(array: ArrayRef, dt: &DataType) -> Result<Option<ScalarValue>> {
                 None => Ok(None),
             }
         }
+        DataType::Boolean => Ok(boolean_min_max(&array, false)),
+        DataType::Utf8 => Ok(utf8_min_max(&array, Ordering::Greater)),
+        DataType::Date(_) | DataType::Time64(_) | DataType::Timestamp(_) => {
+            let values = to_integer_array(&array)?;
+            let value = array_max(values.clone(), values.data_type())?;
+            Ok(value.map(|v| temporal_scalar(v, dt)))
+        }
         _ => Err(ExecutionError::ExecutionError(
             "Unsupported data type for MAX".to_string(),
         )),
@@ -545,69 +1471,264 @@ fn array_sum(array: ArrayRef, dt: &DataType) -> Result<Option<ScalarValue>> {
     }
 }
 
-fn update_accumulators(
+/// Get the value at the given row as a scalar, or `None` if the value is null
+macro_rules! scalar_at {
+    ($ARRAY:expr, $ROW:expr, $ARRAY_TYPE:ident, $TY:ident) => {{
+        let z = $ARRAY.as_any().downcast_ref::<$ARRAY_TYPE>().unwrap();
+        if z.is_null($ROW) {
+            Ok(None)
+        } else {
+            Ok(Some(ScalarValue::$TY(z.value($ROW))))
+        }
+    }};
+}
+
+/// Get the value at the given row of an array as a scalar, or `None` if the value is null
+pub fn scalar_value(array: &ArrayRef, row: usize) -> Result<Option<ScalarValue>> {
+    match array.data_type() {
+        DataType::UInt8 => scalar_at!(array, row, UInt8Array, UInt8),
+        DataType::UInt16 => scalar_at!(array, row, UInt16Array, UInt16),
+        DataType::UInt32 => scalar_at!(array, row, UInt32Array, UInt32),
+        DataType::UInt64 => scalar_at!(array, row, UInt64Array, UInt64),
+        DataType::Int8 => scalar_at!(array, row, Int8Array, Int8),
+        DataType::Int16 => scalar_at!(array, row, Int16Array, Int16),
+        DataType::Int32 => scalar_at!(array, row, Int32Array, Int32),
+        DataType::Int64 => scalar_at!(array, row, Int64Array, Int64),
+        DataType::Float32 => scalar_at!(array, row, Float32Array, Float32),
+        DataType::Float64 => scalar_at!(array, row, Float64Array, Float64),
+        DataType::Boolean => scalar_at!(array, row, BooleanArray, Boolean),
+        DataType::Date(_) => scalar_at!(array, row, DateArray, Date32),
+        DataType::Time64(_) => scalar_at!(array, row, TimeArray, Time64),
+        DataType::Timestamp(_) => scalar_at!(array, row, TimestampArray, Timestamp),
+        DataType::Utf8 => {
+            let z = array.as_any().downcast_ref::<BinaryArray>().unwrap();
+            if z.is_null(row) {
+                Ok(None)
+            } else {
+                Ok(Some(ScalarValue::Utf8(Rc::new(z.get_string(row)))))
+            }
+        }
+        other => Err(ExecutionError::ExecutionError(format!(
+            "Unsupported data type {:?} for aggregate function argument",
+            other
+        ))),
+    }
+}
+
+/// Create a list array from ARRAY_AGG results, in which `None` represents a NULL list
+macro_rules! list_array_from_scalars {
+    ($BUILDER:ident, $TY:ident, $VALUES:expr) => {{
+        let mut builder = ListBuilder::new($BUILDER::new($VALUES.len()));
+        for value in $VALUES {
+            match value {
+                Some(ScalarValue::List(items, _)) => {
+                    for item in items {
+                        match item {
+                            ScalarValue::$TY(n) => builder.values().append_value(*n)?,
+                            ScalarValue::Null => builder.values().append_null()?,
+                            _ => {
+                                return Err(ExecutionError::ExecutionError(
+                                    "unexpected type when creating list array".to_string(),
+                                ));
+                            }
+                        }
+                    }
+                    builder.append(true)?;
+                }
+                _ => builder.append(false)?,
+            }
+        }
+        Ok(Arc::new(builder.finish()) as ArrayRef)
+    }};
+}
+
+fn list_array_from_scalars(
+    values: &[Option<ScalarValue>],
+    element_type: &DataType,
+) -> Result<ArrayRef> {
+    match element_type {
+        DataType::Boolean => list_array_from_scalars!(BooleanBuilder, Boolean, values),
+        DataType::UInt8 => list_array_from_scalars!(UInt8Builder, UInt8, values),
+        DataType::UInt16 => list_array_from_scalars!(UInt16Builder, UInt16, values),
+        DataType::UInt32 => list_array_from_scalars!(UInt32Builder, UInt32, values),
+        DataType::UInt64 => list_array_from_scalars!(UInt64Builder, UInt64, values),
+        DataType::Int8 => list_array_from_scalars!(Int8Builder, Int8, values),
+        DataType::Int16 => list_array_from_scalars!(Int16Builder, Int16, values),
+        DataType::Int32 => list_array_from_scalars!(Int32Builder, Int32, values),
+        DataType::Int64 => list_array_from_scalars!(Int64Builder, Int64, values),
+        DataType::Float32 => list_array_from_scalars!(Float32Builder, Float32, values),
+        DataType::Float64 => list_array_from_scalars!(Float64Builder, Float64, values),
+        DataType::Utf8 => {
+            let mut builder = ListBuilder::new(BinaryBuilder::new(values.len()));
+            for value in values {
+                match value {
+                    Some(ScalarValue::List(items, _)) => {
+                        for item in items {
+                            match item {
+                                ScalarValue::Utf8(s) => builder.values().append_string(s)?,
+                                ScalarValue::Null => builder.values().append_null()?,
+                                _ => {
+                                    return Err(ExecutionError::ExecutionError(
+                                        "unexpected type when creating list array".to_string(),
+                                    ));
+                                }
+                            }
+                        }
+                        builder.append(true)?;
+                    }
+                    _ => builder.append(false)?,
+                }
+            }
+            Ok(Arc::new(builder.finish()) as ArrayRef)
+        }
+        other => Err(ExecutionError::NotImplemented(format!(
+            "ARRAY_AGG is not supported for {:?}",
+            other
+        ))),
+    }
+}
+
+/// Evaluate the arguments to an aggregate function along with its optional filter
+fn evaluate_aggregate(
     batch: &RecordBatch,
+    args: &[CompiledExpr],
+    filter: &Option<CompiledExpr>,
+) -> Result<(Vec<ArrayRef>, Option<ArrayRef>)> {
+    let arrays = args
+        .iter()
+        .map(|arg| arg(batch))
+        .collect::<Result<Vec<ArrayRef>>>()?;
+    let mask = match filter {
+        Some(f) => Some(f(batch)?),
+        None => None,
+    };
+    Ok((arrays, mask))
+}
+
+/// Determine whether the row passes the aggregate filter. Rows where the filter is false or
+/// null are not aggregated.
+fn is_selected(mask: &Option<ArrayRef>, row: usize) -> bool {
+    match mask {
+        Some(mask) => {
+            let mask = mask.as_any().downcast_ref::<BooleanArray>().unwrap();
+            mask.is_valid(row) && mask.value(row)
+        }
+        None => true,
+    }
+}
+
+fn update_accumulators(
+    inputs: &[(Vec<ArrayRef>, Option<ArrayRef>)],
     row: usize,
+    grouping_set: &[usize],
     accumulator_set: &mut AccumulatorSet,
     aggr_expr: &Vec<RuntimeExpr>,
-) {
+) -> Result<()> {
     // update the accumulators
     for j in 0..accumulator_set.aggr_values.len() {
         match &aggr_expr[j] {
-            RuntimeExpr::AggregateFunction { args, t, .. } => {
-                // evaluate argument to aggregate function
-                match args[0](&batch) {
-                    Ok(array) => {
-                        let value: Option<ScalarValue> = match t {
-                            DataType::UInt8 => {
-                                let z = array.as_any().downcast_ref::<UInt8Array>().unwrap();
-                                Some(ScalarValue::UInt8(z.value(row)))
-                            }
-                            DataType::UInt16 => {
-                                let z = array.as_any().downcast_ref::<UInt16Array>().unwrap();
-                                Some(ScalarValue::UInt16(z.value(row)))
-                            }
-                            DataType::UInt32 => {
-                                let z = array.as_any().downcast_ref::<UInt32Array>().unwrap();
-                                Some(ScalarValue::UInt32(z.value(row)))
-                            }
-                            DataType::UInt64 => {
-                                let z = array.as_any().downcast_ref::<UInt64Array>().unwrap();
-                                Some(ScalarValue::UInt64(z.value(row)))
-                            }
-                            DataType::Int8 => {
-                                let z = array.as_any().downcast_ref::<Int8Array>().unwrap();
-                                Some(ScalarValue::Int8(z.value(row)))
-                            }
-                            DataType::Int16 => {
-                                let z = array.as_any().downcast_ref::<Int16Array>().unwrap();
-                                Some(ScalarValue::Int16(z.value(row)))
-                            }
-                            DataType::Int32 => {
-                                let z = array.as_any().downcast_ref::<Int32Array>().unwrap();
-                                Some(ScalarValue::Int32(z.value(row)))
-                            }
-                            DataType::Int64 => {
-                                let z = array.as_any().downcast_ref::<Int64Array>().unwrap();
-                                Some(ScalarValue::Int64(z.value(row)))
-                            }
-                            DataType::Float32 => {
-                                let z = array.as_any().downcast_ref::<Float32Array>().unwrap();
-                                Some(ScalarValue::Float32(z.value(row)))
-                            }
-                            DataType::Float64 => {
-                                let z = array.as_any().downcast_ref::<Float64Array>().unwrap();
-                                Some(ScalarValue::Float64(z.value(row)))
-                            }
-                            _ => panic!(),
-                        };
-                        accumulator_set.accumulate_scalar(j, value);
-                    }
-                    _ => panic!(),
+            RuntimeExpr::AggregateFunction { f, .. } => {
+                if let AggregateType::Grouping(indices) = f {
+                    let id = grouping_id(indices, grouping_set);
+                    accumulator_set.accumulate_scalar(j, Some(ScalarValue::UInt32(id)));
+                    continue;
+                }
+                let (arrays, mask) = &inputs[j];
+                if !is_selected(mask, row) {
+                    continue;
+                }
+                let mut values = arrays
+                    .iter()
+                    .map(|array| scalar_value(array, row))
+                    .collect::<Result<Vec<Option<ScalarValue>>>>()?;
+                if let AggregateType::Count = f {
+                    // COUNT accumulates the number of non-null values
+                    values[0] = values[0].take().map(|_| ScalarValue::UInt64(1));
                 }
+                accumulator_set.accumulate_row(j, &values);
             }
-            _ => panic!(),
+            _ => {
+                return Err(ExecutionError::General(
+                    "Invalid aggregate expression".to_string(),
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Get the group key value at the given row
+macro_rules! group_key {
+    ($ARRAY:expr, $ROW:expr, $ARRAY_TYPE:ident, $TY:ident) => {{
+        let array = $ARRAY.as_any().downcast_ref::<$ARRAY_TYPE>().unwrap();
+        Ok(GroupByScalar::$TY(array.value($ROW)))
+    }};
+}
+
+/// Get the value of a GROUP BY expression at the given row as a group key
+fn group_key(
+    col: &ArrayRef,
+    row: usize,
+    dictionary: &mut StringDictionary,
+) -> Result<GroupByScalar> {
+    if col.is_null(row) {
+        return Ok(GroupByScalar::Null);
+    }
+    match col.data_type() {
+        DataType::Boolean => group_key!(col, row, BooleanArray, Boolean),
+        DataType::UInt8 => group_key!(col, row, UInt8Array, UInt8),
+        DataType::UInt16 => group_key!(col, row, UInt16Array, UInt16),
+        DataType::UInt32 => group_key!(col, row, UInt32Array, UInt32),
+        DataType::UInt64 => group_key!(col, row, UInt64Array, UInt64),
+        DataType::Int8 => group_key!(col, row, Int8Array, Int8),
+        DataType::Int16 => group_key!(col, row, Int16Array, Int16),
+        DataType::Int32 => group_key!(col, row, Int32Array, Int32),
+        DataType::Int64 => group_key!(col, row, Int64Array, Int64),
+        DataType::Float32 => {
+            let array = col.as_any().downcast_ref::<Float32Array>().unwrap();
+            Ok(GroupByScalar::Float32(canonical_f32(array.value(row))))
+        }
+        DataType::Float64 => {
+            let array = col.as_any().downcast_ref::<Float64Array>().unwrap();
+            Ok(GroupByScalar::Float64(canonical_f64(array.value(row))))
+        }
+        DataType::Date(_) => group_key!(col, row, DateArray, Date32),
+        DataType::Time64(_) => group_key!(col, row, TimeArray, Time64),
+        DataType::Timestamp(_) => group_key!(col, row, TimestampArray, Timestamp),
+        DataType::Utf8 => {
+            let array = col.as_any().downcast_ref::<BinaryArray>().unwrap();
+            Ok(GroupByScalar::Utf8(dictionary.key(array.value(row))))
+        }
+        other => Err(ExecutionError::ExecutionError(format!(
+            "Unsupported GROUP BY data type {:?}",
+            other
+        ))),
+    }
+}
+
+/// Hashable key of a whole row, for relations that need to detect duplicate rows. NULL values
+/// are equal to each other, as they are for GROUP BY.
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct RowKey(Vec<GroupByScalar>);
+
+/// Creates the `RowKey`s of rows. Keys are only comparable when created by the same builder.
+#[derive(Debug, Default)]
+pub struct RowKeyBuilder {
+    dictionary: StringDictionary,
+}
+
+impl RowKeyBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the key of a row from the values of its columns
+    pub fn key(&mut self, columns: &[ArrayRef], row: usize) -> Result<RowKey> {
+        let mut key = Vec::with_capacity(columns.len());
+        for column in columns {
+            key.push(group_key(column, row, &mut self.dictionary)?);
         }
+        Ok(RowKey(key))
     }
 }
 
@@ -617,7 +1738,7 @@ impl Relation for AggregateRelation {
             Ok(None)
         } else {
             self.end_of_results = true;
-            if self.group_expr.is_empty() {
+            if self.group_expr.is_empty() && self.grouping_sets.is_none() {
                 self.without_group_by()
             } else {
                 self.with_group_by()
@@ -636,7 +1757,7 @@ macro_rules! array_from_scalar {
         let mut err = false;
         match $ACCUM.result() {
             Some(ScalarValue::$TY(n)) => {
-                b.append_value(*n)?;
+                b.append_value(n)?;
             }
             None => {
                 b.append_null()?;
@@ -657,12 +1778,16 @@ macro_rules! array_from_scalar {
 
 /// Create array from `key` attribute in map entry (representing a grouping scalar value)
 macro_rules! group_array_from_map_entries {
-    ($BUILDER:ident, $TY:ident, $ENTRIES:expr, $COL_INDEX:expr) => {{
-        let mut builder = $BUILDER::new($ENTRIES.len());
+    ($ARRAY_TYPE:ident, $TY:ident, $ENTRIES:expr, $COL_INDEX:expr) => {{
+        group_array_from_map_entries!($ARRAY_TYPE, $TY, $ENTRIES, $COL_INDEX, |n| n)
+    }};
+    ($ARRAY_TYPE:ident, $TY:ident, $ENTRIES:expr, $COL_INDEX:expr, $F:expr) => {{
+        let mut builder = $ARRAY_TYPE::builder($ENTRIES.len());
         let mut err = false;
         for j in 0..$ENTRIES.len() {
             match $ENTRIES[j].k[$COL_INDEX] {
-                GroupByScalar::$TY(n) => builder.append_value(n).unwrap(),
+                GroupByScalar::$TY(n) => builder.append_value($F(n)).unwrap(),
+                GroupByScalar::Null => builder.append_null().unwrap(),
                 _ => err = true,
             }
         }
@@ -707,25 +1832,50 @@ impl AggregateRelation {
         while let Some(batch) = self.input.borrow_mut().next()? {
             for i in 0..aggr_expr_count {
                 match &self.aggr_expr[i] {
-                    RuntimeExpr::AggregateFunction { f, args, t, .. } => {
-                        // evaluate argument to aggregate function
-                        match args[0](&batch) {
-                            Ok(array) => match f {
-                                AggregateType::Min => {
-                                    accumulator_set.accumulate_scalar(i, array_min(array, &t)?)
-                                }
-                                AggregateType::Max => {
-                                    accumulator_set.accumulate_scalar(i, array_max(array, &t)?)
+                    RuntimeExpr::AggregateFunction {
+                        f, args, t, filter, ..
+                    } => {
+                        // evaluate arguments to aggregate function, keeping only the rows that
+                        // pass the filter
+                        match evaluate_aggregate(&batch, args, filter) {
+                            Ok((mut arrays, mask)) => {
+                                if let Some(mask) = mask {
+                                    let mask =
+                                        mask.as_any().downcast_ref::<BooleanArray>().unwrap();
+                                    arrays = arrays
+                                        .iter()
+                                        .map(|a| filter::filter(a, mask))
+                                        .collect::<Result<Vec<ArrayRef>>>()?;
                                 }
-                                AggregateType::Sum => {
-                                    accumulator_set.accumulate_scalar(i, array_sum(array, &t)?)
+                                let array = arrays[0].clone();
+                                match f {
+                                    AggregateType::Min => {
+                                        accumulator_set.accumulate_scalar(i, array_min(array, &t)?)
+                                    }
+                                    AggregateType::Max => {
+                                        accumulator_set.accumulate_scalar(i, array_max(array, &t)?)
+                                    }
+                                    AggregateType::Sum => {
+                                        accumulator_set.accumulate_scalar(i, array_sum(array, &t)?)
+                                    }
+                                    AggregateType::Count => accumulator_set.accumulate_scalar(
+                                        i,
+                                        Some(ScalarValue::UInt64(
+                                            (array.len() - array.null_count()) as u64,
+                                        )),
+                                    ),
+                                    _ => {
+                                        // other aggregates are accumulated row by row
+                                        for row in 0..array.len() {
+                                            let values = arrays
+                                                .iter()
+                                                .map(|a| scalar_value(a, row))
+                                                .collect::<Result<Vec<Option<ScalarValue>>>>()?;
+                                            accumulator_set.accumulate_row(i, &values);
+                                        }
+                                    }
                                 }
-                                _ => {
-                                    return Err(ExecutionError::NotImplemented(
-                                        "Unsupported aggregate function".to_string(),
-                                    ));
-                                }
-                            },
+                            }
                             Err(_) => {
                                 return Err(ExecutionError::ExecutionError(
                                     "Failed to evaluate argument to aggregate function".to_string(),
@@ -777,6 +1927,32 @@ impl AggregateRelation {
                 DataType::Float64 => {
                     result_columns.push(array_from_scalar!(Float64Builder, Float64, accum)?)
                 }
+                DataType::Boolean => {
+                    result_columns.push(array_from_scalar!(BooleanBuilder, Boolean, accum)?)
+                }
+                DataType::Date(_) => result_columns.push(with_data_type(
+                    array_from_scalar!(Int32Builder, Date32, accum)?,
+                    &DATE_TYPE,
+                )),
+                DataType::Time64(_) => result_columns.push(with_data_type(
+                    array_from_scalar!(Int64Builder, Time64, accum)?,
+                    &TIME_TYPE,
+                )),
+                DataType::Timestamp(_) => result_columns.push(with_data_type(
+                    array_from_scalar!(Int64Builder, Timestamp, accum)?,
+                    &TIMESTAMP_TYPE,
+                )),
+                DataType::Utf8 => {
+                    let mut b = BinaryBuilder::new(1);
+                    match accum.result() {
+                        Some(ScalarValue::Utf8(s)) => b.append_string(&s)?,
+                        _ => b.append_null()?,
+                    }
+                    result_columns.push(Arc::new(b.finish()) as ArrayRef)
+                }
+                DataType::List(ref t) => {
+                    result_columns.push(list_array_from_scalars(&[accum.result()], t)?)
+                }
                 _ => return Err(ExecutionError::NotImplemented("tbd".to_string())),
             }
         }
@@ -793,6 +1969,15 @@ impl AggregateRelation {
         let mut map: FnvHashMap<Vec<GroupByScalar>, Rc<RefCell<AccumulatorSet>>> =
             FnvHashMap::default();
 
+        // strings in grouping keys are replaced with their dictionary key
+        let mut dictionary = StringDictionary::default();
+
+        // without explicit grouping sets there is a single set of all grouping expressions
+        let grouping_sets: Vec<Vec<usize>> = match self.grouping_sets {
+            Some(ref sets) => sets.clone(),
+            None => vec![(0..self.group_expr.len()).collect()],
+        };
+
         while let Some(batch) = self.input.borrow_mut().next()? {
             // evaulate the group by expressions on this batch
             let group_by_keys: Vec<ArrayRef> = self
@@ -801,75 +1986,74 @@ impl AggregateRelation {
                 .map(|e| e.get_func()(&batch))
                 .collect::<Result<Vec<ArrayRef>>>()?;
 
-            // iterate over each row in the batch
-            for row in 0..batch.num_rows() {
-                // create key
-                let key: Vec<GroupByScalar> = group_by_keys
-                    .iter()
-                    .map(|col| match col.data_type() {
-                        DataType::UInt8 => {
-                            let array = col.as_any().downcast_ref::<UInt8Array>().unwrap();
-                            Ok(GroupByScalar::UInt8(array.value(row)))
-                        }
-                        DataType::UInt16 => {
-                            let array = col.as_any().downcast_ref::<UInt16Array>().unwrap();
-                            Ok(GroupByScalar::UInt16(array.value(row)))
-                        }
-                        DataType::UInt32 => {
-                            let array = col.as_any().downcast_ref::<UInt32Array>().unwrap();
-                            Ok(GroupByScalar::UInt32(array.value(row)))
-                        }
-                        DataType::UInt64 => {
-                            let array = col.as_any().downcast_ref::<UInt64Array>().unwrap();
-                            Ok(GroupByScalar::UInt64(array.value(row)))
-                        }
-                        DataType::Int8 => {
-                            let array = col.as_any().downcast_ref::<Int8Array>().unwrap();
-                            Ok(GroupByScalar::Int8(array.value(row)))
-                        }
-                        DataType::Int16 => {
-                            let array = col.as_any().downcast_ref::<Int16Array>().unwrap();
-                            Ok(GroupByScalar::Int16(array.value(row)))
-                        }
-                        DataType::Int32 => {
-                            let array = col.as_any().downcast_ref::<Int32Array>().unwrap();
-                            Ok(GroupByScalar::Int32(array.value(row)))
-                        }
-                        DataType::Int64 => {
-                            let array = col.as_any().downcast_ref::<Int64Array>().unwrap();
-                            Ok(GroupByScalar::Int64(array.value(row)))
+            // evaluate the aggregate arguments and filters on this batch
+            let aggr_inputs = self
+                .aggr_expr
+                .iter()
+                .map(|e| match e {
+                    RuntimeExpr::AggregateFunction { args, filter, .. } => {
+                        evaluate_aggregate(&batch, args, filter)
+                    }
+                    _ => Err(ExecutionError::General(
+                        "Invalid aggregate expression".to_string(),
+                    )),
+                })
+                .collect::<Result<Vec<(Vec<ArrayRef>, Option<ArrayRef>)>>>()?;
+
+            // each row is aggregated once per grouping set
+            for (set_index, grouping_set) in grouping_sets.iter().enumerate() {
+                // iterate over each row in the batch
+                for row in 0..batch.num_rows() {
+                    // create key, where grouping expressions that are not part of the grouping set
+                    // are NULL
+                    let mut key: Vec<GroupByScalar> = group_by_keys
+                        .iter()
+                        .enumerate()
+                        .map(|(i, col)| {
+                            if grouping_set.contains(&i) {
+                                group_key(col, row, &mut dictionary)
+                            } else {
+                                Ok(GroupByScalar::Null)
+                            }
+                        })
+                        .collect::<Result<Vec<GroupByScalar>>>()?;
+                    if self.grouping_sets.is_some() {
+                        // keep the groups of different grouping sets apart
+                        key.push(GroupByScalar::UInt32(set_index as u32));
+                    }
+
+                    //TODO: find more elegant way to write this instead of hacking around ownership issues
+
+                    let updated = match map.get(&key) {
+                        Some(entry) => {
+                            let mut accumulator_set = entry.borrow_mut();
+                            update_accumulators(
+                                &aggr_inputs,
+                                row,
+                                grouping_set,
+                                &mut accumulator_set,
+                                &self.aggr_expr,
+                            )?;
+                            true
                         }
-                        DataType::Utf8 => {
-                            let array = col.as_any().downcast_ref::<BinaryArray>().unwrap();
-                            Ok(GroupByScalar::Utf8(String::from(
-                                str::from_utf8(array.value(row)).unwrap(),
-                            )))
+                        None => false,
+                    };
+
+                    if !updated {
+                        let accumulator_set =
+                            Rc::new(RefCell::new(create_accumulators(&self.aggr_expr)?));
+                        {
+                            let mut entry_mut = accumulator_set.borrow_mut();
+                            update_accumulators(
+                                &aggr_inputs,
+                                row,
+                                grouping_set,
+                                &mut entry_mut,
+                                &self.aggr_expr,
+                            )?;
                         }
-                        _ => Err(ExecutionError::ExecutionError(
-                            "Unsupported GROUP BY data type".to_string(),
-                        )),
-                    })
-                    .collect::<Result<Vec<GroupByScalar>>>()?;
-
-                //TODO: find more elegant way to write this instead of hacking around ownership issues
-
-                let updated = match map.get(&key) {
-                    Some(entry) => {
-                        let mut accumulator_set = entry.borrow_mut();
-                        update_accumulators(&batch, row, &mut accumulator_set, &self.aggr_expr);
-                        true
-                    }
-                    None => false,
-                };
-
-                if !updated {
-                    let accumulator_set =
-                        Rc::new(RefCell::new(create_accumulators(&self.aggr_expr)?));
-                    {
-                        let mut entry_mut = accumulator_set.borrow_mut();
-                        update_accumulators(&batch, row, &mut entry_mut, &self.aggr_expr);
+                        map.insert(key.clone(), accumulator_set);
                     }
-                    map.insert(key.clone(), accumulator_set);
                 }
             }
         }
@@ -893,26 +2077,39 @@ impl AggregateRelation {
         // grouping values
         for i in 0..self.group_expr.len() {
             let array: Result<ArrayRef> = match self.group_expr[i].get_type() {
-                DataType::UInt8 => group_array_from_map_entries!(UInt8Builder, UInt8, entries, i),
-                DataType::UInt16 => {
-                    group_array_from_map_entries!(UInt16Builder, UInt16, entries, i)
+                DataType::Boolean => {
+                    group_array_from_map_entries!(BooleanArray, Boolean, entries, i)
                 }
-                DataType::UInt32 => {
-                    group_array_from_map_entries!(UInt32Builder, UInt32, entries, i)
+                DataType::UInt8 => group_array_from_map_entries!(UInt8Array, UInt8, entries, i),
+                DataType::UInt16 => group_array_from_map_entries!(UInt16Array, UInt16, entries, i),
+                DataType::UInt32 => group_array_from_map_entries!(UInt32Array, UInt32, entries, i),
+                DataType::UInt64 => group_array_from_map_entries!(UInt64Array, UInt64, entries, i),
+                DataType::Int8 => group_array_from_map_entries!(Int8Array, Int8, entries, i),
+                DataType::Int16 => group_array_from_map_entries!(Int16Array, Int16, entries, i),
+                DataType::Int32 => group_array_from_map_entries!(Int32Array, Int32, entries, i),
+                DataType::Int64 => group_array_from_map_entries!(Int64Array, Int64, entries, i),
+                DataType::Float32 => {
+                    group_array_from_map_entries!(Float32Array, Float32, entries, i, f32::from_bits)
                 }
-                DataType::UInt64 => {
-                    group_array_from_map_entries!(UInt64Builder, UInt64, entries, i)
+                DataType::Float64 => {
+                    group_array_from_map_entries!(Float64Array, Float64, entries, i, f64::from_bits)
+                }
+                DataType::Date(_) => group_array_from_map_entries!(DateArray, Date32, entries, i)
+                    .map(|a| with_data_type(a, &DATE_TYPE)),
+                DataType::Time64(_) => group_array_from_map_entries!(TimeArray, Time64, entries, i)
+                    .map(|a| with_data_type(a, &TIME_TYPE)),
+                DataType::Timestamp(_) => {
+                    group_array_from_map_entries!(TimestampArray, Timestamp, entries, i)
+                        .map(|a| with_data_type(a, &TIMESTAMP_TYPE))
                 }
-                DataType::Int8 => group_array_from_map_entries!(Int8Builder, Int8, entries, i),
-                DataType::Int16 => group_array_from_map_entries!(Int16Builder, Int16, entries, i),
-                DataType::Int32 => group_array_from_map_entries!(Int32Builder, Int32, entries, i),
-                DataType::Int64 => group_array_from_map_entries!(Int64Builder, Int64, entries, i),
                 DataType::Utf8 => {
-                    let mut builder = BinaryBuilder::new(1);
+                    let mut builder = BinaryBuilder::new(entries.len());
                     for j in 0..entries.len() {
                         match &entries[j].k[i] {
-                            GroupByScalar::Utf8(s) => builder.append_string(&s).unwrap(),
-                            _ => {}
+                            GroupByScalar::Utf8(key) => {
+                                builder.append_string(dictionary.value(*key)).unwrap()
+                            }
+                            _ => builder.append_null().unwrap(),
                         }
                     }
                     Ok(Arc::new(builder.finish()) as ArrayRef)
@@ -931,7 +2128,7 @@ impl AggregateRelation {
                 DataType::UInt16 => aggr_array_from_map_entries!(UInt16Builder, UInt16, entries, i),
                 DataType::UInt32 => aggr_array_from_map_entries!(UInt32Builder, UInt32, entries, i),
                 DataType::UInt64 => aggr_array_from_map_entries!(UInt64Builder, UInt64, entries, i),
-                DataType::Int8 => group_array_from_map_entries!(Int8Builder, Int8, entries, i),
+                DataType::Int8 => aggr_array_from_map_entries!(Int8Builder, Int8, entries, i),
                 DataType::Int16 => aggr_array_from_map_entries!(Int16Builder, Int16, entries, i),
                 DataType::Int32 => aggr_array_from_map_entries!(Int32Builder, Int32, entries, i),
                 DataType::Int64 => aggr_array_from_map_entries!(Int64Builder, Int64, entries, i),
@@ -941,6 +2138,34 @@ impl AggregateRelation {
                 DataType::Float64 => {
                     aggr_array_from_map_entries!(Float64Builder, Float64, entries, i)
                 }
+                DataType::Boolean => {
+                    aggr_array_from_map_entries!(BooleanBuilder, Boolean, entries, i)
+                }
+                DataType::Date(_) => aggr_array_from_map_entries!(Int32Builder, Date32, entries, i)
+                    .map(|a| with_data_type(a, &DATE_TYPE)),
+                DataType::Time64(_) => {
+                    aggr_array_from_map_entries!(Int64Builder, Time64, entries, i)
+                        .map(|a| with_data_type(a, &TIME_TYPE))
+                }
+                DataType::Timestamp(_) => {
+                    aggr_array_from_map_entries!(Int64Builder, Timestamp, entries, i)
+                        .map(|a| with_data_type(a, &TIMESTAMP_TYPE))
+                }
+                DataType::Utf8 => {
+                    let mut builder = BinaryBuilder::new(entries.len());
+                    for j in 0..entries.len() {
+                        match &entries[j].v[i] {
+                            Some(ScalarValue::Utf8(s)) => builder.append_string(s).unwrap(),
+                            _ => builder.append_null().unwrap(),
+                        }
+                    }
+                    Ok(Arc::new(builder.finish()) as ArrayRef)
+                }
+                DataType::List(ref t) => {
+                    let values: Vec<Option<ScalarValue>> =
+                        entries.iter().map(|e| e.v[i].clone()).collect();
+                    list_array_from_scalars(&values, t)
+                }
                 _ => Err(ExecutionError::ExecutionError(
                     "Unsupported aggregate expr".to_string(),
                 )),
@@ -962,6 +2187,73 @@ mod tests {
     use super::*;
     use arrow::datatypes::{DataType, Field, Schema};
 
+    #[test]
+    fn string_dictionary() {
+        let mut dictionary = StringDictionary::default();
+        assert_eq!(0, dictionary.key(b"UK"));
+        assert_eq!(1, dictionary.key(b"US"));
+        assert_eq!(0, dictionary.key(b"UK"));
+        assert_eq!("US", dictionary.value(1));
+        assert_eq!(2, dictionary.values.len());
+    }
+
+    #[test]
+    fn canonical_float_group_keys() {
+        assert_eq!(canonical_f64(0.0), canonical_f64(-0.0));
+        assert_eq!(canonical_f64(std::f64::NAN), canonical_f64(-std::f64::NAN));
+        assert_ne!(canonical_f64(1.0), canonical_f64(-1.0));
+        assert_eq!(canonical_f32(0.0), canonical_f32(-0.0));
+        assert_eq!(canonical_f32(std::f32::NAN), canonical_f32(-std::f32::NAN));
+    }
+
+    #[test]
+    fn grouping_ids() {
+        assert_eq!(0, grouping_id(&[0, 1], &[0, 1]));
+        assert_eq!(1, grouping_id(&[0, 1], &[0]));
+        assert_eq!(2, grouping_id(&[1, 0], &[0]));
+        assert_eq!(3, grouping_id(&[0, 1], &[]));
+    }
+
+    #[test]
+    fn variance_is_numerically_stable() {
+        // summing squares would lose all precision with this offset
+        let mut f = VarianceFunction::new(Variance::Sample);
+        for x in &[4.0, 7.0, 13.0, 16.0] {
+            f.accumulate_scalar(&Some(ScalarValue::Float64(1e9 + x)));
+        }
+        assert_eq!(Some(ScalarValue::Float64(30.0)), f.result());
+    }
+
+    #[test]
+    fn ordered_set_functions() {
+        let result = |statistic: OrderedSet, values: &[i32]| {
+            let mut f = OrderedSetFunction::new(statistic, &DataType::Int32);
+            for v in values {
+                f.accumulate_scalar(&Some(ScalarValue::Int32(*v)));
+            }
+            f.accumulate_scalar(&None);
+            f.result()
+        };
+        let values = [7, 3, 3, 9, 7, 1];
+        assert_eq!(
+            Some(ScalarValue::Int32(3)),
+            result(OrderedSet::Mode, &values)
+        );
+        assert_eq!(
+            Some(ScalarValue::Int32(1)),
+            result(OrderedSet::PercentileDisc(0.0), &values)
+        );
+        assert_eq!(
+            Some(ScalarValue::Int32(3)),
+            result(OrderedSet::PercentileDisc(0.5), &values)
+        );
+        assert_eq!(
+            Some(ScalarValue::Int32(9)),
+            result(OrderedSet::PercentileDisc(1.0), &values)
+        );
+        assert_eq!(None, result(OrderedSet::Mode, &[]));
+    }
+
     #[test]
     fn min_lat() {
         let schema = uk_cities_schema();
@@ -974,7 +2266,9 @@ mod tests {
                 name: String::from("min"),
                 args: vec![Expr::Column(1)],
                 return_type: DataType::Float64,
+                filter: None,
             },
+            &[],
             &schema,
         )
         .unwrap()];
@@ -985,7 +2279,7 @@ mod tests {
             false,
         )]));
 
-        let mut projection = AggregateRelation::new(aggr_schema, relation, vec![], aggr_expr);
+        let mut projection = AggregateRelation::new(aggr_schema, relation, vec![], None, aggr_expr);
         let batch = projection.next().unwrap().unwrap();
         assert_eq!(1, batch.num_columns());
         let min_lat = batch
@@ -1008,7 +2302,9 @@ mod tests {
                 name: String::from("max"),
                 args: vec![Expr::Column(1)],
                 return_type: DataType::Float64,
+                filter: None,
             },
+            &[],
             &schema,
         )
         .unwrap()];
@@ -1019,7 +2315,7 @@ mod tests {
             false,
         )]));
 
-        let mut projection = AggregateRelation::new(aggr_schema, relation, vec![], aggr_expr);
+        let mut projection = AggregateRelation::new(aggr_schema, relation, vec![], None, aggr_expr);
         let batch = projection.next().unwrap().unwrap();
         assert_eq!(1, batch.num_columns());
         let max_lat = batch
@@ -1037,7 +2333,8 @@ mod tests {
 
         let context = ExecutionContext::new();
 
-        let group_by_expr = expression::compile_expr(&context, &Expr::Column(0), &schema).unwrap();
+        let group_by_expr =
+            expression::compile_expr(&context, &Expr::Column(0), &[], &schema).unwrap();
 
         let min_expr = expression::compile_expr(
             &context,
@@ -1045,7 +2342,9 @@ mod tests {
                 name: String::from("min"),
                 args: vec![Expr::Column(1)],
                 return_type: DataType::Float64,
+                filter: None,
             },
+            &[],
             &schema,
         )
         .unwrap();
@@ -1056,7 +2355,9 @@ mod tests {
                 name: String::from("max"),
                 args: vec![Expr::Column(1)],
                 return_type: DataType::Float64,
+                filter: None,
             },
+            &[],
             &schema,
         )
         .unwrap();
@@ -1067,7 +2368,9 @@ mod tests {
                 name: String::from("sum"),
                 args: vec![Expr::Column(1)],
                 return_type: DataType::Float64,
+                filter: None,
             },
+            &[],
             &schema,
         )
         .unwrap();
@@ -1083,6 +2386,7 @@ mod tests {
             aggr_schema,
             relation,
             vec![group_by_expr],
+            None,
             vec![min_expr, max_expr, sum_expr],
         );
         let batch = projection.next().unwrap().unwrap();
@@ -1110,20 +2414,19 @@ mod tests {
             .downcast_ref::<Float64Array>()
             .unwrap();
 
-        assert_eq!(2, a.value(0));
-        assert_eq!(3.3, min.value(0));
-        assert_eq!(5.5, max.value(0));
-        assert_eq!(13.2, sum.value(0));
-
-        assert_eq!(3, a.value(1));
-        assert_eq!(1.0, min.value(1));
-        assert_eq!(2.0, max.value(1));
-        assert_eq!(3.0, sum.value(1));
-
-        assert_eq!(1, a.value(2));
-        assert_eq!(1.1, min.value(2));
-        assert_eq!(2.2, max.value(2));
-        assert_eq!(3.3000000000000003, sum.value(2));
+        // the groups are not returned in any particular order
+        let mut rows: Vec<(i32, f64, f64, f64)> = (0..batch.num_rows())
+            .map(|i| (a.value(i), min.value(i), max.value(i), sum.value(i)))
+            .collect();
+        rows.sort_by_key(|row| row.0);
+        assert_eq!(
+            vec![
+                (1, 1.1, 2.2, 3.3000000000000003),
+                (2, 3.3, 5.5, 13.2),
+                (3, 1.0, 2.0, 3.0),
+            ],
+            rows
+        );
     }
 
     fn uk_cities_schema() -> Arc<Schema> {
@@ -1142,10 +2445,19 @@ mod tests {
     }
 
     fn load_csv(filename: &str, schema: &Arc<Schema>) -> Rc<RefCell<Relation>> {
-        let ds = CsvDataSource::new(filename, schema.clone(), 1024);
+        let ds = CsvDataSource::new(filename, schema.clone(), true, 1024).unwrap();
         Rc::new(RefCell::new(DataSourceRelation::new(Rc::new(
             RefCell::new(ds),
         ))))
     }
 
+    #[test]
+    fn hash_scalar_is_stable() {
+        // sketches can only be merged if values hash the same everywhere
+        assert_eq!(11971793068411553624, hash_scalar(&ScalarValue::Int64(42)));
+        assert_ne!(
+            hash_scalar(&ScalarValue::Boolean(true)) >> 50,
+            hash_scalar(&ScalarValue::Boolean(false)) >> 50
+        );
+    }
 }
@@ -17,7 +17,7 @@ use std::collections::HashMap;
 use std::rc::Rc;
 use std::sync::Arc;
 
-use arrow::datatypes::{Field, Schema};
+use arrow::datatypes::Schema;
 
 use super::super::dfparser::{DFASTNode, DFParser};
 use super::super::logicalplan::*;
@@ -27,17 +27,51 @@ use super::datasource::DataSource;
 use super::error::{ExecutionError, Result};
 use super::expression::*;
 use super::filter::FilterRelation;
+use super::join::{SemiJoinRelation, SingleJoinRelation};
 use super::projection::ProjectRelation;
+use super::recursive::RecursiveQueryRelation;
 use super::relation::{DataSourceRelation, Relation};
+use super::set_operation::{SetOperationRelation, UnionAllRelation};
+use super::unnest::UnnestRelation;
+use super::window::{compile_window_expr, WindowExpr, WindowRelation};
+
+/// Behaviour when a value is divided by zero (either with `/` or `%`)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DivideByZero {
+    /// Fail the query with an execution error
+    Error,
+    /// Produce NULL for the row
+    Null,
+}
 
 pub struct ExecutionContext {
     datasources: Rc<RefCell<HashMap<String, Rc<RefCell<DataSource>>>>>,
+    divide_by_zero: DivideByZero,
 }
 
 impl ExecutionContext {
     pub fn new() -> Self {
         Self {
             datasources: Rc::new(RefCell::new(HashMap::new())),
+            divide_by_zero: DivideByZero::Error,
+        }
+    }
+
+    /// Set the behaviour when a value is divided by zero (the default is to return an error)
+    pub fn set_divide_by_zero(&mut self, divide_by_zero: DivideByZero) {
+        self.divide_by_zero = divide_by_zero;
+    }
+
+    pub fn divide_by_zero(&self) -> DivideByZero {
+        self.divide_by_zero
+    }
+
+    /// Create a context with the same settings and a copy of the registered tables, so that
+    /// tables can be registered on it without affecting this context
+    pub fn fork(&self) -> Self {
+        Self {
+            datasources: Rc::new(RefCell::new(self.datasources.borrow().clone())),
+            divide_by_zero: self.divide_by_zero,
         }
     }
 
@@ -115,7 +149,9 @@ impl ExecutionContext {
                 match self.datasources.borrow().get(table_name) {
                     Some(ds) => {
                         //TODO: projection
-                        Ok(Rc::new(RefCell::new(DataSourceRelation::new(ds.clone()))))
+                        // each scan reads the table from the start
+                        let ds = ds.borrow_mut().scan()?;
+                        Ok(Rc::new(RefCell::new(DataSourceRelation::new(ds))))
                     }
                     _ => Err(ExecutionError::General(format!(
                         "No table registered as '{}'",
@@ -140,30 +176,28 @@ impl ExecutionContext {
             LogicalPlan::Projection {
                 ref expr,
                 ref input,
-                ..
+                ref schema,
             } => {
                 let input_rel = self.execute(input)?;
 
                 let input_schema = input_rel.as_ref().borrow().schema().clone();
 
-                let project_columns: Vec<Field> = exprlist_to_fields(&expr, &input_schema);
-
-                let project_schema = Arc::new(Schema::new(project_columns));
-
                 let compiled_expr: Result<Vec<RuntimeExpr>> = expr
                     .iter()
                     .map(|e| compile_scalar_expr(&self, e, &input_schema))
                     .collect();
 
-                let rel = ProjectRelation::new(input_rel, compiled_expr?, project_schema);
+                // the schema of the plan can rename the columns, as for a table alias
+                let rel = ProjectRelation::new(input_rel, compiled_expr?, schema.clone());
 
                 Ok(Rc::new(RefCell::new(rel)))
             }
             LogicalPlan::Aggregate {
                 ref input,
                 ref group_expr,
+                ref grouping_sets,
                 ref aggr_expr,
-                ..
+                ref schema,
             } => {
                 let input_rel = self.execute(&input)?;
 
@@ -177,19 +211,141 @@ impl ExecutionContext {
 
                 let compiled_aggr_expr_result: Result<Vec<RuntimeExpr>> = aggr_expr
                     .iter()
-                    .map(|e| compile_expr(&self, e, &input_schema))
+                    .map(|e| compile_expr(&self, e, group_expr, &input_schema))
                     .collect();
                 let compiled_aggr_expr = compiled_aggr_expr_result?;
 
                 let rel = AggregateRelation::new(
-                    Arc::new(Schema::empty()), //(expr_to_field(&compiled_group_expr, &input_schema))),
+                    schema.clone(),
                     input_rel,
                     compiled_group_expr,
+                    grouping_sets.clone(),
                     compiled_aggr_expr,
                 );
 
                 Ok(Rc::new(RefCell::new(rel)))
             }
+            LogicalPlan::Unnest {
+                ref expr,
+                ref input,
+                ref schema,
+            } => {
+                let input_rel = self.execute(input)?;
+                let input_schema = input_rel.as_ref().borrow().schema().clone();
+                let runtime_expr = compile_scalar_expr(&self, expr, &input_schema)?;
+                let rel = UnnestRelation::new(input_rel, runtime_expr, schema.clone());
+                Ok(Rc::new(RefCell::new(rel)))
+            }
+            LogicalPlan::Window {
+                ref input,
+                ref window_expr,
+                ref schema,
+            } => {
+                let input_rel = self.execute(input)?;
+                let input_schema = input_rel.as_ref().borrow().schema().clone();
+                let compiled_window_expr = window_expr
+                    .iter()
+                    .map(|e| compile_window_expr(&self, e, &input_schema))
+                    .collect::<Result<Vec<WindowExpr>>>()?;
+                let rel = WindowRelation::new(schema.clone(), input_rel, compiled_window_expr);
+                Ok(Rc::new(RefCell::new(rel)))
+            }
+            LogicalPlan::SemiJoin {
+                ref left,
+                ref right,
+                ref on,
+                anti,
+                null_aware,
+            } => {
+                let left_rel = self.execute(left)?;
+                let right_rel = self.execute(right)?;
+                let left_schema = left_rel.as_ref().borrow().schema().clone();
+                let right_schema = right_rel.as_ref().borrow().schema().clone();
+                let left_keys = on
+                    .iter()
+                    .map(|(l, _)| compile_scalar_expr(&self, l, &left_schema))
+                    .collect::<Result<Vec<RuntimeExpr>>>()?;
+                let right_keys = on
+                    .iter()
+                    .map(|(_, r)| compile_scalar_expr(&self, r, &right_schema))
+                    .collect::<Result<Vec<RuntimeExpr>>>()?;
+                let rel = SemiJoinRelation::new(
+                    left_rel, right_rel, left_keys, right_keys, anti, null_aware,
+                );
+                Ok(Rc::new(RefCell::new(rel)))
+            }
+            LogicalPlan::SingleJoin {
+                ref left,
+                ref right,
+                ref on,
+                ref schema,
+            } => {
+                let left_rel = self.execute(left)?;
+                let right_rel = self.execute(right)?;
+                let left_schema = left_rel.as_ref().borrow().schema().clone();
+                let right_schema = right_rel.as_ref().borrow().schema().clone();
+                let left_keys = on
+                    .iter()
+                    .map(|(l, _)| compile_scalar_expr(&self, l, &left_schema))
+                    .collect::<Result<Vec<RuntimeExpr>>>()?;
+                let right_keys = on
+                    .iter()
+                    .map(|(_, r)| compile_scalar_expr(&self, r, &right_schema))
+                    .collect::<Result<Vec<RuntimeExpr>>>()?;
+                let rel = SingleJoinRelation::new(
+                    schema.clone(),
+                    left_rel,
+                    right_rel,
+                    left_keys,
+                    right_keys,
+                );
+                Ok(Rc::new(RefCell::new(rel)))
+            }
+            LogicalPlan::SetOperation {
+                ref op,
+                all,
+                ref left,
+                ref right,
+                ref schema,
+            } => {
+                let left_rel = self.execute(left)?;
+                let right_rel = self.execute(right)?;
+                let rel: Rc<RefCell<Relation>> = if *op == SetOperator::Union && all {
+                    Rc::new(RefCell::new(UnionAllRelation::new(
+                        schema.clone(),
+                        left_rel,
+                        right_rel,
+                    )))
+                } else {
+                    Rc::new(RefCell::new(SetOperationRelation::new(
+                        schema.clone(),
+                        op.clone(),
+                        all,
+                        left_rel,
+                        right_rel,
+                    )))
+                };
+                Ok(rel)
+            }
+            LogicalPlan::RecursiveQuery {
+                ref name,
+                ref static_term,
+                ref recursive_term,
+                distinct,
+                ref schema,
+            } => {
+                let static_rel = self.execute(static_term)?;
+                // the working table is registered on a context of the relation's own
+                let rel = RecursiveQueryRelation::new(
+                    schema.clone(),
+                    self.fork(),
+                    name,
+                    static_rel,
+                    recursive_term.clone(),
+                    distinct,
+                );
+                Ok(Rc::new(RefCell::new(rel)))
+            }
 
             _ => unimplemented!(),
         }
@@ -203,44 +359,6 @@ pub enum ExecutionResult {
     Str(String),
 }
 
-pub fn expr_to_field(e: &Expr, input_schema: &Schema) -> Field {
-    match e {
-        Expr::Column(i) => input_schema.fields()[*i].clone(),
-        Expr::Literal(ref lit) => Field::new("lit", lit.get_datatype(), true),
-        Expr::ScalarFunction {
-            ref name,
-            ref return_type,
-            ..
-        } => Field::new(&name, return_type.clone(), true),
-        Expr::AggregateFunction {
-            ref name,
-            ref return_type,
-            ..
-        } => Field::new(&name, return_type.clone(), true),
-        Expr::Cast { ref data_type, .. } => Field::new("cast", data_type.clone(), true),
-        Expr::BinaryExpr {
-            ref left,
-            ref right,
-            ..
-        } => {
-            let left_type = left.get_type(input_schema);
-            let right_type = right.get_type(input_schema);
-            Field::new(
-                "binary_expr",
-                get_supertype(&left_type, &right_type).unwrap(),
-                true,
-            )
-        }
-        _ => unimplemented!("Cannot determine schema type for expression {:?}", e),
-    }
-}
-
-pub fn exprlist_to_fields(expr: &Vec<Expr>, input_schema: &Schema) -> Vec<Field> {
-    expr.iter()
-        .map(|e| expr_to_field(e, input_schema))
-        .collect()
-}
-
 struct ExecutionContextSchemaProvider {
     datasources: Rc<RefCell<HashMap<String, Rc<RefCell<DataSource>>>>>,
 }
@@ -14,36 +14,69 @@
 
 //! Data sources
 
+use std::cell::RefCell;
 use std::fs::File;
 use std::rc::Rc;
 use std::sync::Arc;
 
+use arrow::array::ArrayRef;
 use arrow::csv;
-use arrow::datatypes::Schema;
+use arrow::datatypes::{DataType, Field, Schema};
 use arrow::record_batch::RecordBatch;
 
+use super::super::typecoercion::is_temporal;
 use super::error::Result;
+use super::temporal;
 
 pub trait DataSource {
     fn schema(&self) -> &Arc<Schema>;
     fn next(&mut self) -> Result<Option<RecordBatch>>;
+    /// Create a data source that reads the data from the start, so that a registered table can
+    /// be scanned more than once
+    fn scan(&mut self) -> Result<Rc<RefCell<DataSource>>>;
 }
 
-/// CSV data source
+/// CSV data source. Date, time and timestamp columns are read as strings and then parsed, and
+/// empty values in these columns are NULL.
 pub struct CsvDataSource {
     schema: Arc<Schema>,
-    reader: csv::Reader,
+    reader: Option<csv::Reader>,
+    /// The file name, whether it has a header row and the batch size, which are used to read the
+    /// file again
+    file: Option<(String, bool, usize)>,
 }
 
 impl CsvDataSource {
-    pub fn new(filename: &str, schema: Arc<Schema>, batch_size: usize) -> Self {
-        let file = File::open(filename).unwrap();
-        let reader = csv::Reader::new(file, schema.clone(), true, batch_size, None);
-        Self { schema, reader }
+    /// Open a CSV file, returning an error if it cannot be read. The first line is skipped if
+    /// the file has a header row.
+    pub fn new(
+        filename: &str,
+        schema: Arc<Schema>,
+        has_header: bool,
+        batch_size: usize,
+    ) -> Result<Self> {
+        let file = File::open(filename)?;
+        let reader = csv::Reader::new(
+            file,
+            Arc::new(csv_schema(&schema)),
+            has_header,
+            batch_size,
+            None,
+        );
+        Ok(Self {
+            schema,
+            reader: Some(reader),
+            file: Some((filename.to_string(), has_header, batch_size)),
+        })
     }
 
+    /// Create a data source from a reader, which can only be scanned once
     pub fn from_reader(schema: Arc<Schema>, reader: csv::Reader) -> Self {
-        Self { schema, reader }
+        Self {
+            schema,
+            reader: Some(reader),
+            file: None,
+        }
     }
 }
 
@@ -53,10 +86,112 @@ impl DataSource for CsvDataSource {
     }
 
     fn next(&mut self) -> Result<Option<RecordBatch>> {
-        Ok(self.reader.next()?)
+        let batch = match self.reader {
+            Some(ref mut reader) => reader.next()?,
+            None => None,
+        };
+        match batch {
+            Some(batch) => {
+                let columns = (0..batch.num_columns())
+                    .map(|i| {
+                        let data_type = self.schema.field(i).data_type();
+                        if batch.column(i).data_type() == data_type {
+                            Ok(batch.column(i).clone())
+                        } else {
+                            temporal::cast(batch.column(i), data_type)
+                        }
+                    })
+                    .collect::<Result<Vec<ArrayRef>>>()?;
+                Ok(Some(RecordBatch::new(self.schema.clone(), columns)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn scan(&mut self) -> Result<Rc<RefCell<DataSource>>> {
+        let ds = match self.file {
+            Some((ref filename, has_header, batch_size)) => {
+                CsvDataSource::new(filename, self.schema.clone(), has_header, batch_size)?
+            }
+            // the reader cannot be restarted, so it is handed to the first scan
+            None => CsvDataSource {
+                schema: self.schema.clone(),
+                reader: self.reader.take(),
+                file: None,
+            },
+        };
+        Ok(Rc::new(RefCell::new(ds)))
     }
 }
 
+/// In-memory data source that returns record batches which have already been loaded, for
+/// example by one of the Arrow readers.
+///
+/// There is no Parquet data source: the parquet crate we depend on has no Arrow reader, so
+/// Parquet files with nested columns have to be converted to record batches by other means and
+/// registered with this data source.
+pub struct MemoryDataSource {
+    schema: Arc<Schema>,
+    /// The batches, which are shared by all scans of the data source
+    batches: Rc<Vec<RecordBatch>>,
+    /// The index of the next batch to return
+    index: usize,
+}
+
+impl MemoryDataSource {
+    pub fn new(schema: Arc<Schema>, batches: Vec<RecordBatch>) -> Self {
+        Self {
+            schema,
+            batches: Rc::new(batches),
+            index: 0,
+        }
+    }
+}
+
+impl DataSource for MemoryDataSource {
+    fn schema(&self) -> &Arc<Schema> {
+        &self.schema
+    }
+
+    fn next(&mut self) -> Result<Option<RecordBatch>> {
+        match self.batches.get(self.index) {
+            Some(batch) => {
+                self.index += 1;
+                let columns = (0..batch.num_columns())
+                    .map(|i| batch.column(i).clone())
+                    .collect();
+                Ok(Some(RecordBatch::new(batch.schema().clone(), columns)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn scan(&mut self) -> Result<Rc<RefCell<DataSource>>> {
+        Ok(Rc::new(RefCell::new(MemoryDataSource {
+            schema: self.schema.clone(),
+            batches: self.batches.clone(),
+            index: 0,
+        })))
+    }
+}
+
+/// The schema that the CSV reader uses, which reads dates, times and timestamps as strings
+fn csv_schema(schema: &Schema) -> Schema {
+    Schema::new(
+        schema
+            .fields()
+            .iter()
+            .map(|f| {
+                if is_temporal(f.data_type()) {
+                    Field::new(f.name(), DataType::Utf8, f.is_nullable())
+                } else {
+                    f.clone()
+                }
+            })
+            .collect(),
+    )
+}
+
 //pub struct DataSourceIterator {
 //    pub ds: Rc<RefCell<DataSource>>,
 //}
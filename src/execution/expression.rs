@@ -12,17 +12,24 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::HashSet;
 use std::rc::Rc;
 use std::sync::Arc;
 
 use arrow::array::*;
 use arrow::array_ops;
+use arrow::builder::BinaryBuilder;
 use arrow::datatypes::{DataType, Schema};
 use arrow::record_batch::RecordBatch;
 
-use super::super::logicalplan::{Expr, Operator, ScalarValue};
-use super::context::ExecutionContext;
+use super::super::logicalplan::{Expr, LogicalPlan, Operator, ScalarValue};
+use super::super::typecoercion::{
+    binary_operator_data_type, is_temporal, temporal_arithmetic_types,
+};
+use super::aggregate::scalar_value;
+use super::context::{DivideByZero, ExecutionContext};
 use super::error::{ExecutionError, Result};
+use super::temporal::{self, DateArray, IntervalArray, TimeArray, TimestampArray};
 
 /// Compiled Expression (basically just a closure to evaluate the expression at runtime)
 pub type CompiledExpr = Rc<Fn(&RecordBatch) -> Result<ArrayRef>>;
@@ -36,6 +43,29 @@ pub enum AggregateType {
     Count,
     CountDistinct,
     Avg,
+    ArrayAgg,
+    VarSamp,
+    VarPop,
+    StddevSamp,
+    StddevPop,
+    CovarSamp,
+    CovarPop,
+    Corr,
+    RegrSlope,
+    RegrIntercept,
+    ApproxCountDistinct,
+    /// Approximate percentile between 0 and 1
+    ApproxPercentile(f64),
+    /// Interpolated percentile between 0 and 1
+    PercentileCont(f64),
+    /// Percentile between 0 and 1 that is one of the input values
+    PercentileDisc(f64),
+    Mode,
+    /// String concatenation with the given separator
+    StringAgg(String),
+    /// Bit mask of the given grouping expressions (by index) that are not part of the grouping
+    /// set of a group, with the first expression as the most significant bit
+    Grouping(Vec<usize>),
 }
 
 /// Runtime expression
@@ -50,6 +80,8 @@ pub enum RuntimeExpr {
         f: AggregateType,
         args: Vec<CompiledExpr>,
         t: DataType,
+        /// Optional condition restricting the rows that are aggregated
+        filter: Option<CompiledExpr>,
     },
 }
 
@@ -76,10 +108,12 @@ impl RuntimeExpr {
     }
 }
 
-/// Compiles a scalar expression into a closure
+/// Compiles an aggregate or scalar expression into a closure. The grouping expressions of the
+/// aggregate are used to resolve the arguments to `GROUPING()`.
 pub fn compile_expr(
     ctx: &ExecutionContext,
     expr: &Expr,
+    group_expr: &[Expr],
     input_schema: &Schema,
 ) -> Result<RuntimeExpr> {
     match *expr {
@@ -87,33 +121,103 @@ pub fn compile_expr(
             ref name,
             ref args,
             ref return_type,
+            ref filter,
         } => {
-            assert_eq!(1, args.len());
-
-            let compiled_args: Result<Vec<RuntimeExpr>> = args
-                .iter()
-                .map(|e| compile_scalar_expr(&ctx, e, input_schema))
-                .collect();
+            // percentiles are constants that are part of the aggregate type rather than arguments
+            let constant_percentile = |f: fn(f64) -> AggregateType| match args.get(1) {
+                Some(Expr::Literal(ScalarValue::Float64(p))) => Ok(f(*p)),
+                _ => Err(ExecutionError::General(format!(
+                    "{} requires a constant percentile",
+                    name
+                ))),
+            };
 
             let func = match name.to_lowercase().as_ref() {
                 "min" => Ok(AggregateType::Min),
                 "max" => Ok(AggregateType::Max),
+                // on booleans MIN is the logical AND and MAX is the logical OR
+                "bool_and" | "every" => Ok(AggregateType::Min),
+                "bool_or" => Ok(AggregateType::Max),
                 "count" => Ok(AggregateType::Count),
                 "sum" => Ok(AggregateType::Sum),
+                "avg" => Ok(AggregateType::Avg),
+                "array_agg" => Ok(AggregateType::ArrayAgg),
+                "var_samp" | "variance" => Ok(AggregateType::VarSamp),
+                "var_pop" => Ok(AggregateType::VarPop),
+                "stddev_samp" | "stddev" => Ok(AggregateType::StddevSamp),
+                "stddev_pop" => Ok(AggregateType::StddevPop),
+                "covar_samp" => Ok(AggregateType::CovarSamp),
+                "covar_pop" => Ok(AggregateType::CovarPop),
+                "corr" => Ok(AggregateType::Corr),
+                "regr_slope" => Ok(AggregateType::RegrSlope),
+                "regr_intercept" => Ok(AggregateType::RegrIntercept),
+                "approx_count_distinct" => Ok(AggregateType::ApproxCountDistinct),
+                "approx_percentile_cont" => constant_percentile(AggregateType::ApproxPercentile),
+                "approx_median" => Ok(AggregateType::ApproxPercentile(0.5)),
+                "percentile_cont" => constant_percentile(AggregateType::PercentileCont),
+                "percentile_disc" => constant_percentile(AggregateType::PercentileDisc),
+                "median" => Ok(AggregateType::PercentileCont(0.5)),
+                "mode" => Ok(AggregateType::Mode),
+                "string_agg" | "listagg" => match args.get(1) {
+                    Some(Expr::Literal(ScalarValue::Utf8(separator))) => {
+                        Ok(AggregateType::StringAgg(separator.as_ref().clone()))
+                    }
+                    _ => Err(ExecutionError::General(format!(
+                        "{} requires a constant separator",
+                        name
+                    ))),
+                },
+                "grouping" => args
+                    .iter()
+                    .map(|a| {
+                        group_expr.iter().position(|g| g == a).ok_or_else(|| {
+                            ExecutionError::General(format!(
+                                "GROUPING argument {:?} is not a grouping expression",
+                                a
+                            ))
+                        })
+                    })
+                    .collect::<Result<Vec<usize>>>()
+                    .map(AggregateType::Grouping),
                 _ => Err(ExecutionError::General(format!(
                     "Unsupported aggregate function '{}'",
                     name
                 ))),
+            }?;
+
+            // constant percentiles and separators are part of the aggregate type, so only the
+            // remaining arguments are evaluated
+            let runtime_args: Vec<&Expr> = match func {
+                AggregateType::ApproxPercentile(_)
+                | AggregateType::PercentileCont(_)
+                | AggregateType::PercentileDisc(_) => vec![&args[0]],
+                AggregateType::Grouping(_) => vec![],
+                AggregateType::StringAgg(_) => args
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| *i != 1)
+                    .map(|(_, e)| e)
+                    .collect(),
+                _ => args.iter().collect(),
+            };
+            let compiled_args: Result<Vec<RuntimeExpr>> = runtime_args
+                .iter()
+                .map(|e| compile_scalar_expr(&ctx, e, input_schema))
+                .collect();
+            let filter = match filter {
+                Some(e) => Some(compile_scalar_expr(&ctx, e, input_schema)?.get_func()),
+                None => None,
             };
 
             Ok(RuntimeExpr::AggregateFunction {
                 name: name.to_string(),
-                f: func?,
+                f: func,
                 args: compiled_args?
                     .iter()
                     .map(|e| e.get_func().clone())
                     .collect(),
                 t: return_type.clone(),
+                filter,
             })
         }
         _ => Ok(compile_scalar_expr(&ctx, expr, input_schema)?),
@@ -168,40 +272,280 @@ macro_rules! math_ops {
     }};
 }
 
+/// Applies a division operator element-wise. A zero divisor either produces NULL or fails,
+/// depending on `$DIVIDE_BY_ZERO`.
+macro_rules! division_op {
+    ($LEFT:expr, $RIGHT:expr, $ARRAY_TYPE:ident, $ZERO:expr, $OP:expr, $DIVIDE_BY_ZERO:expr) => {{
+        let op = $OP;
+        let l = $LEFT.as_any().downcast_ref::<$ARRAY_TYPE>().unwrap();
+        let r = $RIGHT.as_any().downcast_ref::<$ARRAY_TYPE>().unwrap();
+        let mut builder = $ARRAY_TYPE::builder(l.len());
+        for i in 0..l.len() {
+            if l.is_null(i) || r.is_null(i) {
+                builder.append_null()?;
+            } else if r.value(i) == $ZERO {
+                match $DIVIDE_BY_ZERO {
+                    DivideByZero::Null => builder.append_null()?,
+                    DivideByZero::Error => {
+                        return Err(ExecutionError::ExecutionError(
+                            "Division by zero".to_string(),
+                        ));
+                    }
+                }
+            } else {
+                builder.append_value(op(l.value(i), r.value(i)))?;
+            }
+        }
+        Ok(Arc::new(builder.finish()) as ArrayRef)
+    }};
+}
+
+/// Divides two arrays of the same type. Integer division truncates towards zero.
+fn divide(left: &ArrayRef, right: &ArrayRef, divide_by_zero: DivideByZero) -> Result<ArrayRef> {
+    match left.data_type() {
+        DataType::Int8 => division_op!(left, right, Int8Array, 0, i8::wrapping_div, divide_by_zero),
+        DataType::Int16 => {
+            division_op!(
+                left,
+                right,
+                Int16Array,
+                0,
+                i16::wrapping_div,
+                divide_by_zero
+            )
+        }
+        DataType::Int32 => {
+            division_op!(
+                left,
+                right,
+                Int32Array,
+                0,
+                i32::wrapping_div,
+                divide_by_zero
+            )
+        }
+        DataType::Int64 => {
+            division_op!(
+                left,
+                right,
+                Int64Array,
+                0,
+                i64::wrapping_div,
+                divide_by_zero
+            )
+        }
+        DataType::UInt8 => {
+            division_op!(left, right, UInt8Array, 0, u8::wrapping_div, divide_by_zero)
+        }
+        DataType::UInt16 => {
+            division_op!(
+                left,
+                right,
+                UInt16Array,
+                0,
+                u16::wrapping_div,
+                divide_by_zero
+            )
+        }
+        DataType::UInt32 => {
+            division_op!(
+                left,
+                right,
+                UInt32Array,
+                0,
+                u32::wrapping_div,
+                divide_by_zero
+            )
+        }
+        DataType::UInt64 => {
+            division_op!(
+                left,
+                right,
+                UInt64Array,
+                0,
+                u64::wrapping_div,
+                divide_by_zero
+            )
+        }
+        DataType::Float32 => {
+            division_op!(
+                left,
+                right,
+                Float32Array,
+                0.0,
+                |a: f32, b: f32| a / b,
+                divide_by_zero
+            )
+        }
+        DataType::Float64 => {
+            division_op!(
+                left,
+                right,
+                Float64Array,
+                0.0,
+                |a: f64, b: f64| a / b,
+                divide_by_zero
+            )
+        }
+        other => Err(ExecutionError::ExecutionError(format!(
+            "Division is not supported for {:?}",
+            other
+        ))),
+    }
+}
+
+/// Computes the remainder of dividing two arrays of the same type. The result has the same
+/// sign as the dividend.
+fn modulus(left: &ArrayRef, right: &ArrayRef, divide_by_zero: DivideByZero) -> Result<ArrayRef> {
+    match left.data_type() {
+        DataType::Int8 => division_op!(left, right, Int8Array, 0, i8::wrapping_rem, divide_by_zero),
+        DataType::Int16 => {
+            division_op!(
+                left,
+                right,
+                Int16Array,
+                0,
+                i16::wrapping_rem,
+                divide_by_zero
+            )
+        }
+        DataType::Int32 => {
+            division_op!(
+                left,
+                right,
+                Int32Array,
+                0,
+                i32::wrapping_rem,
+                divide_by_zero
+            )
+        }
+        DataType::Int64 => {
+            division_op!(
+                left,
+                right,
+                Int64Array,
+                0,
+                i64::wrapping_rem,
+                divide_by_zero
+            )
+        }
+        DataType::UInt8 => {
+            division_op!(left, right, UInt8Array, 0, u8::wrapping_rem, divide_by_zero)
+        }
+        DataType::UInt16 => {
+            division_op!(
+                left,
+                right,
+                UInt16Array,
+                0,
+                u16::wrapping_rem,
+                divide_by_zero
+            )
+        }
+        DataType::UInt32 => {
+            division_op!(
+                left,
+                right,
+                UInt32Array,
+                0,
+                u32::wrapping_rem,
+                divide_by_zero
+            )
+        }
+        DataType::UInt64 => {
+            division_op!(
+                left,
+                right,
+                UInt64Array,
+                0,
+                u64::wrapping_rem,
+                divide_by_zero
+            )
+        }
+        DataType::Float32 => {
+            division_op!(
+                left,
+                right,
+                Float32Array,
+                0.0,
+                |a: f32, b: f32| a % b,
+                divide_by_zero
+            )
+        }
+        DataType::Float64 => {
+            division_op!(
+                left,
+                right,
+                Float64Array,
+                0.0,
+                |a: f64, b: f64| a % b,
+                divide_by_zero
+            )
+        }
+        other => Err(ExecutionError::ExecutionError(format!(
+            "Modulus is not supported for {:?}",
+            other
+        ))),
+    }
+}
+
+/// Invokes a comparison kernel and then applies SQL null semantics to the result (comparing
+/// anything with NULL yields NULL)
+macro_rules! comparison_op {
+    ($LEFT:expr, $RIGHT:expr, $OP:ident, $DT:ident) => {{
+        let ll = $LEFT.as_any().downcast_ref::<$DT>().unwrap();
+        let rr = $RIGHT.as_any().downcast_ref::<$DT>().unwrap();
+        let result = array_ops::$OP(&ll, &rr)?;
+        Ok(Arc::new(propagate_nulls(&result, &$LEFT, &$RIGHT)?) as ArrayRef)
+    }};
+}
+
 macro_rules! comparison_ops {
     ($LEFT:expr, $RIGHT:expr, $BATCH:expr, $OP:ident) => {{
         let left_values = $LEFT.get_func()($BATCH)?;
         let right_values = $RIGHT.get_func()($BATCH)?;
         match (left_values.data_type(), right_values.data_type()) {
             (DataType::Int8, DataType::Int8) => {
-                binary_op!(left_values, right_values, $OP, Int8Array)
+                comparison_op!(left_values, right_values, $OP, Int8Array)
             }
             (DataType::Int16, DataType::Int16) => {
-                binary_op!(left_values, right_values, $OP, Int16Array)
+                comparison_op!(left_values, right_values, $OP, Int16Array)
             }
             (DataType::Int32, DataType::Int32) => {
-                binary_op!(left_values, right_values, $OP, Int32Array)
+                comparison_op!(left_values, right_values, $OP, Int32Array)
             }
             (DataType::Int64, DataType::Int64) => {
-                binary_op!(left_values, right_values, $OP, Int64Array)
+                comparison_op!(left_values, right_values, $OP, Int64Array)
             }
             (DataType::UInt8, DataType::UInt8) => {
-                binary_op!(left_values, right_values, $OP, UInt8Array)
+                comparison_op!(left_values, right_values, $OP, UInt8Array)
             }
             (DataType::UInt16, DataType::UInt16) => {
-                binary_op!(left_values, right_values, $OP, UInt16Array)
+                comparison_op!(left_values, right_values, $OP, UInt16Array)
             }
             (DataType::UInt32, DataType::UInt32) => {
-                binary_op!(left_values, right_values, $OP, UInt32Array)
+                comparison_op!(left_values, right_values, $OP, UInt32Array)
             }
             (DataType::UInt64, DataType::UInt64) => {
-                binary_op!(left_values, right_values, $OP, UInt64Array)
+                comparison_op!(left_values, right_values, $OP, UInt64Array)
             }
             (DataType::Float32, DataType::Float32) => {
-                binary_op!(left_values, right_values, $OP, Float32Array)
+                comparison_op!(left_values, right_values, $OP, Float32Array)
             }
             (DataType::Float64, DataType::Float64) => {
-                binary_op!(left_values, right_values, $OP, Float64Array)
+                comparison_op!(left_values, right_values, $OP, Float64Array)
+            }
+            // dates, times and timestamps are compared as the underlying integers
+            (DataType::Date(_), DataType::Date(_)) => {
+                let l = temporal::to_integer_array(&left_values)?;
+                let r = temporal::to_integer_array(&right_values)?;
+                comparison_op!(l, r, $OP, Int32Array)
+            }
+            (DataType::Time64(_), DataType::Time64(_))
+            | (DataType::Timestamp(_), DataType::Timestamp(_)) => {
+                let l = temporal::to_integer_array(&left_values)?;
+                let r = temporal::to_integer_array(&right_values)?;
+                comparison_op!(l, r, $OP, Int64Array)
             }
             //TODO other types
             _ => Err(ExecutionError::ExecutionError(format!("comparison_ops"))),
@@ -213,16 +557,640 @@ macro_rules! boolean_ops {
     ($LEFT:expr, $RIGHT:expr, $BATCH:expr, $OP:ident) => {{
         let left_values = $LEFT.get_func()($BATCH)?;
         let right_values = $RIGHT.get_func()($BATCH)?;
-        Ok(Arc::new(array_ops::$OP(
+        Ok(Arc::new($OP(
             left_values.as_any().downcast_ref::<BooleanArray>().unwrap(),
             right_values
                 .as_any()
                 .downcast_ref::<BooleanArray>()
                 .unwrap(),
-        )?))
+        )?) as ArrayRef)
     }};
 }
 
+/// Returns a copy of a comparison result that is null wherever either input was null
+fn propagate_nulls(
+    result: &BooleanArray,
+    left: &ArrayRef,
+    right: &ArrayRef,
+) -> Result<BooleanArray> {
+    let mut builder = BooleanArray::builder(result.len());
+    for i in 0..result.len() {
+        if left.is_null(i) || right.is_null(i) {
+            builder.append_null()?;
+        } else {
+            builder.append_value(result.value(i))?;
+        }
+    }
+    Ok(builder.finish())
+}
+
+/// Logical AND using SQL three-valued logic (FALSE AND NULL is FALSE, TRUE AND NULL is NULL)
+fn and_kleene(left: &BooleanArray, right: &BooleanArray) -> Result<BooleanArray> {
+    let mut builder = BooleanArray::builder(left.len());
+    for i in 0..left.len() {
+        let l = if left.is_null(i) {
+            None
+        } else {
+            Some(left.value(i))
+        };
+        let r = if right.is_null(i) {
+            None
+        } else {
+            Some(right.value(i))
+        };
+        match (l, r) {
+            (Some(false), _) | (_, Some(false)) => builder.append_value(false)?,
+            (Some(true), Some(true)) => builder.append_value(true)?,
+            _ => builder.append_null()?,
+        }
+    }
+    Ok(builder.finish())
+}
+
+/// Logical OR using SQL three-valued logic (TRUE OR NULL is TRUE, FALSE OR NULL is NULL)
+fn or_kleene(left: &BooleanArray, right: &BooleanArray) -> Result<BooleanArray> {
+    let mut builder = BooleanArray::builder(left.len());
+    for i in 0..left.len() {
+        let l = if left.is_null(i) {
+            None
+        } else {
+            Some(left.value(i))
+        };
+        let r = if right.is_null(i) {
+            None
+        } else {
+            Some(right.value(i))
+        };
+        match (l, r) {
+            (Some(true), _) | (_, Some(true)) => builder.append_value(true)?,
+            (Some(false), Some(false)) => builder.append_value(false)?,
+            _ => builder.append_null()?,
+        }
+    }
+    Ok(builder.finish())
+}
+
+/// Evaluates IS NULL (or IS NOT NULL when `negated` is true) against an array. The result is
+/// never null.
+fn is_null(array: &ArrayRef, negated: bool) -> Result<ArrayRef> {
+    let mut builder = BooleanArray::builder(array.len());
+    for i in 0..array.len() {
+        builder.append_value(array.is_null(i) != negated)?;
+    }
+    Ok(Arc::new(builder.finish()) as ArrayRef)
+}
+
+/// Returns the first non-null value from a list of arrays of the same type
+macro_rules! coalesce_arrays {
+    ($ARRAYS:expr, $ARRAY_TYPE:ident) => {{
+        let arrays: Vec<&$ARRAY_TYPE> = $ARRAYS
+            .iter()
+            .map(|a| a.as_any().downcast_ref::<$ARRAY_TYPE>().unwrap())
+            .collect();
+        let mut builder = $ARRAY_TYPE::builder(arrays[0].len());
+        for i in 0..arrays[0].len() {
+            match arrays.iter().find(|a| a.is_valid(i)) {
+                Some(a) => builder.append_value(a.value(i))?,
+                None => builder.append_null()?,
+            }
+        }
+        Ok(Arc::new(builder.finish()) as ArrayRef)
+    }};
+}
+
+/// Returns NULL where the left value equals the right value, otherwise returns the left value
+macro_rules! nullif_arrays {
+    ($LEFT:expr, $RIGHT:expr, $ARRAY_TYPE:ident) => {{
+        let l = $LEFT.as_any().downcast_ref::<$ARRAY_TYPE>().unwrap();
+        let r = $RIGHT.as_any().downcast_ref::<$ARRAY_TYPE>().unwrap();
+        let mut builder = $ARRAY_TYPE::builder(l.len());
+        for i in 0..l.len() {
+            if l.is_null(i) || (r.is_valid(i) && l.value(i) == r.value(i)) {
+                builder.append_null()?;
+            } else {
+                builder.append_value(l.value(i))?;
+            }
+        }
+        Ok(Arc::new(builder.finish()) as ArrayRef)
+    }};
+}
+
+fn coalesce(arrays: &Vec<ArrayRef>) -> Result<ArrayRef> {
+    match arrays[0].data_type() {
+        DataType::Boolean => coalesce_arrays!(arrays, BooleanArray),
+        DataType::Int8 => coalesce_arrays!(arrays, Int8Array),
+        DataType::Int16 => coalesce_arrays!(arrays, Int16Array),
+        DataType::Int32 => coalesce_arrays!(arrays, Int32Array),
+        DataType::Int64 => coalesce_arrays!(arrays, Int64Array),
+        DataType::UInt8 => coalesce_arrays!(arrays, UInt8Array),
+        DataType::UInt16 => coalesce_arrays!(arrays, UInt16Array),
+        DataType::UInt32 => coalesce_arrays!(arrays, UInt32Array),
+        DataType::UInt64 => coalesce_arrays!(arrays, UInt64Array),
+        DataType::Float32 => coalesce_arrays!(arrays, Float32Array),
+        DataType::Float64 => coalesce_arrays!(arrays, Float64Array),
+        DataType::Utf8 => {
+            let arrays: Vec<&BinaryArray> = arrays
+                .iter()
+                .map(|a| a.as_any().downcast_ref::<BinaryArray>().unwrap())
+                .collect();
+            let mut builder = BinaryBuilder::new(arrays[0].len());
+            for i in 0..arrays[0].len() {
+                match arrays.iter().find(|a| a.is_valid(i)) {
+                    Some(a) => builder.append_string(&a.get_string(i))?,
+                    None => builder.append_null()?,
+                }
+            }
+            Ok(Arc::new(builder.finish()) as ArrayRef)
+        }
+        other => Err(ExecutionError::NotImplemented(format!(
+            "COALESCE is not supported for {:?}",
+            other
+        ))),
+    }
+}
+
+fn nullif(left: &ArrayRef, right: &ArrayRef) -> Result<ArrayRef> {
+    match left.data_type() {
+        DataType::Boolean => nullif_arrays!(left, right, BooleanArray),
+        DataType::Int8 => nullif_arrays!(left, right, Int8Array),
+        DataType::Int16 => nullif_arrays!(left, right, Int16Array),
+        DataType::Int32 => nullif_arrays!(left, right, Int32Array),
+        DataType::Int64 => nullif_arrays!(left, right, Int64Array),
+        DataType::UInt8 => nullif_arrays!(left, right, UInt8Array),
+        DataType::UInt16 => nullif_arrays!(left, right, UInt16Array),
+        DataType::UInt32 => nullif_arrays!(left, right, UInt32Array),
+        DataType::UInt64 => nullif_arrays!(left, right, UInt64Array),
+        DataType::Float32 => nullif_arrays!(left, right, Float32Array),
+        DataType::Float64 => nullif_arrays!(left, right, Float64Array),
+        DataType::Utf8 => {
+            let l = left.as_any().downcast_ref::<BinaryArray>().unwrap();
+            let r = right.as_any().downcast_ref::<BinaryArray>().unwrap();
+            let mut builder = BinaryBuilder::new(l.len());
+            for i in 0..l.len() {
+                if l.is_null(i) || (r.is_valid(i) && l.value(i) == r.value(i)) {
+                    builder.append_null()?;
+                } else {
+                    builder.append_string(&l.get_string(i))?;
+                }
+            }
+            Ok(Arc::new(builder.finish()) as ArrayRef)
+        }
+        other => Err(ExecutionError::NotImplemented(format!(
+            "NULLIF is not supported for {:?}",
+            other
+        ))),
+    }
+}
+
+/// Takes each row from the array chosen for that row, or NULL where no array was chosen
+macro_rules! choose_arrays {
+    ($ARRAYS:expr, $CHOICES:expr, $ARRAY_TYPE:ident) => {{
+        let arrays: Vec<&$ARRAY_TYPE> = $ARRAYS
+            .iter()
+            .map(|a| a.as_any().downcast_ref::<$ARRAY_TYPE>().unwrap())
+            .collect();
+        let mut builder = $ARRAY_TYPE::builder($CHOICES.len());
+        for (i, choice) in $CHOICES.iter().enumerate() {
+            match choice {
+                Some(n) if arrays[*n].is_valid(i) => builder.append_value(arrays[*n].value(i))?,
+                _ => builder.append_null()?,
+            }
+        }
+        Ok(Arc::new(builder.finish()) as ArrayRef)
+    }};
+}
+
+/// Determines which CASE result each row takes its value from: the first branch whose
+/// condition is true, otherwise the ELSE result (which follows the THEN results) if present
+fn case_choices(when: &Vec<ArrayRef>, num_rows: usize, has_else: bool) -> Vec<Option<usize>> {
+    let when: Vec<&BooleanArray> = when
+        .iter()
+        .map(|a| a.as_any().downcast_ref::<BooleanArray>().unwrap())
+        .collect();
+    (0..num_rows)
+        .map(
+            |i| match when.iter().position(|w| w.is_valid(i) && w.value(i)) {
+                Some(n) => Some(n),
+                None if has_else => Some(when.len()),
+                None => None,
+            },
+        )
+        .collect()
+}
+
+fn choose(arrays: &Vec<ArrayRef>, choices: &[Option<usize>]) -> Result<ArrayRef> {
+    match arrays[0].data_type() {
+        DataType::Boolean => choose_arrays!(arrays, choices, BooleanArray),
+        DataType::Int8 => choose_arrays!(arrays, choices, Int8Array),
+        DataType::Int16 => choose_arrays!(arrays, choices, Int16Array),
+        DataType::Int32 => choose_arrays!(arrays, choices, Int32Array),
+        DataType::Int64 => choose_arrays!(arrays, choices, Int64Array),
+        DataType::UInt8 => choose_arrays!(arrays, choices, UInt8Array),
+        DataType::UInt16 => choose_arrays!(arrays, choices, UInt16Array),
+        DataType::UInt32 => choose_arrays!(arrays, choices, UInt32Array),
+        DataType::UInt64 => choose_arrays!(arrays, choices, UInt64Array),
+        DataType::Float32 => choose_arrays!(arrays, choices, Float32Array),
+        DataType::Float64 => choose_arrays!(arrays, choices, Float64Array),
+        DataType::Utf8 => {
+            let arrays: Vec<&BinaryArray> = arrays
+                .iter()
+                .map(|a| a.as_any().downcast_ref::<BinaryArray>().unwrap())
+                .collect();
+            let mut builder = BinaryBuilder::new(choices.len());
+            for (i, choice) in choices.iter().enumerate() {
+                match choice {
+                    Some(n) if arrays[*n].is_valid(i) => {
+                        builder.append_string(&arrays[*n].get_string(i))?
+                    }
+                    _ => builder.append_null()?,
+                }
+            }
+            Ok(Arc::new(builder.finish()) as ArrayRef)
+        }
+        other => Err(ExecutionError::NotImplemented(format!(
+            "CASE is not supported for {:?}",
+            other
+        ))),
+    }
+}
+
+/// Evaluates `value [NOT] IN (list)` for arrays of the same type. A constant list is hashed
+/// once per batch, otherwise each row is compared with every list value. A value not found
+/// in a list containing NULL yields NULL.
+macro_rules! in_list_arrays {
+    ($VALUES:expr, $LIST:expr, $NEGATED:expr, $CONSTANT:expr, $ARRAY_TYPE:ident, $KEY:expr) => {{
+        let key = $KEY;
+        let values = $VALUES.as_any().downcast_ref::<$ARRAY_TYPE>().unwrap();
+        let list: Vec<&$ARRAY_TYPE> = $LIST
+            .iter()
+            .map(|a| a.as_any().downcast_ref::<$ARRAY_TYPE>().unwrap())
+            .collect();
+        let mut builder = BooleanArray::builder(values.len());
+        if $CONSTANT {
+            let mut set = HashSet::new();
+            let mut list_has_null = false;
+            if values.len() > 0 {
+                for &l in &list {
+                    if l.is_null(0) {
+                        list_has_null = true;
+                    } else {
+                        set.insert(key(l, 0));
+                    }
+                }
+            }
+            for i in 0..values.len() {
+                if values.is_null(i) {
+                    builder.append_null()?;
+                } else if set.contains(&key(values, i)) {
+                    builder.append_value(!$NEGATED)?;
+                } else if list_has_null {
+                    builder.append_null()?;
+                } else {
+                    builder.append_value($NEGATED)?;
+                }
+            }
+        } else {
+            for i in 0..values.len() {
+                if values.is_null(i) {
+                    builder.append_null()?;
+                    continue;
+                }
+                let value = key(values, i);
+                let mut found = false;
+                let mut list_has_null = false;
+                for &l in &list {
+                    if l.is_null(i) {
+                        list_has_null = true;
+                    } else if key(l, i) == value {
+                        found = true;
+                        break;
+                    }
+                }
+                if found {
+                    builder.append_value(!$NEGATED)?;
+                } else if list_has_null {
+                    builder.append_null()?;
+                } else {
+                    builder.append_value($NEGATED)?;
+                }
+            }
+        }
+        Ok(Arc::new(builder.finish()) as ArrayRef)
+    }};
+}
+
+macro_rules! in_list_primitive {
+    ($VALUES:expr, $LIST:expr, $NEGATED:expr, $CONSTANT:expr, $ARRAY_TYPE:ident) => {{
+        in_list_arrays!(
+            $VALUES,
+            $LIST,
+            $NEGATED,
+            $CONSTANT,
+            $ARRAY_TYPE,
+            |a: &$ARRAY_TYPE, i: usize| a.value(i)
+        )
+    }};
+}
+
+fn in_list(
+    values: &ArrayRef,
+    list: &Vec<ArrayRef>,
+    negated: bool,
+    constant: bool,
+) -> Result<ArrayRef> {
+    match values.data_type() {
+        DataType::Boolean => in_list_primitive!(values, list, negated, constant, BooleanArray),
+        DataType::Int8 => in_list_primitive!(values, list, negated, constant, Int8Array),
+        DataType::Int16 => in_list_primitive!(values, list, negated, constant, Int16Array),
+        DataType::Int32 => in_list_primitive!(values, list, negated, constant, Int32Array),
+        DataType::Int64 => in_list_primitive!(values, list, negated, constant, Int64Array),
+        DataType::UInt8 => in_list_primitive!(values, list, negated, constant, UInt8Array),
+        DataType::UInt16 => in_list_primitive!(values, list, negated, constant, UInt16Array),
+        DataType::UInt32 => in_list_primitive!(values, list, negated, constant, UInt32Array),
+        DataType::UInt64 => in_list_primitive!(values, list, negated, constant, UInt64Array),
+        // floats are not hashable so they are compared by their bit patterns
+        DataType::Float32 => in_list_arrays!(
+            values,
+            list,
+            negated,
+            constant,
+            Float32Array,
+            |a: &Float32Array, i: usize| a.value(i).to_bits()
+        ),
+        DataType::Float64 => in_list_arrays!(
+            values,
+            list,
+            negated,
+            constant,
+            Float64Array,
+            |a: &Float64Array, i: usize| a.value(i).to_bits()
+        ),
+        DataType::Utf8 => in_list_arrays!(
+            values,
+            list,
+            negated,
+            constant,
+            BinaryArray,
+            |a: &BinaryArray, i: usize| a.get_string(i)
+        ),
+        other => Err(ExecutionError::NotImplemented(format!(
+            "IN is not supported for {:?}",
+            other
+        ))),
+    }
+}
+
+/// Logical NOT using SQL three-valued logic (NOT NULL is NULL)
+fn not(array: &ArrayRef) -> Result<ArrayRef> {
+    let a = array.as_any().downcast_ref::<BooleanArray>().unwrap();
+    let mut builder = BooleanArray::builder(a.len());
+    for i in 0..a.len() {
+        if a.is_null(i) {
+            builder.append_null()?;
+        } else {
+            builder.append_value(!a.value(i))?;
+        }
+    }
+    Ok(Arc::new(builder.finish()) as ArrayRef)
+}
+
+macro_rules! negate_array {
+    ($ARRAY:expr, $ARRAY_TYPE:ident, $NEG:expr) => {{
+        let neg = $NEG;
+        let a = $ARRAY.as_any().downcast_ref::<$ARRAY_TYPE>().unwrap();
+        let mut builder = $ARRAY_TYPE::builder(a.len());
+        for i in 0..a.len() {
+            if a.is_null(i) {
+                builder.append_null()?;
+            } else {
+                match neg(a.value(i)) {
+                    Some(v) => builder.append_value(v)?,
+                    None => {
+                        return Err(ExecutionError::ExecutionError(format!(
+                            "Negation of {} overflows",
+                            a.value(i)
+                        )));
+                    }
+                }
+            }
+        }
+        Ok(Arc::new(builder.finish()) as ArrayRef)
+    }};
+}
+
+/// Numeric negation of a signed integer or floating point array. Negating the minimum value of
+/// an integer type is an error.
+fn negate(array: &ArrayRef) -> Result<ArrayRef> {
+    match array.data_type() {
+        DataType::Int8 => negate_array!(array, Int8Array, i8::checked_neg),
+        DataType::Int16 => negate_array!(array, Int16Array, i16::checked_neg),
+        DataType::Int32 => negate_array!(array, Int32Array, i32::checked_neg),
+        DataType::Int64 => negate_array!(array, Int64Array, i64::checked_neg),
+        DataType::Float32 => negate_array!(array, Float32Array, |v: f32| Some(-v)),
+        DataType::Float64 => negate_array!(array, Float64Array, |v: f64| Some(-v)),
+        other => Err(ExecutionError::ExecutionError(format!(
+            "Cannot negate {:?}",
+            other
+        ))),
+    }
+}
+
+macro_rules! null_array {
+    ($LEN:expr, $ARRAY_TYPE:ident) => {{
+        let mut builder = $ARRAY_TYPE::builder($LEN);
+        for _ in 0..$LEN {
+            builder.append_null()?;
+        }
+        Ok(Arc::new(builder.finish()) as ArrayRef)
+    }};
+}
+
+/// Gets a field of a struct array. The field is NULL wherever the struct itself is NULL.
+fn get_field(array: &ArrayRef, index: usize) -> Result<ArrayRef> {
+    let struct_array = array.as_any().downcast_ref::<StructArray>().unwrap();
+    let field = struct_array.column(index).clone();
+    if struct_array.null_count() == 0 {
+        Ok(field)
+    } else {
+        let choices: Vec<Option<usize>> = (0..array.len())
+            .map(|i| if array.is_valid(i) { Some(0) } else { None })
+            .collect();
+        choose(&vec![field], &choices)
+    }
+}
+
+/// Returns the number of elements in each list
+fn array_length(lists: &ArrayRef) -> Result<ArrayRef> {
+    let lists = lists.as_any().downcast_ref::<ListArray>().unwrap();
+    let mut builder = Int64Array::builder(lists.len());
+    for i in 0..lists.len() {
+        if lists.is_null(i) {
+            builder.append_null()?;
+        } else {
+            builder.append_value(lists.value_length(i) as i64)?;
+        }
+    }
+    Ok(Arc::new(builder.finish()) as ArrayRef)
+}
+
+macro_rules! list_contains {
+    ($LISTS:expr, $VALUES:expr, $ARRAY_TYPE:ident) => {{
+        let elements = $LISTS.values();
+        let elements = elements.as_any().downcast_ref::<$ARRAY_TYPE>().unwrap();
+        let values = $VALUES.as_any().downcast_ref::<$ARRAY_TYPE>().unwrap();
+        let mut builder = BooleanArray::builder($LISTS.len());
+        for i in 0..$LISTS.len() {
+            if $LISTS.is_null(i) || values.is_null(i) {
+                builder.append_null()?;
+            } else {
+                let start = $LISTS.value_offset(i) as usize;
+                let end = start + $LISTS.value_length(i) as usize;
+                let found = (start..end)
+                    .any(|j| elements.is_valid(j) && elements.value(j) == values.value(i));
+                builder.append_value(found)?;
+            }
+        }
+        Ok(Arc::new(builder.finish()) as ArrayRef)
+    }};
+}
+
+/// Determines whether each list contains the corresponding value. NULL elements never match.
+fn array_contains(lists: &ArrayRef, values: &ArrayRef) -> Result<ArrayRef> {
+    let lists = lists.as_any().downcast_ref::<ListArray>().unwrap();
+    match values.data_type() {
+        DataType::Boolean => list_contains!(lists, values, BooleanArray),
+        DataType::Int8 => list_contains!(lists, values, Int8Array),
+        DataType::Int16 => list_contains!(lists, values, Int16Array),
+        DataType::Int32 => list_contains!(lists, values, Int32Array),
+        DataType::Int64 => list_contains!(lists, values, Int64Array),
+        DataType::UInt8 => list_contains!(lists, values, UInt8Array),
+        DataType::UInt16 => list_contains!(lists, values, UInt16Array),
+        DataType::UInt32 => list_contains!(lists, values, UInt32Array),
+        DataType::UInt64 => list_contains!(lists, values, UInt64Array),
+        DataType::Float32 => list_contains!(lists, values, Float32Array),
+        DataType::Float64 => list_contains!(lists, values, Float64Array),
+        DataType::Utf8 => list_contains!(lists, values, BinaryArray),
+        other => Err(ExecutionError::NotImplemented(format!(
+            "array_contains is not supported for {:?}",
+            other
+        ))),
+    }
+}
+
+/// Get the position in the list values of the element at a 1-based index, or None if the list
+/// or index is NULL or the index is out of range
+fn list_element_index(lists: &ListArray, indices: &Int64Array, row: usize) -> Option<usize> {
+    if lists.is_null(row) || indices.is_null(row) {
+        return None;
+    }
+    let n = indices.value(row);
+    if n < 1 || n > lists.value_length(row) as i64 {
+        None
+    } else {
+        Some(lists.value_offset(row) as usize + n as usize - 1)
+    }
+}
+
+macro_rules! list_element {
+    ($LISTS:expr, $INDICES:expr, $ARRAY_TYPE:ident) => {{
+        let elements = $LISTS.values();
+        let elements = elements.as_any().downcast_ref::<$ARRAY_TYPE>().unwrap();
+        let mut builder = $ARRAY_TYPE::builder($LISTS.len());
+        for i in 0..$LISTS.len() {
+            match list_element_index($LISTS, $INDICES, i) {
+                Some(j) if elements.is_valid(j) => builder.append_value(elements.value(j))?,
+                _ => builder.append_null()?,
+            }
+        }
+        Ok(Arc::new(builder.finish()) as ArrayRef)
+    }};
+}
+
+/// Gets the element at a 1-based index from each list, which is NULL if the index is out of
+/// range
+fn array_element(lists: &ArrayRef, indices: &ArrayRef) -> Result<ArrayRef> {
+    let lists = lists.as_any().downcast_ref::<ListArray>().unwrap();
+    let indices = indices.as_any().downcast_ref::<Int64Array>().unwrap();
+    match lists.values().data_type() {
+        DataType::Boolean => list_element!(lists, indices, BooleanArray),
+        DataType::Int8 => list_element!(lists, indices, Int8Array),
+        DataType::Int16 => list_element!(lists, indices, Int16Array),
+        DataType::Int32 => list_element!(lists, indices, Int32Array),
+        DataType::Int64 => list_element!(lists, indices, Int64Array),
+        DataType::UInt8 => list_element!(lists, indices, UInt8Array),
+        DataType::UInt16 => list_element!(lists, indices, UInt16Array),
+        DataType::UInt32 => list_element!(lists, indices, UInt32Array),
+        DataType::UInt64 => list_element!(lists, indices, UInt64Array),
+        DataType::Float32 => list_element!(lists, indices, Float32Array),
+        DataType::Float64 => list_element!(lists, indices, Float64Array),
+        DataType::Utf8 => {
+            let elements = lists.values();
+            let elements = elements.as_any().downcast_ref::<BinaryArray>().unwrap();
+            let mut builder = BinaryBuilder::new(lists.len());
+            for i in 0..lists.len() {
+                match list_element_index(lists, indices, i) {
+                    Some(j) if elements.is_valid(j) => {
+                        builder.append_string(&elements.get_string(j))?
+                    }
+                    _ => builder.append_null()?,
+                }
+            }
+            Ok(Arc::new(builder.finish()) as ArrayRef)
+        }
+        other => Err(ExecutionError::NotImplemented(format!(
+            "array_element is not supported for {:?}",
+            other
+        ))),
+    }
+}
+
+/// Creates an array of the given type in which every value is NULL
+fn new_null_array(data_type: &DataType, len: usize) -> Result<ArrayRef> {
+    match data_type {
+        DataType::Boolean => null_array!(len, BooleanArray),
+        DataType::Int8 => null_array!(len, Int8Array),
+        DataType::Int16 => null_array!(len, Int16Array),
+        DataType::Int32 => null_array!(len, Int32Array),
+        DataType::Int64 => null_array!(len, Int64Array),
+        DataType::UInt8 => null_array!(len, UInt8Array),
+        DataType::UInt16 => null_array!(len, UInt16Array),
+        DataType::UInt32 => null_array!(len, UInt32Array),
+        DataType::UInt64 => null_array!(len, UInt64Array),
+        DataType::Float32 => null_array!(len, Float32Array),
+        DataType::Float64 => null_array!(len, Float64Array),
+        DataType::Utf8 => {
+            let mut builder = BinaryBuilder::new(len);
+            for _ in 0..len {
+                builder.append_null()?;
+            }
+            Ok(Arc::new(builder.finish()) as ArrayRef)
+        }
+        other => Err(ExecutionError::NotImplemented(format!(
+            "NULL literal of type {:?}",
+            other
+        ))),
+    }
+}
+
+/// Compiles a NULL literal of the given type
+fn null_literal(data_type: &DataType) -> RuntimeExpr {
+    let t = data_type.clone();
+    RuntimeExpr::Compiled {
+        name: "NULL".to_string(),
+        f: Rc::new(move |batch: &RecordBatch| new_null_array(&t, batch.num_rows())),
+        t: data_type.clone(),
+    }
+}
+
+/// Determines whether an expression evaluates to the same value for every row
+fn is_constant(expr: &Expr) -> bool {
+    match expr {
+        Expr::Literal(_) => true,
+        Expr::Cast { ref expr, .. } => is_constant(expr),
+        _ => false,
+    }
+}
+
 macro_rules! literal_array {
     ($VALUE:expr, $ARRAY_TYPE:ident, $TY:ident) => {{
         let nn = *$VALUE;
@@ -240,45 +1208,168 @@ macro_rules! literal_array {
             t: DataType::$TY,
         })
     }};
+    ($VALUE:expr, $ARRAY_TYPE:ident, $DATA_TYPE:expr, $NAME:expr) => {{
+        let nn = *$VALUE;
+        Ok(RuntimeExpr::Compiled {
+            name: $NAME,
+            f: Rc::new(move |batch: &RecordBatch| {
+                let capacity = batch.num_rows();
+                let mut builder = $ARRAY_TYPE::builder(capacity);
+                for _ in 0..capacity {
+                    builder.append_value(nn)?;
+                }
+                Ok(temporal::with_data_type(
+                    Arc::new(builder.finish()),
+                    &$DATA_TYPE,
+                ))
+            }),
+            t: $DATA_TYPE,
+        })
+    }};
 }
 
-/// Casts a column to an array with a different data type
-macro_rules! cast_column {
-    ($INDEX:expr, $FROM_TYPE:ty, $TO_TYPE:ident, $DT:ty) => {{
-        Rc::new(move |batch: &RecordBatch| {
-            // get data and cast to known type
-            match batch.column($INDEX).as_any().downcast_ref::<$FROM_TYPE>() {
-                Some(array) => {
-                    // create builder for desired type
-                    let mut builder = $TO_TYPE::builder(batch.num_rows());
-                    for i in 0..batch.num_rows() {
-                        if array.is_null(i) {
-                            builder.append_null()?;
-                        } else {
-                            builder.append_value(array.value(i) as $DT)?;
-                        }
+/// Casts a primitive array to another primitive type
+macro_rules! cast_primitive_array {
+    ($ARRAY:expr, $FROM_TYPE:ident, $TO_TYPE:ident, $TO_NATIVE:ty) => {{
+        let a = $ARRAY.as_any().downcast_ref::<$FROM_TYPE>().unwrap();
+        let mut builder = $TO_TYPE::builder(a.len());
+        for i in 0..a.len() {
+            if a.is_null(i) {
+                builder.append_null()?;
+            } else {
+                builder.append_value(a.value(i) as $TO_NATIVE)?;
+            }
+        }
+        Ok(Arc::new(builder.finish()) as ArrayRef)
+    }};
+}
+
+/// Casts a numeric array to any numeric type or to a string
+macro_rules! cast_from_numeric {
+    ($ARRAY:expr, $FROM_TYPE:ident, $TO_DATA_TYPE:expr) => {{
+        match $TO_DATA_TYPE {
+            DataType::Int8 => cast_primitive_array!($ARRAY, $FROM_TYPE, Int8Array, i8),
+            DataType::Int16 => cast_primitive_array!($ARRAY, $FROM_TYPE, Int16Array, i16),
+            DataType::Int32 => cast_primitive_array!($ARRAY, $FROM_TYPE, Int32Array, i32),
+            DataType::Int64 => cast_primitive_array!($ARRAY, $FROM_TYPE, Int64Array, i64),
+            DataType::UInt8 => cast_primitive_array!($ARRAY, $FROM_TYPE, UInt8Array, u8),
+            DataType::UInt16 => cast_primitive_array!($ARRAY, $FROM_TYPE, UInt16Array, u16),
+            DataType::UInt32 => cast_primitive_array!($ARRAY, $FROM_TYPE, UInt32Array, u32),
+            DataType::UInt64 => cast_primitive_array!($ARRAY, $FROM_TYPE, UInt64Array, u64),
+            DataType::Float32 => cast_primitive_array!($ARRAY, $FROM_TYPE, Float32Array, f32),
+            DataType::Float64 => cast_primitive_array!($ARRAY, $FROM_TYPE, Float64Array, f64),
+            DataType::Utf8 => {
+                let a = $ARRAY.as_any().downcast_ref::<$FROM_TYPE>().unwrap();
+                let mut builder = BinaryBuilder::new(a.len());
+                for i in 0..a.len() {
+                    if a.is_null(i) {
+                        builder.append_null()?;
+                    } else {
+                        builder.append_string(&a.value(i).to_string())?;
                     }
-                    Ok(Arc::new(builder.finish()) as ArrayRef)
                 }
-                None => Err(ExecutionError::InternalError(format!(
-                    "Column at index {} is not of expected type",
-                    $INDEX
-                ))),
+                Ok(Arc::new(builder.finish()) as ArrayRef)
             }
-        })
+            other => Err(ExecutionError::NotImplemented(format!(
+                "CAST from {:?} to {:?}",
+                $ARRAY.data_type(),
+                other
+            ))),
+        }
     }};
 }
 
-macro_rules! cast_column_outer {
-    ($INDEX:expr, $FROM_TYPE:ty, $TO_TYPE:expr) => {{
-        match $TO_TYPE {
-            DataType::Int16 => cast_column!($INDEX, $FROM_TYPE, Int16Array, i16),
-            DataType::Int32 => cast_column!($INDEX, $FROM_TYPE, Int32Array, i32),
-            _ => unimplemented!(),
+/// Parses a string array into a primitive type
+macro_rules! parse_string_array {
+    ($ARRAY:expr, $TO_TYPE:ident, $TO_NATIVE:ty) => {{
+        let a = $ARRAY.as_any().downcast_ref::<BinaryArray>().unwrap();
+        let mut builder = $TO_TYPE::builder(a.len());
+        for i in 0..a.len() {
+            if a.is_null(i) {
+                builder.append_null()?;
+            } else {
+                let s = a.get_string(i);
+                match s.trim().parse::<$TO_NATIVE>() {
+                    Ok(v) => builder.append_value(v)?,
+                    Err(_) => {
+                        return Err(ExecutionError::ExecutionError(format!(
+                            "Cannot cast string '{}' to {}",
+                            s,
+                            stringify!($TO_NATIVE)
+                        )));
+                    }
+                }
+            }
         }
+        Ok(Arc::new(builder.finish()) as ArrayRef)
     }};
 }
 
+/// Casts an array to a different data type
+fn cast_array(array: &ArrayRef, to_type: &DataType) -> Result<ArrayRef> {
+    if array.data_type() == to_type {
+        return Ok(array.clone());
+    }
+    if is_temporal(array.data_type()) || is_temporal(to_type) {
+        return temporal::cast(array, to_type);
+    }
+    match array.data_type() {
+        DataType::Int8 => cast_from_numeric!(array, Int8Array, to_type),
+        DataType::Int16 => cast_from_numeric!(array, Int16Array, to_type),
+        DataType::Int32 => cast_from_numeric!(array, Int32Array, to_type),
+        DataType::Int64 => cast_from_numeric!(array, Int64Array, to_type),
+        DataType::UInt8 => cast_from_numeric!(array, UInt8Array, to_type),
+        DataType::UInt16 => cast_from_numeric!(array, UInt16Array, to_type),
+        DataType::UInt32 => cast_from_numeric!(array, UInt32Array, to_type),
+        DataType::UInt64 => cast_from_numeric!(array, UInt64Array, to_type),
+        DataType::Float32 => cast_from_numeric!(array, Float32Array, to_type),
+        DataType::Float64 => cast_from_numeric!(array, Float64Array, to_type),
+        DataType::Utf8 => match to_type {
+            DataType::Int8 => parse_string_array!(array, Int8Array, i8),
+            DataType::Int16 => parse_string_array!(array, Int16Array, i16),
+            DataType::Int32 => parse_string_array!(array, Int32Array, i32),
+            DataType::Int64 => parse_string_array!(array, Int64Array, i64),
+            DataType::UInt8 => parse_string_array!(array, UInt8Array, u8),
+            DataType::UInt16 => parse_string_array!(array, UInt16Array, u16),
+            DataType::UInt32 => parse_string_array!(array, UInt32Array, u32),
+            DataType::UInt64 => parse_string_array!(array, UInt64Array, u64),
+            DataType::Float32 => parse_string_array!(array, Float32Array, f32),
+            DataType::Float64 => parse_string_array!(array, Float64Array, f64),
+            DataType::Boolean => parse_string_array!(array, BooleanArray, bool),
+            other => Err(ExecutionError::NotImplemented(format!(
+                "CAST from Utf8 to {:?}",
+                other
+            ))),
+        },
+        other => Err(ExecutionError::NotImplemented(format!(
+            "CAST from {:?} to {:?}",
+            other, to_type
+        ))),
+    }
+}
+
+/// Get the value of a scalar subquery, which is NULL when it returns no rows
+fn evaluate_scalar_subquery(
+    ctx: &ExecutionContext,
+    plan: &LogicalPlan,
+) -> Result<Option<ScalarValue>> {
+    let relation = ctx.fork().execute(plan)?;
+    let mut value = None;
+    let mut rows = 0;
+    while let Some(batch) = relation.borrow_mut().next()? {
+        rows += batch.num_rows();
+        if rows > 1 {
+            return Err(ExecutionError::ExecutionError(
+                "Scalar subquery returned more than one row".to_string(),
+            ));
+        }
+        if batch.num_rows() == 1 {
+            value = scalar_value(batch.column(0), 0)?;
+        }
+    }
+    Ok(value)
+}
+
 /// Compiles a scalar expression into a closure
 pub fn compile_scalar_expr(
     ctx: &ExecutionContext,
@@ -303,6 +1394,40 @@ pub fn compile_scalar_expr(
             ScalarValue::UInt64(n) => literal_array!(n, UInt64Array, UInt64),
             ScalarValue::Float32(n) => literal_array!(n, Float32Array, Float32),
             ScalarValue::Float64(n) => literal_array!(n, Float64Array, Float64),
+            ScalarValue::Boolean(b) => literal_array!(b, BooleanArray, Boolean),
+            ScalarValue::Null => Ok(null_literal(&DataType::Utf8)),
+            ScalarValue::Date32(d) => {
+                literal_array!(d, DateArray, temporal::DATE_TYPE, temporal::format_date(*d))
+            }
+            ScalarValue::Time64(t) => {
+                literal_array!(t, TimeArray, temporal::TIME_TYPE, temporal::format_time(*t))
+            }
+            ScalarValue::Timestamp(t) => literal_array!(
+                t,
+                TimestampArray,
+                temporal::TIMESTAMP_TYPE,
+                temporal::format_timestamp(*t)
+            ),
+            ScalarValue::Interval(n) => literal_array!(
+                n,
+                IntervalArray,
+                temporal::INTERVAL_TYPE,
+                format!("{:?}", value)
+            ),
+            ScalarValue::Utf8(ref s) => {
+                let s = s.clone();
+                Ok(RuntimeExpr::Compiled {
+                    name: s.to_string(),
+                    f: Rc::new(move |batch: &RecordBatch| {
+                        let mut builder = BinaryBuilder::new(batch.num_rows());
+                        for _ in 0..batch.num_rows() {
+                            builder.append_string(&s)?;
+                        }
+                        Ok(Arc::new(builder.finish()) as ArrayRef)
+                    }),
+                    t: DataType::Utf8,
+                })
+            }
             other => Err(ExecutionError::ExecutionError(format!(
                 "No support for literal type {:?}",
                 other
@@ -317,86 +1442,275 @@ pub fn compile_scalar_expr(
             ref expr,
             ref data_type,
         } => match expr.as_ref() {
-            &Expr::Column(index) => {
-                let col = input_schema.field(index);
+            // a NULL literal has no type of its own
+            &Expr::Literal(ScalarValue::Null) => Ok(null_literal(data_type)),
+            _ => {
+                let compiled_expr = compile_scalar_expr(ctx, expr, input_schema)?;
+                let name = compiled_expr.get_name().clone();
+                let f = compiled_expr.get_func();
+                let to_type = data_type.clone();
                 Ok(RuntimeExpr::Compiled {
-                    name: col.name().clone(),
-                    t: col.data_type().clone(),
-                    f: match col.data_type() {
-                        DataType::Int8 => cast_column_outer!(index, Int8Array, &data_type),
-                        DataType::Int16 => cast_column_outer!(index, Int16Array, &data_type),
-                        DataType::Int32 => cast_column_outer!(index, Int32Array, &data_type),
-                        DataType::Int64 => cast_column_outer!(index, Int64Array, &data_type),
-                        DataType::UInt8 => cast_column_outer!(index, UInt8Array, &data_type),
-                        DataType::UInt16 => cast_column_outer!(index, UInt16Array, &data_type),
-                        DataType::UInt32 => cast_column_outer!(index, UInt32Array, &data_type),
-                        DataType::UInt64 => cast_column_outer!(index, UInt64Array, &data_type),
-                        DataType::Float32 => cast_column_outer!(index, Float32Array, &data_type),
-                        DataType::Float64 => cast_column_outer!(index, Float64Array, &data_type),
-                        _ => panic!("unsupported CAST operation"), //TODO
-                                                                   /*Err(ExecutionError::NotImplemented(format!(
-                                                                       "CAST column from {:?} to {:?}",
-                                                                       col.data_type(),
-                                                                       data_type
-                                                                   )))*/
-                    },
+                    name,
+                    f: Rc::new(move |batch: &RecordBatch| cast_array(&f(batch)?, &to_type)),
+                    t: data_type.clone(),
                 })
             }
-            &Expr::Literal(ref value) => {
-                //NOTE this is all very inefficient and needs to be optimized - tracking
-                // issue is https://github.com/andygrove/datafusion/issues/191
-                match value {
-                    ScalarValue::Int64(n) => {
-                        let nn = *n;
-                        match data_type {
-                            DataType::Float64 => Ok(RuntimeExpr::Compiled {
-                                name: "lit".to_string(),
-                                f: Rc::new(move |batch: &RecordBatch| {
-                                    let mut b = Float64Array::builder(batch.num_rows());
-                                    for _ in 0..batch.num_rows() {
-                                        b.append_value(nn as f64)?;
-                                    }
-                                    Ok(Arc::new(b.finish()) as ArrayRef)
-                                }),
-                                t: data_type.clone(),
-                            }),
-                            other => Err(ExecutionError::NotImplemented(format!(
-                                "CAST from Int64 to {:?}",
-                                other
-                            ))),
-                        }
+        },
+        &Expr::GetField {
+            expr: ref struct_expr,
+            ref name,
+        } => {
+            let compiled_expr = compile_scalar_expr(ctx, struct_expr, input_schema)?;
+            let (index, t) = match compiled_expr.get_type() {
+                DataType::Struct(fields) => match fields.iter().position(|f| f.name() == name) {
+                    Some(i) => (i, fields[i].data_type().clone()),
+                    None => {
+                        return Err(ExecutionError::InvalidColumn(format!(
+                            "Struct {:?} has no field named '{}'",
+                            struct_expr, name
+                        )));
                     }
-                    other => Err(ExecutionError::NotImplemented(format!(
-                        "CAST from {:?} to {:?}",
-                        other, data_type
-                    ))),
+                },
+                other => {
+                    return Err(ExecutionError::ExecutionError(format!(
+                        "Cannot access field '{}' of type {:?}",
+                        name, other
+                    )));
+                }
+            };
+            let f = compiled_expr.get_func();
+            Ok(RuntimeExpr::Compiled {
+                name: name.clone(),
+                f: Rc::new(move |batch: &RecordBatch| get_field(&f(batch)?, index)),
+                t,
+            })
+        }
+        &Expr::IsNotNull(ref expr) => {
+            let compiled_expr = compile_scalar_expr(ctx, expr, input_schema)?;
+            Ok(RuntimeExpr::Compiled {
+                name: format!("{:?} IS NOT NULL", expr),
+                f: Rc::new(move |batch: &RecordBatch| {
+                    let values = compiled_expr.get_func()(batch)?;
+                    is_null(&values, true)
+                }),
+                t: DataType::Boolean,
+            })
+        }
+        &Expr::ScalarSubquery(ref subquery) => {
+            // the subquery is evaluated once, and its value is compiled as a literal
+            let literal = match evaluate_scalar_subquery(ctx, &subquery.0)? {
+                Some(value) => Expr::Literal(value),
+                None => Expr::Cast {
+                    expr: Rc::new(Expr::Literal(ScalarValue::Null)),
+                    data_type: expr.get_type(input_schema)?,
+                },
+            };
+            compile_scalar_expr(ctx, &literal, input_schema)
+        }
+        &Expr::Not(ref expr) => {
+            let compiled_expr = compile_scalar_expr(ctx, expr, input_schema)?;
+            Ok(RuntimeExpr::Compiled {
+                name: format!("NOT {:?}", expr),
+                f: Rc::new(move |batch: &RecordBatch| {
+                    let values = compiled_expr.get_func()(batch)?;
+                    not(&values)
+                }),
+                t: DataType::Boolean,
+            })
+        }
+        &Expr::Negative(ref expr) => {
+            let compiled_expr = compile_scalar_expr(ctx, expr, input_schema)?;
+            let t = compiled_expr.get_type();
+            Ok(RuntimeExpr::Compiled {
+                name: format!("(- {:?})", expr),
+                f: Rc::new(move |batch: &RecordBatch| {
+                    let values = compiled_expr.get_func()(batch)?;
+                    negate(&values)
+                }),
+                t,
+            })
+        }
+        &Expr::IsNull(ref expr) => {
+            let compiled_expr = compile_scalar_expr(ctx, expr, input_schema)?;
+            Ok(RuntimeExpr::Compiled {
+                name: format!("{:?} IS NULL", expr),
+                f: Rc::new(move |batch: &RecordBatch| {
+                    let values = compiled_expr.get_func()(batch)?;
+                    is_null(&values, false)
+                }),
+                t: DataType::Boolean,
+            })
+        }
+        &Expr::ScalarFunction {
+            ref name,
+            ref args,
+            ref return_type,
+        } => {
+            let compiled_args = args
+                .iter()
+                .map(|e| compile_scalar_expr(ctx, e, input_schema))
+                .collect::<Result<Vec<RuntimeExpr>>>()?;
+            let arg_funcs: Vec<CompiledExpr> = compiled_args.iter().map(|e| e.get_func()).collect();
+            let f: CompiledExpr = match name.to_lowercase().as_ref() {
+                "coalesce" => Rc::new(move |batch: &RecordBatch| {
+                    let arrays = arg_funcs
+                        .iter()
+                        .map(|f| f(batch))
+                        .collect::<Result<Vec<ArrayRef>>>()?;
+                    coalesce(&arrays)
+                }),
+                "nullif" if args.len() == 2 => Rc::new(move |batch: &RecordBatch| {
+                    let left = arg_funcs[0](batch)?;
+                    let right = arg_funcs[1](batch)?;
+                    nullif(&left, &right)
+                }),
+                "array_length" => {
+                    Rc::new(move |batch: &RecordBatch| array_length(&arg_funcs[0](batch)?))
+                }
+                "array_contains" if args.len() == 2 => Rc::new(move |batch: &RecordBatch| {
+                    array_contains(&arg_funcs[0](batch)?, &arg_funcs[1](batch)?)
+                }),
+                "array_element" if args.len() == 2 => Rc::new(move |batch: &RecordBatch| {
+                    array_element(&arg_funcs[0](batch)?, &arg_funcs[1](batch)?)
+                }),
+                // evaluated once so that every row (and every batch) sees the same time
+                "now" => {
+                    let now = temporal::now();
+                    Rc::new(move |batch: &RecordBatch| {
+                        let mut builder = TimestampArray::builder(batch.num_rows());
+                        for _ in 0..batch.num_rows() {
+                            builder.append_value(now)?;
+                        }
+                        Ok(temporal::with_data_type(
+                            Arc::new(builder.finish()),
+                            &temporal::TIMESTAMP_TYPE,
+                        ))
+                    })
+                }
+                "date_trunc" | "date_part" if args.len() == 2 => {
+                    let unit = match &args[0] {
+                        Expr::Literal(ScalarValue::Utf8(unit)) => unit.to_string(),
+                        other => {
+                            return Err(ExecutionError::General(format!(
+                                "Expected a string literal for the unit of '{}' but found {:?}",
+                                name, other
+                            )));
+                        }
+                    };
+                    let trunc = name.to_lowercase() == "date_trunc";
+                    Rc::new(move |batch: &RecordBatch| {
+                        let values = arg_funcs[1](batch)?;
+                        if trunc {
+                            temporal::date_trunc_array(&unit, &values)
+                        } else {
+                            temporal::date_part_array(&unit, &values)
+                        }
+                    })
                 }
+                _ => {
+                    return Err(ExecutionError::NotImplemented(format!(
+                        "Scalar function '{}'",
+                        name
+                    )));
+                }
+            };
+            Ok(RuntimeExpr::Compiled {
+                name: format!("{:?}", expr),
+                f,
+                t: return_type.clone(),
+            })
+        }
+        &Expr::Case {
+            expr: ref operand,
+            ref when_then_expr,
+            ref else_expr,
+        } => {
+            // a simple CASE compares the operand with each WHEN value
+            let when_funcs = when_then_expr
+                .iter()
+                .map(|(w, _)| match operand {
+                    Some(operand) => compile_scalar_expr(ctx, &Expr::eq(operand, w), input_schema),
+                    None => compile_scalar_expr(ctx, w, input_schema),
+                })
+                .map(|e| e.map(|e| e.get_func()))
+                .collect::<Result<Vec<CompiledExpr>>>()?;
+            let mut result_exprs = when_then_expr
+                .iter()
+                .map(|(_, t)| compile_scalar_expr(ctx, t, input_schema))
+                .collect::<Result<Vec<RuntimeExpr>>>()?;
+            if let Some(e) = else_expr {
+                result_exprs.push(compile_scalar_expr(ctx, e, input_schema)?);
             }
-            other => Err(ExecutionError::General(format!(
-                "CAST not implemented for expression {:?}",
-                other
-            ))),
-        },
-        //        &Expr::IsNotNull(ref expr) => {
-        //            let compiled_expr = compile_scalar_expr(ctx, expr, input_schema)?;
-        //            Ok(RuntimeExpr::Compiled {
-        //                f: Rc::new(move |batch: &RecordBatch| {
-        //                    let left_values = compiled_expr.get_func()(batch)?;
-        //                    left_values.is_not_null()
-        //                }),
-        //                t: DataType::Boolean,
-        //            })
-        //        }
-        //        &Expr::IsNull(ref expr) => {
-        //            let compiled_expr = compile_scalar_expr(ctx, expr, input_schema)?;
-        //            Ok(RuntimeExpr::Compiled {
-        //                f: Rc::new(move |batch: &RecordBatch| {
-        //                    let left_values = compiled_expr.get_func()(batch)?;
-        //                    left_values.is_null()
-        //                }),
-        //                t: DataType::Boolean,
-        //            })
-        //        }
+            let has_else = else_expr.is_some();
+            let t = result_exprs[0].get_type();
+            let result_funcs: Vec<CompiledExpr> =
+                result_exprs.iter().map(|e| e.get_func()).collect();
+            Ok(RuntimeExpr::Compiled {
+                name: format!("{:?}", expr),
+                f: Rc::new(move |batch: &RecordBatch| {
+                    let when = when_funcs
+                        .iter()
+                        .map(|f| f(batch))
+                        .collect::<Result<Vec<ArrayRef>>>()?;
+                    let results = result_funcs
+                        .iter()
+                        .map(|f| f(batch))
+                        .collect::<Result<Vec<ArrayRef>>>()?;
+                    choose(&results, &case_choices(&when, batch.num_rows(), has_else))
+                }),
+                t,
+            })
+        }
+        &Expr::InList {
+            expr: ref value,
+            ref list,
+            negated,
+        } => {
+            let value_func = compile_scalar_expr(ctx, value, input_schema)?.get_func();
+            let list_funcs = list
+                .iter()
+                .map(|e| compile_scalar_expr(ctx, e, input_schema).map(|e| e.get_func()))
+                .collect::<Result<Vec<CompiledExpr>>>()?;
+            let constant = list.iter().all(is_constant);
+            Ok(RuntimeExpr::Compiled {
+                name: format!("{:?}", expr),
+                f: Rc::new(move |batch: &RecordBatch| {
+                    let values = value_func(batch)?;
+                    let list = list_funcs
+                        .iter()
+                        .map(|f| f(batch))
+                        .collect::<Result<Vec<ArrayRef>>>()?;
+                    in_list(&values, &list, negated, constant)
+                }),
+                t: DataType::Boolean,
+            })
+        }
+        &Expr::Between {
+            expr: ref value,
+            negated,
+            ref low,
+            ref high,
+        } => {
+            // evaluated as a pair of comparisons, which gives the correct null semantics
+            let range = if negated {
+                Expr::BinaryExpr {
+                    left: Rc::new(Expr::lt(value, low)),
+                    op: Operator::Or,
+                    right: Rc::new(Expr::gt(value, high)),
+                }
+            } else {
+                Expr::BinaryExpr {
+                    left: Rc::new(Expr::gt_eq(value, low)),
+                    op: Operator::And,
+                    right: Rc::new(Expr::lt_eq(value, high)),
+                }
+            };
+            Ok(RuntimeExpr::Compiled {
+                name: format!("{:?}", expr),
+                f: compile_scalar_expr(ctx, &range, input_schema)?.get_func(),
+                t: DataType::Boolean,
+            })
+        }
         &Expr::BinaryExpr {
             ref left,
             ref op,
@@ -405,7 +1719,22 @@ pub fn compile_scalar_expr(
             let left_expr = compile_scalar_expr(ctx, left, input_schema)?;
             let right_expr = compile_scalar_expr(ctx, right, input_schema)?;
             let name = format!("{:?} {:?} {:?}", left, op, right);
-            let op_type = left_expr.get_type().clone();
+            let op_type =
+                binary_operator_data_type(&left_expr.get_type(), op, &right_expr.get_type())?;
+            if temporal_arithmetic_types(&left_expr.get_type(), op, &right_expr.get_type())
+                .is_some()
+            {
+                let op = op.clone();
+                return Ok(RuntimeExpr::Compiled {
+                    name,
+                    f: Rc::new(move |batch: &RecordBatch| {
+                        let left_values = left_expr.get_func()(batch)?;
+                        let right_values = right_expr.get_func()(batch)?;
+                        temporal::temporal_arithmetic(&left_values, &op, &right_values)
+                    }),
+                    t: op_type,
+                });
+            }
             match op {
                 &Operator::Eq => Ok(RuntimeExpr::Compiled {
                     name,
@@ -452,14 +1781,14 @@ pub fn compile_scalar_expr(
                 &Operator::And => Ok(RuntimeExpr::Compiled {
                     name,
                     f: Rc::new(move |batch: &RecordBatch| {
-                        boolean_ops!(left_expr, right_expr, batch, and)
+                        boolean_ops!(left_expr, right_expr, batch, and_kleene)
                     }),
                     t: DataType::Boolean,
                 }),
                 &Operator::Or => Ok(RuntimeExpr::Compiled {
                     name,
                     f: Rc::new(move |batch: &RecordBatch| {
-                        boolean_ops!(left_expr, right_expr, batch, or)
+                        boolean_ops!(left_expr, right_expr, batch, or_kleene)
                     }),
                     t: DataType::Boolean,
                 }),
@@ -484,13 +1813,30 @@ pub fn compile_scalar_expr(
                     }),
                     t: op_type,
                 }),
-                &Operator::Divide => Ok(RuntimeExpr::Compiled {
-                    name,
-                    f: Rc::new(move |batch: &RecordBatch| {
-                        math_ops!(left_expr, right_expr, batch, divide)
-                    }),
-                    t: op_type,
-                }),
+                &Operator::Divide => {
+                    let divide_by_zero = ctx.divide_by_zero();
+                    Ok(RuntimeExpr::Compiled {
+                        name,
+                        f: Rc::new(move |batch: &RecordBatch| {
+                            let left_values = left_expr.get_func()(batch)?;
+                            let right_values = right_expr.get_func()(batch)?;
+                            divide(&left_values, &right_values, divide_by_zero)
+                        }),
+                        t: op_type,
+                    })
+                }
+                &Operator::Modulus => {
+                    let divide_by_zero = ctx.divide_by_zero();
+                    Ok(RuntimeExpr::Compiled {
+                        name,
+                        f: Rc::new(move |batch: &RecordBatch| {
+                            let left_values = left_expr.get_func()(batch)?;
+                            let right_values = right_expr.get_func()(batch)?;
+                            modulus(&left_values, &right_values, divide_by_zero)
+                        }),
+                        t: op_type,
+                    })
+                }
                 other => Err(ExecutionError::ExecutionError(format!(
                     "operator: {:?}",
                     other
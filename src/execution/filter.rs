@@ -18,13 +18,17 @@ use std::cell::RefCell;
 use std::rc::Rc;
 use std::sync::Arc;
 
-use arrow::array::{Array, ArrayRef, BinaryArray, BooleanArray, Float64Array};
-use arrow::datatypes::{DataType, Schema};
+use arrow::array::*;
+use arrow::array_data::{ArrayData, ArrayDataRef};
+use arrow::buffer::Buffer;
+use arrow::datatypes::{DataType, DateUnit, Schema, TimeUnit, ToByteSlice};
 use arrow::record_batch::RecordBatch;
+use arrow::util::bit_util;
 
 use super::error::{ExecutionError, Result};
 use super::expression::RuntimeExpr;
 use super::relation::Relation;
+use super::temporal::{DateArray, IntervalArray, TimeArray, TimestampArray};
 
 pub struct FilterRelation {
     schema: Arc<Schema>,
@@ -52,12 +56,14 @@ impl Relation for FilterRelation {
                     .downcast_ref::<BooleanArray>()
                 {
                     Some(filter_bools) => {
+                        let indices = selected_indices(filter_bools);
+
                         let filtered_columns: Result<Vec<ArrayRef>> = (0..batch.num_columns())
-                            .map(|i| filter(batch.column(i), &filter_bools))
+                            .map(|i| take(batch.column(i), &indices))
                             .collect();
 
                         let filtered_batch: RecordBatch =
-                            RecordBatch::new(Arc::new(Schema::empty()), filtered_columns?);
+                            RecordBatch::new(self.schema.clone(), filtered_columns?);
 
                         Ok(Some(filtered_batch))
                     }
@@ -75,36 +81,338 @@ impl Relation for FilterRelation {
     }
 }
 
+/// Returns the indices of the rows where the filter evaluates to true. Rows where the filter
+/// is false or null are removed.
+fn selected_indices(filter: &BooleanArray) -> Vec<usize> {
+    (0..filter.len())
+        .filter(|&i| filter.is_valid(i) && filter.value(i))
+        .collect()
+}
+
 //TODO: move into Arrow array_ops
-fn filter(array: &Arc<Array>, filter: &BooleanArray) -> Result<ArrayRef> {
-    let a = array.as_ref();
-
-    match a.data_type() {
-        DataType::Float64 => {
-            let b = a.as_any().downcast_ref::<Float64Array>().unwrap();
-            let mut builder = Float64Array::builder(b.len());
-            for i in 0..b.len() {
-                if filter.value(i) {
-                    builder.append_value(b.value(i))?;
+/// Filters an array, keeping the rows where the filter evaluates to true
+pub fn filter(array: &ArrayRef, filter: &BooleanArray) -> Result<ArrayRef> {
+    take(array, &selected_indices(filter))
+}
+
+/// Copies the values (and nulls) at the given indices into a new array, working directly on
+/// the value buffers and validity bitmap. Nested types are copied recursively.
+pub fn take(array: &ArrayRef, indices: &[usize]) -> Result<ArrayRef> {
+    let data = array.data();
+    let validity = take_validity(&data, indices);
+
+    let taken = match data.data_type() {
+        DataType::Boolean => {
+            let values = data.buffers()[0].data();
+            let mut bitmap = vec![0u8; bit_util::ceil(indices.len(), 8)];
+            for (i, &index) in indices.iter().enumerate() {
+                if bit_util::get_bit(values, data.offset() + index) {
+                    bit_util::set_bit(&mut bitmap, i);
                 }
             }
-            Ok(Arc::new(builder.finish()))
+            build_array_data(
+                &data,
+                indices.len(),
+                validity,
+                vec![Buffer::from(bitmap)],
+                vec![],
+            )
+        }
+        DataType::Int8 | DataType::UInt8 => take_fixed_width(&data, indices, validity, 1),
+        DataType::Int16 | DataType::UInt16 => take_fixed_width(&data, indices, validity, 2),
+        DataType::Int32 | DataType::UInt32 | DataType::Float32 | DataType::Date(_) => {
+            take_fixed_width(&data, indices, validity, 4)
         }
+        DataType::Int64
+        | DataType::UInt64
+        | DataType::Float64
+        | DataType::Time64(_)
+        | DataType::Timestamp(_)
+        | DataType::Interval(_) => take_fixed_width(&data, indices, validity, 8),
         DataType::Utf8 => {
-            //TODO: this is inefficient and we should improve the Arrow impl to help make this more concise
-            let b = a.as_any().downcast_ref::<BinaryArray>().unwrap();
-            let mut values: Vec<String> = Vec::with_capacity(b.len());
-            for i in 0..b.len() {
-                if filter.value(i) {
-                    values.push(b.get_string(i));
-                }
+            let b = array.as_any().downcast_ref::<BinaryArray>().unwrap();
+            let values = data.buffers()[1].data();
+            let mut offsets: Vec<i32> = Vec::with_capacity(indices.len() + 1);
+            let mut bytes: Vec<u8> = vec![];
+            offsets.push(0);
+            for &index in indices {
+                let start = b.value_offset(index) as usize;
+                let end = start + b.value_length(index) as usize;
+                bytes.extend_from_slice(&values[start..end]);
+                offsets.push(bytes.len() as i32);
+            }
+            build_array_data(
+                &data,
+                indices.len(),
+                validity,
+                vec![Buffer::from(offsets.to_byte_slice()), Buffer::from(bytes)],
+                vec![],
+            )
+        }
+        DataType::List(_) => {
+            let list = array.as_any().downcast_ref::<ListArray>().unwrap();
+            let mut offsets: Vec<i32> = Vec::with_capacity(indices.len() + 1);
+            let mut child_indices: Vec<usize> = vec![];
+            offsets.push(0);
+            for &index in indices {
+                let start = list.value_offset(index) as usize;
+                let end = start + list.value_length(index) as usize;
+                child_indices.extend(start..end);
+                offsets.push(child_indices.len() as i32);
             }
-            let tmp: Vec<&str> = values.iter().map(|s| s.as_str()).collect();
-            Ok(Arc::new(BinaryArray::from(tmp)))
+            let values = take(&list.values(), &child_indices)?;
+            build_array_data(
+                &data,
+                indices.len(),
+                validity,
+                vec![Buffer::from(offsets.to_byte_slice())],
+                vec![values.data()],
+            )
+        }
+        DataType::Struct(_) => {
+            let s = array.as_any().downcast_ref::<StructArray>().unwrap();
+            // the children are not sliced with the struct, so its offset applies to them too
+            let child_indices: Vec<usize> = indices.iter().map(|i| data.offset() + i).collect();
+            let children = (0..data.child_data().len())
+                .map(|i| take(s.column(i), &child_indices).map(|a| a.data()))
+                .collect::<Result<Vec<ArrayDataRef>>>()?;
+            build_array_data(&data, indices.len(), validity, vec![], children)
+        }
+        other => {
+            return Err(ExecutionError::ExecutionError(format!(
+                "filter not supported for {:?}",
+                other
+            )));
+        }
+    };
+
+    make_array(taken)
+}
+
+/// Builds the validity bitmap for the values at the given indices, along with the number of
+/// nulls. Returns no bitmap if the input has no nulls.
+fn take_validity(data: &ArrayDataRef, indices: &[usize]) -> (Option<Buffer>, usize) {
+    if data.null_count() == 0 {
+        return (None, 0);
+    }
+    let mut bitmap = vec![0u8; bit_util::ceil(indices.len(), 8)];
+    let mut null_count = 0;
+    for (i, &index) in indices.iter().enumerate() {
+        if data.is_null(data.offset() + index) {
+            null_count += 1;
+        } else {
+            bit_util::set_bit(&mut bitmap, i);
         }
+    }
+    (Some(Buffer::from(bitmap)), null_count)
+}
+
+/// Copies the fixed width values at the given indices
+fn take_fixed_width(
+    data: &ArrayDataRef,
+    indices: &[usize],
+    validity: (Option<Buffer>, usize),
+    byte_width: usize,
+) -> ArrayDataRef {
+    let values = data.buffers()[0].data();
+    let mut bytes: Vec<u8> = Vec::with_capacity(indices.len() * byte_width);
+    for &index in indices {
+        let start = (data.offset() + index) * byte_width;
+        bytes.extend_from_slice(&values[start..start + byte_width]);
+    }
+    build_array_data(
+        data,
+        indices.len(),
+        validity,
+        vec![Buffer::from(bytes)],
+        vec![],
+    )
+}
+
+/// Creates array data of the same type as the input
+fn build_array_data(
+    input: &ArrayDataRef,
+    len: usize,
+    validity: (Option<Buffer>, usize),
+    buffers: Vec<Buffer>,
+    child_data: Vec<ArrayDataRef>,
+) -> ArrayDataRef {
+    let (null_bit_buffer, null_count) = validity;
+    let mut builder = ArrayData::builder(input.data_type().clone())
+        .len(len)
+        .null_count(null_count)
+        .buffers(buffers)
+        .child_data(child_data);
+    if let Some(buffer) = null_bit_buffer {
+        builder = builder.null_bit_buffer(buffer);
+    }
+    builder.build()
+}
+
+/// Wraps array data in the concrete array type for its data type
+fn make_array(data: ArrayDataRef) -> Result<ArrayRef> {
+    let data_type = data.data_type().clone();
+    match data_type {
+        DataType::Boolean => Ok(Arc::new(BooleanArray::from(data))),
+        DataType::Int8 => Ok(Arc::new(Int8Array::from(data))),
+        DataType::Int16 => Ok(Arc::new(Int16Array::from(data))),
+        DataType::Int32 => Ok(Arc::new(Int32Array::from(data))),
+        DataType::Int64 => Ok(Arc::new(Int64Array::from(data))),
+        DataType::UInt8 => Ok(Arc::new(UInt8Array::from(data))),
+        DataType::UInt16 => Ok(Arc::new(UInt16Array::from(data))),
+        DataType::UInt32 => Ok(Arc::new(UInt32Array::from(data))),
+        DataType::UInt64 => Ok(Arc::new(UInt64Array::from(data))),
+        DataType::Float32 => Ok(Arc::new(Float32Array::from(data))),
+        DataType::Float64 => Ok(Arc::new(Float64Array::from(data))),
+        DataType::Utf8 => Ok(Arc::new(BinaryArray::from(data))),
+        DataType::Date(DateUnit::Day) => Ok(Arc::new(DateArray::from(data))),
+        DataType::Time64(TimeUnit::Microsecond) => Ok(Arc::new(TimeArray::from(data))),
+        DataType::Timestamp(TimeUnit::Microsecond) => Ok(Arc::new(TimestampArray::from(data))),
+        DataType::Interval(_) => Ok(Arc::new(IntervalArray::from(data))),
+        DataType::List(_) => Ok(Arc::new(ListArray::from(data))),
+        DataType::Struct(_) => Ok(Arc::new(StructArray::from(data))),
         other => Err(ExecutionError::ExecutionError(format!(
             "filter not supported for {:?}",
             other
         ))),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::builder::*;
+    use arrow::datatypes::Field;
+
+    fn bools(values: Vec<Option<bool>>) -> BooleanArray {
+        let mut builder = BooleanArray::builder(values.len());
+        for v in values {
+            match v {
+                Some(b) => builder.append_value(b).unwrap(),
+                None => builder.append_null().unwrap(),
+            }
+        }
+        builder.finish()
+    }
+
+    #[test]
+    fn filter_int32_with_nulls() {
+        let mut builder = Int32Array::builder(4);
+        builder.append_value(1).unwrap();
+        builder.append_null().unwrap();
+        builder.append_value(3).unwrap();
+        builder.append_value(4).unwrap();
+        let a: ArrayRef = Arc::new(builder.finish());
+
+        let f = bools(vec![Some(true), Some(true), None, Some(true)]);
+        let result = filter(&a, &f).unwrap();
+        let result = result.as_any().downcast_ref::<Int32Array>().unwrap();
+
+        assert_eq!(3, result.len());
+        assert_eq!(1, result.null_count());
+        assert_eq!(1, result.value(0));
+        assert!(result.is_null(1));
+        assert_eq!(4, result.value(2));
+    }
+
+    #[test]
+    fn filter_boolean() {
+        let a: ArrayRef = Arc::new(bools(vec![Some(true), None, Some(false), Some(true)]));
+        let f = bools(vec![Some(false), Some(true), Some(true), Some(true)]);
+        let result = filter(&a, &f).unwrap();
+        let result = result.as_any().downcast_ref::<BooleanArray>().unwrap();
+
+        assert_eq!(3, result.len());
+        assert!(result.is_null(0));
+        assert_eq!(false, result.value(1));
+        assert_eq!(true, result.value(2));
+    }
+
+    #[test]
+    fn filter_utf8() {
+        let mut builder = BinaryBuilder::new(3);
+        builder.append_string("one").unwrap();
+        builder.append_null().unwrap();
+        builder.append_string("three").unwrap();
+        let a: ArrayRef = Arc::new(builder.finish());
+
+        let f = bools(vec![Some(false), Some(true), Some(true)]);
+        let result = filter(&a, &f).unwrap();
+        let result = result.as_any().downcast_ref::<BinaryArray>().unwrap();
+
+        assert_eq!(2, result.len());
+        assert!(result.is_null(0));
+        assert_eq!("three", result.get_string(1));
+    }
+
+    #[test]
+    fn filter_list() {
+        let mut builder = ListBuilder::new(Int32Builder::new(6));
+        builder.values().append_value(1).unwrap();
+        builder.values().append_value(2).unwrap();
+        builder.append(true).unwrap();
+        builder.values().append_value(3).unwrap();
+        builder.append(true).unwrap();
+        builder.values().append_value(4).unwrap();
+        builder.values().append_value(5).unwrap();
+        builder.append(true).unwrap();
+        let a: ArrayRef = Arc::new(builder.finish());
+
+        let f = bools(vec![Some(true), Some(false), Some(true)]);
+        let result = filter(&a, &f).unwrap();
+        let result = result.as_any().downcast_ref::<ListArray>().unwrap();
+
+        assert_eq!(2, result.len());
+        assert_eq!(2, result.value_length(0));
+        assert_eq!(2, result.value_length(1));
+        let values = result.values();
+        let values = values.as_any().downcast_ref::<Int32Array>().unwrap();
+        assert_eq!(4, values.len());
+        assert_eq!(1, values.value(0));
+        assert_eq!(5, values.value(3));
+    }
+
+    #[test]
+    fn filter_struct() {
+        let a: ArrayRef = Arc::new(Int32Array::from(vec![1, 2, 3]));
+        let b: ArrayRef = Arc::new(Float64Array::from(vec![1.5, 2.5, 3.5]));
+        let s: ArrayRef = Arc::new(StructArray::from(vec![
+            (Field::new("a", DataType::Int32, false), a),
+            (Field::new("b", DataType::Float64, false), b),
+        ]));
+
+        let f = bools(vec![Some(false), Some(true), Some(true)]);
+        let result = filter(&s, &f).unwrap();
+        let result = result.as_any().downcast_ref::<StructArray>().unwrap();
+
+        assert_eq!(2, result.len());
+        let b = result.column(1);
+        let b = b.as_any().downcast_ref::<Float64Array>().unwrap();
+        assert_eq!(2.5, b.value(0));
+        assert_eq!(3.5, b.value(1));
+    }
+
+    #[test]
+    fn filter_sliced_struct() {
+        let a: ArrayRef = Arc::new(Int32Array::from(vec![1, 2, 3, 4]));
+        let fields = vec![Field::new("a", DataType::Int32, false)];
+        // a struct array over the last three values of its child
+        let data = ArrayData::builder(DataType::Struct(fields))
+            .len(3)
+            .offset(1)
+            .child_data(vec![a.data()])
+            .build();
+        let s: ArrayRef = Arc::new(StructArray::from(data));
+
+        let f = bools(vec![Some(true), Some(false), Some(true)]);
+        let result = filter(&s, &f).unwrap();
+        let result = result.as_any().downcast_ref::<StructArray>().unwrap();
+
+        let a = result.column(0);
+        let a = a.as_any().downcast_ref::<Int32Array>().unwrap();
+        assert_eq!(2, a.len());
+        assert_eq!(2, a.value(0));
+        assert_eq!(4, a.value(1));
+    }
+
+}
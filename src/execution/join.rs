@@ -0,0 +1,276 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Execution of joins, which combine the rows of two relations by hashing the values of their
+//! join keys
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+use std::sync::Arc;
+
+use arrow::array::ArrayRef;
+use arrow::datatypes::Schema;
+use arrow::record_batch::RecordBatch;
+
+use super::super::logicalplan::ScalarValue;
+use super::aggregate::{scalar_value, RowKey, RowKeyBuilder};
+use super::error::{ExecutionError, Result};
+use super::expression::RuntimeExpr;
+use super::filter::take;
+use super::relation::Relation;
+use super::window::array_from_scalars;
+
+/// The rows of the right input of a semi join that have the same join keys
+#[derive(Default)]
+struct Matches {
+    /// For a null-aware join, the keys of the values compared with the left value
+    values: HashSet<RowKey>,
+    /// For a null-aware join, whether any of the values is NULL
+    has_null: bool,
+}
+
+/// Keeps the rows of the left input that have a matching row in the right input, or for an
+/// anti join the rows that do not. The right input is read into a hash table before the first
+/// batch is returned.
+pub struct SemiJoinRelation {
+    schema: Arc<Schema>,
+    left: Rc<RefCell<Relation>>,
+    right: Rc<RefCell<Relation>>,
+    left_keys: Vec<RuntimeExpr>,
+    right_keys: Vec<RuntimeExpr>,
+    anti: bool,
+    null_aware: bool,
+    keys: RowKeyBuilder,
+    /// The rows of the right input by the keys that must be equal, once it has been read
+    right_rows: Option<HashMap<RowKey, Matches>>,
+}
+
+impl SemiJoinRelation {
+    pub fn new(
+        left: Rc<RefCell<Relation>>,
+        right: Rc<RefCell<Relation>>,
+        left_keys: Vec<RuntimeExpr>,
+        right_keys: Vec<RuntimeExpr>,
+        anti: bool,
+        null_aware: bool,
+    ) -> Self {
+        let schema = left.borrow().schema().clone();
+        Self {
+            schema,
+            left,
+            right,
+            left_keys,
+            right_keys,
+            anti,
+            null_aware,
+            keys: RowKeyBuilder::new(),
+            right_rows: None,
+        }
+    }
+
+    /// The number of keys that must be equal for rows to match, which excludes the value
+    /// compared by a null-aware join
+    fn num_equal_keys(&self) -> usize {
+        if self.null_aware {
+            self.left_keys.len() - 1
+        } else {
+            self.left_keys.len()
+        }
+    }
+
+    fn build(&mut self) -> Result<HashMap<RowKey, Matches>> {
+        let n = self.num_equal_keys();
+        let mut right_rows: HashMap<RowKey, Matches> = HashMap::new();
+        while let Some(batch) = self.right.borrow_mut().next()? {
+            let columns = evaluate(&self.right_keys, &batch)?;
+            for row in 0..batch.num_rows() {
+                // NULL is not equal to anything
+                if columns[..n].iter().any(|c| c.is_null(row)) {
+                    continue;
+                }
+                let key = self.keys.key(&columns[..n], row)?;
+                let matches = right_rows.entry(key).or_insert_with(Matches::default);
+                if self.null_aware {
+                    if columns[n].is_null(row) {
+                        matches.has_null = true;
+                    } else {
+                        matches.values.insert(self.keys.key(&columns[n..], row)?);
+                    }
+                }
+            }
+        }
+        Ok(right_rows)
+    }
+}
+
+/// Evaluate the join keys for the rows of a batch
+fn evaluate(keys: &[RuntimeExpr], batch: &RecordBatch) -> Result<Vec<ArrayRef>> {
+    keys.iter().map(|k| k.get_func()(batch)).collect()
+}
+
+impl Relation for SemiJoinRelation {
+    fn next(&mut self) -> Result<Option<RecordBatch>> {
+        if self.right_rows.is_none() {
+            self.right_rows = Some(self.build()?);
+        }
+        let batch = match self.left.borrow_mut().next()? {
+            Some(batch) => batch,
+            None => return Ok(None),
+        };
+
+        let n = self.num_equal_keys();
+        let right_rows = self.right_rows.as_ref().unwrap();
+        let columns = evaluate(&self.left_keys, &batch)?;
+        let mut rows = vec![];
+        for row in 0..batch.num_rows() {
+            let matched = if columns[..n].iter().any(|c| c.is_null(row)) {
+                false
+            } else {
+                match right_rows.get(&self.keys.key(&columns[..n], row)?) {
+                    // NOT IN is not true when the value is NULL or could be equal to a NULL
+                    Some(matches) if self.null_aware => {
+                        columns[n].is_null(row)
+                            || matches.has_null
+                            || matches.values.contains(&self.keys.key(&columns[n..], row)?)
+                    }
+                    Some(_) => true,
+                    None => false,
+                }
+            };
+            if matched != self.anti {
+                rows.push(row);
+            }
+        }
+
+        let columns = (0..batch.num_columns())
+            .map(|i| take(batch.column(i), &rows))
+            .collect::<Result<Vec<ArrayRef>>>()?;
+        Ok(Some(RecordBatch::new(self.schema.clone(), columns)))
+    }
+
+    fn schema(&self) -> &Arc<Schema> {
+        &self.schema
+    }
+}
+
+/// Appends the columns of the matching row of the right input to each row of the left input, as
+/// for a correlated scalar subquery. The right input is read into a hash table before the first
+/// batch is returned.
+pub struct SingleJoinRelation {
+    schema: Arc<Schema>,
+    left: Rc<RefCell<Relation>>,
+    right: Rc<RefCell<Relation>>,
+    left_keys: Vec<RuntimeExpr>,
+    right_keys: Vec<RuntimeExpr>,
+    keys: RowKeyBuilder,
+    /// The values of the rows of the right input by their keys, once it has been read
+    right_rows: Option<HashMap<RowKey, Vec<Vec<Option<ScalarValue>>>>>,
+}
+
+impl SingleJoinRelation {
+    pub fn new(
+        schema: Arc<Schema>,
+        left: Rc<RefCell<Relation>>,
+        right: Rc<RefCell<Relation>>,
+        left_keys: Vec<RuntimeExpr>,
+        right_keys: Vec<RuntimeExpr>,
+    ) -> Self {
+        Self {
+            schema,
+            left,
+            right,
+            left_keys,
+            right_keys,
+            keys: RowKeyBuilder::new(),
+            right_rows: None,
+        }
+    }
+
+    fn build(&mut self) -> Result<HashMap<RowKey, Vec<Vec<Option<ScalarValue>>>>> {
+        let mut right_rows: HashMap<RowKey, Vec<Vec<Option<ScalarValue>>>> = HashMap::new();
+        while let Some(batch) = self.right.borrow_mut().next()? {
+            let columns = evaluate(&self.right_keys, &batch)?;
+            for row in 0..batch.num_rows() {
+                // NULL is not equal to anything
+                if columns.iter().any(|c| c.is_null(row)) {
+                    continue;
+                }
+                let values = (0..batch.num_columns())
+                    .map(|i| scalar_value(batch.column(i), row))
+                    .collect::<Result<Vec<Option<ScalarValue>>>>()?;
+                right_rows
+                    .entry(self.keys.key(&columns, row)?)
+                    .or_insert_with(Vec::new)
+                    .push(values);
+            }
+        }
+        Ok(right_rows)
+    }
+}
+
+impl Relation for SingleJoinRelation {
+    fn next(&mut self) -> Result<Option<RecordBatch>> {
+        if self.right_rows.is_none() {
+            self.right_rows = Some(self.build()?);
+        }
+        let batch = match self.left.borrow_mut().next()? {
+            Some(batch) => batch,
+            None => return Ok(None),
+        };
+
+        let right_rows = self.right_rows.as_ref().unwrap();
+        let num_right_columns = self.schema.fields().len() - batch.num_columns();
+        let columns = evaluate(&self.left_keys, &batch)?;
+        let mut right_values: Vec<Vec<Option<ScalarValue>>> =
+            vec![Vec::with_capacity(batch.num_rows()); num_right_columns];
+        for row in 0..batch.num_rows() {
+            let matches = if columns.iter().any(|c| c.is_null(row)) {
+                None
+            } else {
+                right_rows.get(&self.keys.key(&columns, row)?)
+            };
+            match matches {
+                Some(matches) if matches.len() > 1 => {
+                    return Err(ExecutionError::ExecutionError(
+                        "Scalar subquery returned more than one row".to_string(),
+                    ));
+                }
+                Some(matches) => {
+                    for (values, value) in right_values.iter_mut().zip(matches[0].iter()) {
+                        values.push(value.clone());
+                    }
+                }
+                None => {
+                    for values in right_values.iter_mut() {
+                        values.push(None);
+                    }
+                }
+            }
+        }
+
+        let mut output = (0..batch.num_columns())
+            .map(|i| batch.column(i).clone())
+            .collect::<Vec<ArrayRef>>();
+        for (i, values) in right_values.iter().enumerate() {
+            let field = self.schema.field(batch.num_columns() + i);
+            output.push(array_from_scalars(values, field.data_type())?);
+        }
+        Ok(Some(RecordBatch::new(self.schema.clone(), output)))
+    }
+
+    fn schema(&self) -> &Arc<Schema> {
+        &self.schema
+    }
+}
@@ -18,7 +18,14 @@ pub mod datasource;
 pub mod error;
 pub mod expression;
 pub mod filter;
+pub mod join;
 pub mod physicalplan;
 pub mod projection;
+pub mod recursive;
 pub mod relation;
+pub mod set_operation;
+pub mod sketch;
+pub mod temporal;
+pub mod unnest;
 pub mod value;
+pub mod window;
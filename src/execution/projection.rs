@@ -86,14 +86,16 @@ mod tests {
             Field::new("id", DataType::Int32, false),
             Field::new("first_name", DataType::Utf8, false),
         ]));
-        let ds = CsvDataSource::new("test/data/people.csv", schema.clone(), 1024);
+        let ds = CsvDataSource::new("test/data/people.csv", schema.clone(), true, 1024).unwrap();
         let relation = Rc::new(RefCell::new(DataSourceRelation::new(Rc::new(
             RefCell::new(ds),
         ))));
         let context = ExecutionContext::new();
 
         let projection_expr =
-            vec![expression::compile_expr(&context, &Expr::Column(0), schema.as_ref()).unwrap()];
+            vec![
+                expression::compile_expr(&context, &Expr::Column(0), &[], schema.as_ref()).unwrap(),
+            ];
 
         let mut projection = ProjectRelation::new(relation, projection_expr, schema);
         let batch = projection.next().unwrap().unwrap();
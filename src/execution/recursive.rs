@@ -0,0 +1,165 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Execution of recursive common table expressions, which evaluate their recursive term
+//! repeatedly against the rows produced by the previous iteration
+
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use arrow::array::ArrayRef;
+use arrow::datatypes::Schema;
+use arrow::record_batch::RecordBatch;
+
+use super::aggregate::{RowKey, RowKeyBuilder};
+use super::context::ExecutionContext;
+use super::datasource::MemoryDataSource;
+use super::error::{ExecutionError, Result};
+use super::filter::take;
+use super::relation::Relation;
+use crate::logicalplan::LogicalPlan;
+
+/// The number of times the recursive term can be evaluated before the query fails, since a
+/// query that never stops producing new rows would otherwise never finish
+const MAX_ITERATIONS: usize = 10000;
+
+/// Returns the rows of the static term followed by the rows of each iteration of the recursive
+/// term. Each iteration reads the rows of the previous one as the working table.
+pub struct RecursiveQueryRelation {
+    schema: Arc<Schema>,
+    /// A context of its own, in which the working table is registered
+    ctx: ExecutionContext,
+    name: String,
+    static_term: Rc<RefCell<Relation>>,
+    recursive_term: Rc<LogicalPlan>,
+    distinct: bool,
+    /// The keys of the rows returned so far, when duplicates are discarded
+    keys: RowKeyBuilder,
+    seen: HashSet<RowKey>,
+    /// The relation of the current iteration, once the static term has been read
+    iteration: Option<Rc<RefCell<Relation>>>,
+    iterations: usize,
+    /// The rows returned by the current iteration, which are the working table of the next
+    working_table: Vec<RecordBatch>,
+}
+
+impl RecursiveQueryRelation {
+    pub fn new(
+        schema: Arc<Schema>,
+        ctx: ExecutionContext,
+        name: &str,
+        static_term: Rc<RefCell<Relation>>,
+        recursive_term: Rc<LogicalPlan>,
+        distinct: bool,
+    ) -> Self {
+        Self {
+            schema,
+            ctx,
+            name: name.to_string(),
+            static_term,
+            recursive_term,
+            distinct,
+            keys: RowKeyBuilder::new(),
+            seen: HashSet::new(),
+            iteration: None,
+            iterations: 0,
+            working_table: vec![],
+        }
+    }
+
+    /// Start the next iteration with the rows of the previous one, returning false when the
+    /// previous iteration produced no rows
+    fn next_iteration(&mut self) -> Result<bool> {
+        if self.working_table.is_empty() {
+            return Ok(false);
+        }
+        self.iterations += 1;
+        if self.iterations > MAX_ITERATIONS {
+            return Err(ExecutionError::ExecutionError(format!(
+                "Recursive query '{}' did not finish after {} iterations",
+                self.name, MAX_ITERATIONS
+            )));
+        }
+        let working_table = self.working_table.drain(..).collect();
+        self.ctx.register_datasource(
+            &self.name,
+            Rc::new(RefCell::new(MemoryDataSource::new(
+                self.schema.clone(),
+                working_table,
+            ))),
+        );
+        self.iteration = Some(self.ctx.execute(&self.recursive_term)?);
+        Ok(true)
+    }
+
+    /// Add the rows of a batch to the working table, dropping the rows that have already been
+    /// returned if duplicates are discarded. Returns the rows that were added.
+    fn add(&mut self, batch: RecordBatch) -> Result<Option<RecordBatch>> {
+        let mut columns = (0..batch.num_columns())
+            .map(|i| batch.column(i).clone())
+            .collect::<Vec<ArrayRef>>();
+        let mut num_rows = batch.num_rows();
+        if self.distinct {
+            let mut rows = vec![];
+            for row in 0..batch.num_rows() {
+                if self.seen.insert(self.keys.key(&columns, row)?) {
+                    rows.push(row);
+                }
+            }
+            if rows.len() < num_rows {
+                num_rows = rows.len();
+                columns = columns
+                    .iter()
+                    .map(|c| take(c, &rows))
+                    .collect::<Result<Vec<ArrayRef>>>()?;
+            }
+        }
+        if num_rows == 0 {
+            return Ok(None);
+        }
+        self.working_table
+            .push(RecordBatch::new(self.schema.clone(), columns.clone()));
+        Ok(Some(RecordBatch::new(self.schema.clone(), columns)))
+    }
+}
+
+impl Relation for RecursiveQueryRelation {
+    fn next(&mut self) -> Result<Option<RecordBatch>> {
+        loop {
+            let input = match self.iteration {
+                Some(ref iteration) => iteration.clone(),
+                None => self.static_term.clone(),
+            };
+            let batch = input.borrow_mut().next()?;
+            match batch {
+                Some(batch) => {
+                    if let Some(batch) = self.add(batch)? {
+                        return Ok(Some(batch));
+                    }
+                }
+                None => {
+                    if !self.next_iteration()? {
+                        return Ok(None);
+                    }
+                }
+            }
+        }
+    }
+
+    fn schema(&self) -> &Arc<Schema> {
+        &self.schema
+    }
+}
@@ -0,0 +1,207 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Execution of set operations, which combine the rows of two relations with the same columns
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+use std::sync::Arc;
+
+use arrow::array::ArrayRef;
+use arrow::datatypes::Schema;
+use arrow::record_batch::RecordBatch;
+
+use super::super::logicalplan::SetOperator;
+use super::aggregate::{RowKey, RowKeyBuilder};
+use super::error::Result;
+use super::filter::take;
+use super::relation::Relation;
+
+/// Returns the rows of the left input followed by the rows of the right input, as for UNION ALL
+pub struct UnionAllRelation {
+    schema: Arc<Schema>,
+    left: Rc<RefCell<Relation>>,
+    right: Rc<RefCell<Relation>>,
+    /// Whether all of the rows of the left input have been returned
+    left_done: bool,
+}
+
+impl UnionAllRelation {
+    pub fn new(
+        schema: Arc<Schema>,
+        left: Rc<RefCell<Relation>>,
+        right: Rc<RefCell<Relation>>,
+    ) -> Self {
+        Self {
+            schema,
+            left,
+            right,
+            left_done: false,
+        }
+    }
+}
+
+impl Relation for UnionAllRelation {
+    fn next(&mut self) -> Result<Option<RecordBatch>> {
+        let batch = if self.left_done {
+            self.right.borrow_mut().next()?
+        } else {
+            match self.left.borrow_mut().next()? {
+                Some(batch) => Some(batch),
+                None => {
+                    self.left_done = true;
+                    self.right.borrow_mut().next()?
+                }
+            }
+        };
+        // the columns of the inputs can differ in whether they are nullable
+        Ok(batch.map(|batch| RecordBatch::new(self.schema.clone(), batch_columns(&batch))))
+    }
+
+    fn schema(&self) -> &Arc<Schema> {
+        &self.schema
+    }
+}
+
+/// Returns the distinct rows of either input (UNION), or the rows of the left input that are
+/// (INTERSECT) or are not (EXCEPT) in the right input. For INTERSECT and EXCEPT the right input
+/// is read into a hash table before the first batch is returned.
+pub struct SetOperationRelation {
+    schema: Arc<Schema>,
+    op: SetOperator,
+    all: bool,
+    left: Rc<RefCell<Relation>>,
+    right: Rc<RefCell<Relation>>,
+    keys: RowKeyBuilder,
+    /// The number of times each row appears in the right input, once it has been read
+    right_rows: Option<HashMap<RowKey, usize>>,
+    /// The keys of the rows returned so far, when duplicates are discarded
+    seen: HashSet<RowKey>,
+    /// Whether all of the rows of the left input have been read, for UNION
+    left_done: bool,
+}
+
+impl SetOperationRelation {
+    pub fn new(
+        schema: Arc<Schema>,
+        op: SetOperator,
+        all: bool,
+        left: Rc<RefCell<Relation>>,
+        right: Rc<RefCell<Relation>>,
+    ) -> Self {
+        Self {
+            schema,
+            op,
+            all,
+            left,
+            right,
+            keys: RowKeyBuilder::new(),
+            right_rows: None,
+            seen: HashSet::new(),
+            left_done: false,
+        }
+    }
+
+    fn build(&mut self) -> Result<HashMap<RowKey, usize>> {
+        let mut right_rows: HashMap<RowKey, usize> = HashMap::new();
+        while let Some(batch) = self.right.borrow_mut().next()? {
+            let columns = batch_columns(&batch);
+            for row in 0..batch.num_rows() {
+                *right_rows.entry(self.keys.key(&columns, row)?).or_insert(0) += 1;
+            }
+        }
+        Ok(right_rows)
+    }
+
+    /// Get the next batch of the input rows, which for UNION are the rows of both inputs
+    fn next_input(&mut self) -> Result<Option<RecordBatch>> {
+        if self.op != SetOperator::Union {
+            return self.left.borrow_mut().next();
+        }
+        if !self.left_done {
+            match self.left.borrow_mut().next()? {
+                Some(batch) => return Ok(Some(batch)),
+                None => self.left_done = true,
+            }
+        }
+        self.right.borrow_mut().next()
+    }
+
+    /// Whether a row of the input is returned
+    fn keep(&mut self, key: RowKey) -> bool {
+        let right_rows = self.right_rows.as_mut().unwrap();
+        match self.op {
+            SetOperator::Union => self.seen.insert(key),
+            // each row of the right input matches one row of the left input
+            SetOperator::Intersect if self.all => match right_rows.get_mut(&key) {
+                Some(n) if *n > 0 => {
+                    *n -= 1;
+                    true
+                }
+                _ => false,
+            },
+            SetOperator::Except if self.all => match right_rows.get_mut(&key) {
+                Some(n) if *n > 0 => {
+                    *n -= 1;
+                    false
+                }
+                _ => true,
+            },
+            SetOperator::Intersect => right_rows.contains_key(&key) && self.seen.insert(key),
+            SetOperator::Except => !right_rows.contains_key(&key) && self.seen.insert(key),
+        }
+    }
+}
+
+/// The columns of a batch
+fn batch_columns(batch: &RecordBatch) -> Vec<ArrayRef> {
+    (0..batch.num_columns())
+        .map(|i| batch.column(i).clone())
+        .collect()
+}
+
+impl Relation for SetOperationRelation {
+    fn next(&mut self) -> Result<Option<RecordBatch>> {
+        if self.right_rows.is_none() {
+            self.right_rows = Some(match self.op {
+                SetOperator::Union => HashMap::new(),
+                _ => self.build()?,
+            });
+        }
+        let batch = match self.next_input()? {
+            Some(batch) => batch,
+            None => return Ok(None),
+        };
+
+        let columns = batch_columns(&batch);
+        let mut rows = vec![];
+        for row in 0..batch.num_rows() {
+            let key = self.keys.key(&columns, row)?;
+            if self.keep(key) {
+                rows.push(row);
+            }
+        }
+
+        let columns = columns
+            .iter()
+            .map(|c| take(c, &rows))
+            .collect::<Result<Vec<ArrayRef>>>()?;
+        Ok(Some(RecordBatch::new(self.schema.clone(), columns)))
+    }
+
+    fn schema(&self) -> &Arc<Schema> {
+        &self.schema
+    }
+}
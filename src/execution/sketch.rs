@@ -0,0 +1,293 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Mergeable sketches used by the approximate aggregate functions. Each sketch can be merged
+//! with another sketch of the same kind, so partial results computed over separate partitions
+//! can be combined.
+
+use std::f64::consts::PI;
+
+/// Number of bits of the hash used to choose a HyperLogLog register
+const HLL_PRECISION: u32 = 14;
+
+/// Number of HyperLogLog registers (standard error is about 1.04 / sqrt(registers), or 0.8%)
+const HLL_REGISTERS: usize = 1 << HLL_PRECISION;
+
+/// HyperLogLog sketch for estimating the number of distinct values. Values are added as 64 bit
+/// hashes, which must be computed the same way for every sketch that is merged.
+#[derive(Debug, Clone)]
+pub struct HyperLogLog {
+    registers: Vec<u8>,
+}
+
+impl HyperLogLog {
+    pub fn new() -> Self {
+        HyperLogLog {
+            registers: vec![0; HLL_REGISTERS],
+        }
+    }
+
+    /// Add the hash of a value
+    pub fn add_hash(&mut self, hash: u64) {
+        let index = (hash >> (64 - HLL_PRECISION)) as usize;
+        // the marker bit limits the rank when the remaining bits are all zero
+        let remaining = (hash << HLL_PRECISION) | (1 << (HLL_PRECISION - 1));
+        let rank = remaining.leading_zeros() as u8 + 1;
+        if rank > self.registers[index] {
+            self.registers[index] = rank;
+        }
+    }
+
+    /// Merge another sketch into this one
+    pub fn merge(&mut self, other: &HyperLogLog) {
+        for (a, b) in self.registers.iter_mut().zip(&other.registers) {
+            if *b > *a {
+                *a = *b;
+            }
+        }
+    }
+
+    /// Estimate the number of distinct values that have been added
+    pub fn count(&self) -> u64 {
+        let m = HLL_REGISTERS as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / m);
+        let sum: f64 = self.registers.iter().map(|&r| 2f64.powi(-(r as i32))).sum();
+        let estimate = alpha * m * m / sum;
+        let zeros = self.registers.iter().filter(|&&r| r == 0).count();
+        if estimate <= 2.5 * m && zeros > 0 {
+            // linear counting is more accurate for small cardinalities
+            (m * (m / zeros as f64).ln()).round() as u64
+        } else {
+            estimate.round() as u64
+        }
+    }
+}
+
+/// Compression of a t-digest, which bounds the number of centroids it keeps
+const TDIGEST_COMPRESSION: f64 = 100.0;
+
+/// Number of unmerged values a t-digest buffers before compressing
+const TDIGEST_BUFFER_SIZE: usize = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Centroid {
+    mean: f64,
+    weight: f64,
+}
+
+/// t-digest sketch for estimating quantiles. Values are buffered and periodically merged into
+/// centroids that are small near the extremes and large near the median, which keeps the
+/// estimates of extreme quantiles accurate.
+#[derive(Debug, Clone)]
+pub struct TDigest {
+    centroids: Vec<Centroid>,
+    buffer: Vec<Centroid>,
+    min: f64,
+    max: f64,
+}
+
+impl TDigest {
+    pub fn new() -> Self {
+        TDigest {
+            centroids: vec![],
+            buffer: vec![],
+            min: std::f64::INFINITY,
+            max: std::f64::NEG_INFINITY,
+        }
+    }
+
+    /// Add a value. NaN values are ignored.
+    pub fn add(&mut self, value: f64) {
+        if value.is_nan() {
+            return;
+        }
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.buffer.push(Centroid {
+            mean: value,
+            weight: 1.0,
+        });
+        if self.buffer.len() >= TDIGEST_BUFFER_SIZE {
+            self.compress();
+        }
+    }
+
+    /// Merge another sketch into this one
+    pub fn merge(&mut self, other: &TDigest) {
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.buffer
+            .extend(other.centroids.iter().chain(&other.buffer));
+        if self.buffer.len() >= TDIGEST_BUFFER_SIZE {
+            self.compress();
+        }
+    }
+
+    /// Merge the buffered values into the centroids
+    fn compress(&mut self) {
+        self.centroids = self.compressed();
+        self.buffer.clear();
+    }
+
+    /// Get the centroids that result from merging the buffered values into the centroids
+    fn compressed(&self) -> Vec<Centroid> {
+        let mut all: Vec<Centroid> = self.centroids.iter().chain(&self.buffer).cloned().collect();
+        if all.is_empty() {
+            return all;
+        }
+        all.sort_by(|a, b| a.mean.partial_cmp(&b.mean).unwrap());
+
+        let total: f64 = all.iter().map(|c| c.weight).sum();
+        // scale function that limits the size of each centroid by its quantile
+        let k = |q: f64| TDIGEST_COMPRESSION / (2.0 * PI) * (2.0 * q - 1.0).asin();
+
+        let mut result = Vec::with_capacity(TDIGEST_COMPRESSION as usize);
+        let mut current = all[0];
+        let mut weight_before = 0.0;
+        for c in &all[1..] {
+            let q_left = weight_before / total;
+            let q_right = (weight_before + current.weight + c.weight) / total;
+            if k(q_right.min(1.0)) - k(q_left) <= 1.0 {
+                let weight = current.weight + c.weight;
+                current.mean += (c.mean - current.mean) * c.weight / weight;
+                current.weight = weight;
+            } else {
+                weight_before += current.weight;
+                result.push(current);
+                current = *c;
+            }
+        }
+        result.push(current);
+        result
+    }
+
+    /// Estimate the value at the given quantile (between 0 and 1) by interpolating between the
+    /// centroids, or `None` if no values have been added
+    pub fn quantile(&self, q: f64) -> Option<f64> {
+        let centroids = self.compressed();
+        if centroids.is_empty() {
+            return None;
+        }
+        let total: f64 = centroids.iter().map(|c| c.weight).sum();
+        let rank = q * total;
+
+        // each centroid is treated as a point at the middle of its weight, with the minimum
+        // and maximum values at either end
+        let mut previous_rank = 0.0;
+        let mut previous_value = self.min;
+        let mut weight_before = 0.0;
+        for c in &centroids {
+            let center = weight_before + c.weight / 2.0;
+            if rank < center {
+                return Some(interpolate(
+                    previous_rank,
+                    previous_value,
+                    center,
+                    c.mean,
+                    rank,
+                ));
+            }
+            previous_rank = center;
+            previous_value = c.mean;
+            weight_before += c.weight;
+        }
+        Some(interpolate(
+            previous_rank,
+            previous_value,
+            total,
+            self.max,
+            rank,
+        ))
+    }
+}
+
+/// Linear interpolation between the points `(x0, y0)` and `(x1, y1)`
+fn interpolate(x0: f64, y0: f64, x1: f64, y1: f64, x: f64) -> f64 {
+    if x1 <= x0 {
+        y1
+    } else {
+        y0 + (y1 - y0) * (x - x0) / (x1 - x0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+
+    fn hash(n: u64) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        n.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn hyperloglog_small_cardinality_is_exact() {
+        let mut hll = HyperLogLog::new();
+        for n in &[1, 2, 3, 2, 1, 5] {
+            hll.add_hash(hash(*n));
+        }
+        assert_eq!(4, hll.count());
+        assert_eq!(0, HyperLogLog::new().count());
+    }
+
+    #[test]
+    fn hyperloglog_estimate_and_merge() {
+        let mut a = HyperLogLog::new();
+        let mut b = HyperLogLog::new();
+        for n in 0..100_000 {
+            a.add_hash(hash(n));
+            // overlaps with the first half of `a`
+            b.add_hash(hash(n + 50_000));
+        }
+        let error = |estimate: u64, actual: f64| (estimate as f64 - actual).abs() / actual;
+        assert!(error(a.count(), 100_000.0) < 0.03, "{}", a.count());
+        a.merge(&b);
+        assert!(error(a.count(), 150_000.0) < 0.03, "{}", a.count());
+    }
+
+    #[test]
+    fn tdigest_small_input_is_exact() {
+        let mut digest = TDigest::new();
+        for n in &[4.0, 1.0, 3.0, 2.0] {
+            digest.add(*n);
+        }
+        assert_eq!(Some(1.0), digest.quantile(0.0));
+        assert_eq!(Some(2.5), digest.quantile(0.5));
+        assert_eq!(Some(4.0), digest.quantile(1.0));
+        assert_eq!(None, TDigest::new().quantile(0.5));
+    }
+
+    #[test]
+    fn tdigest_estimate_and_merge() {
+        let mut a = TDigest::new();
+        let mut b = TDigest::new();
+        for n in 0..10_000 {
+            a.add(n as f64);
+            b.add((n + 10_000) as f64);
+        }
+        let median = a.quantile(0.5).unwrap();
+        assert!((median - 5_000.0).abs() < 50.0, "{}", median);
+        let p99 = a.quantile(0.99).unwrap();
+        assert!((p99 - 9_900.0).abs() < 20.0, "{}", p99);
+        assert_eq!(Some(0.0), a.quantile(0.0));
+        assert_eq!(Some(9_999.0), a.quantile(1.0));
+
+        a.merge(&b);
+        let median = a.quantile(0.5).unwrap();
+        assert!((median - 10_000.0).abs() < 100.0, "{}", median);
+        assert_eq!(Some(19_999.0), a.quantile(1.0));
+    }
+}
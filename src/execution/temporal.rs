@@ -0,0 +1,582 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Support for dates, times, timestamps and intervals.
+//!
+//! Dates are days since the UNIX epoch, times are microseconds since midnight and timestamps
+//! are microseconds since the UNIX epoch, in UTC. Intervals only exist as literal values and
+//! are a number of microseconds.
+//!
+//! Arrow 0.12 has no array types for temporal data, so dates are stored in `Int32Array`s and
+//! times, timestamps and intervals in `Int64Array`s whose array data has the temporal data type.
+
+use std::sync::Arc;
+
+use arrow::array::*;
+use arrow::array_data::ArrayData;
+use arrow::buffer::Buffer;
+use arrow::builder::BinaryBuilder;
+use arrow::datatypes::{DataType, DateUnit, IntervalUnit, TimeUnit};
+use arrow::util::bit_util;
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, Timelike, Utc};
+
+use super::super::logicalplan::Operator;
+use super::error::{ExecutionError, Result};
+
+pub const MICROS_PER_SECOND: i64 = 1_000_000;
+pub const MICROS_PER_DAY: i64 = 86_400 * MICROS_PER_SECOND;
+
+/// The data type used for dates
+pub const DATE_TYPE: DataType = DataType::Date(DateUnit::Day);
+
+/// The data type used for times of day
+pub const TIME_TYPE: DataType = DataType::Time64(TimeUnit::Microsecond);
+
+/// The data type used for timestamps
+pub const TIMESTAMP_TYPE: DataType = DataType::Timestamp(TimeUnit::Microsecond);
+
+/// The data type of interval literals
+pub const INTERVAL_TYPE: DataType = DataType::Interval(IntervalUnit::DayTime);
+
+/// The array type that stores dates
+pub type DateArray = Int32Array;
+
+/// The array type that stores times of day
+pub type TimeArray = Int64Array;
+
+/// The array type that stores timestamps
+pub type TimestampArray = Int64Array;
+
+/// The array type that stores intervals
+pub type IntervalArray = Int64Array;
+
+/// Gives an integer array the data type of a date, time, timestamp or interval, sharing its
+/// values. The data type must have the same width as the integers.
+pub fn with_data_type(array: ArrayRef, data_type: &DataType) -> ArrayRef {
+    if array.data_type() == data_type {
+        return array;
+    }
+    let data = array.data();
+    let mut builder = ArrayData::builder(data_type.clone())
+        .len(data.len())
+        .offset(data.offset())
+        .buffers(data.buffers().to_vec());
+    if data.null_count() > 0 {
+        let end = data.offset() + data.len();
+        let mut bitmap = vec![0u8; bit_util::ceil(end, 8)];
+        for i in data.offset()..end {
+            if data.is_valid(i) {
+                bit_util::set_bit(&mut bitmap, i);
+            }
+        }
+        builder = builder
+            .null_count(data.null_count())
+            .null_bit_buffer(Buffer::from(bitmap));
+    }
+    match data_type {
+        DataType::Date(_) | DataType::Int32 => Arc::new(Int32Array::from(builder.build())),
+        _ => Arc::new(Int64Array::from(builder.build())),
+    }
+}
+
+fn epoch() -> NaiveDate {
+    NaiveDate::from_ymd(1970, 1, 1)
+}
+
+/// Parse a date in the form `YYYY-MM-DD` into the number of days since the UNIX epoch
+pub fn parse_date(s: &str) -> Option<i32> {
+    NaiveDate::parse_from_str(s.trim(), "%Y-%m-%d")
+        .ok()
+        .map(|d| (d - epoch()).num_days() as i32)
+}
+
+/// Parse a time in the form `HH:MM:SS[.ffffff]` into the number of microseconds since midnight
+pub fn parse_time(s: &str) -> Option<i64> {
+    NaiveTime::parse_from_str(s.trim(), "%H:%M:%S%.f")
+        .ok()
+        .map(|t| {
+            t.num_seconds_from_midnight() as i64 * MICROS_PER_SECOND
+                + (t.nanosecond() / 1000) as i64
+        })
+}
+
+/// Parse a timestamp into the number of microseconds since the UNIX epoch. The date and time
+/// may be separated by a space or `T`, and an optional time zone offset (or `Z`) converts the
+/// value to UTC. A date on its own is parsed as midnight.
+pub fn parse_timestamp(s: &str) -> Option<i64> {
+    let s = s.trim();
+    if let Ok(dt) = DateTime::parse_from_rfc3339(s) {
+        return Some(to_micros(&dt.naive_utc()));
+    }
+    for format in &["%Y-%m-%d %H:%M:%S%.f%:z", "%Y-%m-%d %H:%M:%S%.f%z"] {
+        if let Ok(dt) = DateTime::parse_from_str(s, format) {
+            return Some(to_micros(&dt.naive_utc()));
+        }
+    }
+    for format in &["%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M:%S%.f"] {
+        if let Ok(dt) = NaiveDateTime::parse_from_str(s, format) {
+            return Some(to_micros(&dt));
+        }
+    }
+    parse_date(s).map(|d| d as i64 * MICROS_PER_DAY)
+}
+
+/// Parse an interval such as `1 day 2 hours` into a number of microseconds. Months and years
+/// are not supported because they do not have a fixed length.
+pub fn parse_interval(s: &str) -> Result<i64> {
+    let parts: Vec<&str> = s.split_whitespace().collect();
+    if parts.is_empty() || parts.len() % 2 != 0 {
+        return Err(invalid_interval(s));
+    }
+    let mut micros = 0;
+    for pair in parts.chunks(2) {
+        let n = pair[0].parse::<i64>().map_err(|_| invalid_interval(s))?;
+        let unit = match pair[1].to_lowercase().trim_end_matches('s') {
+            "week" => 7 * MICROS_PER_DAY,
+            "day" => MICROS_PER_DAY,
+            "hour" => 3600 * MICROS_PER_SECOND,
+            "minute" => 60 * MICROS_PER_SECOND,
+            "second" => MICROS_PER_SECOND,
+            "millisecond" => 1000,
+            "microsecond" => 1,
+            _ => return Err(invalid_interval(s)),
+        };
+        micros = n
+            .checked_mul(unit)
+            .and_then(|n| n.checked_add(micros))
+            .ok_or_else(|| invalid_interval(s))?;
+    }
+    Ok(micros)
+}
+
+fn invalid_interval(s: &str) -> ExecutionError {
+    ExecutionError::General(format!("Invalid interval '{}'", s))
+}
+
+fn to_micros(dt: &NaiveDateTime) -> i64 {
+    dt.timestamp() * MICROS_PER_SECOND + dt.timestamp_subsec_micros() as i64
+}
+
+fn from_micros(micros: i64) -> NaiveDateTime {
+    // round towards negative infinity so that the sub-second part is never negative
+    let seconds = if micros >= 0 {
+        micros / MICROS_PER_SECOND
+    } else {
+        (micros - (MICROS_PER_SECOND - 1)) / MICROS_PER_SECOND
+    };
+    let sub_micros = micros - seconds * MICROS_PER_SECOND;
+    NaiveDateTime::from_timestamp(seconds, (sub_micros * 1000) as u32)
+}
+
+pub fn format_date(days: i32) -> String {
+    (epoch() + Duration::days(days as i64))
+        .format("%Y-%m-%d")
+        .to_string()
+}
+
+pub fn format_time(micros: i64) -> String {
+    from_micros(micros).format("%H:%M:%S%.f").to_string()
+}
+
+pub fn format_timestamp(micros: i64) -> String {
+    from_micros(micros)
+        .format("%Y-%m-%d %H:%M:%S%.f")
+        .to_string()
+}
+
+/// The current time in microseconds since the UNIX epoch
+pub fn now() -> i64 {
+    to_micros(&Utc::now().naive_utc())
+}
+
+/// Truncate a timestamp to the start of the given unit (year, quarter, month, week, day, hour,
+/// minute or second)
+pub fn date_trunc(unit: &str, micros: i64) -> Result<i64> {
+    let dt = from_micros(micros);
+    let date = dt.date();
+    let truncated = match unit.to_lowercase().as_ref() {
+        "year" => NaiveDate::from_ymd(date.year(), 1, 1).and_hms(0, 0, 0),
+        "quarter" => {
+            NaiveDate::from_ymd(date.year(), (date.month() - 1) / 3 * 3 + 1, 1).and_hms(0, 0, 0)
+        }
+        "month" => NaiveDate::from_ymd(date.year(), date.month(), 1).and_hms(0, 0, 0),
+        "week" => {
+            (date - Duration::days(date.weekday().num_days_from_monday() as i64)).and_hms(0, 0, 0)
+        }
+        "day" => date.and_hms(0, 0, 0),
+        "hour" => date.and_hms(dt.hour(), 0, 0),
+        "minute" => date.and_hms(dt.hour(), dt.minute(), 0),
+        "second" => date.and_hms(dt.hour(), dt.minute(), dt.second()),
+        other => {
+            return Err(ExecutionError::General(format!(
+                "Unsupported date_trunc unit '{}'",
+                other
+            )));
+        }
+    };
+    Ok(to_micros(&truncated))
+}
+
+/// Extract a field (year, quarter, month, week, day, dow, doy, hour, minute, second or epoch)
+/// from a timestamp
+pub fn date_part(field: &str, micros: i64) -> Result<i64> {
+    let dt = from_micros(micros);
+    let value = match field.to_lowercase().as_ref() {
+        "year" => dt.year() as i64,
+        "quarter" => ((dt.month() - 1) / 3 + 1) as i64,
+        "month" => dt.month() as i64,
+        "week" => dt.iso_week().week() as i64,
+        "day" => dt.day() as i64,
+        "dow" => dt.weekday().num_days_from_sunday() as i64,
+        "doy" => dt.ordinal() as i64,
+        "hour" => dt.hour() as i64,
+        "minute" => dt.minute() as i64,
+        "second" => dt.second() as i64,
+        "epoch" => dt.timestamp(),
+        other => {
+            return Err(ExecutionError::General(format!(
+                "Unsupported date_part field '{}'",
+                other
+            )));
+        }
+    };
+    Ok(value)
+}
+
+/// Applies a function to each non-null value of a primitive array
+macro_rules! map_array {
+    ($ARRAY:expr, $FROM_TYPE:ident, $TO_TYPE:ident, $DATA_TYPE:expr, $F:expr) => {{
+        let f = $F;
+        let a = $ARRAY.as_any().downcast_ref::<$FROM_TYPE>().unwrap();
+        let mut builder = $TO_TYPE::builder(a.len());
+        for i in 0..a.len() {
+            if a.is_null(i) {
+                builder.append_null()?;
+            } else {
+                builder.append_value(f(a.value(i))?)?;
+            }
+        }
+        Ok(with_data_type(Arc::new(builder.finish()), &$DATA_TYPE))
+    }};
+}
+
+/// Formats each non-null value of a primitive array as a string
+macro_rules! format_array {
+    ($ARRAY:expr, $FROM_TYPE:ident, $F:expr) => {{
+        let a = $ARRAY.as_any().downcast_ref::<$FROM_TYPE>().unwrap();
+        let mut builder = BinaryBuilder::new(a.len());
+        for i in 0..a.len() {
+            if a.is_null(i) {
+                builder.append_null()?;
+            } else {
+                builder.append_string(&$F(a.value(i)))?;
+            }
+        }
+        Ok(Arc::new(builder.finish()) as ArrayRef)
+    }};
+}
+
+/// Parses a string array, treating empty strings as NULL
+macro_rules! parse_array {
+    ($ARRAY:expr, $TO_TYPE:ident, $DATA_TYPE:expr, $F:expr, $TYPE_NAME:expr) => {{
+        let a = $ARRAY.as_any().downcast_ref::<BinaryArray>().unwrap();
+        let mut builder = $TO_TYPE::builder(a.len());
+        for i in 0..a.len() {
+            let s = a.get_string(i);
+            if a.is_null(i) || s.is_empty() {
+                builder.append_null()?;
+            } else {
+                match $F(&s) {
+                    Some(v) => builder.append_value(v)?,
+                    None => {
+                        return Err(ExecutionError::ExecutionError(format!(
+                            "Cannot parse '{}' as {}",
+                            s, $TYPE_NAME
+                        )));
+                    }
+                }
+            }
+        }
+        Ok(with_data_type(Arc::new(builder.finish()), &$DATA_TYPE))
+    }};
+}
+
+/// Casts to or from a date, time or timestamp. Strings are parsed and formatted, and dates
+/// and timestamps can be converted to each other.
+pub fn cast(array: &ArrayRef, to_type: &DataType) -> Result<ArrayRef> {
+    match (array.data_type(), to_type) {
+        (DataType::Utf8, DataType::Date(DateUnit::Day)) => {
+            parse_array!(array, DateArray, DATE_TYPE, parse_date, "DATE")
+        }
+        (DataType::Utf8, DataType::Time64(TimeUnit::Microsecond)) => {
+            parse_array!(array, TimeArray, TIME_TYPE, parse_time, "TIME")
+        }
+        (DataType::Utf8, DataType::Timestamp(TimeUnit::Microsecond)) => parse_array!(
+            array,
+            TimestampArray,
+            TIMESTAMP_TYPE,
+            parse_timestamp,
+            "TIMESTAMP"
+        ),
+        (DataType::Date(DateUnit::Day), DataType::Utf8) => {
+            format_array!(array, DateArray, format_date)
+        }
+        (DataType::Time64(TimeUnit::Microsecond), DataType::Utf8) => {
+            format_array!(array, TimeArray, format_time)
+        }
+        (DataType::Timestamp(TimeUnit::Microsecond), DataType::Utf8) => {
+            format_array!(array, TimestampArray, format_timestamp)
+        }
+        (DataType::Date(DateUnit::Day), DataType::Timestamp(TimeUnit::Microsecond)) => map_array!(
+            array,
+            DateArray,
+            TimestampArray,
+            TIMESTAMP_TYPE,
+            |d: i32| -> Result<i64> { Ok(d as i64 * MICROS_PER_DAY) }
+        ),
+        (DataType::Timestamp(TimeUnit::Microsecond), DataType::Date(DateUnit::Day)) => map_array!(
+            array,
+            TimestampArray,
+            DateArray,
+            DATE_TYPE,
+            |t: i64| -> Result<i32> { Ok((from_micros(t).date() - epoch()).num_days() as i32) }
+        ),
+        (from, to) => Err(ExecutionError::NotImplemented(format!(
+            "CAST from {:?} to {:?}",
+            from, to
+        ))),
+    }
+}
+
+/// Gives the values of a date, time or timestamp array an integer data type so that they can
+/// be compared with the integer kernels
+pub fn to_integer_array(array: &ArrayRef) -> Result<ArrayRef> {
+    match array.data_type() {
+        DataType::Date(DateUnit::Day) => Ok(with_data_type(array.clone(), &DataType::Int32)),
+        DataType::Time64(TimeUnit::Microsecond) | DataType::Timestamp(TimeUnit::Microsecond) => {
+            Ok(with_data_type(array.clone(), &DataType::Int64))
+        }
+        other => Err(ExecutionError::NotImplemented(format!(
+            "Comparison of {:?} values",
+            other
+        ))),
+    }
+}
+
+/// Applies `date_trunc` to a timestamp array
+pub fn date_trunc_array(unit: &str, array: &ArrayRef) -> Result<ArrayRef> {
+    map_array!(
+        array,
+        TimestampArray,
+        TimestampArray,
+        TIMESTAMP_TYPE,
+        |t: i64| date_trunc(unit, t)
+    )
+}
+
+/// Applies `date_part` to a timestamp array
+pub fn date_part_array(field: &str, array: &ArrayRef) -> Result<ArrayRef> {
+    map_array!(
+        array,
+        TimestampArray,
+        Int64Array,
+        DataType::Int64,
+        |t: i64| date_part(field, t)
+    )
+}
+
+/// Applies a checked binary function to two primitive arrays, producing NULL where either input
+/// is NULL and an error where the function overflows
+macro_rules! date_binary_op {
+    ($LEFT:expr, $LEFT_TYPE:ident, $RIGHT:expr, $RIGHT_TYPE:ident, $TO_TYPE:ident, $DATA_TYPE:expr, $F:expr) => {{
+        let f = $F;
+        let l = $LEFT.as_any().downcast_ref::<$LEFT_TYPE>().unwrap();
+        let r = $RIGHT.as_any().downcast_ref::<$RIGHT_TYPE>().unwrap();
+        let mut builder = $TO_TYPE::builder(l.len());
+        for i in 0..l.len() {
+            if l.is_null(i) || r.is_null(i) {
+                builder.append_null()?;
+            } else {
+                match f(l.value(i), r.value(i)) {
+                    Some(v) => builder.append_value(v)?,
+                    None => {
+                        return Err(ExecutionError::ExecutionError(
+                            "Date arithmetic overflows".to_string(),
+                        ));
+                    }
+                }
+            }
+        }
+        Ok(with_data_type(Arc::new(builder.finish()), &$DATA_TYPE))
+    }};
+}
+
+/// Evaluates date and timestamp arithmetic. Intervals can be added to or subtracted from
+/// timestamps. A number of days can be added to or subtracted from a date, and subtracting two
+/// dates gives the number of days between them. Results that overflow are an error.
+pub fn temporal_arithmetic(left: &ArrayRef, op: &Operator, right: &ArrayRef) -> Result<ArrayRef> {
+    match (left.data_type(), op, right.data_type()) {
+        (DataType::Timestamp(_), Operator::Plus, DataType::Interval(_)) => date_binary_op!(
+            left,
+            TimestampArray,
+            right,
+            IntervalArray,
+            TimestampArray,
+            TIMESTAMP_TYPE,
+            i64::checked_add
+        ),
+        (DataType::Timestamp(_), Operator::Minus, DataType::Interval(_)) => date_binary_op!(
+            left,
+            TimestampArray,
+            right,
+            IntervalArray,
+            TimestampArray,
+            TIMESTAMP_TYPE,
+            i64::checked_sub
+        ),
+        (DataType::Interval(_), Operator::Plus, DataType::Timestamp(_)) => date_binary_op!(
+            left,
+            IntervalArray,
+            right,
+            TimestampArray,
+            TimestampArray,
+            TIMESTAMP_TYPE,
+            i64::checked_add
+        ),
+        (DataType::Date(_), Operator::Minus, DataType::Date(_)) => date_binary_op!(
+            left,
+            DateArray,
+            right,
+            DateArray,
+            Int32Array,
+            DataType::Int32,
+            i32::checked_sub
+        ),
+        (DataType::Date(_), Operator::Plus, DataType::Int32) => date_binary_op!(
+            left,
+            DateArray,
+            right,
+            Int32Array,
+            DateArray,
+            DATE_TYPE,
+            i32::checked_add
+        ),
+        (DataType::Date(_), Operator::Minus, DataType::Int32) => date_binary_op!(
+            left,
+            DateArray,
+            right,
+            Int32Array,
+            DateArray,
+            DATE_TYPE,
+            i32::checked_sub
+        ),
+        (DataType::Int32, Operator::Plus, DataType::Date(_)) => date_binary_op!(
+            left,
+            Int32Array,
+            right,
+            DateArray,
+            DateArray,
+            DATE_TYPE,
+            i32::checked_add
+        ),
+        (l, op, r) => Err(ExecutionError::ExecutionError(format!(
+            "Operator {:?} cannot be applied to types {:?} and {:?}",
+            op, l, r
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_format_dates() {
+        assert_eq!(Some(0), parse_date("1970-01-01"));
+        assert_eq!(Some(17897), parse_date("2019-01-01"));
+        assert_eq!(Some(-1), parse_date("1969-12-31"));
+        assert_eq!(None, parse_date("2019-02-30"));
+        assert_eq!("2019-01-01", format_date(17897));
+        assert_eq!("1969-12-31", format_date(-1));
+    }
+
+    #[test]
+    fn parse_and_format_times() {
+        assert_eq!(Some(3_723_000_000), parse_time("01:02:03"));
+        assert_eq!(Some(3_723_500_000), parse_time("01:02:03.5"));
+        assert_eq!(None, parse_time("25:00:00"));
+        assert_eq!("01:02:03.500", format_time(3_723_500_000));
+    }
+
+    #[test]
+    fn parse_and_format_timestamps() {
+        let expected = Some(17897 * MICROS_PER_DAY + 3600 * MICROS_PER_SECOND);
+        assert_eq!(expected, parse_timestamp("2019-01-01 01:00:00"));
+        assert_eq!(expected, parse_timestamp("2019-01-01T01:00:00"));
+        assert_eq!(expected, parse_timestamp("2019-01-01T03:00:00+02:00"));
+        assert_eq!(expected, parse_timestamp("2019-01-01 00:00:00-01:00"));
+        assert_eq!(expected, parse_timestamp("2019-01-01T01:00:00Z"));
+        assert_eq!(Some(17897 * MICROS_PER_DAY), parse_timestamp("2019-01-01"));
+        assert_eq!(None, parse_timestamp("yesterday"));
+        assert_eq!("1969-12-31 23:59:59.999999", format_timestamp(-1));
+    }
+
+    #[test]
+    fn parse_intervals() {
+        assert_eq!(
+            26 * 3600 * MICROS_PER_SECOND,
+            parse_interval("1 day 2 hours").unwrap()
+        );
+        assert_eq!(
+            90 * MICROS_PER_SECOND,
+            parse_interval("90 SECONDS").unwrap()
+        );
+        assert!(parse_interval("1 month").is_err());
+        assert!(parse_interval("day").is_err());
+    }
+
+    #[test]
+    fn truncate_timestamps() {
+        let ts = parse_timestamp("2019-08-14 13:45:30.25").unwrap();
+        let trunc = |unit| format_timestamp(date_trunc(unit, ts).unwrap());
+        assert_eq!("2019-01-01 00:00:00", trunc("year"));
+        assert_eq!("2019-07-01 00:00:00", trunc("quarter"));
+        assert_eq!("2019-08-01 00:00:00", trunc("MONTH"));
+        assert_eq!("2019-08-12 00:00:00", trunc("week"));
+        assert_eq!("2019-08-14 00:00:00", trunc("day"));
+        assert_eq!("2019-08-14 13:00:00", trunc("hour"));
+        assert_eq!("2019-08-14 13:45:00", trunc("minute"));
+        assert_eq!("2019-08-14 13:45:30", trunc("second"));
+        assert!(date_trunc("decade", ts).is_err());
+    }
+
+    #[test]
+    fn extract_date_parts() {
+        let ts = parse_timestamp("2019-08-14 13:45:30").unwrap();
+        let part = |field| date_part(field, ts).unwrap();
+        assert_eq!(2019, part("year"));
+        assert_eq!(3, part("quarter"));
+        assert_eq!(8, part("month"));
+        assert_eq!(33, part("week"));
+        assert_eq!(14, part("day"));
+        assert_eq!(3, part("dow"));
+        assert_eq!(226, part("doy"));
+        assert_eq!(13, part("hour"));
+        assert_eq!(45, part("minute"));
+        assert_eq!(30, part("second"));
+        assert_eq!(1_565_790_330, part("epoch"));
+        assert!(date_part("century", ts).is_err());
+    }
+
+}
@@ -0,0 +1,87 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Execution of UNNEST, which expands each element of a list into its own row
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use arrow::array::*;
+use arrow::datatypes::Schema;
+use arrow::record_batch::RecordBatch;
+
+use super::error::{ExecutionError, Result};
+use super::expression::RuntimeExpr;
+use super::filter::take;
+use super::relation::Relation;
+
+pub struct UnnestRelation {
+    schema: Arc<Schema>,
+    input: Rc<RefCell<Relation>>,
+    expr: RuntimeExpr,
+}
+
+impl UnnestRelation {
+    pub fn new(input: Rc<RefCell<Relation>>, expr: RuntimeExpr, schema: Arc<Schema>) -> Self {
+        Self {
+            schema,
+            input,
+            expr,
+        }
+    }
+}
+
+impl Relation for UnnestRelation {
+    fn next(&mut self) -> Result<Option<RecordBatch>> {
+        match self.input.borrow_mut().next()? {
+            Some(batch) => {
+                let lists = self.expr.get_func()(&batch)?;
+                let lists = match lists.as_any().downcast_ref::<ListArray>() {
+                    Some(lists) => lists,
+                    None => {
+                        return Err(ExecutionError::ExecutionError(format!(
+                            "Cannot unnest values of type {:?}",
+                            lists.data_type()
+                        )));
+                    }
+                };
+
+                // the input row and the list element that each output row is made from
+                let mut rows: Vec<usize> = vec![];
+                let mut elements: Vec<usize> = vec![];
+                for row in 0..lists.len() {
+                    if lists.is_valid(row) {
+                        let offset = lists.value_offset(row) as usize;
+                        for i in 0..lists.value_length(row) as usize {
+                            rows.push(row);
+                            elements.push(offset + i);
+                        }
+                    }
+                }
+
+                let mut columns = (0..batch.num_columns())
+                    .map(|i| take(batch.column(i), &rows))
+                    .collect::<Result<Vec<ArrayRef>>>()?;
+                columns.push(take(&lists.values(), &elements)?);
+                Ok(Some(RecordBatch::new(self.schema.clone(), columns)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn schema(&self) -> &Arc<Schema> {
+        &self.schema
+    }
+}
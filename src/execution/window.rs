@@ -0,0 +1,561 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Execution of window functions, which compute a value for each row from the other rows of
+//! its partition without collapsing them the way an aggregate does
+
+use std::cell::RefCell;
+use std::cmp::{min, Ordering};
+use std::rc::Rc;
+use std::sync::Arc;
+
+use arrow::array::*;
+use arrow::builder::BinaryBuilder;
+use arrow::datatypes::{DataType, Schema};
+use arrow::record_batch::RecordBatch;
+
+use super::aggregate::{compare_scalars, scalar_value, Accumulator};
+use super::context::ExecutionContext;
+use super::error::{ExecutionError, Result};
+use super::expression::{compile_expr, compile_scalar_expr, CompiledExpr, RuntimeExpr};
+use super::relation::Relation;
+use super::temporal::{with_data_type, DateArray, TimeArray, TimestampArray};
+use crate::logicalplan::{Expr, ScalarValue, WindowFrame, WindowFrameBound, WindowFrameUnits};
+
+/// The function that a window expression applies to each row of a partition
+enum WindowFunction {
+    RowNumber,
+    Rank,
+    DenseRank,
+    Ntile(usize),
+    Lag(usize),
+    Lead(usize),
+    FirstValue,
+    LastValue,
+    /// An aggregate function evaluated over the frame of each row
+    Aggregate(RuntimeExpr),
+}
+
+/// A compiled window expression
+pub struct WindowExpr {
+    function: WindowFunction,
+    args: Vec<CompiledExpr>,
+    partition_by: Vec<CompiledExpr>,
+    /// The sort expressions and whether each of them is ascending
+    order_by: Vec<(CompiledExpr, bool)>,
+    frame: WindowFrame,
+    data_type: DataType,
+}
+
+/// Get the value of a non-negative integer literal that is an argument to a window function
+fn integer_argument(name: &str, expr: &Expr) -> Result<usize> {
+    match expr {
+        Expr::Literal(ScalarValue::Int64(n)) if *n >= 0 => Ok(*n as usize),
+        other => Err(ExecutionError::General(format!(
+            "Invalid argument {:?} to window function {}",
+            other, name
+        ))),
+    }
+}
+
+/// Compiles a window function expression
+pub fn compile_window_expr(
+    ctx: &ExecutionContext,
+    expr: &Expr,
+    input_schema: &Schema,
+) -> Result<WindowExpr> {
+    match expr {
+        Expr::WindowFunction {
+            name,
+            args,
+            partition_by,
+            order_by,
+            frame,
+            return_type,
+        } => {
+            let compile = |e: &Expr| -> Result<CompiledExpr> {
+                Ok(compile_scalar_expr(ctx, e, input_schema)?.get_func())
+            };
+            let (function, args) = match name.to_lowercase().as_ref() {
+                "row_number" => (WindowFunction::RowNumber, vec![]),
+                "rank" => (WindowFunction::Rank, vec![]),
+                "dense_rank" => (WindowFunction::DenseRank, vec![]),
+                "ntile" => {
+                    let buckets = integer_argument(name, &args[0])?;
+                    if buckets == 0 {
+                        return Err(ExecutionError::General(
+                            "The number of NTILE buckets must be greater than zero".to_string(),
+                        ));
+                    }
+                    (WindowFunction::Ntile(buckets), vec![])
+                }
+                "lag" | "lead" => {
+                    let offset = match args.get(1) {
+                        Some(e) => integer_argument(name, e)?,
+                        None => 1,
+                    };
+                    // the value and the optional default used when the offset row does not exist
+                    let mut compiled = vec![compile(&args[0])?];
+                    if let Some(default) = args.get(2) {
+                        compiled.push(compile(default)?);
+                    }
+                    if name.to_lowercase() == "lag" {
+                        (WindowFunction::Lag(offset), compiled)
+                    } else {
+                        (WindowFunction::Lead(offset), compiled)
+                    }
+                }
+                "first_value" => (WindowFunction::FirstValue, vec![compile(&args[0])?]),
+                "last_value" => (WindowFunction::LastValue, vec![compile(&args[0])?]),
+                _ => {
+                    let aggregate = Expr::AggregateFunction {
+                        name: name.clone(),
+                        args: args.clone(),
+                        return_type: return_type.clone(),
+                        filter: None,
+                    };
+                    let aggregate = compile_expr(ctx, &aggregate, &[], input_schema)?;
+                    let args = match &aggregate {
+                        RuntimeExpr::AggregateFunction { args, .. } => args.clone(),
+                        _ => {
+                            return Err(ExecutionError::General(format!(
+                                "{} is not a window function",
+                                name
+                            )));
+                        }
+                    };
+                    (WindowFunction::Aggregate(aggregate), args)
+                }
+            };
+
+            let order_by = order_by
+                .iter()
+                .map(|e| match e {
+                    Expr::Sort { expr, asc } => Ok((compile(expr)?, *asc)),
+                    other => Ok((compile(other)?, true)),
+                })
+                .collect::<Result<Vec<(CompiledExpr, bool)>>>()?;
+
+            Ok(WindowExpr {
+                function,
+                args,
+                partition_by: partition_by
+                    .iter()
+                    .map(&compile)
+                    .collect::<Result<Vec<CompiledExpr>>>()?,
+                order_by,
+                // without a frame, each row sees the rows up to and including its last peer
+                frame: frame.clone().unwrap_or(WindowFrame {
+                    units: WindowFrameUnits::Range,
+                    start: WindowFrameBound::UnboundedPreceding,
+                    end: WindowFrameBound::CurrentRow,
+                }),
+                data_type: return_type.clone(),
+            })
+        }
+        other => Err(ExecutionError::General(format!(
+            "{:?} is not a window function",
+            other
+        ))),
+    }
+}
+
+/// Evaluate expressions against all input batches, returning the values of each row
+fn row_values(
+    batches: &[RecordBatch],
+    exprs: &[CompiledExpr],
+) -> Result<Vec<Vec<Option<ScalarValue>>>> {
+    let mut rows = vec![];
+    for batch in batches {
+        let arrays = exprs
+            .iter()
+            .map(|e| e(batch))
+            .collect::<Result<Vec<ArrayRef>>>()?;
+        for row in 0..batch.num_rows() {
+            rows.push(
+                arrays
+                    .iter()
+                    .map(|a| scalar_value(a, row))
+                    .collect::<Result<Vec<Option<ScalarValue>>>>()?,
+            );
+        }
+    }
+    Ok(rows)
+}
+
+/// Compare two rows of sort keys, ordering NULLs after all other values
+fn compare_keys(a: &[Option<ScalarValue>], b: &[Option<ScalarValue>], asc: &[bool]) -> Ordering {
+    for ((a, b), asc) in a.iter().zip(b.iter()).zip(asc.iter()) {
+        let ordering = match (a, b) {
+            (None, None) => Ordering::Equal,
+            (None, Some(_)) => Ordering::Greater,
+            (Some(_), None) => Ordering::Less,
+            (Some(a), Some(b)) => compare_scalars(a, b),
+        };
+        let ordering = if *asc { ordering } else { ordering.reverse() };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    Ordering::Equal
+}
+
+/// Get the range of positions in a partition of `n` rows that make up the frame of row `i`,
+/// whose peers are the rows from `peer_start` up to `peer_end`. The range is empty when the
+/// end is not after the start.
+fn frame_bounds(
+    frame: &WindowFrame,
+    i: usize,
+    n: usize,
+    peer_start: usize,
+    peer_end: usize,
+) -> (usize, usize) {
+    let range = frame.units == WindowFrameUnits::Range;
+    let start = match frame.start {
+        WindowFrameBound::UnboundedPreceding => 0,
+        WindowFrameBound::Preceding(k) => i.saturating_sub(k as usize),
+        WindowFrameBound::CurrentRow if range => peer_start,
+        WindowFrameBound::CurrentRow => i,
+        WindowFrameBound::Following(k) => min(i.saturating_add(k as usize), n),
+        WindowFrameBound::UnboundedFollowing => n,
+    };
+    let end = match frame.end {
+        WindowFrameBound::UnboundedPreceding => 0,
+        WindowFrameBound::Preceding(k) => (i + 1).saturating_sub(k as usize),
+        WindowFrameBound::CurrentRow if range => peer_end,
+        WindowFrameBound::CurrentRow => i + 1,
+        WindowFrameBound::Following(k) => min((i + 1).saturating_add(k as usize), n),
+        WindowFrameBound::UnboundedFollowing => n,
+    };
+    (start, end)
+}
+
+/// Get the NTILE bucket (starting at 1) of row `i` in a partition of `n` rows. The first
+/// `n % buckets` buckets have one more row than the others.
+fn ntile(i: usize, n: usize, buckets: usize) -> usize {
+    let size = n / buckets;
+    let remainder = n % buckets;
+    let large = remainder * (size + 1);
+    if i < large {
+        i / (size + 1) + 1
+    } else {
+        (i - large) / size + remainder + 1
+    }
+}
+
+impl WindowExpr {
+    /// Evaluate the window function for every row of the input
+    fn evaluate(&self, batches: &[RecordBatch]) -> Result<Vec<Option<ScalarValue>>> {
+        let partition_keys = row_values(batches, &self.partition_by)?;
+        let order_exprs = self
+            .order_by
+            .iter()
+            .map(|(e, _)| e.clone())
+            .collect::<Vec<CompiledExpr>>();
+        let order_keys = row_values(batches, &order_exprs)?;
+        let args = row_values(batches, &self.args)?;
+        let partition_asc = vec![true; self.partition_by.len()];
+        let order_asc = self
+            .order_by
+            .iter()
+            .map(|(_, asc)| *asc)
+            .collect::<Vec<bool>>();
+
+        // sort the rows by partition and then by the window ordering, keeping the input order
+        // of rows that compare equal
+        let mut rows: Vec<usize> = (0..partition_keys.len()).collect();
+        rows.sort_by(|a, b| {
+            compare_keys(&partition_keys[*a], &partition_keys[*b], &partition_asc)
+                .then_with(|| compare_keys(&order_keys[*a], &order_keys[*b], &order_asc))
+        });
+
+        let mut results = vec![None; rows.len()];
+        let mut start = 0;
+        while start < rows.len() {
+            let mut end = start + 1;
+            while end < rows.len()
+                && compare_keys(
+                    &partition_keys[rows[start]],
+                    &partition_keys[rows[end]],
+                    &partition_asc,
+                ) == Ordering::Equal
+            {
+                end += 1;
+            }
+            self.evaluate_partition(
+                &rows[start..end],
+                &order_keys,
+                &order_asc,
+                &args,
+                &mut results,
+            )?;
+            start = end;
+        }
+        Ok(results)
+    }
+
+    /// Evaluate the window function for the rows of one partition, in window order
+    fn evaluate_partition(
+        &self,
+        rows: &[usize],
+        order_keys: &[Vec<Option<ScalarValue>>],
+        order_asc: &[bool],
+        args: &[Vec<Option<ScalarValue>>],
+        results: &mut [Option<ScalarValue>],
+    ) -> Result<()> {
+        let n = rows.len();
+
+        // rows are peers when they are equal in the window ordering. Each row has the range
+        // of positions of its peers and its dense rank.
+        let mut peers = vec![(0, 0, 0); n];
+        let mut start = 0;
+        let mut rank: usize = 0;
+        while start < n {
+            let mut end = start + 1;
+            while end < n
+                && compare_keys(&order_keys[rows[start]], &order_keys[rows[end]], order_asc)
+                    == Ordering::Equal
+            {
+                end += 1;
+            }
+            rank += 1;
+            for peer in &mut peers[start..end] {
+                *peer = (start, end, rank);
+            }
+            start = end;
+        }
+
+        let value = |i: usize| args[rows[i]][0].clone();
+        let default = |i: usize| args[rows[i]].get(1).cloned().flatten();
+        let frame = |i: usize| frame_bounds(&self.frame, i, n, peers[i].0, peers[i].1);
+
+        match &self.function {
+            WindowFunction::Aggregate(aggr_expr)
+                if self.frame.start == WindowFrameBound::UnboundedPreceding =>
+            {
+                // the frames only grow, so rows are added to a single accumulator
+                let mut accumulator = Accumulator::new(aggr_expr)?;
+                let mut accumulated = 0;
+                for (i, row) in rows.iter().enumerate() {
+                    let (_, end) = frame(i);
+                    while accumulated < end {
+                        accumulator.accumulate(&args[rows[accumulated]]);
+                        accumulated += 1;
+                    }
+                    results[*row] = accumulator.result();
+                }
+            }
+            WindowFunction::Aggregate(aggr_expr) => {
+                for (i, row) in rows.iter().enumerate() {
+                    let (start, end) = frame(i);
+                    let mut accumulator = Accumulator::new(aggr_expr)?;
+                    for frame_row in &rows[start..end] {
+                        accumulator.accumulate(&args[*frame_row]);
+                    }
+                    results[*row] = accumulator.result();
+                }
+            }
+            function => {
+                for (i, row) in rows.iter().enumerate() {
+                    results[*row] = match function {
+                        WindowFunction::RowNumber => Some(ScalarValue::UInt64(i as u64 + 1)),
+                        WindowFunction::Rank => Some(ScalarValue::UInt64(peers[i].0 as u64 + 1)),
+                        WindowFunction::DenseRank => Some(ScalarValue::UInt64(peers[i].2 as u64)),
+                        WindowFunction::Ntile(buckets) => {
+                            Some(ScalarValue::UInt64(ntile(i, n, *buckets) as u64))
+                        }
+                        WindowFunction::Lag(offset) if i >= *offset => value(i - offset),
+                        WindowFunction::Lead(offset) if i + offset < n => value(i + offset),
+                        WindowFunction::Lag(_) | WindowFunction::Lead(_) => default(i),
+                        WindowFunction::FirstValue => match frame(i) {
+                            (start, end) if start < end => value(start),
+                            _ => None,
+                        },
+                        WindowFunction::LastValue => match frame(i) {
+                            (start, end) if start < end => value(end - 1),
+                            _ => None,
+                        },
+                        WindowFunction::Aggregate(_) => unreachable!(),
+                    };
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Create an array of scalar values, such as the results of a window function
+macro_rules! array_from_scalars {
+    ($ARRAY_TYPE:ident, $TY:ident, $VALUES:expr) => {{
+        let mut builder = $ARRAY_TYPE::builder($VALUES.len());
+        for value in $VALUES {
+            match value {
+                Some(ScalarValue::$TY(n)) => builder.append_value(*n)?,
+                None => builder.append_null()?,
+                Some(other) => {
+                    return Err(ExecutionError::ExecutionError(format!(
+                        "unexpected value {:?} when creating array from scalar values",
+                        other
+                    )));
+                }
+            }
+        }
+        Ok(Arc::new(builder.finish()) as ArrayRef)
+    }};
+}
+
+pub fn array_from_scalars(
+    values: &[Option<ScalarValue>],
+    data_type: &DataType,
+) -> Result<ArrayRef> {
+    match data_type {
+        DataType::UInt8 => array_from_scalars!(UInt8Array, UInt8, values),
+        DataType::UInt16 => array_from_scalars!(UInt16Array, UInt16, values),
+        DataType::UInt32 => array_from_scalars!(UInt32Array, UInt32, values),
+        DataType::UInt64 => array_from_scalars!(UInt64Array, UInt64, values),
+        DataType::Int8 => array_from_scalars!(Int8Array, Int8, values),
+        DataType::Int16 => array_from_scalars!(Int16Array, Int16, values),
+        DataType::Int32 => array_from_scalars!(Int32Array, Int32, values),
+        DataType::Int64 => array_from_scalars!(Int64Array, Int64, values),
+        DataType::Float32 => array_from_scalars!(Float32Array, Float32, values),
+        DataType::Float64 => array_from_scalars!(Float64Array, Float64, values),
+        DataType::Boolean => array_from_scalars!(BooleanArray, Boolean, values),
+        DataType::Date(_) => {
+            array_from_scalars!(DateArray, Date32, values).map(|a| with_data_type(a, data_type))
+        }
+        DataType::Time64(_) => {
+            array_from_scalars!(TimeArray, Time64, values).map(|a| with_data_type(a, data_type))
+        }
+        DataType::Timestamp(_) => array_from_scalars!(TimestampArray, Timestamp, values)
+            .map(|a| with_data_type(a, data_type)),
+        DataType::Utf8 => {
+            let mut builder = BinaryBuilder::new(values.len());
+            for value in values {
+                match value {
+                    Some(ScalarValue::Utf8(s)) => builder.append_string(s)?,
+                    _ => builder.append_null()?,
+                }
+            }
+            Ok(Arc::new(builder.finish()) as ArrayRef)
+        }
+        other => Err(ExecutionError::NotImplemented(format!(
+            "Creating array of {:?} from scalar values",
+            other
+        ))),
+    }
+}
+
+/// Appends the result of each window expression to the input rows. All of the input is read
+/// before the first batch is returned, since a partition can span many batches.
+pub struct WindowRelation {
+    schema: Arc<Schema>,
+    input: Rc<RefCell<Relation>>,
+    window_expr: Vec<WindowExpr>,
+    /// The output batches in reverse order, once the input has been read
+    results: Option<Vec<RecordBatch>>,
+}
+
+impl WindowRelation {
+    pub fn new(
+        schema: Arc<Schema>,
+        input: Rc<RefCell<Relation>>,
+        window_expr: Vec<WindowExpr>,
+    ) -> Self {
+        Self {
+            schema,
+            input,
+            window_expr,
+            results: None,
+        }
+    }
+
+    fn evaluate(&mut self) -> Result<Vec<RecordBatch>> {
+        let mut batches = vec![];
+        while let Some(batch) = self.input.borrow_mut().next()? {
+            batches.push(batch);
+        }
+
+        let values = self
+            .window_expr
+            .iter()
+            .map(|e| e.evaluate(&batches))
+            .collect::<Result<Vec<Vec<Option<ScalarValue>>>>>()?;
+
+        let mut results = vec![];
+        let mut offset = 0;
+        for batch in &batches {
+            let mut columns = (0..batch.num_columns())
+                .map(|i| batch.column(i).clone())
+                .collect::<Vec<ArrayRef>>();
+            for (expr, values) in self.window_expr.iter().zip(values.iter()) {
+                columns.push(array_from_scalars(
+                    &values[offset..offset + batch.num_rows()],
+                    &expr.data_type,
+                )?);
+            }
+            offset += batch.num_rows();
+            results.push(RecordBatch::new(self.schema.clone(), columns));
+        }
+        results.reverse();
+        Ok(results)
+    }
+}
+
+impl Relation for WindowRelation {
+    fn next(&mut self) -> Result<Option<RecordBatch>> {
+        if self.results.is_none() {
+            self.results = Some(self.evaluate()?);
+        }
+        Ok(self.results.as_mut().unwrap().pop())
+    }
+
+    fn schema(&self) -> &Arc<Schema> {
+        &self.schema
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ntile_buckets() {
+        let buckets = (0..10).map(|i| ntile(i, 10, 4)).collect::<Vec<usize>>();
+        assert_eq!(vec![1, 1, 1, 2, 2, 2, 3, 3, 4, 4], buckets);
+        let buckets = (0..3).map(|i| ntile(i, 3, 5)).collect::<Vec<usize>>();
+        assert_eq!(vec![1, 2, 3], buckets);
+    }
+
+    #[test]
+    fn rows_frame_bounds() {
+        let frame = WindowFrame {
+            units: WindowFrameUnits::Rows,
+            start: WindowFrameBound::Preceding(2),
+            end: WindowFrameBound::Following(1),
+        };
+        assert_eq!((0, 2), frame_bounds(&frame, 0, 5, 0, 1));
+        assert_eq!((1, 5), frame_bounds(&frame, 3, 5, 3, 4));
+        assert_eq!((2, 5), frame_bounds(&frame, 4, 5, 4, 5));
+    }
+
+    #[test]
+    fn range_frame_bounds_include_peers() {
+        let frame = WindowFrame {
+            units: WindowFrameUnits::Range,
+            start: WindowFrameBound::CurrentRow,
+            end: WindowFrameBound::UnboundedFollowing,
+        };
+        assert_eq!((1, 5), frame_bounds(&frame, 2, 5, 1, 3));
+    }
+}
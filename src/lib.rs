@@ -16,6 +16,7 @@
 //! as the memory model
 
 extern crate arrow;
+extern crate chrono;
 #[macro_use]
 extern crate serde_derive;
 extern crate serde_json;
@@ -25,3 +26,4 @@ pub mod dfparser;
 pub mod execution;
 pub mod logicalplan;
 pub mod sqlplanner;
+pub mod typecoercion;
@@ -21,6 +21,8 @@ use std::sync::Arc;
 
 use arrow::datatypes::*;
 
+use super::typecoercion::{binary_operator_data_type, get_supertype};
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum FunctionType {
     Scalar,
@@ -85,12 +87,48 @@ pub enum Operator {
 
 impl Operator {
     /// Get the result type of applying this operation to its left and right inputs
-    pub fn get_datatype(&self, l: &Expr, _r: &Expr, schema: &Schema) -> DataType {
-        //TODO: implement correctly, just go with left side for now
-        l.get_type(schema).clone()
+    pub fn get_datatype(&self, l: &Expr, r: &Expr, schema: &Schema) -> Result<DataType, String> {
+        binary_operator_data_type(&l.get_type(schema)?, self, &r.get_type(schema)?)
+    }
+}
+
+/// Whether a window frame is measured in rows or in ranges of ORDER BY values
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum WindowFrameUnits {
+    Rows,
+    Range,
+}
+
+/// Start or end of a window frame, relative to the current row
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum WindowFrameBound {
+    UnboundedPreceding,
+    Preceding(u64),
+    CurrentRow,
+    Following(u64),
+    UnboundedFollowing,
+}
+
+impl fmt::Display for WindowFrameBound {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        match self {
+            WindowFrameBound::UnboundedPreceding => write!(f, "UNBOUNDED PRECEDING"),
+            WindowFrameBound::Preceding(n) => write!(f, "{} PRECEDING", n),
+            WindowFrameBound::CurrentRow => write!(f, "CURRENT ROW"),
+            WindowFrameBound::Following(n) => write!(f, "{} FOLLOWING", n),
+            WindowFrameBound::UnboundedFollowing => write!(f, "UNBOUNDED FOLLOWING"),
+        }
     }
 }
 
+/// The rows of a partition that a window function is evaluated over
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct WindowFrame {
+    pub units: WindowFrameUnits,
+    pub start: WindowFrameBound,
+    pub end: WindowFrameBound,
+}
+
 /// ScalarValue enumeration
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum ScalarValue {
@@ -107,7 +145,18 @@ pub enum ScalarValue {
     UInt32(u32),
     UInt64(u64),
     Utf8(Rc<String>),
-    Struct(Vec<ScalarValue>),
+    /// The values of the fields of a struct, which keep the names they have in their source
+    Struct(Vec<ScalarValue>, Vec<Field>),
+    /// Days since the UNIX epoch
+    Date32(i32),
+    /// Microseconds since midnight
+    Time64(i64),
+    /// Microseconds since the UNIX epoch (UTC)
+    Timestamp(i64),
+    /// A fixed length interval in microseconds
+    Interval(i64),
+    /// A list of values of the given element type, in which NULL elements are `Null`
+    List(Vec<ScalarValue>, DataType),
 }
 
 impl ScalarValue {
@@ -125,8 +174,14 @@ impl ScalarValue {
             ScalarValue::Float32(_) => DataType::Float32,
             ScalarValue::Float64(_) => DataType::Float64,
             ScalarValue::Utf8(_) => DataType::Utf8,
-            ScalarValue::Struct(_) => unimplemented!(),
-            ScalarValue::Null => unimplemented!(),
+            ScalarValue::Struct(_, ref fields) => DataType::Struct(fields.clone()),
+            ScalarValue::Date32(_) => DataType::Date(DateUnit::Day),
+            ScalarValue::Time64(_) => DataType::Time64(TimeUnit::Microsecond),
+            ScalarValue::Timestamp(_) => DataType::Timestamp(TimeUnit::Microsecond),
+            ScalarValue::Interval(_) => DataType::Interval(IntervalUnit::DayTime),
+            ScalarValue::List(_, ref t) => DataType::List(Box::new(t.clone())),
+            // an untyped NULL is treated as a string unless it is cast to another type
+            ScalarValue::Null => DataType::Utf8,
         }
     }
 }
@@ -148,8 +203,14 @@ pub enum Expr {
     IsNotNull(Rc<Expr>),
     /// unary IS NULL
     IsNull(Rc<Expr>),
+    /// logical NOT
+    Not(Rc<Expr>),
+    /// numeric negation e.g. "-age"
+    Negative(Rc<Expr>),
     /// cast a value to a different type
     Cast { expr: Rc<Expr>, data_type: DataType },
+    /// access a named field of a struct e.g. "address.zip"
+    GetField { expr: Rc<Expr>, name: String },
     /// sort expression
     Sort { expr: Rc<Expr>, asc: bool },
     /// scalar function
@@ -158,50 +219,118 @@ pub enum Expr {
         args: Vec<Expr>,
         return_type: DataType,
     },
-    /// aggregate function
+    /// aggregate function, with an optional `FILTER (WHERE ...)` condition that limits the rows
+    /// it aggregates
     AggregateFunction {
         name: String,
         args: Vec<Expr>,
         return_type: DataType,
+        filter: Option<Rc<Expr>>,
+    },
+    /// window function evaluated over the rows of the partition of each row, in the given order.
+    /// Without an explicit frame the frame is the whole partition when there is no ORDER BY, or
+    /// the rows up to and including the peers of the current row otherwise.
+    WindowFunction {
+        name: String,
+        args: Vec<Expr>,
+        partition_by: Vec<Expr>,
+        /// sort expressions
+        order_by: Vec<Expr>,
+        frame: Option<WindowFrame>,
+        return_type: DataType,
     },
+    /// CASE expression. When `expr` is present, each WHEN value is compared with it for
+    /// equality, otherwise each WHEN value is a boolean condition
+    Case {
+        expr: Option<Rc<Expr>>,
+        when_then_expr: Vec<(Expr, Expr)>,
+        else_expr: Option<Rc<Expr>>,
+    },
+    /// expr [NOT] IN (list)
+    InList {
+        expr: Rc<Expr>,
+        list: Vec<Expr>,
+        negated: bool,
+    },
+    /// expr [NOT] BETWEEN low AND high
+    Between {
+        expr: Rc<Expr>,
+        negated: bool,
+        low: Rc<Expr>,
+        high: Rc<Expr>,
+    },
+    /// uncorrelated subquery that returns a single column, whose value is that of its only row
+    /// or NULL when it returns no rows. It is evaluated once per query.
+    ScalarSubquery(SubqueryPlan),
+}
+
+/// The plan of a subquery within an expression. Plans are not comparable, so expressions with
+/// subqueries are only equal when they share the same plan.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SubqueryPlan(pub Rc<LogicalPlan>);
+
+impl PartialEq for SubqueryPlan {
+    fn eq(&self, other: &SubqueryPlan) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
 }
 
 impl Expr {
-    pub fn get_type(&self, schema: &Schema) -> DataType {
+    /// Get the data type of this expression, returning an error if the types of its inputs
+    /// are not compatible
+    pub fn get_type(&self, schema: &Schema) -> Result<DataType, String> {
         match self {
-            Expr::Column(n) => schema.field(*n).data_type().clone(),
-            Expr::Literal(l) => l.get_datatype(),
-            Expr::Cast { data_type, .. } => data_type.clone(),
-            Expr::ScalarFunction { return_type, .. } => return_type.clone(),
-            Expr::AggregateFunction { return_type, .. } => return_type.clone(),
-            Expr::IsNull(_) => DataType::Boolean,
-            Expr::IsNotNull(_) => DataType::Boolean,
+            Expr::Column(n) => Ok(schema.field(*n).data_type().clone()),
+            Expr::Literal(l) => Ok(l.get_datatype()),
+            Expr::Cast { data_type, .. } => Ok(data_type.clone()),
+            Expr::GetField { ref expr, ref name } => match expr.get_type(schema)? {
+                DataType::Struct(fields) => fields
+                    .iter()
+                    .find(|f| f.name() == name)
+                    .map(|f| f.data_type().clone())
+                    .ok_or_else(|| format!("Struct {:?} has no field named '{}'", expr, name)),
+                other => Err(format!(
+                    "Cannot access field '{}' of {:?} because it has type {:?}",
+                    name, expr, other
+                )),
+            },
+            Expr::ScalarFunction { return_type, .. } => Ok(return_type.clone()),
+            Expr::AggregateFunction { return_type, .. } => Ok(return_type.clone()),
+            Expr::WindowFunction { return_type, .. } => Ok(return_type.clone()),
+            Expr::IsNull(_) => Ok(DataType::Boolean),
+            Expr::IsNotNull(_) => Ok(DataType::Boolean),
+            Expr::Not(_) => Ok(DataType::Boolean),
+            Expr::Negative(ref expr) => expr.get_type(schema),
+            Expr::InList { .. } => Ok(DataType::Boolean),
+            Expr::Between { .. } => Ok(DataType::Boolean),
+            Expr::Case {
+                ref when_then_expr, ..
+            } => when_then_expr[0].1.get_type(schema),
             Expr::BinaryExpr {
                 ref left,
                 ref right,
                 ref op,
-            } => {
-                match op {
-                    Operator::Eq | Operator::NotEq => DataType::Boolean,
-                    Operator::Lt | Operator::LtEq => DataType::Boolean,
-                    Operator::Gt | Operator::GtEq => DataType::Boolean,
-                    Operator::And | Operator::Or => DataType::Boolean,
-                    _ => {
-                        let left_type = left.get_type(schema);
-                        let right_type = right.get_type(schema);
-                        get_supertype(&left_type, &right_type).unwrap_or(DataType::Utf8) //TODO ???
-                    }
-                }
-            }
+            } => op.get_datatype(left, right, schema),
             Expr::Sort { ref expr, .. } => expr.get_type(schema),
+            Expr::ScalarSubquery(ref subquery) => {
+                Ok(subquery.0.schema().field(0).data_type().clone())
+            }
         }
     }
 
     pub fn cast_to(&self, cast_to_type: &DataType, schema: &Schema) -> Result<Expr, String> {
-        let this_type = self.get_type(schema);
-        if this_type == *cast_to_type {
+        let this_type = self.get_type(schema)?;
+        if *self == Expr::Literal(ScalarValue::Null) {
+            // a NULL literal can take on any type
+            Ok(Expr::Cast {
+                expr: Rc::new(self.clone()),
+                data_type: cast_to_type.clone(),
+            })
+        } else if this_type == *cast_to_type {
             Ok(self.clone())
-        } else if can_coerce_from(cast_to_type, &this_type) {
+        } else if get_supertype(cast_to_type, &this_type).as_ref() == Some(cast_to_type) {
+            // implicit conversions follow `get_supertype`, so an integer that is converted to a
+            // float can be rounded
             Ok(Expr::Cast {
                 expr: Rc::new(self.clone()),
                 data_type: cast_to_type.clone(),
@@ -261,6 +390,43 @@ impl Expr {
             right: Rc::new(other.clone()),
         }
     }
+
+    pub fn get_field(&self, name: &str) -> Expr {
+        Expr::GetField {
+            expr: Rc::new(self.clone()),
+            name: name.to_string(),
+        }
+    }
+
+    pub fn not(&self) -> Expr {
+        Expr::Not(Rc::new(self.clone()))
+    }
+
+    pub fn in_list(&self, list: Vec<Expr>, negated: bool) -> Expr {
+        Expr::InList {
+            expr: Rc::new(self.clone()),
+            list,
+            negated,
+        }
+    }
+
+    pub fn between(&self, low: &Expr, high: &Expr) -> Expr {
+        Expr::Between {
+            expr: Rc::new(self.clone()),
+            negated: false,
+            low: Rc::new(low.clone()),
+            high: Rc::new(high.clone()),
+        }
+    }
+
+    pub fn not_between(&self, low: &Expr, high: &Expr) -> Expr {
+        Expr::Between {
+            expr: Rc::new(self.clone()),
+            negated: true,
+            low: Rc::new(low.clone()),
+            high: Rc::new(high.clone()),
+        }
+    }
 }
 
 impl fmt::Debug for Expr {
@@ -269,8 +435,12 @@ impl fmt::Debug for Expr {
             Expr::Column(i) => write!(f, "#{}", i),
             Expr::Literal(v) => write!(f, "{:?}", v),
             Expr::Cast { expr, data_type } => write!(f, "CAST({:?} AS {:?})", expr, data_type),
+            Expr::GetField { expr, name } => write!(f, "{:?}.{}", expr, name),
             Expr::IsNull(expr) => write!(f, "{:?} IS NULL", expr),
             Expr::IsNotNull(expr) => write!(f, "{:?} IS NOT NULL", expr),
+            Expr::Not(expr) => write!(f, "NOT {:?}", expr),
+            Expr::Negative(expr) => write!(f, "(- {:?})", expr),
+            Expr::ScalarSubquery(subquery) => write!(f, "({:?})", subquery.0),
             Expr::BinaryExpr { left, op, right } => write!(f, "{:?} {:?} {:?}", left, op, right),
             Expr::Sort { expr, asc } => {
                 if *asc {
@@ -290,7 +460,12 @@ impl fmt::Debug for Expr {
 
                 write!(f, ")")
             }
-            Expr::AggregateFunction { name, ref args, .. } => {
+            Expr::AggregateFunction {
+                name,
+                ref args,
+                ref filter,
+                ..
+            } => {
                 write!(f, "{}(", name)?;
                 for i in 0..args.len() {
                     if i > 0 {
@@ -298,13 +473,113 @@ impl fmt::Debug for Expr {
                     }
                     write!(f, "{:?}", args[i])?;
                 }
-
+                write!(f, ")")?;
+                if let Some(filter) = filter {
+                    write!(f, " FILTER (WHERE {:?})", filter)?;
+                }
+                Ok(())
+            }
+            Expr::WindowFunction {
+                name,
+                ref args,
+                ref partition_by,
+                ref order_by,
+                ref frame,
+                ..
+            } => {
+                write!(f, "{}(", name)?;
+                for i in 0..args.len() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{:?}", args[i])?;
+                }
+                write!(f, ") OVER (")?;
+                let list = |exprs: &Vec<Expr>| {
+                    exprs
+                        .iter()
+                        .map(|e| format!("{:?}", e))
+                        .collect::<Vec<String>>()
+                        .join(", ")
+                };
+                let mut clauses = vec![];
+                if !partition_by.is_empty() {
+                    clauses.push(format!("PARTITION BY {}", list(partition_by)));
+                }
+                if !order_by.is_empty() {
+                    clauses.push(format!("ORDER BY {}", list(order_by)));
+                }
+                if let Some(frame) = frame {
+                    let units = match frame.units {
+                        WindowFrameUnits::Rows => "ROWS",
+                        WindowFrameUnits::Range => "RANGE",
+                    };
+                    clauses.push(format!(
+                        "{} BETWEEN {} AND {}",
+                        units, frame.start, frame.end
+                    ));
+                }
+                write!(f, "{})", clauses.join(" "))
+            }
+            Expr::Case {
+                expr,
+                when_then_expr,
+                else_expr,
+            } => {
+                write!(f, "CASE ")?;
+                if let Some(e) = expr {
+                    write!(f, "{:?} ", e)?;
+                }
+                for (w, t) in when_then_expr {
+                    write!(f, "WHEN {:?} THEN {:?} ", w, t)?;
+                }
+                if let Some(e) = else_expr {
+                    write!(f, "ELSE {:?} ", e)?;
+                }
+                write!(f, "END")
+            }
+            Expr::InList {
+                expr,
+                list,
+                negated,
+            } => {
+                if *negated {
+                    write!(f, "{:?} NOT IN (", expr)?;
+                } else {
+                    write!(f, "{:?} IN (", expr)?;
+                }
+                for i in 0..list.len() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{:?}", list[i])?;
+                }
                 write!(f, ")")
             }
+            Expr::Between {
+                expr,
+                negated,
+                low,
+                high,
+            } => {
+                if *negated {
+                    write!(f, "{:?} NOT BETWEEN {:?} AND {:?}", expr, low, high)
+                } else {
+                    write!(f, "{:?} BETWEEN {:?} AND {:?}", expr, low, high)
+                }
+            }
         }
     }
 }
 
+/// The operators that combine the rows of two queries
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum SetOperator {
+    Union,
+    Intersect,
+    Except,
+}
+
 /// The LogicalPlan represents different types of relations (such as Projection, Selection, etc) and
 /// can be created by the SQL query planner and the DataFrame API.
 #[derive(Serialize, Deserialize, Clone)]
@@ -327,6 +602,11 @@ pub enum LogicalPlan {
     Aggregate {
         input: Rc<LogicalPlan>,
         group_expr: Vec<Expr>,
+        /// Optional grouping sets (from GROUPING SETS, ROLLUP or CUBE) as indices into
+        /// `group_expr`. The input is aggregated once per set and the grouping expressions that
+        /// are not part of a set are NULL in its rows. When this is `None` there is a single set
+        /// containing all of the grouping expressions.
+        grouping_sets: Option<Vec<Vec<usize>>>,
         aggr_expr: Vec<Expr>,
         schema: Arc<Schema>,
     },
@@ -345,6 +625,64 @@ pub enum LogicalPlan {
     },
     /// An empty relation with an empty schema
     EmptyRelation { schema: Arc<Schema> },
+    /// Appends the value of each window function to the rows of its input
+    Window {
+        input: Rc<LogicalPlan>,
+        window_expr: Vec<Expr>,
+        schema: Arc<Schema>,
+    },
+    /// Expands each element of a list expression into its own row. The element is appended to
+    /// the input columns, and rows with an empty or NULL list are dropped.
+    Unnest {
+        expr: Expr,
+        input: Rc<LogicalPlan>,
+        schema: Arc<Schema>,
+    },
+    /// Keeps the rows of the left input that have a matching row in the right input, or for an
+    /// anti join the rows that do not. Rows match when each pair of expressions in `on`, the
+    /// first over the left input and the second over the right input, are equal and not NULL.
+    SemiJoin {
+        left: Rc<LogicalPlan>,
+        right: Rc<LogicalPlan>,
+        on: Vec<(Expr, Expr)>,
+        anti: bool,
+        /// The last pair of `on` is compared as for NOT IN, which is not true when the left
+        /// value is NULL or the matching right rows contain a NULL
+        null_aware: bool,
+    },
+    /// Appends the columns of the matching row of the right input to each row of the left
+    /// input, or NULLs when there is no matching row, as for a correlated scalar subquery. It is
+    /// an error for a row of the left input to match more than one row. Rows match when each
+    /// pair of expressions in `on` are equal and not NULL.
+    SingleJoin {
+        left: Rc<LogicalPlan>,
+        right: Rc<LogicalPlan>,
+        on: Vec<(Expr, Expr)>,
+        schema: Arc<Schema>,
+    },
+    /// A recursive common table expression. The rows of the static term are the first working
+    /// table, and the recursive term is evaluated against the rows of the previous iteration,
+    /// which it reads as the table `name`, until it produces no new rows. The result is all of
+    /// the rows produced, without duplicates when `distinct` is set (as for UNION rather than
+    /// UNION ALL).
+    RecursiveQuery {
+        name: String,
+        static_term: Rc<LogicalPlan>,
+        recursive_term: Rc<LogicalPlan>,
+        distinct: bool,
+        schema: Arc<Schema>,
+    },
+    /// Combines the rows of two inputs whose columns have the types of `schema`. UNION returns
+    /// the rows of either input, INTERSECT the rows of the left input that are in the right
+    /// input and EXCEPT those that are not. Duplicate rows are removed unless `all` is set, in
+    /// which case each row of the right input matches one row of the left input.
+    SetOperation {
+        op: SetOperator,
+        all: bool,
+        left: Rc<LogicalPlan>,
+        right: Rc<LogicalPlan>,
+        schema: Arc<Schema>,
+    },
 }
 
 impl LogicalPlan {
@@ -358,6 +696,12 @@ impl LogicalPlan {
             LogicalPlan::Aggregate { schema, .. } => &schema,
             LogicalPlan::Sort { schema, .. } => &schema,
             LogicalPlan::Limit { schema, .. } => &schema,
+            LogicalPlan::Unnest { schema, .. } => &schema,
+            LogicalPlan::Window { schema, .. } => &schema,
+            LogicalPlan::RecursiveQuery { schema, .. } => &schema,
+            LogicalPlan::SemiJoin { left, .. } => left.schema(),
+            LogicalPlan::SingleJoin { schema, .. } => &schema,
+            LogicalPlan::SetOperation { schema, .. } => &schema,
         }
     }
 }
@@ -402,14 +746,15 @@ impl LogicalPlan {
             LogicalPlan::Aggregate {
                 ref input,
                 ref group_expr,
+                ref grouping_sets,
                 ref aggr_expr,
                 ..
             } => {
-                write!(
-                    f,
-                    "Aggregate: groupBy=[{:?}], aggr=[{:?}]",
-                    group_expr, aggr_expr
-                )?;
+                write!(f, "Aggregate: groupBy=[{:?}], ", group_expr)?;
+                if let Some(sets) = grouping_sets {
+                    write!(f, "groupingSets=[{:?}], ", sets)?;
+                }
+                write!(f, "aggr=[{:?}]", aggr_expr)?;
                 input.fmt_with_indent(f, indent + 1)
             }
             LogicalPlan::Sort {
@@ -432,6 +777,87 @@ impl LogicalPlan {
                 write!(f, "Limit: {}", limit)?;
                 input.fmt_with_indent(f, indent + 1)
             }
+            LogicalPlan::Unnest {
+                ref expr,
+                ref input,
+                ..
+            } => {
+                write!(f, "Unnest: {:?}", expr)?;
+                input.fmt_with_indent(f, indent + 1)
+            }
+            LogicalPlan::Window {
+                ref window_expr,
+                ref input,
+                ..
+            } => {
+                write!(f, "Window: ")?;
+                for i in 0..window_expr.len() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{:?}", window_expr[i])?;
+                }
+                input.fmt_with_indent(f, indent + 1)
+            }
+            LogicalPlan::SemiJoin {
+                ref left,
+                ref right,
+                ref on,
+                anti,
+                null_aware,
+            } => {
+                match (anti, null_aware) {
+                    (false, _) => write!(f, "SemiJoin: ")?,
+                    (true, false) => write!(f, "AntiJoin: ")?,
+                    (true, true) => write!(f, "NullAwareAntiJoin: ")?,
+                }
+                for (i, (l, r)) in on.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{:?} = {:?}", l, r)?;
+                }
+                left.fmt_with_indent(f, indent + 1)?;
+                right.fmt_with_indent(f, indent + 1)
+            }
+            LogicalPlan::SingleJoin {
+                ref left,
+                ref right,
+                ref on,
+                ..
+            } => {
+                write!(f, "SingleJoin: ")?;
+                for (i, (l, r)) in on.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{:?} = {:?}", l, r)?;
+                }
+                left.fmt_with_indent(f, indent + 1)?;
+                right.fmt_with_indent(f, indent + 1)
+            }
+            LogicalPlan::RecursiveQuery {
+                ref name,
+                ref static_term,
+                ref recursive_term,
+                distinct,
+                ..
+            } => {
+                write!(f, "RecursiveQuery: {} distinct={}", name, distinct)?;
+                static_term.fmt_with_indent(f, indent + 1)?;
+                recursive_term.fmt_with_indent(f, indent + 1)
+            }
+            LogicalPlan::SetOperation {
+                ref op,
+                all,
+                ref left,
+                ref right,
+                ..
+            } => {
+                write!(f, "{:?}: all={}", op, all)?;
+                left.fmt_with_indent(f, indent + 1)?;
+                right.fmt_with_indent(f, indent + 1)
+            }
         }
     }
 }
@@ -442,168 +868,6 @@ impl fmt::Debug for LogicalPlan {
     }
 }
 
-//TODO move to Arrow DataType impl?
-pub fn get_supertype(l: &DataType, r: &DataType) -> Option<DataType> {
-    match _get_supertype(l, r) {
-        Some(dt) => Some(dt),
-        None => match _get_supertype(r, l) {
-            Some(dt) => Some(dt),
-            None => None,
-        },
-    }
-}
-
-fn _get_supertype(l: &DataType, r: &DataType) -> Option<DataType> {
-    use self::DataType::*;
-    match (l, r) {
-        (UInt8, Int8) => Some(Int8),
-        (UInt8, Int16) => Some(Int16),
-        (UInt8, Int32) => Some(Int32),
-        (UInt8, Int64) => Some(Int64),
-
-        (UInt16, Int16) => Some(Int16),
-        (UInt16, Int32) => Some(Int32),
-        (UInt16, Int64) => Some(Int64),
-
-        (UInt32, Int32) => Some(Int32),
-        (UInt32, Int64) => Some(Int64),
-
-        (UInt64, Int64) => Some(Int64),
-
-        (Int8, UInt8) => Some(Int8),
-
-        (Int16, UInt8) => Some(Int16),
-        (Int16, UInt16) => Some(Int16),
-
-        (Int32, UInt8) => Some(Int32),
-        (Int32, UInt16) => Some(Int32),
-        (Int32, UInt32) => Some(Int32),
-
-        (Int64, UInt8) => Some(Int64),
-        (Int64, UInt16) => Some(Int64),
-        (Int64, UInt32) => Some(Int64),
-        (Int64, UInt64) => Some(Int64),
-
-        (UInt8, UInt8) => Some(UInt8),
-        (UInt8, UInt16) => Some(UInt16),
-        (UInt8, UInt32) => Some(UInt32),
-        (UInt8, UInt64) => Some(UInt64),
-        (UInt8, Float32) => Some(Float32),
-        (UInt8, Float64) => Some(Float64),
-
-        (UInt16, UInt8) => Some(UInt16),
-        (UInt16, UInt16) => Some(UInt16),
-        (UInt16, UInt32) => Some(UInt32),
-        (UInt16, UInt64) => Some(UInt64),
-        (UInt16, Float32) => Some(Float32),
-        (UInt16, Float64) => Some(Float64),
-
-        (UInt32, UInt8) => Some(UInt32),
-        (UInt32, UInt16) => Some(UInt32),
-        (UInt32, UInt32) => Some(UInt32),
-        (UInt32, UInt64) => Some(UInt64),
-        (UInt32, Float32) => Some(Float32),
-        (UInt32, Float64) => Some(Float64),
-
-        (UInt64, UInt8) => Some(UInt64),
-        (UInt64, UInt16) => Some(UInt64),
-        (UInt64, UInt32) => Some(UInt64),
-        (UInt64, UInt64) => Some(UInt64),
-        (UInt64, Float32) => Some(Float32),
-        (UInt64, Float64) => Some(Float64),
-
-        (Int8, Int8) => Some(Int8),
-        (Int8, Int16) => Some(Int16),
-        (Int8, Int32) => Some(Int32),
-        (Int8, Int64) => Some(Int64),
-        (Int8, Float32) => Some(Float32),
-        (Int8, Float64) => Some(Float64),
-
-        (Int16, Int8) => Some(Int16),
-        (Int16, Int16) => Some(Int16),
-        (Int16, Int32) => Some(Int32),
-        (Int16, Int64) => Some(Int64),
-        (Int16, Float32) => Some(Float32),
-        (Int16, Float64) => Some(Float64),
-
-        (Int32, Int8) => Some(Int32),
-        (Int32, Int16) => Some(Int32),
-        (Int32, Int32) => Some(Int32),
-        (Int32, Int64) => Some(Int64),
-        (Int32, Float32) => Some(Float32),
-        (Int32, Float64) => Some(Float64),
-
-        (Int64, Int8) => Some(Int64),
-        (Int64, Int16) => Some(Int64),
-        (Int64, Int32) => Some(Int64),
-        (Int64, Int64) => Some(Int64),
-        (Int64, Float32) => Some(Float32),
-        (Int64, Float64) => Some(Float64),
-
-        (Float32, Float32) => Some(Float32),
-        (Float32, Float64) => Some(Float64),
-        (Float64, Float32) => Some(Float64),
-        (Float64, Float64) => Some(Float64),
-
-        (Utf8, Utf8) => Some(Utf8),
-
-        (Boolean, Boolean) => Some(Boolean),
-
-        _ => None,
-    }
-}
-
-pub fn can_coerce_from(left: &DataType, other: &DataType) -> bool {
-    use self::DataType::*;
-    match left {
-        Int8 => match other {
-            Int8 => true,
-            _ => false,
-        },
-        Int16 => match other {
-            Int8 | Int16 => true,
-            _ => false,
-        },
-        Int32 => match other {
-            Int8 | Int16 | Int32 => true,
-            _ => false,
-        },
-        Int64 => match other {
-            Int8 | Int16 | Int32 | Int64 => true,
-            _ => false,
-        },
-        UInt8 => match other {
-            UInt8 => true,
-            _ => false,
-        },
-        UInt16 => match other {
-            UInt8 | UInt16 => true,
-            _ => false,
-        },
-        UInt32 => match other {
-            UInt8 | UInt16 | UInt32 => true,
-            _ => false,
-        },
-        UInt64 => match other {
-            UInt8 | UInt16 | UInt32 | UInt64 => true,
-            _ => false,
-        },
-        Float32 => match other {
-            Int8 | Int16 | Int32 | Int64 => true,
-            UInt8 | UInt16 | UInt32 | UInt64 => true,
-            Float32 => true,
-            _ => false,
-        },
-        Float64 => match other {
-            Int8 | Int16 | Int32 | Int64 => true,
-            UInt8 | UInt16 | UInt32 | UInt64 => true,
-            Float32 | Float64 => true,
-            _ => false,
-        },
-        _ => false,
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -649,4 +913,20 @@ mod tests {
             serialized
         );
     }
+
+    #[test]
+    fn struct_scalar_keeps_field_names() {
+        let fields = vec![
+            Field::new("street", DataType::Utf8, true),
+            Field::new("zip", DataType::UInt16, true),
+        ];
+        let value = ScalarValue::Struct(
+            vec![
+                ScalarValue::Utf8(Rc::new("Main St".to_string())),
+                ScalarValue::UInt16(12345),
+            ],
+            fields.clone(),
+        );
+        assert_eq!(DataType::Struct(fields), value.get_datatype());
+    }
 }
@@ -14,13 +14,15 @@
 
 //! SQL Query Planner (produces logical plan from SQL AST)
 
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::rc::Rc;
 use std::string::String;
 use std::sync::Arc;
 
 use super::execution::error::*;
+use super::execution::temporal;
 use super::logicalplan::*;
+use super::typecoercion::*;
 
 use arrow::datatypes::*;
 
@@ -34,12 +36,35 @@ pub trait SchemaProvider {
 /// SQL query planner
 pub struct SqlToRel {
     schema_provider: Rc<SchemaProvider>,
+    /// The name or alias of the relation in the FROM clause being planned, which can qualify
+    /// its column names as in `t.a`
+    qualifier: Option<String>,
+    /// The plans of the common table expressions in scope, which shadow registered tables
+    ctes: HashMap<String, Rc<LogicalPlan>>,
+    /// The correlated scalar subqueries of the SELECT being planned, which are joined to its
+    /// input, and the expressions for their values
+    correlated: Vec<(ASTNode, Expr)>,
 }
 
 impl SqlToRel {
     /// Create a new query planner
     pub fn new(schema_provider: Rc<SchemaProvider>) -> Self {
-        SqlToRel { schema_provider }
+        SqlToRel {
+            schema_provider,
+            qualifier: None,
+            ctes: HashMap::new(),
+            correlated: vec![],
+        }
+    }
+
+    /// Create a planner for a nested scope, which sees the same common table expressions
+    fn scope(&self, qualifier: Option<String>) -> Self {
+        SqlToRel {
+            schema_provider: self.schema_provider.clone(),
+            qualifier,
+            ctes: self.ctes.clone(),
+            correlated: vec![],
+        }
     }
 
     /// Generate a logic plan from a SQL AST node
@@ -53,8 +78,15 @@ impl SqlToRel {
                 ref order_by,
                 ref group_by,
                 ref having,
+                ref joins,
                 ..
             } => {
+                if !joins.is_empty() {
+                    return Err(ExecutionError::NotImplemented(
+                        "Joins are not supported".to_string(),
+                    ));
+                }
+
                 // parse the input relation so we have access to the row type
                 let input = match relation {
                     &Some(ref r) => self.sql_to_rel(r)?,
@@ -63,21 +95,70 @@ impl SqlToRel {
                     }),
                 };
 
+                // the rest of the query is planned in the scope of the relation
+                let mut planner = self.scope(relation.as_ref().and_then(|r| relation_name(r)));
+
+                // correlated scalar subqueries are joined to the input
+                let (input, correlated) = planner.join_correlated_subqueries(
+                    selection.as_ref().map(|s| s.as_ref()),
+                    projection,
+                    input,
+                )?;
+                planner.correlated = correlated;
+
                 let input_schema = input.schema();
 
                 // selection first
                 let selection_plan = match selection {
-                    &Some(ref filter_expr) => Some(LogicalPlan::Selection {
-                        expr: self.sql_to_rex(&filter_expr, &input_schema.clone())?,
-                        input: input.clone(),
-                    }),
+                    &Some(ref filter_expr) => Some(planner.plan_selection(filter_expr, &input)?),
                     _ => None,
                 };
 
+                // unnest() in the SELECT list expands the rows of the (filtered) input, and the
+                // rest of the SELECT list is planned against the expanded rows
+                let unnest_plan = match projection.iter().filter(|e| is_unnest(e)).count() {
+                    0 => None,
+                    1 => {
+                        let unnest_input = match selection_plan {
+                            Some(ref s) => s.clone(),
+                            None => input.clone(),
+                        };
+                        let unnest_call = projection.iter().find(|e| is_unnest(e)).unwrap();
+                        Some(planner.plan_unnest(unnest_call, unnest_input)?)
+                    }
+                    _ => {
+                        return Err(ExecutionError::NotImplemented(
+                            "Only one unnest() is supported per SELECT".to_string(),
+                        ));
+                    }
+                };
+                let projection_input_schema = match unnest_plan {
+                    Some(ref plan) => plan.schema().clone(),
+                    None => input_schema.clone(),
+                };
+
                 let expr: Vec<Expr> = projection
                     .iter()
-                    .map(|e| self.sql_to_rex(&e, &input_schema))
-                    .collect::<Result<Vec<Expr>>>()?;
+                    .map(|e| {
+                        if is_unnest(e) {
+                            // the unnested element is the last column of the Unnest plan
+                            Ok(vec![Expr::Column(
+                                projection_input_schema.fields().len() - 1,
+                            )])
+                        } else if *e == ASTNode::SQLWildcard {
+                            // `*` selects every column of the input, which is how the DFParser
+                            // applies an ORDER BY or LIMIT to a set operation
+                            Ok((0..projection_input_schema.fields().len())
+                                .map(Expr::Column)
+                                .collect())
+                        } else {
+                            Ok(vec![planner.sql_to_rex(&e, &projection_input_schema)?])
+                        }
+                    })
+                    .collect::<Result<Vec<Vec<Expr>>>>()?
+                    .into_iter()
+                    .flatten()
+                    .collect();
 
                 // collect aggregate expressions
                 let aggr_expr: Vec<Expr> = expr
@@ -89,43 +170,122 @@ impl SqlToRel {
                     .map(|e| e.clone())
                     .collect();
 
+                // collect window function expressions
+                let window_expr: Vec<Expr> = expr
+                    .iter()
+                    .filter(|e| match e {
+                        Expr::WindowFunction { .. } => true,
+                        _ => false,
+                    })
+                    .cloned()
+                    .collect();
+                if !window_expr.is_empty() && unnest_plan.is_some() {
+                    return Err(ExecutionError::NotImplemented(
+                        "unnest() cannot be combined with window functions".to_string(),
+                    ));
+                }
+
                 if aggr_expr.len() > 0 {
+                    if !window_expr.is_empty() {
+                        return Err(ExecutionError::NotImplemented(
+                            "Window functions cannot be combined with aggregate functions"
+                                .to_string(),
+                        ));
+                    }
+                    if unnest_plan.is_some() {
+                        return Err(ExecutionError::NotImplemented(
+                            "unnest() cannot be combined with aggregate functions".to_string(),
+                        ));
+                    }
                     let aggregate_input: Rc<LogicalPlan> = match selection_plan {
-                        Some(s) => Rc::new(s),
+                        Some(s) => s,
                         _ => input.clone(),
                     };
 
-                    let group_expr: Vec<Expr> = match group_by {
-                        Some(gbe) => gbe
-                            .iter()
-                            .map(|e| self.sql_to_rex(&e, &input_schema))
-                            .collect::<Result<Vec<Expr>>>()?,
-                        None => vec![],
+                    let (group_expr, grouping_sets) = match group_by {
+                        Some(gbe) => planner.plan_group_by(gbe, &input_schema)?,
+                        None => (vec![], None),
                     };
                     //println!("GROUP BY: {:?}", group_expr);
 
+                    // the arguments to GROUPING() must be grouping expressions
+                    for e in &aggr_expr {
+                        match e {
+                            Expr::AggregateFunction { name, args, .. }
+                                if name.eq_ignore_ascii_case("grouping") =>
+                            {
+                                if let Some(a) = args.iter().find(|a| !group_expr.contains(*a)) {
+                                    return Err(ExecutionError::General(format!(
+                                        "GROUPING argument {:?} is not a grouping expression",
+                                        a
+                                    )));
+                                }
+                            }
+                            _ => {}
+                        }
+                    }
+
                     let mut all_fields: Vec<Expr> = group_expr.clone();
                     aggr_expr.iter().for_each(|x| all_fields.push(x.clone()));
 
-                    let aggr_schema = Schema::new(exprlist_to_fields(&all_fields, input_schema));
+                    let mut fields = exprlist_to_fields(&all_fields, input_schema)?;
+                    if let Some(ref sets) = grouping_sets {
+                        // grouping expressions are NULL in the rows of grouping sets that do not
+                        // contain them
+                        for (i, field) in fields.iter_mut().enumerate().take(group_expr.len()) {
+                            if sets.iter().any(|set| !set.contains(&i)) {
+                                *field = Field::new(field.name(), field.data_type().clone(), true);
+                            }
+                        }
+                    }
+                    let aggr_schema = Schema::new(fields);
 
                     //TODO: selection, projection, everything else
                     Ok(Rc::new(LogicalPlan::Aggregate {
                         input: aggregate_input,
                         group_expr,
+                        grouping_sets,
                         aggr_expr,
                         schema: Arc::new(aggr_schema),
                     }))
                 } else {
-                    let projection_input: Rc<LogicalPlan> = match selection_plan {
-                        Some(s) => Rc::new(s),
+                    let projection_input: Rc<LogicalPlan> = match (unnest_plan, selection_plan) {
+                        (Some(u), _) => u,
+                        (None, Some(s)) => s,
                         _ => input.clone(),
                     };
 
+                    // window functions are evaluated by a Window plan that appends a column for
+                    // each of them to its input, which the projection then refers to
+                    let (expr, projection_input) = if window_expr.is_empty() {
+                        (expr, projection_input)
+                    } else {
+                        let input_len = projection_input_schema.fields().len();
+                        let mut fields = projection_input_schema.fields().clone();
+                        fields.extend(exprlist_to_fields(&window_expr, &projection_input_schema)?);
+                        let window_plan = LogicalPlan::Window {
+                            input: projection_input,
+                            window_expr,
+                            schema: Arc::new(Schema::new(fields)),
+                        };
+                        let mut window_index = input_len;
+                        let expr = expr
+                            .into_iter()
+                            .map(|e| match e {
+                                Expr::WindowFunction { .. } => {
+                                    window_index += 1;
+                                    Expr::Column(window_index - 1)
+                                }
+                                e => e,
+                            })
+                            .collect();
+                        (expr, Rc::new(window_plan))
+                    };
+
                     let projection_schema = Arc::new(Schema::new(exprlist_to_fields(
                         &expr,
-                        input_schema.as_ref(),
-                    )));
+                        projection_input.schema().as_ref(),
+                    )?));
 
                     let projection = LogicalPlan::Projection {
                         expr: expr,
@@ -146,9 +306,7 @@ impl SqlToRel {
                                 .iter()
                                 .map(|e| {
                                     Ok(Expr::Sort {
-                                        expr: Rc::new(
-                                            self.sql_to_rex(&e.expr, &input_schema).unwrap(),
-                                        ),
+                                        expr: Rc::new(planner.sql_to_rex(&e.expr, &input_schema)?),
                                         asc: e.asc,
                                     })
                                 })
@@ -186,6 +344,10 @@ impl SqlToRel {
                 }
             }
 
+            &ASTNode::SQLIdentifier(ref id) if self.ctes.contains_key(id) => {
+                Ok(self.ctes[id].clone())
+            }
+
             &ASTNode::SQLIdentifier(ref id) => {
                 match self.schema_provider.get_table_meta(id.as_ref()) {
                     Some(schema) => Ok(Rc::new(LogicalPlan::TableScan {
@@ -201,6 +363,34 @@ impl SqlToRel {
                 }
             }
 
+            // a derived table without an alias
+            &ASTNode::SQLNested(ref query) => self.sql_to_rel(query),
+
+            // `FROM relation AS alias (a, b)` is rewritten to this form by the DFParser
+            &ASTNode::SQLFunction { ref id, ref args } if id == "$table_alias" => {
+                let input = match args.first() {
+                    Some(relation) => self.sql_to_rel(relation)?,
+                    None => {
+                        return Err(ExecutionError::General(
+                            "$table_alias requires a relation".to_string(),
+                        ));
+                    }
+                };
+                let columns = identifier_names(&args[1..])?;
+                // the first name is the alias itself, which only qualifies the columns
+                rename_columns(input, &columns[1..])
+            }
+
+            // a query with a WITH clause is rewritten to this form by the DFParser
+            &ASTNode::SQLFunction { ref id, ref args } if id == "$with_query" => {
+                self.plan_with(args)
+            }
+
+            // UNION, INTERSECT and EXCEPT are rewritten to this form by the DFParser
+            &ASTNode::SQLFunction { ref id, ref args } if id == "$set_operation" => {
+                self.plan_set_operation(args)
+            }
+
             _ => Err(ExecutionError::ExecutionError(format!(
                 "sql_to_rel does not support this relation: {:?}",
                 sql
@@ -208,6 +398,989 @@ impl SqlToRel {
         }
     }
 
+    /// Plan a WHERE clause. Subquery predicates among the conditions that it combines with AND
+    /// become semi and anti joins with the input, applied after the other conditions.
+    fn plan_selection(&self, sql: &ASTNode, input: &Rc<LogicalPlan>) -> Result<Rc<LogicalPlan>> {
+        let schema = input.schema().clone();
+        let conditions = conjuncts(sql);
+        if conditions.iter().all(|c| subquery_predicate(c).is_none()) {
+            return Ok(Rc::new(LogicalPlan::Selection {
+                expr: self.sql_to_rex(sql, &schema)?,
+                input: input.clone(),
+            }));
+        }
+
+        let mut filter: Option<Expr> = None;
+        let mut subqueries = vec![];
+        for condition in conditions {
+            match subquery_predicate(condition) {
+                Some(subquery) => subqueries.push(subquery),
+                None => {
+                    let expr = self.sql_to_rex(condition, &schema)?;
+                    filter = Some(match filter {
+                        Some(left) => Expr::BinaryExpr {
+                            left: Rc::new(left),
+                            op: Operator::And,
+                            right: Rc::new(expr),
+                        },
+                        None => expr,
+                    });
+                }
+            }
+        }
+
+        let mut plan = match filter {
+            Some(expr) => Rc::new(LogicalPlan::Selection {
+                expr,
+                input: input.clone(),
+            }),
+            None => input.clone(),
+        };
+        for (value, query, negated) in subqueries {
+            let (right, correlation) = self.plan_subquery(query, &schema, value.is_some())?;
+            let right_schema = right.schema().clone();
+            let mut on = correlation
+                .into_iter()
+                .map(|(l, r)| coerce_join_keys(l, &schema, r, &right_schema))
+                .collect::<Result<Vec<(Expr, Expr)>>>()?;
+            // the value of IN is compared with the first column of the subquery
+            if let Some(value) = value {
+                let value = self.sql_to_rex(value, &schema)?;
+                on.push(coerce_join_keys(
+                    value,
+                    &schema,
+                    Expr::Column(0),
+                    &right_schema,
+                )?);
+            }
+            plan = Rc::new(LogicalPlan::SemiJoin {
+                left: plan,
+                right,
+                on,
+                anti: negated,
+                null_aware: negated && value.is_some(),
+            });
+        }
+        Ok(plan)
+    }
+
+    /// Plan a subquery of a WHERE clause for a semi or anti join, returning its plan along with
+    /// the pairs of outer and inner expressions that correlate it with the outer query. Only
+    /// equalities between an outer and an inner expression can correlate a subquery, and they
+    /// are removed from its WHERE clause and appended to its columns. When `value` is set, the
+    /// first column of the plan is the value of the subquery (as for IN).
+    fn plan_subquery(
+        &self,
+        sql: &ASTNode,
+        outer_schema: &Schema,
+        value: bool,
+    ) -> Result<(Rc<LogicalPlan>, Vec<(Expr, Expr)>)> {
+        let (projection, relation, selection, group_by, having, limit) = match sql {
+            ASTNode::SQLNested(query) => return self.plan_subquery(query, outer_schema, value),
+            ASTNode::SQLSelect {
+                projection,
+                relation,
+                selection,
+                group_by,
+                having,
+                limit,
+                ..
+            } => (projection, relation, selection, group_by, having, limit),
+            // a set operation cannot be correlated
+            ASTNode::SQLFunction { id, .. } if id == "$set_operation" => {
+                return Ok((self.sql_to_rel(sql)?, vec![]));
+            }
+            other => {
+                return Err(ExecutionError::NotImplemented(format!(
+                    "Unsupported subquery {:?}",
+                    other
+                )));
+            }
+        };
+
+        let input = match relation {
+            Some(r) => self.sql_to_rel(r)?,
+            None => Rc::new(LogicalPlan::EmptyRelation {
+                schema: Arc::new(Schema::empty()),
+            }),
+        };
+        let input_schema = input.schema().clone();
+        let planner = self.scope(relation.as_ref().and_then(|r| relation_name(r)));
+
+        let (filter, correlation) =
+            self.split_correlation(&planner, selection, &input_schema, outer_schema)?;
+
+        // the SELECT list only matters for the value of IN, or when it aggregates the rows
+        let select_expr = projection
+            .iter()
+            .filter(|e| **e != ASTNode::SQLWildcard)
+            .map(|e| planner.sql_to_rex(e, &input_schema))
+            .collect::<Result<Vec<Expr>>>()?;
+        let aggregates = select_expr.iter().any(|e| match e {
+            Expr::AggregateFunction { .. } => true,
+            _ => false,
+        });
+        if aggregates || group_by.is_some() || having.is_some() || limit.is_some() {
+            if !correlation.is_empty() {
+                return Err(ExecutionError::NotImplemented(
+                    "Correlated subqueries with aggregates, GROUP BY, HAVING or LIMIT are not \
+                     supported"
+                        .to_string(),
+                ));
+            }
+            let plan = self.sql_to_rel(sql)?;
+            if value && plan.schema().fields().len() != 1 {
+                return Err(ExecutionError::General(format!(
+                    "Subquery of IN must return one column but returns {}",
+                    plan.schema().fields().len()
+                )));
+            }
+            return Ok((plan, vec![]));
+        }
+
+        let filtered = match filter {
+            Some(expr) => Rc::new(LogicalPlan::Selection {
+                expr,
+                input: input.clone(),
+            }),
+            None => input.clone(),
+        };
+        let mut expr = vec![];
+        if value {
+            if select_expr.len() != 1 || projection.len() != 1 {
+                return Err(ExecutionError::General(format!(
+                    "Subquery of IN must return one column but returns {}",
+                    projection.len()
+                )));
+            }
+            expr.push(select_expr[0].clone());
+        }
+        let offset = expr.len();
+        let mut outer_expr = vec![];
+        for (outer, inner) in correlation {
+            outer_expr.push(outer);
+            expr.push(inner);
+        }
+        if expr.is_empty() {
+            return Ok((filtered, vec![]));
+        }
+        let schema = Arc::new(Schema::new(exprlist_to_fields(&expr, &input_schema)?));
+        let plan = Rc::new(LogicalPlan::Projection {
+            expr,
+            input: filtered,
+            schema,
+        });
+        let on = outer_expr
+            .into_iter()
+            .enumerate()
+            .map(|(i, outer)| (outer, Expr::Column(offset + i)))
+            .collect();
+        Ok((plan, on))
+    }
+
+    /// Plan the WHERE clause of a subquery, returning the conditions on its own rows along with
+    /// the pairs of outer and inner expressions of the conditions that correlate it with the
+    /// outer query
+    fn split_correlation(
+        &self,
+        planner: &SqlToRel,
+        selection: &Option<Box<ASTNode>>,
+        inner_schema: &Schema,
+        outer_schema: &Schema,
+    ) -> Result<(Option<Expr>, Vec<(Expr, Expr)>)> {
+        // conditions that the subquery cannot plan on its own rows correlate it
+        let mut filter: Option<Expr> = None;
+        let mut correlation: Vec<(Expr, Expr)> = vec![];
+        if let Some(selection) = selection {
+            for condition in conjuncts(selection) {
+                match planner.sql_to_rex(condition, inner_schema) {
+                    Ok(expr) => {
+                        filter = Some(match filter {
+                            Some(left) => Expr::BinaryExpr {
+                                left: Rc::new(left),
+                                op: Operator::And,
+                                right: Rc::new(expr),
+                            },
+                            None => expr,
+                        })
+                    }
+                    Err(e) => {
+                        match self.correlation(planner, condition, inner_schema, outer_schema) {
+                            Some(pair) => correlation.push(pair),
+                            None => return Err(e),
+                        }
+                    }
+                }
+            }
+        }
+        Ok((filter, correlation))
+    }
+
+    /// Join the correlated scalar subqueries in the WHERE clause and the SELECT list of a query
+    /// to its input, returning the joined plan along with the subqueries and the expressions
+    /// for their values. Uncorrelated scalar subqueries are left to `sql_to_rex`.
+    fn join_correlated_subqueries(
+        &self,
+        selection: Option<&ASTNode>,
+        projection: &[ASTNode],
+        input: Rc<LogicalPlan>,
+    ) -> Result<(Rc<LogicalPlan>, Vec<(ASTNode, Expr)>)> {
+        let mut subqueries = vec![];
+        for sql in selection.into_iter().chain(projection.iter()) {
+            scalar_subqueries(sql, &mut subqueries);
+        }
+
+        let outer_schema = input.schema().clone();
+        let mut plan = input;
+        let mut correlated: Vec<(ASTNode, Expr)> = vec![];
+        for sql in subqueries {
+            if correlated.iter().any(|(s, _)| s == sql) {
+                continue;
+            }
+            let (right, correlation, count) = match self.decorrelate_subquery(sql, &outer_schema)? {
+                Some(decorrelated) => decorrelated,
+                None => continue,
+            };
+            let right_schema = right.schema().clone();
+            let on = correlation
+                .into_iter()
+                .map(|(l, r)| coerce_join_keys(l, &outer_schema, r, &right_schema))
+                .collect::<Result<Vec<(Expr, Expr)>>>()?;
+
+            // the value is the last column of the right input
+            let left_len = plan.schema().fields().len();
+            let mut fields = plan.schema().fields().clone();
+            for field in right_schema.fields() {
+                fields.push(Field::new(field.name(), field.data_type().clone(), true));
+            }
+            let value = Expr::Column(left_len + right_schema.fields().len() - 1);
+            // COUNT is zero rather than NULL when no rows match
+            let value = if count {
+                Expr::ScalarFunction {
+                    name: "COALESCE".to_string(),
+                    args: vec![value, Expr::Literal(ScalarValue::UInt64(0))],
+                    return_type: DataType::UInt64,
+                }
+            } else {
+                value
+            };
+            plan = Rc::new(LogicalPlan::SingleJoin {
+                left: plan,
+                right,
+                on,
+                schema: Arc::new(Schema::new(fields)),
+            });
+            correlated.push((sql.clone(), value));
+        }
+        Ok((plan, correlated))
+    }
+
+    /// Plan a correlated scalar subquery as the right input of a single join, returning its
+    /// plan, the pairs of outer and inner expressions that correlate it and whether its value
+    /// is a COUNT. The value is the last column of the plan, after the correlated columns. An
+    /// aggregate subquery is grouped by the correlated columns so that it has one row for each
+    /// of their values. Returns `None` when the subquery is not correlated.
+    fn decorrelate_subquery(
+        &self,
+        sql: &ASTNode,
+        outer_schema: &Schema,
+    ) -> Result<Option<(Rc<LogicalPlan>, Vec<(Expr, Expr)>, bool)>> {
+        let (projection, relation, selection, group_by, having, limit) = match sql {
+            ASTNode::SQLNested(query) => return self.decorrelate_subquery(query, outer_schema),
+            ASTNode::SQLSelect {
+                projection,
+                relation,
+                selection,
+                group_by,
+                having,
+                limit,
+                ..
+            } => (projection, relation, selection, group_by, having, limit),
+            _ => return Ok(None),
+        };
+
+        let input = match relation {
+            Some(r) => self.sql_to_rel(r)?,
+            None => Rc::new(LogicalPlan::EmptyRelation {
+                schema: Arc::new(Schema::empty()),
+            }),
+        };
+        let input_schema = input.schema().clone();
+        let planner = self.scope(relation.as_ref().and_then(|r| relation_name(r)));
+        let (filter, correlation) =
+            self.split_correlation(&planner, selection, &input_schema, outer_schema)?;
+        if correlation.is_empty() {
+            return Ok(None);
+        }
+        if group_by.is_some() || having.is_some() || limit.is_some() {
+            return Err(ExecutionError::NotImplemented(
+                "Correlated scalar subqueries with GROUP BY, HAVING or LIMIT are not supported"
+                    .to_string(),
+            ));
+        }
+        if projection.len() != 1 {
+            return Err(ExecutionError::General(format!(
+                "Scalar subquery must return one column but returns {}",
+                projection.len()
+            )));
+        }
+
+        let filtered = match filter {
+            Some(expr) => Rc::new(LogicalPlan::Selection {
+                expr,
+                input: input.clone(),
+            }),
+            None => input.clone(),
+        };
+        let value = planner.sql_to_rex(&projection[0], &input_schema)?;
+        let (outer_expr, mut expr): (Vec<Expr>, Vec<Expr>) = correlation.into_iter().unzip();
+        let on = outer_expr
+            .into_iter()
+            .enumerate()
+            .map(|(i, outer)| (outer, Expr::Column(i)))
+            .collect();
+        let (plan, count) = match value {
+            Expr::AggregateFunction { ref name, .. } => {
+                let count = name.eq_ignore_ascii_case("count");
+                let mut all_expr = expr.clone();
+                all_expr.push(value.clone());
+                let schema = Arc::new(Schema::new(exprlist_to_fields(&all_expr, &input_schema)?));
+                let plan = LogicalPlan::Aggregate {
+                    input: filtered,
+                    group_expr: expr,
+                    grouping_sets: None,
+                    aggr_expr: vec![value.clone()],
+                    schema,
+                };
+                (plan, count)
+            }
+            _ => {
+                expr.push(value);
+                let schema = Arc::new(Schema::new(exprlist_to_fields(&expr, &input_schema)?));
+                let plan = LogicalPlan::Projection {
+                    expr,
+                    input: filtered,
+                    schema,
+                };
+                (plan, false)
+            }
+        };
+        Ok(Some((Rc::new(plan), on, count)))
+    }
+
+    /// Plan a condition of a subquery as an equality between an outer expression and an
+    /// expression over the rows of the subquery, which `planner` plans
+    fn correlation(
+        &self,
+        planner: &SqlToRel,
+        sql: &ASTNode,
+        inner_schema: &Schema,
+        outer_schema: &Schema,
+    ) -> Option<(Expr, Expr)> {
+        match sql {
+            ASTNode::SQLBinaryExpr {
+                left,
+                op: SQLOperator::Eq,
+                right,
+            } => {
+                for (inner, outer) in &[(left, right), (right, left)] {
+                    if let (Ok(inner), Ok(outer)) = (
+                        planner.sql_to_rex(inner, inner_schema),
+                        self.sql_to_rex(outer, outer_schema),
+                    ) {
+                        return Some((outer, inner));
+                    }
+                }
+                None
+            }
+            _ => None,
+        }
+    }
+
+    /// Plan the arguments of `$with_query($cte(...), ..., (query))`. Each common table expression
+    /// is in scope for the ones after it and for the query.
+    fn plan_with(&self, args: &[ASTNode]) -> Result<Rc<LogicalPlan>> {
+        let (query, ctes) = match args.split_last() {
+            Some(split) => split,
+            None => {
+                return Err(ExecutionError::General(
+                    "$with_query requires a query".to_string(),
+                ));
+            }
+        };
+
+        let mut planner = self.scope(None);
+        let mut names: HashSet<String> = HashSet::new();
+        for cte in ctes {
+            let args = match cte {
+                ASTNode::SQLFunction { id, args } if id == "$cte" && args.len() >= 3 => args,
+                other => {
+                    return Err(ExecutionError::General(format!(
+                        "Invalid common table expression {:?}",
+                        other
+                    )));
+                }
+            };
+            let (name, columns) = match (&args[0], &args[1]) {
+                (ASTNode::SQLIdentifier(name), ASTNode::SQLFunction { id, args: columns })
+                    if id == "$cte_columns" =>
+                {
+                    (name.clone(), identifier_names(columns)?)
+                }
+                _ => {
+                    return Err(ExecutionError::General(format!(
+                        "Invalid common table expression {:?}",
+                        cte
+                    )));
+                }
+            };
+            if !names.insert(name.clone()) {
+                return Err(ExecutionError::General(format!(
+                    "WITH query name '{}' is specified more than once",
+                    name
+                )));
+            }
+
+            let plan = match &args[2..] {
+                [query] => rename_columns(planner.sql_to_rel(query)?, &columns)?,
+                [static_term, recursive_term, union] => {
+                    // the union is either 'ALL' or 'DISTINCT'
+                    let distinct = match union {
+                        ASTNode::SQLValue(sqlparser::sqlast::Value::SingleQuotedString(s)) => {
+                            s == "DISTINCT"
+                        }
+                        _ => false,
+                    };
+                    planner.plan_recursive_query(
+                        &name,
+                        &columns,
+                        static_term,
+                        recursive_term,
+                        distinct,
+                    )?
+                }
+                _ => {
+                    return Err(ExecutionError::General(format!(
+                        "Invalid common table expression {:?}",
+                        cte
+                    )));
+                }
+            };
+            planner.ctes.insert(name, plan);
+        }
+
+        planner.sql_to_rel(query)
+    }
+
+    /// Plan a recursive common table expression. The column types are those of the static term,
+    /// and the recursive term is planned with `name` bound to the working table.
+    fn plan_recursive_query(
+        &self,
+        name: &str,
+        columns: &[String],
+        static_term: &ASTNode,
+        recursive_term: &ASTNode,
+        distinct: bool,
+    ) -> Result<Rc<LogicalPlan>> {
+        let static_term = rename_columns(self.sql_to_rel(static_term)?, columns)?;
+
+        // the recursive term can produce NULLs where the static term does not
+        let fields: Vec<Field> = static_term
+            .schema()
+            .fields()
+            .iter()
+            .map(|f| Field::new(f.name(), f.data_type().clone(), true))
+            .collect();
+        let schema = Arc::new(Schema::new(fields));
+
+        let mut planner = self.scope(None);
+        planner.ctes.insert(
+            name.to_string(),
+            Rc::new(LogicalPlan::TableScan {
+                schema_name: String::from("default"),
+                table_name: name.to_string(),
+                schema: schema.clone(),
+                projection: None,
+            }),
+        );
+        let recursive_term = planner.sql_to_rel(recursive_term)?;
+
+        // the values of the recursive term are cast to the column types
+        let recursive_schema = recursive_term.schema().clone();
+        if recursive_schema.fields().len() != schema.fields().len() {
+            return Err(ExecutionError::General(format!(
+                "The recursive term of '{}' has {} columns but the static term has {}",
+                name,
+                recursive_schema.fields().len(),
+                schema.fields().len()
+            )));
+        }
+        let expr = schema
+            .fields()
+            .iter()
+            .zip(recursive_schema.fields())
+            .enumerate()
+            .map(|(i, (field, recursive_field))| {
+                let column = Expr::Column(i);
+                if field.data_type() == recursive_field.data_type() {
+                    Ok(column)
+                } else if get_supertype(field.data_type(), recursive_field.data_type()).is_some() {
+                    Ok(Expr::Cast {
+                        expr: Rc::new(column),
+                        data_type: field.data_type().clone(),
+                    })
+                } else {
+                    Err(ExecutionError::General(format!(
+                        "Column '{}' of '{}' has type {:?} in the static term but type {:?} in \
+                         the recursive term",
+                        field.name(),
+                        name,
+                        field.data_type(),
+                        recursive_field.data_type()
+                    )))
+                }
+            })
+            .collect::<Result<Vec<Expr>>>()?;
+        let recursive_term = Rc::new(LogicalPlan::Projection {
+            expr,
+            input: recursive_term,
+            schema: schema.clone(),
+        });
+
+        Ok(Rc::new(LogicalPlan::RecursiveQuery {
+            name: name.to_string(),
+            static_term,
+            recursive_term,
+            distinct,
+            schema,
+        }))
+    }
+
+    /// Plan a UNION, INTERSECT or EXCEPT of two queries. Their columns are matched by position
+    /// and cast to a common type, and are named after the columns of the left query.
+    fn plan_set_operation(&self, args: &[ASTNode]) -> Result<Rc<LogicalPlan>> {
+        let (name, quantifier, left, right) = match args {
+            [ASTNode::SQLValue(name), ASTNode::SQLValue(quantifier), left, right] => {
+                (name, quantifier, left, right)
+            }
+            _ => {
+                return Err(ExecutionError::General(
+                    "$set_operation requires an operator, a quantifier and two queries".to_string(),
+                ));
+            }
+        };
+        let (name, op) = match name {
+            sqlparser::sqlast::Value::SingleQuotedString(name) => match name.as_str() {
+                "UNION" => (name, SetOperator::Union),
+                "INTERSECT" => (name, SetOperator::Intersect),
+                "EXCEPT" => (name, SetOperator::Except),
+                other => {
+                    return Err(ExecutionError::General(format!(
+                        "Unknown set operation {}",
+                        other
+                    )));
+                }
+            },
+            other => {
+                return Err(ExecutionError::General(format!(
+                    "Unknown set operation {:?}",
+                    other
+                )));
+            }
+        };
+        // the quantifier is either 'ALL' or 'DISTINCT'
+        let all = match quantifier {
+            sqlparser::sqlast::Value::SingleQuotedString(s) => s == "ALL",
+            _ => false,
+        };
+
+        let left = self.sql_to_rel(left)?;
+        let right = self.sql_to_rel(right)?;
+        let left_schema = left.schema().clone();
+        let right_schema = right.schema().clone();
+        if left_schema.fields().len() != right_schema.fields().len() {
+            return Err(ExecutionError::General(format!(
+                "Each query of {} must have the same number of columns but they have {} and {}",
+                name,
+                left_schema.fields().len(),
+                right_schema.fields().len()
+            )));
+        }
+        let fields = left_schema
+            .fields()
+            .iter()
+            .zip(right_schema.fields())
+            .map(|(l, r)| match get_supertype(l.data_type(), r.data_type()) {
+                Some(data_type) => Ok(Field::new(
+                    l.name(),
+                    data_type,
+                    l.is_nullable() || r.is_nullable(),
+                )),
+                None => Err(ExecutionError::General(format!(
+                    "Column '{}' of {} has type {:?} in the left query but type {:?} in the \
+                     right query",
+                    l.name(),
+                    name,
+                    l.data_type(),
+                    r.data_type()
+                ))),
+            })
+            .collect::<Result<Vec<Field>>>()?;
+        let schema = Arc::new(Schema::new(fields));
+
+        Ok(Rc::new(LogicalPlan::SetOperation {
+            op,
+            all,
+            left: cast_columns(left, &schema),
+            right: cast_columns(right, &schema),
+            schema,
+        }))
+    }
+
+    /// Plan an `unnest(list)` call from the SELECT list as an Unnest relation over the input
+    fn plan_unnest(&self, sql: &ASTNode, input: Rc<LogicalPlan>) -> Result<Rc<LogicalPlan>> {
+        let args = match sql {
+            ASTNode::SQLFunction { args, .. } if args.len() == 1 => args,
+            _ => {
+                return Err(ExecutionError::General(
+                    "unnest() requires a single argument".to_string(),
+                ));
+            }
+        };
+        let input_schema = input.schema().clone();
+        let expr = self.sql_to_rex(&args[0], &input_schema)?;
+        let element_type = match expr.get_type(&input_schema)? {
+            DataType::List(t) => *t,
+            other => {
+                return Err(ExecutionError::General(format!(
+                    "unnest() requires a list argument but found {:?}",
+                    other
+                )));
+            }
+        };
+
+        let mut fields = input_schema.fields().clone();
+        fields.push(Field::new("unnest", element_type, true));
+        Ok(Rc::new(LogicalPlan::Unnest {
+            expr,
+            input,
+            schema: Arc::new(Schema::new(fields)),
+        }))
+    }
+
+    /// Plan a window function. `f(...) OVER (PARTITION BY p ORDER BY o ROWS ...)` is rewritten by
+    /// the DFParser to `$window_over(f(...), $window_partition(p), $window_order(o),
+    /// $window_frame(...))`.
+    fn plan_window_function(&self, args: &[ASTNode], schema: &Schema) -> Result<Expr> {
+        let (id, function_args) = match args.first() {
+            Some(ASTNode::SQLFunction { id, args }) => (id, args),
+            _ => {
+                return Err(ExecutionError::General(
+                    "OVER must follow a function call".to_string(),
+                ));
+            }
+        };
+
+        let mut partition_by = vec![];
+        let mut order_by = vec![];
+        let mut frame = None;
+        for clause in &args[1..] {
+            match clause {
+                ASTNode::SQLFunction { id, args } if id == "$window_partition" => {
+                    partition_by = args
+                        .iter()
+                        .map(|a| self.sql_to_rex(a, schema))
+                        .collect::<Result<Vec<Expr>>>()?;
+                }
+                ASTNode::SQLFunction { id, args } if id == "$window_order" => {
+                    order_by = args
+                        .iter()
+                        .map(|a| match a {
+                            ASTNode::SQLFunction { id, args }
+                                if id == "$sort_desc" && args.len() == 1 =>
+                            {
+                                Ok(Expr::Sort {
+                                    expr: Rc::new(self.sql_to_rex(&args[0], schema)?),
+                                    asc: false,
+                                })
+                            }
+                            _ => Ok(Expr::Sort {
+                                expr: Rc::new(self.sql_to_rex(a, schema)?),
+                                asc: true,
+                            }),
+                        })
+                        .collect::<Result<Vec<Expr>>>()?;
+                }
+                ASTNode::SQLFunction { id, args } if id == "$window_frame" => {
+                    frame = Some(plan_window_frame(args)?);
+                }
+                other => {
+                    return Err(ExecutionError::General(format!(
+                        "Invalid window specification {:?}",
+                        other
+                    )));
+                }
+            }
+        }
+        for e in partition_by.iter().chain(order_by.iter()) {
+            match e.get_type(schema)? {
+                t @ DataType::List(_) | t @ DataType::Struct(_) => {
+                    return Err(ExecutionError::NotImplemented(format!(
+                        "Window PARTITION BY and ORDER BY expressions of type {:?}",
+                        t
+                    )));
+                }
+                _ => {}
+            }
+        }
+
+        let name = id.to_lowercase();
+        let (args, return_type) = match name.as_ref() {
+            "row_number" | "rank" | "dense_rank" => {
+                if !function_args.is_empty() {
+                    return Err(ExecutionError::General(format!(
+                        "{} does not take any arguments",
+                        id
+                    )));
+                }
+                (vec![], DataType::UInt64)
+            }
+            "ntile" => match function_args.as_slice() {
+                [ASTNode::SQLValue(sqlparser::sqlast::Value::Long(n))] if *n > 0 => (
+                    vec![Expr::Literal(ScalarValue::Int64(*n))],
+                    DataType::UInt64,
+                ),
+                _ => {
+                    return Err(ExecutionError::General(
+                        "NTILE requires a positive integer number of buckets".to_string(),
+                    ));
+                }
+            },
+            "lag" | "lead" => {
+                if function_args.is_empty() || function_args.len() > 3 {
+                    return Err(ExecutionError::General(format!(
+                        "{} requires between 1 and 3 arguments",
+                        id
+                    )));
+                }
+                let mut value = self.sql_to_rex(&function_args[0], schema)?;
+                let mut value_type = value.get_type(schema)?;
+                let mut default = None;
+                if let Some(e) = function_args.get(2) {
+                    // the default takes the place of the value, so both have a common type
+                    let (mut coerced, common_type) = coerce_to_common_type(
+                        &vec![value, self.sql_to_rex(e, schema)?],
+                        schema,
+                        id,
+                    )?;
+                    default = coerced.pop();
+                    value = coerced.pop().unwrap();
+                    value_type = common_type;
+                }
+                let mut rex_args = vec![value];
+                if let Some(offset) = function_args.get(1) {
+                    match offset {
+                        ASTNode::SQLValue(sqlparser::sqlast::Value::Long(n)) if *n >= 0 => {
+                            rex_args.push(Expr::Literal(ScalarValue::Int64(*n)))
+                        }
+                        _ => {
+                            return Err(ExecutionError::General(format!(
+                                "The offset of {} must be a non-negative integer",
+                                id
+                            )));
+                        }
+                    }
+                }
+                rex_args.extend(default);
+                (rex_args, value_type)
+            }
+            "first_value" | "last_value" => {
+                if function_args.len() != 1 {
+                    return Err(ExecutionError::General(format!(
+                        "{} requires a single argument",
+                        id
+                    )));
+                }
+                let value = self.sql_to_rex(&function_args[0], schema)?;
+                let value_type = value.get_type(schema)?;
+                (vec![value], value_type)
+            }
+            _ => match self.sql_to_rex(&args[0], schema)? {
+                Expr::AggregateFunction { ref name, .. }
+                    if name.eq_ignore_ascii_case("grouping") =>
+                {
+                    return Err(ExecutionError::General(
+                        "GROUPING cannot be used as a window function".to_string(),
+                    ));
+                }
+                Expr::AggregateFunction {
+                    args,
+                    return_type,
+                    filter: None,
+                    ..
+                } => (args, return_type),
+                Expr::AggregateFunction { .. } => {
+                    return Err(ExecutionError::NotImplemented(
+                        "FILTER on aggregate functions used as window functions".to_string(),
+                    ));
+                }
+                _ => {
+                    return Err(ExecutionError::General(format!(
+                        "{} is not a window function",
+                        id
+                    )));
+                }
+            },
+        };
+
+        Ok(Expr::WindowFunction {
+            name: id.clone(),
+            args,
+            partition_by,
+            order_by,
+            frame,
+            return_type,
+        })
+    }
+
+    /// Plan the GROUP BY clause as a list of distinct grouping expressions and, when ROLLUP,
+    /// CUBE or GROUPING SETS are used, the grouping sets as indices into that list
+    fn plan_group_by(
+        &self,
+        group_by: &[ASTNode],
+        schema: &Schema,
+    ) -> Result<(Vec<Expr>, Option<Vec<Vec<usize>>>)> {
+        if !group_by.iter().any(is_grouping_sets) {
+            let group_expr = group_by
+                .iter()
+                .map(|e| self.sql_to_rex(&e, schema))
+                .collect::<Result<Vec<Expr>>>()?;
+            return Ok((group_expr, None));
+        }
+
+        // the grouping sets of the GROUP BY items are combined with a cross product
+        let mut sql_sets: Vec<Vec<ASTNode>> = vec![vec![]];
+        for item in group_by {
+            let item_sets = expand_grouping_sets(item)?;
+            sql_sets = sql_sets
+                .iter()
+                .flat_map(|a| {
+                    item_sets
+                        .iter()
+                        .map(move |b| a.iter().chain(b.iter()).cloned().collect())
+                })
+                .collect();
+        }
+
+        let mut group_expr: Vec<Expr> = vec![];
+        let mut grouping_sets: Vec<Vec<usize>> = vec![];
+        for sql_set in &sql_sets {
+            let mut set = vec![];
+            for sql in sql_set {
+                let expr = self.sql_to_rex(sql, schema)?;
+                let index = match group_expr.iter().position(|e| *e == expr) {
+                    Some(index) => index,
+                    None => {
+                        group_expr.push(expr);
+                        group_expr.len() - 1
+                    }
+                };
+                if !set.contains(&index) {
+                    set.push(index);
+                }
+            }
+            grouping_sets.push(set);
+        }
+        Ok((group_expr, Some(grouping_sets)))
+    }
+
+    /// Plan the ORDER BY key of an aggregate function such as `array_agg(x, key)`. sqlparser
+    /// cannot parse `array_agg(x ORDER BY key)`, so the DFParser passes the key as the last
+    /// argument.
+    fn aggregate_order_key(&self, sql: &ASTNode, schema: &Schema) -> Result<Expr> {
+        if is_sort_desc(sql) {
+            return Err(ExecutionError::NotImplemented(
+                "Aggregates cannot be ordered in descending order".to_string(),
+            ));
+        }
+        let key = self.sql_to_rex(sql, schema)?;
+        match key.get_type(schema)? {
+            DataType::List(_) | DataType::Struct(_) => Err(ExecutionError::NotImplemented(
+                "Aggregates cannot be ordered by nested types".to_string(),
+            )),
+            _ => Ok(key),
+        }
+    }
+
+    /// Plan a CASE expression. A simple CASE compares the operand with each WHEN value, so they
+    /// are coerced to a common type, while the WHEN conditions of a searched CASE must be
+    /// boolean. The THEN and ELSE values are coerced to a common type.
+    fn case_to_rex(
+        &self,
+        operand: Option<&ASTNode>,
+        case: &ASTNode,
+        schema: &Schema,
+    ) -> Result<Expr> {
+        let (conditions, results, else_result) = match case {
+            ASTNode::SQLCase {
+                conditions,
+                results,
+                else_result,
+            } => (conditions, results, else_result),
+            other => {
+                return Err(ExecutionError::General(format!(
+                    "Expected a CASE expression but found {:?}",
+                    other
+                )));
+            }
+        };
+
+        let mut when_expr = conditions
+            .iter()
+            .map(|c| self.sql_to_rex(c, schema))
+            .collect::<Result<Vec<Expr>>>()?;
+        let operand = match operand {
+            Some(operand) => {
+                when_expr.insert(0, self.sql_to_rex(operand, schema)?);
+                let (mut all, _) = coerce_to_common_type(&when_expr, schema, "CASE")?;
+                let operand = all.remove(0);
+                when_expr = all;
+                Some(Rc::new(operand))
+            }
+            None => {
+                for w in &when_expr {
+                    let when_type = w.get_type(schema)?;
+                    if when_type != DataType::Boolean {
+                        return Err(ExecutionError::General(format!(
+                            "CASE WHEN condition must be boolean but found {:?}",
+                            when_type
+                        )));
+                    }
+                }
+                None
+            }
+        };
+
+        let mut result_expr = results
+            .iter()
+            .map(|r| self.sql_to_rex(r, schema))
+            .collect::<Result<Vec<Expr>>>()?;
+        if let Some(e) = else_result {
+            result_expr.push(self.sql_to_rex(e, schema)?);
+        }
+        let (mut result_expr, _) = coerce_to_common_type(&result_expr, schema, "CASE")?;
+        let else_expr = match else_result {
+            Some(_) => Some(Rc::new(result_expr.pop().unwrap())),
+            None => None,
+        };
+
+        Ok(Expr::Case {
+            expr: operand,
+            when_then_expr: when_expr.into_iter().zip(result_expr).collect(),
+            else_expr,
+        })
+    }
+
     /// Generate a relational expression from a SQL expression
     pub fn sql_to_rex(&self, sql: &ASTNode, schema: &Schema) -> Result<Expr> {
         match sql {
@@ -220,6 +1393,12 @@ impl SqlToRel {
             &ASTNode::SQLValue(sqlparser::sqlast::Value::SingleQuotedString(ref s)) => {
                 Ok(Expr::Literal(ScalarValue::Utf8(Rc::new(s.clone()))))
             }
+            &ASTNode::SQLValue(sqlparser::sqlast::Value::Boolean(b)) => {
+                Ok(Expr::Literal(ScalarValue::Boolean(b)))
+            }
+            &ASTNode::SQLValue(sqlparser::sqlast::Value::Null) => {
+                Ok(Expr::Literal(ScalarValue::Null))
+            }
 
             &ASTNode::SQLIdentifier(ref id) => {
                 match schema.fields().iter().position(|c| c.name().eq(id)) {
@@ -232,6 +1411,24 @@ impl SqlToRel {
                 }
             }
 
+            &ASTNode::SQLCompoundIdentifier(ref ids) => {
+                // a leading relation name qualifies the column, as in `t.a`
+                let ids = match self.qualifier {
+                    Some(ref qualifier) if ids.len() > 1 && ids[0] == *qualifier => &ids[1..],
+                    _ => &ids[..],
+                };
+                if ids.len() == 1 {
+                    return self.sql_to_rex(&ASTNode::SQLIdentifier(ids[0].clone()), schema);
+                }
+                // the first identifier is a column and the rest are nested struct fields
+                let mut expr = self.sql_to_rex(&ASTNode::SQLIdentifier(ids[0].clone()), schema)?;
+                for name in &ids[1..] {
+                    expr = expr.get_field(name);
+                    expr.get_type(schema)?;
+                }
+                Ok(expr)
+            }
+
             &ASTNode::SQLWildcard => {
                 //                schema.columns().iter().enumerate()
                 //                    .map(|(i,c)| Ok(Expr::Column(i))).collect()
@@ -246,6 +1443,77 @@ impl SqlToRel {
                 data_type: convert_data_type(data_type)?,
             }),
 
+            &ASTNode::SQLNested(ref expr) => self.sql_to_rex(expr, schema),
+
+            &ASTNode::SQLSelect { .. } => {
+                // correlated subqueries were joined to the input of the SELECT
+                if let Some((_, expr)) = self.correlated.iter().find(|(s, _)| s == sql) {
+                    return Ok(expr.clone());
+                }
+                let plan = self.sql_to_rel(sql)?;
+                if plan.schema().fields().len() != 1 {
+                    return Err(ExecutionError::General(format!(
+                        "Scalar subquery must return one column but returns {}",
+                        plan.schema().fields().len()
+                    )));
+                }
+                Ok(Expr::ScalarSubquery(SubqueryPlan(plan)))
+            }
+
+            &ASTNode::SQLUnary {
+                ref operator,
+                expr: ref operand,
+            } => {
+                let expr = self.sql_to_rex(operand, schema)?;
+                let expr_type = expr.get_type(schema)?;
+                match operator {
+                    &SQLOperator::Not => match expr_type {
+                        DataType::Boolean => Ok(expr.not()),
+                        other => Err(ExecutionError::General(format!(
+                            "NOT requires a boolean argument but found {:?}",
+                            other
+                        ))),
+                    },
+                    &SQLOperator::Plus | &SQLOperator::Minus => {
+                        match expr_type {
+                            DataType::Int8
+                            | DataType::Int16
+                            | DataType::Int32
+                            | DataType::Int64
+                            | DataType::Float32
+                            | DataType::Float64 => {}
+                            other => {
+                                return Err(ExecutionError::General(format!(
+                                    "Unary {:?} requires a signed numeric argument but found {:?}",
+                                    operator, other
+                                )));
+                            }
+                        }
+                        if let &SQLOperator::Plus = operator {
+                            return Ok(expr);
+                        }
+                        // negative literals are folded rather than evaluated per row
+                        match expr {
+                            Expr::Literal(ScalarValue::Int64(n)) => match n.checked_neg() {
+                                Some(n) => Ok(Expr::Literal(ScalarValue::Int64(n))),
+                                None => Err(ExecutionError::General(format!(
+                                    "Negation of {} overflows",
+                                    n
+                                ))),
+                            },
+                            Expr::Literal(ScalarValue::Float64(n)) => {
+                                Ok(Expr::Literal(ScalarValue::Float64(-n)))
+                            }
+                            _ => Ok(Expr::Negative(Rc::new(expr))),
+                        }
+                    }
+                    other => Err(ExecutionError::General(format!(
+                        "Unsupported unary operator {:?}",
+                        other
+                    ))),
+                }
+            }
+
             &ASTNode::SQLIsNull(ref expr) => {
                 Ok(Expr::IsNull(Rc::new(self.sql_to_rex(expr, schema)?)))
             }
@@ -259,6 +1527,32 @@ impl SqlToRel {
                 ref op,
                 ref right,
             } => {
+                // `x [NOT] IN (list)` and `x [NOT] BETWEEN low AND high` are rewritten to this
+                // form by the DFParser
+                if let (&SQLOperator::Eq, ASTNode::SQLFunction { id, args }) = (op, right.as_ref())
+                {
+                    match id.as_ref() {
+                        "$in_list" | "$not_in_list" => {
+                            let list = args
+                                .iter()
+                                .map(|a| self.sql_to_rex(a, schema))
+                                .collect::<Result<Vec<Expr>>>()?;
+                            let expr = self.sql_to_rex(left, schema)?;
+                            return in_list_to_rex(expr, list, id == "$not_in_list", schema);
+                        }
+                        "$between" | "$not_between" if args.len() == 2 => {
+                            return between_to_rex(
+                                self.sql_to_rex(left, schema)?,
+                                self.sql_to_rex(&args[0], schema)?,
+                                self.sql_to_rex(&args[1], schema)?,
+                                id == "$not_between",
+                                schema,
+                            );
+                        }
+                        _ => {}
+                    }
+                }
+
                 let operator = match op {
                     &SQLOperator::Gt => Operator::Gt,
                     &SQLOperator::GtEq => Operator::GtEq,
@@ -280,25 +1574,48 @@ impl SqlToRel {
 
                 let left_expr = self.sql_to_rex(&left, &schema)?;
                 let right_expr = self.sql_to_rex(&right, &schema)?;
-                let left_type = left_expr.get_type(schema);
-                let right_type = right_expr.get_type(schema);
+                // a NULL literal takes on the type of the other side
+                let left_type = match left_expr {
+                    Expr::Literal(ScalarValue::Null) => right_expr.get_type(schema)?,
+                    _ => left_expr.get_type(schema)?,
+                };
+                let right_type = match right_expr {
+                    Expr::Literal(ScalarValue::Null) => left_type.clone(),
+                    _ => right_expr.get_type(schema)?,
+                };
 
-                match get_supertype(&left_type, &right_type) {
-                    Some(supertype) => Ok(Expr::BinaryExpr {
-                        left: Rc::new(left_expr.cast_to(&supertype, schema)?),
+                // date and interval arithmetic casts each side to its own input type, which
+                // can narrow an integer number of days to Int32
+                if let Some(types) = temporal_arithmetic_types(&left_type, &operator, &right_type) {
+                    let (left_input, right_input, _) = types?;
+                    let cast = |e: Expr, from: &DataType, to: DataType| {
+                        if *from == to {
+                            e
+                        } else {
+                            Expr::Cast {
+                                expr: Rc::new(e),
+                                data_type: to,
+                            }
+                        }
+                    };
+                    return Ok(Expr::BinaryExpr {
+                        left: Rc::new(cast(left_expr, &left_type, left_input)),
                         op: operator,
-                        right: Rc::new(right_expr.cast_to(&supertype, schema)?),
-                    }),
-                    None => {
-                        return Err(ExecutionError::General(format!(
-                            "No common supertype found for binary operator {:?} \
-                             with input types {:?} and {:?}",
-                            operator, left_type, right_type
-                        )));
-                    }
+                        right: Rc::new(cast(right_expr, &right_type, right_input)),
+                    });
                 }
+
+                // both sides are cast to the type that the operator is evaluated with
+                let input_type = binary_operator_input_type(&left_type, &operator, &right_type)?;
+                Ok(Expr::BinaryExpr {
+                    left: Rc::new(left_expr.cast_to(&input_type, schema)?),
+                    op: operator,
+                    right: Rc::new(right_expr.cast_to(&input_type, schema)?),
+                })
             }
 
+            &ASTNode::SQLCase { .. } => self.case_to_rex(None, sql, schema),
+
             //            &ASTNode::SQLOrderBy { ref expr, asc } => Ok(Expr::Sort {
             //                expr: Rc::new(self.sql_to_rex(&expr, &schema)?),
             //                asc,
@@ -306,30 +1623,358 @@ impl SqlToRel {
             &ASTNode::SQLFunction { ref id, ref args } => {
                 //TODO: fix this hack
                 match id.to_lowercase().as_ref() {
-                    "min" | "max" | "sum" | "avg" => {
+                    // `CASE x WHEN ...` is rewritten to this form by the DFParser
+                    "$simple_case" if args.len() == 2 => {
+                        self.case_to_rex(Some(&args[0]), &args[1], schema)
+                    }
+                    // `f(...) OVER (...)` is rewritten to this form by the DFParser
+                    "$window_over" => self.plan_window_function(args, schema),
+                    // subquery predicates are planned as joins by `plan_selection`
+                    "$in_subquery" | "$not_in_subquery" | "$exists_subquery" => {
+                        Err(ExecutionError::NotImplemented(
+                            "IN and EXISTS subqueries are only supported as conditions of a \
+                             WHERE clause that are combined with AND"
+                                .to_string(),
+                        ))
+                    }
+                    // `agg(...) FILTER (WHERE cond)` is rewritten to this form by the DFParser
+                    "$aggregate_filter" => {
+                        if args.len() != 2 {
+                            return Err(ExecutionError::General(
+                                "$aggregate_filter requires an aggregate function and a condition"
+                                    .to_string(),
+                            ));
+                        }
+                        let filter = self.sql_to_rex(&args[1], schema)?;
+                        let filter_type = filter.get_type(schema)?;
+                        if filter_type != DataType::Boolean {
+                            return Err(ExecutionError::General(format!(
+                                "FILTER condition must be boolean but found {:?}",
+                                filter_type
+                            )));
+                        }
+                        match self.sql_to_rex(&args[0], schema)? {
+                            Expr::AggregateFunction {
+                                name,
+                                args,
+                                return_type,
+                                filter: None,
+                            } => Ok(Expr::AggregateFunction {
+                                name,
+                                args,
+                                return_type,
+                                filter: Some(Rc::new(filter)),
+                            }),
+                            other => Err(ExecutionError::General(format!(
+                                "FILTER can only be applied to an aggregate function, not {:?}",
+                                other
+                            ))),
+                        }
+                    }
+                    "grouping" => {
+                        if args.is_empty() || args.len() > 32 {
+                            return Err(ExecutionError::General(
+                                "GROUPING requires between 1 and 32 arguments".to_string(),
+                            ));
+                        }
+                        let rex_args = args
+                            .iter()
+                            .map(|a| self.sql_to_rex(a, schema))
+                            .collect::<Result<Vec<Expr>>>()?;
+
+                        Ok(Expr::AggregateFunction {
+                            name: id.clone(),
+                            args: rex_args,
+                            return_type: DataType::UInt32,
+                            filter: None,
+                        })
+                    }
+                    "avg" => {
+                        if args.len() != 1 {
+                            return Err(ExecutionError::General(format!(
+                                "Invalid number of arguments for function '{}'",
+                                id
+                            )));
+                        }
+                        // the average is computed in double precision
+                        let arg = self.sql_to_rex(&args[0], schema)?;
+                        Ok(Expr::AggregateFunction {
+                            name: id.clone(),
+                            args: vec![arg.cast_to(&DataType::Float64, schema)?],
+                            return_type: DataType::Float64,
+                            filter: None,
+                        })
+                    }
+                    "min" | "max" | "sum" => {
                         let rex_args = args
                             .iter()
                             .map(|a| self.sql_to_rex(a, schema))
                             .collect::<Result<Vec<Expr>>>()?;
 
                         // return type is same as the argument type for these aggregate functions
-                        let return_type = rex_args[0].get_type(schema).clone();
+                        let return_type = rex_args[0].get_type(schema)?;
 
                         Ok(Expr::AggregateFunction {
                             name: id.clone(),
                             args: rex_args,
                             return_type,
+                            filter: None,
+                        })
+                    }
+                    "var_samp" | "var_pop" | "variance" | "stddev_samp" | "stddev_pop"
+                    | "stddev" | "covar_samp" | "covar_pop" | "corr" | "regr_slope"
+                    | "regr_intercept" => {
+                        let name = id.to_lowercase();
+                        let expected_args = match name.as_ref() {
+                            "covar_samp" | "covar_pop" | "corr" | "regr_slope"
+                            | "regr_intercept" => 2,
+                            _ => 1,
+                        };
+                        if args.len() != expected_args {
+                            return Err(ExecutionError::General(format!(
+                                "Invalid number of arguments for function '{}'",
+                                id
+                            )));
+                        }
+                        // statistics are always computed in double precision
+                        let rex_args = args
+                            .iter()
+                            .map(|a| {
+                                Ok(self
+                                    .sql_to_rex(a, schema)?
+                                    .cast_to(&DataType::Float64, schema)?)
+                            })
+                            .collect::<Result<Vec<Expr>>>()?;
+                        Ok(Expr::AggregateFunction {
+                            name,
+                            args: rex_args,
+                            return_type: DataType::Float64,
+                            filter: None,
+                        })
+                    }
+                    "approx_count_distinct" => {
+                        if args.len() != 1 {
+                            return Err(ExecutionError::General(
+                                "approx_count_distinct() requires a single argument".to_string(),
+                            ));
+                        }
+                        let arg = self.sql_to_rex(&args[0], schema)?;
+                        match arg.get_type(schema)? {
+                            DataType::List(_) | DataType::Struct(_) => {
+                                Err(ExecutionError::NotImplemented(
+                                    "approx_count_distinct() of nested types is not supported"
+                                        .to_string(),
+                                ))
+                            }
+                            _ => Ok(Expr::AggregateFunction {
+                                name: "approx_count_distinct".to_string(),
+                                args: vec![arg],
+                                return_type: DataType::UInt64,
+                                filter: None,
+                            }),
+                        }
+                    }
+                    "approx_percentile_cont" | "approx_median" => {
+                        let name = id.to_lowercase();
+                        let expected_args = if name == "approx_median" { 1 } else { 2 };
+                        if args.len() != expected_args {
+                            return Err(ExecutionError::General(format!(
+                                "Invalid number of arguments for function '{}'",
+                                id
+                            )));
+                        }
+                        let mut rex_args = vec![self
+                            .sql_to_rex(&args[0], schema)?
+                            .cast_to(&DataType::Float64, schema)?];
+                        if args.len() == 2 {
+                            rex_args.push(Expr::Literal(ScalarValue::Float64(percentile_literal(
+                                &args[1],
+                            )?)));
+                        }
+                        Ok(Expr::AggregateFunction {
+                            name,
+                            args: rex_args,
+                            return_type: DataType::Float64,
+                            filter: None,
+                        })
+                    }
+                    "median" | "percentile_cont" | "percentile_disc" | "mode" => {
+                        let name = id.to_lowercase();
+                        let expected_args = match name.as_ref() {
+                            "median" | "mode" => 1,
+                            _ => 2,
+                        };
+                        if args.len() != expected_args {
+                            return Err(ExecutionError::General(format!(
+                                "Invalid number of arguments for function '{}'",
+                                id
+                            )));
+                        }
+                        if is_sort_desc(&args[0]) {
+                            return Err(ExecutionError::NotImplemented(format!(
+                                "{}() with a descending ordering is not supported",
+                                id
+                            )));
+                        }
+                        let arg = self.sql_to_rex(&args[0], schema)?;
+                        let (arg, return_type) = match name.as_ref() {
+                            // interpolation needs a numeric type
+                            "median" | "percentile_cont" => {
+                                (arg.cast_to(&DataType::Float64, schema)?, DataType::Float64)
+                            }
+                            _ => match arg.get_type(schema)? {
+                                DataType::List(_) | DataType::Struct(_) => {
+                                    return Err(ExecutionError::NotImplemented(format!(
+                                        "{}() of nested types is not supported",
+                                        id
+                                    )));
+                                }
+                                t => (arg, t),
+                            },
+                        };
+                        let mut rex_args = vec![arg];
+                        if args.len() == 2 {
+                            rex_args.push(Expr::Literal(ScalarValue::Float64(percentile_literal(
+                                &args[1],
+                            )?)));
+                        }
+                        Ok(Expr::AggregateFunction {
+                            name,
+                            args: rex_args,
+                            return_type,
+                            filter: None,
+                        })
+                    }
+                    "bool_and" | "bool_or" | "every" => {
+                        if args.len() != 1 {
+                            return Err(ExecutionError::General(format!(
+                                "{}() requires a single argument",
+                                id
+                            )));
+                        }
+                        let arg = self.sql_to_rex(&args[0], schema)?;
+                        match arg.get_type(schema)? {
+                            DataType::Boolean => Ok(Expr::AggregateFunction {
+                                name: id.to_lowercase(),
+                                args: vec![arg],
+                                return_type: DataType::Boolean,
+                                filter: None,
+                            }),
+                            other => Err(ExecutionError::General(format!(
+                                "{}() requires a Boolean argument but found {:?}",
+                                id, other
+                            ))),
+                        }
+                    }
+                    "array_agg" => {
+                        if args.len() < 1 || args.len() > 2 {
+                            return Err(ExecutionError::General(
+                                "array_agg() requires a value and an optional ordering".to_string(),
+                            ));
+                        }
+                        let mut rex_args = vec![self.sql_to_rex(&args[0], schema)?];
+                        if args.len() == 2 {
+                            rex_args.push(self.aggregate_order_key(&args[1], schema)?);
+                        }
+                        let return_type = DataType::List(Box::new(rex_args[0].get_type(schema)?));
+                        Ok(Expr::AggregateFunction {
+                            name: "array_agg".to_string(),
+                            args: rex_args,
+                            return_type,
+                            filter: None,
+                        })
+                    }
+                    "string_agg" | "listagg" => {
+                        if args.len() < 2 || args.len() > 3 {
+                            return Err(ExecutionError::General(format!(
+                                "{}() requires a value, a separator and an optional ordering",
+                                id
+                            )));
+                        }
+                        let value = self.sql_to_rex(&args[0], schema)?;
+                        match value.get_type(schema)? {
+                            DataType::Utf8 => {}
+                            other => {
+                                return Err(ExecutionError::General(format!(
+                                    "{}() requires a Utf8 value but found {:?}",
+                                    id, other
+                                )));
+                            }
+                        }
+                        let separator = match &args[1] {
+                            ASTNode::SQLValue(sqlparser::sqlast::Value::SingleQuotedString(s)) => {
+                                Expr::Literal(ScalarValue::Utf8(Rc::new(s.clone())))
+                            }
+                            _ => {
+                                return Err(ExecutionError::General(format!(
+                                    "The separator of {}() must be a string literal",
+                                    id
+                                )));
+                            }
+                        };
+                        let mut rex_args = vec![value, separator];
+                        if args.len() == 3 {
+                            rex_args.push(self.aggregate_order_key(&args[2], schema)?);
+                        }
+                        Ok(Expr::AggregateFunction {
+                            name: "string_agg".to_string(),
+                            args: rex_args,
+                            return_type: DataType::Utf8,
+                            filter: None,
+                        })
+                    }
+                    "array_length" | "array_contains" | "array_element" => {
+                        let name = id.to_lowercase();
+                        let expected_args = if name == "array_length" { 1 } else { 2 };
+                        if args.len() != expected_args {
+                            return Err(ExecutionError::General(format!(
+                                "Invalid number of arguments for function '{}'",
+                                id
+                            )));
+                        }
+                        let list = self.sql_to_rex(&args[0], schema)?;
+                        let element_type = match list.get_type(schema)? {
+                            DataType::List(t) => *t,
+                            other => {
+                                return Err(ExecutionError::General(format!(
+                                    "The first argument of '{}' must be a list but found {:?}",
+                                    id, other
+                                )));
+                            }
+                        };
+                        let (args, return_type) = match name.as_ref() {
+                            "array_length" => (vec![list], DataType::Int64),
+                            "array_contains" => {
+                                // the value is coerced to the element type of the list
+                                let value = self
+                                    .sql_to_rex(&args[1], schema)?
+                                    .cast_to(&element_type, schema)?;
+                                (vec![list, value], DataType::Boolean)
+                            }
+                            _ => {
+                                // elements are numbered from 1
+                                let index = self
+                                    .sql_to_rex(&args[1], schema)?
+                                    .cast_to(&DataType::Int64, schema)?;
+                                (vec![list, index], element_type)
+                            }
+                        };
+                        Ok(Expr::ScalarFunction {
+                            name,
+                            args,
+                            return_type,
                         })
                     }
+                    "unnest" => Err(ExecutionError::NotImplemented(
+                        "unnest() is only supported as a top-level SELECT expression".to_string(),
+                    )),
                     "count" => {
                         let rex_args = args
                             .iter()
                             .map(|a| match a {
-                                // this feels hacky but translate COUNT(1)/COUNT(*) to COUNT(first_column)
-                                ASTNode::SQLValue(sqlparser::sqlast::Value::Long(1)) => {
-                                    Ok(Expr::Column(0))
-                                }
-                                ASTNode::SQLWildcard => Ok(Expr::Column(0)),
+                                // COUNT(*) counts the rows, so it counts a value that is never
+                                // NULL rather than a column that may be
+                                ASTNode::SQLWildcard => Ok(Expr::Literal(ScalarValue::Int64(1))),
                                 _ => self.sql_to_rex(a, schema),
                             })
                             .collect::<Result<Vec<Expr>>>()?;
@@ -338,8 +1983,94 @@ impl SqlToRel {
                             name: id.clone(),
                             args: rex_args,
                             return_type: DataType::UInt64,
+                            filter: None,
+                        })
+                    }
+                    "coalesce" | "nullif" => {
+                        let rex_args = args
+                            .iter()
+                            .map(|a| self.sql_to_rex(a, schema))
+                            .collect::<Result<Vec<Expr>>>()?;
+
+                        if rex_args.is_empty()
+                            || (id.to_lowercase() == "nullif" && rex_args.len() != 2)
+                        {
+                            return Err(ExecutionError::General(format!(
+                                "Invalid number of arguments for function '{}'",
+                                id
+                            )));
+                        }
+
+                        // all arguments are coerced to a common type, which is also the return type
+                        let (safe_args, return_type) =
+                            coerce_to_common_type(&rex_args, schema, id)?;
+
+                        Ok(Expr::ScalarFunction {
+                            name: id.clone(),
+                            args: safe_args,
+                            return_type,
+                        })
+                    }
+                    "now" => {
+                        if !args.is_empty() {
+                            return Err(ExecutionError::General(
+                                "Function 'now' does not take any arguments".to_string(),
+                            ));
+                        }
+                        Ok(Expr::ScalarFunction {
+                            name: "now".to_string(),
+                            args: vec![],
+                            return_type: temporal::TIMESTAMP_TYPE,
+                        })
+                    }
+                    "date_trunc" | "date_part" => {
+                        let name = id.to_lowercase();
+                        if args.len() != 2 {
+                            return Err(ExecutionError::General(format!(
+                                "Invalid number of arguments for function '{}'",
+                                id
+                            )));
+                        }
+
+                        // the unit must be a string literal so it can be validated here
+                        let unit = match &args[0] {
+                            ASTNode::SQLValue(sqlparser::sqlast::Value::SingleQuotedString(s)) => {
+                                s.clone()
+                            }
+                            _ => {
+                                return Err(ExecutionError::General(format!(
+                                    "The first argument of '{}' must be a string literal",
+                                    id
+                                )));
+                            }
+                        };
+                        let return_type = if name == "date_trunc" {
+                            temporal::date_trunc(&unit, 0)?;
+                            temporal::TIMESTAMP_TYPE
+                        } else {
+                            temporal::date_part(&unit, 0)?;
+                            DataType::Int64
+                        };
+
+                        // dates and strings are converted to timestamps
+                        let arg = self
+                            .sql_to_rex(&args[1], schema)?
+                            .cast_to(&temporal::TIMESTAMP_TYPE, schema)?;
+
+                        Ok(Expr::ScalarFunction {
+                            name,
+                            args: vec![Expr::Literal(ScalarValue::Utf8(Rc::new(unit))), arg],
+                            return_type,
                         })
                     }
+                    "to_interval" => match args.as_slice() {
+                        [ASTNode::SQLValue(sqlparser::sqlast::Value::SingleQuotedString(s))] => Ok(
+                            Expr::Literal(ScalarValue::Interval(temporal::parse_interval(s)?)),
+                        ),
+                        _ => Err(ExecutionError::General(
+                            "Function 'to_interval' requires a single string literal".to_string(),
+                        )),
+                    },
                     _ => match self.schema_provider.get_function_meta(id) {
                         Some(fm) => {
                             let rex_args = args
@@ -375,6 +2106,234 @@ impl SqlToRel {
     }
 }
 
+/// Get the value of a constant percentile argument, which must be between 0 and 1
+fn percentile_literal(sql: &ASTNode) -> Result<f64> {
+    let p = match sql {
+        ASTNode::SQLValue(sqlparser::sqlast::Value::Double(n)) => *n,
+        ASTNode::SQLValue(sqlparser::sqlast::Value::Long(n)) => *n as f64,
+        _ => {
+            return Err(ExecutionError::General(
+                "The percentile must be a numeric literal".to_string(),
+            ));
+        }
+    };
+    if p >= 0.0 && p <= 1.0 {
+        Ok(p)
+    } else {
+        Err(ExecutionError::General(format!(
+            "The percentile must be between 0 and 1 but was {}",
+            p
+        )))
+    }
+}
+
+/// Determine whether an ordering key is descending, which the DFParser rewrites to
+/// `$sort_desc(key)`
+fn is_sort_desc(sql: &ASTNode) -> bool {
+    match sql {
+        ASTNode::SQLFunction { id, .. } => id == "$sort_desc",
+        _ => false,
+    }
+}
+
+/// Get the name that qualifies the columns of a relation in a FROM clause, which is the table
+/// name or the alias
+fn relation_name(sql: &ASTNode) -> Option<String> {
+    match sql {
+        ASTNode::SQLIdentifier(id) => Some(id.clone()),
+        ASTNode::SQLFunction { id, args } if id == "$table_alias" => match args.get(1) {
+            Some(ASTNode::SQLIdentifier(alias)) => Some(alias.clone()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Split a condition into the conditions that it combines with AND
+fn conjuncts(sql: &ASTNode) -> Vec<&ASTNode> {
+    match sql {
+        ASTNode::SQLBinaryExpr {
+            left,
+            op: SQLOperator::And,
+            right,
+        } => {
+            let mut conditions = conjuncts(left);
+            conditions.extend(conjuncts(right));
+            conditions
+        }
+        ASTNode::SQLNested(expr) => conjuncts(expr),
+        _ => vec![sql],
+    }
+}
+
+/// Match an `[NOT] IN` or `[NOT] EXISTS` subquery predicate, as rewritten by the DFParser,
+/// returning the value of IN, the subquery and whether the predicate is negated
+fn subquery_predicate(sql: &ASTNode) -> Option<(Option<&ASTNode>, &ASTNode, bool)> {
+    match sql {
+        ASTNode::SQLBinaryExpr {
+            left,
+            op: SQLOperator::Eq,
+            right,
+        } => match right.as_ref() {
+            ASTNode::SQLFunction { id, args } if args.len() == 1 && id == "$in_subquery" => {
+                Some((Some(left.as_ref()), &args[0], false))
+            }
+            ASTNode::SQLFunction { id, args } if args.len() == 1 && id == "$not_in_subquery" => {
+                Some((Some(left.as_ref()), &args[0], true))
+            }
+            _ => None,
+        },
+        ASTNode::SQLFunction { id, args } if args.len() == 1 && id == "$exists_subquery" => {
+            Some((None, &args[0], false))
+        }
+        ASTNode::SQLUnary {
+            operator: SQLOperator::Not,
+            expr,
+        } => subquery_predicate(expr).map(|(value, query, negated)| (value, query, !negated)),
+        ASTNode::SQLNested(expr) => subquery_predicate(expr),
+        _ => None,
+    }
+}
+
+/// Find the scalar subqueries of an expression, other than those of IN and EXISTS predicates
+fn scalar_subqueries<'a>(sql: &'a ASTNode, found: &mut Vec<&'a ASTNode>) {
+    match sql {
+        ASTNode::SQLSelect { .. } => found.push(sql),
+        ASTNode::SQLNested(expr)
+        | ASTNode::SQLIsNull(expr)
+        | ASTNode::SQLIsNotNull(expr)
+        | ASTNode::SQLCast { expr, .. }
+        | ASTNode::SQLUnary { expr, .. } => scalar_subqueries(expr, found),
+        ASTNode::SQLBinaryExpr { left, right, .. } => {
+            scalar_subqueries(left, found);
+            scalar_subqueries(right, found);
+        }
+        ASTNode::SQLFunction { id, .. }
+            if id == "$in_subquery" || id == "$not_in_subquery" || id == "$exists_subquery" => {}
+        ASTNode::SQLFunction { args, .. } => {
+            args.iter().for_each(|a| scalar_subqueries(a, found));
+        }
+        ASTNode::SQLCase {
+            conditions,
+            results,
+            else_result,
+        } => {
+            conditions
+                .iter()
+                .chain(results.iter())
+                .chain(else_result.iter().map(|e| e.as_ref()))
+                .for_each(|e| scalar_subqueries(e, found));
+        }
+        _ => {}
+    }
+}
+
+/// Cast the columns of a plan to the types of the columns of `schema`
+fn cast_columns(input: Rc<LogicalPlan>, schema: &Arc<Schema>) -> Rc<LogicalPlan> {
+    let input_schema = input.schema().clone();
+    if input_schema
+        .fields()
+        .iter()
+        .zip(schema.fields())
+        .all(|(f, to)| f.data_type() == to.data_type())
+    {
+        return input;
+    }
+    let expr = input_schema
+        .fields()
+        .iter()
+        .zip(schema.fields())
+        .enumerate()
+        .map(|(i, (f, to))| {
+            if f.data_type() == to.data_type() {
+                Expr::Column(i)
+            } else {
+                Expr::Cast {
+                    expr: Rc::new(Expr::Column(i)),
+                    data_type: to.data_type().clone(),
+                }
+            }
+        })
+        .collect();
+    Rc::new(LogicalPlan::Projection {
+        expr,
+        input,
+        schema: schema.clone(),
+    })
+}
+
+/// Cast a pair of join keys, the first over the left input and the second over the right
+/// input, to a common type so that equal values are equal when hashed
+fn coerce_join_keys(
+    left: Expr,
+    left_schema: &Schema,
+    right: Expr,
+    right_schema: &Schema,
+) -> Result<(Expr, Expr)> {
+    let left_type = left.get_type(left_schema)?;
+    let right_type = right.get_type(right_schema)?;
+    match get_supertype(&left_type, &right_type) {
+        Some(common_type) => Ok((
+            left.cast_to(&common_type, left_schema)?,
+            right.cast_to(&common_type, right_schema)?,
+        )),
+        None => Err(ExecutionError::General(format!(
+            "Cannot compare {:?} with {:?} in a subquery predicate",
+            left_type, right_type
+        ))),
+    }
+}
+
+/// Get the names of a list of identifiers, such as the column names of a table alias
+fn identifier_names(args: &[ASTNode]) -> Result<Vec<String>> {
+    args.iter()
+        .map(|a| match a {
+            ASTNode::SQLIdentifier(name) => Ok(name.clone()),
+            other => Err(ExecutionError::General(format!(
+                "Invalid column name {:?}",
+                other
+            ))),
+        })
+        .collect()
+}
+
+/// Rename the leading columns of a relation, with a projection that keeps all of them
+fn rename_columns(input: Rc<LogicalPlan>, columns: &[String]) -> Result<Rc<LogicalPlan>> {
+    if columns.is_empty() {
+        return Ok(input);
+    }
+    let fields = input.schema().fields();
+    if columns.len() > fields.len() {
+        return Err(ExecutionError::General(format!(
+            "{} column names were specified but the relation only has {} columns",
+            columns.len(),
+            fields.len()
+        )));
+    }
+    let renamed = fields
+        .iter()
+        .enumerate()
+        .map(|(i, field)| match columns.get(i) {
+            Some(name) => Field::new(name, field.data_type().clone(), field.is_nullable()),
+            None => field.clone(),
+        })
+        .collect();
+    let expr = (0..fields.len()).map(Expr::Column).collect();
+    Ok(Rc::new(LogicalPlan::Projection {
+        expr,
+        input,
+        schema: Arc::new(Schema::new(renamed)),
+    }))
+}
+
+/// Determine whether a SELECT expression is a call to unnest()
+fn is_unnest(sql: &ASTNode) -> bool {
+    match sql {
+        ASTNode::SQLFunction { id, .. } => id.to_lowercase() == "unnest",
+        _ => false,
+    }
+}
+
 /// Convert SQL data type to relational representation of data type
 pub fn convert_data_type(sql: &SQLType) -> Result<DataType> {
     match sql {
@@ -385,6 +2344,9 @@ pub fn convert_data_type(sql: &SQLType) -> Result<DataType> {
         SQLType::Float(_) | SQLType::Real => Ok(DataType::Float64),
         SQLType::Double => Ok(DataType::Float64),
         SQLType::Char(_) | SQLType::Varchar(_) => Ok(DataType::Utf8),
+        SQLType::Date => Ok(temporal::DATE_TYPE),
+        SQLType::Time => Ok(temporal::TIME_TYPE),
+        SQLType::Timestamp => Ok(temporal::TIMESTAMP_TYPE),
         other => Err(ExecutionError::NotImplemented(format!(
             "Unsupported SQL type {:?}",
             other
@@ -392,68 +2354,307 @@ pub fn convert_data_type(sql: &SQLType) -> Result<DataType> {
     }
 }
 
-pub fn expr_to_field(e: &Expr, input_schema: &Schema) -> Field {
+/// Create the schema field for an expression, returning an error if the expression is not
+/// valid for the input schema
+pub fn expr_to_field(e: &Expr, input_schema: &Schema) -> Result<Field> {
+    let name = match e {
+        Expr::Column(i) => return Ok(input_schema.fields()[*i].clone()),
+        Expr::Literal(_) => "lit",
+        Expr::ScalarFunction { ref name, .. } => name.as_str(),
+        Expr::AggregateFunction { ref name, .. } => name.as_str(),
+        Expr::Cast { .. } => "cast",
+        Expr::GetField { ref name, .. } => name.as_str(),
+        Expr::BinaryExpr { .. } => "binary_expr",
+        Expr::IsNull(_) => "is_null",
+        Expr::IsNotNull(_) => "is_not_null",
+        Expr::InList { .. } => "in_list",
+        Expr::Between { .. } => "between",
+        Expr::Case { .. } => "case",
+        Expr::Not(_) => "not",
+        Expr::Negative(_) => "negative",
+        Expr::WindowFunction { ref name, .. } => name.as_str(),
+        Expr::ScalarSubquery(_) => "subquery",
+        Expr::Sort { .. } => {
+            return Err(ExecutionError::General(format!(
+                "Cannot determine schema type for expression {:?}",
+                e
+            )));
+        }
+    };
+    Ok(Field::new(name, e.get_type(input_schema)?, true))
+}
+
+/// Coerces a list of expressions to their common supertype, returning the coerced expressions
+/// along with that type
+fn coerce_to_common_type(
+    expr: &Vec<Expr>,
+    schema: &Schema,
+    context: &str,
+) -> Result<(Vec<Expr>, DataType)> {
+    // NULL literals take on the type of the other expressions
+    let typed: Vec<&Expr> = expr
+        .iter()
+        .filter(|e| **e != Expr::Literal(ScalarValue::Null))
+        .collect();
+    if typed.is_empty() {
+        return Ok((expr.clone(), DataType::Utf8));
+    }
+
+    let mut common_type = typed[0].get_type(schema)?;
+    for e in &typed[1..] {
+        let expr_type = e.get_type(schema)?;
+        common_type = match get_supertype(&common_type, &expr_type) {
+            Some(t) => t,
+            None => {
+                return Err(ExecutionError::General(format!(
+                    "No common supertype found for arguments to {} \
+                     with types {:?} and {:?}",
+                    context, common_type, expr_type
+                )));
+            }
+        };
+    }
+
+    let mut safe_expr: Vec<Expr> = vec![];
+    for e in expr {
+        safe_expr.push(e.cast_to(&common_type, schema)?);
+    }
+    Ok((safe_expr, common_type))
+}
+
+/// Creates an IN list expression, coercing the expression and the list values to a common type
+pub fn in_list_to_rex(expr: Expr, list: Vec<Expr>, negated: bool, schema: &Schema) -> Result<Expr> {
+    let mut all = vec![expr];
+    all.extend(list);
+    let (mut all, _) = coerce_to_common_type(&all, schema, "IN")?;
+    let expr = all.remove(0);
+    Ok(expr.in_list(all, negated))
+}
+
+/// Creates a BETWEEN expression, coercing the expression and both bounds to a common type
+pub fn between_to_rex(
+    expr: Expr,
+    low: Expr,
+    high: Expr,
+    negated: bool,
+    schema: &Schema,
+) -> Result<Expr> {
+    let (all, _) = coerce_to_common_type(&vec![expr, low, high], schema, "BETWEEN")?;
+    if negated {
+        Ok(all[0].not_between(&all[1], &all[2]))
+    } else {
+        Ok(all[0].between(&all[1], &all[2]))
+    }
+}
+
+pub fn exprlist_to_fields(expr: &Vec<Expr>, input_schema: &Schema) -> Result<Vec<Field>> {
+    expr.iter()
+        .map(|e| expr_to_field(e, input_schema))
+        .collect()
+}
+
+fn collect_expr(e: &Expr, accum: &mut HashSet<usize>) {
     match e {
-        Expr::Column(i) => input_schema.fields()[*i].clone(),
-        Expr::Literal(ref lit) => Field::new("lit", lit.get_datatype(), true),
-        Expr::ScalarFunction {
-            ref name,
-            ref return_type,
-            ..
-        } => Field::new(name, return_type.clone(), true),
-        Expr::AggregateFunction {
-            ref name,
-            ref return_type,
-            ..
-        } => Field::new(name, return_type.clone(), true),
-        Expr::Cast { ref data_type, .. } => Field::new("cast", data_type.clone(), true),
+        Expr::Column(i) => {
+            accum.insert(*i);
+        }
+        Expr::Cast { ref expr, .. } => collect_expr(expr, accum),
+        Expr::GetField { ref expr, .. } => collect_expr(expr, accum),
+        Expr::Literal(_) => {}
+        Expr::IsNotNull(ref expr) => collect_expr(expr, accum),
+        Expr::IsNull(ref expr) => collect_expr(expr, accum),
+        Expr::Not(ref expr) => collect_expr(expr, accum),
+        Expr::Negative(ref expr) => collect_expr(expr, accum),
         Expr::BinaryExpr {
             ref left,
             ref right,
             ..
         } => {
-            let left_type = left.get_type(input_schema);
-            let right_type = right.get_type(input_schema);
-            Field::new(
-                "binary_expr",
-                get_supertype(&left_type, &right_type).unwrap(),
-                true,
-            )
+            collect_expr(left, accum);
+            collect_expr(right, accum);
+        }
+        Expr::AggregateFunction {
+            ref args,
+            ref filter,
+            ..
+        } => {
+            args.iter().for_each(|e| collect_expr(e, accum));
+            if let Some(e) = filter {
+                collect_expr(e, accum);
+            }
+        }
+        Expr::ScalarFunction { ref args, .. } => {
+            args.iter().for_each(|e| collect_expr(e, accum));
+        }
+        Expr::WindowFunction {
+            ref args,
+            ref partition_by,
+            ref order_by,
+            ..
+        } => {
+            args.iter()
+                .chain(partition_by.iter())
+                .chain(order_by.iter())
+                .for_each(|e| collect_expr(e, accum));
+        }
+        Expr::Sort { ref expr, .. } => collect_expr(expr, accum),
+        // a subquery does not refer to the columns of the input
+        Expr::ScalarSubquery(_) => {}
+        Expr::Case {
+            ref expr,
+            ref when_then_expr,
+            ref else_expr,
+        } => {
+            if let Some(e) = expr {
+                collect_expr(e, accum);
+            }
+            for (w, t) in when_then_expr {
+                collect_expr(w, accum);
+                collect_expr(t, accum);
+            }
+            if let Some(e) = else_expr {
+                collect_expr(e, accum);
+            }
+        }
+        Expr::InList {
+            ref expr, ref list, ..
+        } => {
+            collect_expr(expr, accum);
+            list.iter().for_each(|e| collect_expr(e, accum));
+        }
+        Expr::Between {
+            ref expr,
+            ref low,
+            ref high,
+            ..
+        } => {
+            collect_expr(expr, accum);
+            collect_expr(low, accum);
+            collect_expr(high, accum);
+        }
+    }
+}
+
+/// Determine whether a GROUP BY item is ROLLUP, CUBE or GROUPING SETS
+fn is_grouping_sets(sql: &ASTNode) -> bool {
+    match sql {
+        ASTNode::SQLFunction { id, .. } => match id.to_lowercase().as_ref() {
+            "rollup" | "cube" | "$grouping_sets" => true,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Parse a single bound of a window frame, such as `UNBOUNDED PRECEDING` or `3 FOLLOWING`
+fn plan_window_frame_bound(bound: &str) -> Result<WindowFrameBound> {
+    let words: Vec<&str> = bound.split_whitespace().collect();
+    match words.as_slice() {
+        ["UNBOUNDED", "PRECEDING"] => Ok(WindowFrameBound::UnboundedPreceding),
+        ["UNBOUNDED", "FOLLOWING"] => Ok(WindowFrameBound::UnboundedFollowing),
+        ["CURRENT", "ROW"] => Ok(WindowFrameBound::CurrentRow),
+        [n, "PRECEDING"] | [n, "FOLLOWING"] => match n.parse::<u64>() {
+            Ok(n) if words[1] == "PRECEDING" => Ok(WindowFrameBound::Preceding(n)),
+            Ok(n) => Ok(WindowFrameBound::Following(n)),
+            Err(_) => Err(ExecutionError::General(format!(
+                "Invalid window frame bound '{}'",
+                bound
+            ))),
+        },
+        _ => Err(ExecutionError::General(format!(
+            "Invalid window frame bound '{}'",
+            bound
+        ))),
+    }
+}
+
+/// Plan a window frame, which the DFParser rewrites to `$window_frame('ROWS', start, end)`
+fn plan_window_frame(args: &[ASTNode]) -> Result<WindowFrame> {
+    let args = args
+        .iter()
+        .map(|a| match a {
+            ASTNode::SQLValue(sqlparser::sqlast::Value::SingleQuotedString(s)) => Ok(s.as_str()),
+            _ => Err(ExecutionError::General("Invalid window frame".to_string())),
+        })
+        .collect::<Result<Vec<&str>>>()?;
+    let (units, start, end) = match args.as_slice() {
+        ["ROWS", start, end] => (WindowFrameUnits::Rows, start, end),
+        ["RANGE", start, end] => (WindowFrameUnits::Range, start, end),
+        _ => {
+            return Err(ExecutionError::General("Invalid window frame".to_string()));
         }
-        _ => unimplemented!("Cannot determine schema type for expression {:?}", e),
+    };
+    let frame = WindowFrame {
+        units,
+        start: plan_window_frame_bound(start)?,
+        end: plan_window_frame_bound(end)?,
+    };
+
+    if frame.start == WindowFrameBound::UnboundedFollowing
+        || frame.end == WindowFrameBound::UnboundedPreceding
+    {
+        return Err(ExecutionError::General(format!(
+            "Invalid window frame from {} to {}",
+            frame.start, frame.end
+        )));
+    }
+    let is_offset = |bound: &WindowFrameBound| match bound {
+        WindowFrameBound::Preceding(_) | WindowFrameBound::Following(_) => true,
+        _ => false,
+    };
+    if frame.units == WindowFrameUnits::Range && (is_offset(&frame.start) || is_offset(&frame.end))
+    {
+        return Err(ExecutionError::NotImplemented(
+            "RANGE window frames with an offset".to_string(),
+        ));
     }
+    Ok(frame)
 }
 
-pub fn exprlist_to_fields(expr: &Vec<Expr>, input_schema: &Schema) -> Vec<Field> {
-    expr.iter()
-        .map(|e| expr_to_field(e, input_schema))
-        .collect()
-}
+/// The maximum number of expressions in a CUBE, which has 2^n grouping sets
+const MAX_CUBE_EXPRESSIONS: usize = 12;
 
-fn collect_expr(e: &Expr, accum: &mut HashSet<usize>) {
-    match e {
-        Expr::Column(i) => {
-            accum.insert(*i);
-        }
-        Expr::Cast { ref expr, .. } => collect_expr(expr, accum),
-        Expr::Literal(_) => {}
-        Expr::IsNotNull(ref expr) => collect_expr(expr, accum),
-        Expr::IsNull(ref expr) => collect_expr(expr, accum),
-        Expr::BinaryExpr {
-            ref left,
-            ref right,
-            ..
-        } => {
-            collect_expr(left, accum);
-            collect_expr(right, accum);
-        }
-        Expr::AggregateFunction { ref args, .. } => {
-            args.iter().for_each(|e| collect_expr(e, accum));
-        }
-        Expr::ScalarFunction { ref args, .. } => {
-            args.iter().for_each(|e| collect_expr(e, accum));
-        }
-        Expr::Sort { ref expr, .. } => collect_expr(expr, accum),
+/// Expand a GROUP BY item into its grouping sets. `GROUPING SETS ((a, b), c, ())` is rewritten
+/// by the DFParser to `$grouping_sets($grouping_set(a, b), c, $grouping_set())`.
+fn expand_grouping_sets(sql: &ASTNode) -> Result<Vec<Vec<ASTNode>>> {
+    match sql {
+        ASTNode::SQLFunction { id, args } => match id.to_lowercase().as_ref() {
+            // ROLLUP(a, b) is (a, b), (a), ()
+            "rollup" => Ok((0..=args.len()).rev().map(|n| args[..n].to_vec()).collect()),
+            // CUBE(a, b) is (a, b), (a), (b), ()
+            "cube" => {
+                if args.len() > MAX_CUBE_EXPRESSIONS {
+                    return Err(ExecutionError::NotImplemented(format!(
+                        "CUBE supports at most {} expressions",
+                        MAX_CUBE_EXPRESSIONS
+                    )));
+                }
+                let n = args.len();
+                Ok((0..1usize << n)
+                    .rev()
+                    .map(|mask| {
+                        (0..n)
+                            .filter(|i| mask & (1 << (n - 1 - i)) != 0)
+                            .map(|i| args[i].clone())
+                            .collect()
+                    })
+                    .collect())
+            }
+            "$grouping_sets" => {
+                let mut sets = vec![];
+                for arg in args {
+                    match arg {
+                        ASTNode::SQLFunction { id, args } if id == "$grouping_set" => {
+                            sets.push(args.clone())
+                        }
+                        _ => sets.extend(expand_grouping_sets(arg)?),
+                    }
+                }
+                Ok(sets)
+            }
+            _ => Ok(vec![vec![sql.clone()]]),
+        },
+        _ => Ok(vec![vec![sql.clone()]]),
     }
 }
 
@@ -466,6 +2667,7 @@ pub fn push_down_projection(
         LogicalPlan::Aggregate {
             ref input,
             ref group_expr,
+            ref grouping_sets,
             ref aggr_expr,
             ref schema,
         } => {
@@ -476,6 +2678,7 @@ pub fn push_down_projection(
             Rc::new(LogicalPlan::Aggregate {
                 input: push_down_projection(&input, &accum),
                 group_expr: group_expr.clone(),
+                grouping_sets: grouping_sets.clone(),
                 aggr_expr: aggr_expr.clone(),
                 schema: schema.clone(),
             })
@@ -535,6 +2738,12 @@ pub fn push_down_projection(
         LogicalPlan::Limit { .. } => plan.clone(),
         LogicalPlan::Sort { .. } => plan.clone(),
         LogicalPlan::EmptyRelation { .. } => plan.clone(),
+        LogicalPlan::Unnest { .. } => plan.clone(),
+        LogicalPlan::Window { .. } => plan.clone(),
+        LogicalPlan::RecursiveQuery { .. } => plan.clone(),
+        LogicalPlan::SemiJoin { .. } => plan.clone(),
+        LogicalPlan::SingleJoin { .. } => plan.clone(),
+        LogicalPlan::SetOperation { .. } => plan.clone(),
     }
 }
 
@@ -634,9 +2843,11 @@ mod tests {
     #[test]
     fn select_count_one() {
         let sql = "SELECT COUNT(1) FROM person";
-        let expected = "Aggregate: groupBy=[[]], aggr=[[COUNT(#0)]]\
+        let expected = "Aggregate: groupBy=[[]], aggr=[[COUNT(Int64(1))]]\
                         \n  TableScan: person projection=None";
         quick_test(sql, expected);
+        let sql = "SELECT COUNT(*) FROM person";
+        quick_test(sql, expected);
     }
 
     #[test]
@@ -647,6 +2858,124 @@ mod tests {
         quick_test(sql, expected);
     }
 
+    #[test]
+    fn select_is_null() {
+        let sql = "SELECT id FROM person WHERE salary IS NULL";
+        let expected = "Projection: #0\
+                        \n  Selection: #5 IS NULL\
+                        \n    TableScan: person projection=None";
+        quick_test(sql, expected);
+    }
+
+    #[test]
+    fn select_coalesce() {
+        let sql = "SELECT COALESCE(salary, 0.0) FROM person";
+        let expected = "Projection: COALESCE(#5, Float64(0.0))\
+                        \n  TableScan: person projection=None";
+        quick_test(sql, expected);
+    }
+
+    #[test]
+    fn select_case_when() {
+        let sql = "SELECT CASE WHEN salary > 100.0 THEN 'high' ELSE 'low' END FROM person";
+        let expected = "Projection: CASE WHEN #5 Gt Float64(100.0) THEN Utf8(\"high\") \
+                        ELSE Utf8(\"low\") END\
+                        \n  TableScan: person projection=None";
+        quick_test(sql, expected);
+    }
+
+    #[test]
+    fn select_case_when_coerces_results() {
+        let sql = "SELECT CASE WHEN age > 21 THEN age ELSE 0 END FROM person";
+        let expected = "Projection: CASE WHEN CAST(#3 AS Int64) Gt Int64(21) \
+                        THEN CAST(#3 AS Int64) ELSE Int64(0) END\
+                        \n  TableScan: person projection=None";
+        quick_test(sql, expected);
+    }
+
+    #[test]
+    fn select_simple_case_coerces_operand() {
+        let sql = "SELECT CASE age WHEN 1 THEN 'one' WHEN 2.5 THEN 'other' END FROM person";
+        let expected = "Projection: CASE CAST(#3 AS Float64) WHEN CAST(Int64(1) AS Float64) \
+                        THEN Utf8(\"one\") WHEN Float64(2.5) THEN Utf8(\"other\") END\
+                        \n  TableScan: person projection=None";
+        df_quick_test(sql, expected);
+    }
+
+    #[test]
+    fn in_list_coerces_values() {
+        let schema = MockSchemaProvider {}.get_table_meta("person").unwrap();
+        let list = vec![
+            Expr::Literal(ScalarValue::Int64(1)),
+            Expr::Literal(ScalarValue::Int64(2)),
+        ];
+        let expr = in_list_to_rex(Expr::Column(3), list, true, &schema).unwrap();
+        assert_eq!(
+            "CAST(#3 AS Int64) NOT IN (Int64(1), Int64(2))",
+            format!("{:?}", expr)
+        );
+        assert_eq!(Ok(DataType::Boolean), expr.get_type(&schema));
+    }
+
+    #[test]
+    fn between_coerces_bounds() {
+        let schema = MockSchemaProvider {}.get_table_meta("person").unwrap();
+        let expr = between_to_rex(
+            Expr::Column(5),
+            Expr::Literal(ScalarValue::Int64(1)),
+            Expr::Literal(ScalarValue::Float64(2.5)),
+            false,
+            &schema,
+        )
+        .unwrap();
+        assert_eq!(
+            "#5 BETWEEN CAST(Int64(1) AS Float64) AND Float64(2.5)",
+            format!("{:?}", expr)
+        );
+    }
+
+    #[test]
+    fn select_not() {
+        let sql = "SELECT id FROM person WHERE NOT (salary > 100.0)";
+        let expected = "Projection: #0\
+                        \n  Selection: NOT #5 Gt Float64(100.0)\
+                        \n    TableScan: person projection=None";
+        quick_test(sql, expected);
+    }
+
+    #[test]
+    fn select_negative() {
+        let sql = "SELECT -age FROM person";
+        let expected = "Projection: (- #3)\
+                        \n  TableScan: person projection=None";
+        df_quick_test(sql, expected);
+    }
+
+    #[test]
+    fn select_negative_literal() {
+        let sql = "SELECT id FROM person WHERE salary > -1.5";
+        let expected = "Projection: #0\
+                        \n  Selection: #5 Gt Float64(-1.5)\
+                        \n    TableScan: person projection=None";
+        df_quick_test(sql, expected);
+    }
+
+    #[test]
+    fn select_boolean_literal() {
+        let sql = "SELECT id, TRUE FROM person";
+        let expected = "Projection: #0, Boolean(true)\
+                        \n  TableScan: person projection=None";
+        df_quick_test(sql, expected);
+    }
+
+    #[test]
+    fn select_null_literal_is_coerced() {
+        let sql = "SELECT COALESCE(salary, NULL) FROM person";
+        let expected = "Projection: COALESCE(#5, CAST(Null AS Float64))\
+                        \n  TableScan: person projection=None";
+        quick_test(sql, expected);
+    }
+
     #[test]
     fn select_order_by() {
         let sql = "SELECT id FROM person ORDER BY id";
@@ -684,6 +3013,286 @@ mod tests {
         quick_test(sql, expected);
     }
 
+    #[test]
+    fn select_struct_field() {
+        let sql = "SELECT id, address.zip FROM customer WHERE address.street = 'Main St'";
+        let expected = "Projection: #0, #1.zip\
+                        \n  Selection: #1.street Eq Utf8(\"Main St\")\
+                        \n    TableScan: customer projection=None";
+        quick_test(sql, expected);
+    }
+
+    #[test]
+    fn select_invalid_struct_field() {
+        use sqlparser::dialect::*;
+        let planner = SqlToRel::new(Rc::new(MockSchemaProvider {}));
+        for sql in &[
+            "SELECT address.country FROM customer",
+            "SELECT id.zip FROM customer",
+        ] {
+            let ast = Parser::parse_sql(&GenericSqlDialect {}, sql.to_string()).unwrap();
+            assert!(planner.sql_to_rel(&ast).is_err());
+        }
+    }
+
+    #[test]
+    fn select_unnest() {
+        let sql = "SELECT id, unnest(tags) FROM customer WHERE id > 1";
+        let expected = "Projection: #0, #3\
+                        \n  Unnest: #2\
+                        \n    Selection: CAST(#0 AS Int64) Gt Int64(1)\
+                        \n      TableScan: customer projection=None";
+        quick_test(sql, expected);
+    }
+
+    #[test]
+    fn select_array_functions() {
+        let sql = "SELECT array_length(tags), array_element(tags, 1) FROM customer \
+                   WHERE array_contains(tags, 'vip')";
+        let expected = "Projection: array_length(#2), array_element(#2, Int64(1))\
+                        \n  Selection: array_contains(#2, Utf8(\"vip\"))\
+                        \n    TableScan: customer projection=None";
+        quick_test(sql, expected);
+    }
+
+    #[test]
+    fn select_array_agg() {
+        let sql = "SELECT id, array_agg(address.zip) FROM customer GROUP BY id";
+        let expected = "Aggregate: groupBy=[[#0]], aggr=[[array_agg(#1.zip)]]\
+                        \n  TableScan: customer projection=None";
+        quick_test(sql, expected);
+    }
+
+    #[test]
+    fn select_bool_and_requires_boolean() {
+        quick_test(
+            "SELECT bool_and(age > 21), every(age < 65) FROM person",
+            "Aggregate: groupBy=[[]], aggr=[[bool_and(CAST(#3 AS Int64) Gt Int64(21)), \
+             every(CAST(#3 AS Int64) Lt Int64(65))]]\
+             \n  TableScan: person projection=None",
+        );
+
+        use sqlparser::dialect::*;
+        let planner = SqlToRel::new(Rc::new(MockSchemaProvider {}));
+        let sql = "SELECT bool_or(age) FROM person";
+        let ast = Parser::parse_sql(&GenericSqlDialect {}, sql.to_string()).unwrap();
+        assert!(planner.sql_to_rel(&ast).is_err());
+    }
+
+    #[test]
+    fn select_statistical_aggregates() {
+        quick_test(
+            "SELECT stddev_samp(age), corr(salary, age) FROM person",
+            "Aggregate: groupBy=[[]], aggr=[[stddev_samp(CAST(#3 AS Float64)), \
+             corr(#5, CAST(#3 AS Float64))]]\
+             \n  TableScan: person projection=None",
+        );
+
+        use sqlparser::dialect::*;
+        let planner = SqlToRel::new(Rc::new(MockSchemaProvider {}));
+        for sql in &[
+            "SELECT corr(age) FROM person",
+            "SELECT var_pop(age, salary) FROM person",
+            "SELECT var_pop(state) FROM person",
+        ] {
+            let ast = Parser::parse_sql(&GenericSqlDialect {}, sql.to_string()).unwrap();
+            assert!(planner.sql_to_rel(&ast).is_err());
+        }
+    }
+
+    #[test]
+    fn select_approximate_aggregates() {
+        quick_test(
+            "SELECT approx_count_distinct(state), approx_percentile_cont(age, 0.9) FROM person",
+            "Aggregate: groupBy=[[]], aggr=[[approx_count_distinct(#4), \
+             approx_percentile_cont(CAST(#3 AS Float64), Float64(0.9))]]\
+             \n  TableScan: person projection=None",
+        );
+
+        use sqlparser::dialect::*;
+        let planner = SqlToRel::new(Rc::new(MockSchemaProvider {}));
+        for sql in &[
+            "SELECT approx_percentile_cont(age, 1.5) FROM person",
+            "SELECT approx_percentile_cont(age, salary) FROM person",
+            "SELECT approx_median(age, 0.5) FROM person",
+        ] {
+            let ast = Parser::parse_sql(&GenericSqlDialect {}, sql.to_string()).unwrap();
+            assert!(planner.sql_to_rel(&ast).is_err());
+        }
+    }
+
+    #[test]
+    fn select_ordered_set_aggregates() {
+        quick_test(
+            "SELECT median(age), percentile_disc(age, 0.25), mode(state) FROM person",
+            "Aggregate: groupBy=[[]], aggr=[[median(CAST(#3 AS Float64)), \
+             percentile_disc(#3, Float64(0.25)), mode(#4)]]\
+             \n  TableScan: person projection=None",
+        );
+    }
+
+    #[test]
+    fn select_ordered_set_aggregates_within_group() {
+        use crate::dfparser::{DFASTNode, DFParser};
+        let planner = SqlToRel::new(Rc::new(MockSchemaProvider {}));
+        let plan = |sql: &str| match DFParser::parse_sql(sql.to_string()).unwrap() {
+            DFASTNode::ANSI(ast) => planner.sql_to_rel(&ast),
+            _ => panic!(),
+        };
+
+        let sql = "SELECT percentile_cont(0.5) WITHIN GROUP (ORDER BY age), \
+                   percentile_disc(0.25) WITHIN GROUP (ORDER BY age), \
+                   mode() WITHIN GROUP (ORDER BY state) FROM person";
+        let expected =
+            "Aggregate: groupBy=[[]], aggr=[[percentile_cont(CAST(#3 AS Float64), Float64(0.5)), \
+                        percentile_disc(#3, Float64(0.25)), mode(#4)]]\
+                        \n  TableScan: person projection=None";
+        assert_eq!(expected, format!("{:?}", plan(sql).unwrap()));
+
+        let sql = "SELECT percentile_cont(0.5) WITHIN GROUP (ORDER BY age DESC) FROM person";
+        assert_eq!(
+            "NotImplemented(\"percentile_cont() with a descending ordering is not supported\")",
+            format!("{:?}", plan(sql).unwrap_err())
+        );
+    }
+
+    #[test]
+    fn select_string_agg() {
+        quick_test(
+            "SELECT state, string_agg(last_name, ', ', first_name), array_agg(id, age) \
+             FROM person GROUP BY state",
+            "Aggregate: groupBy=[[#4]], aggr=[[string_agg(#2, Utf8(\", \"), #1), \
+             array_agg(#0, #3)]]\
+             \n  TableScan: person projection=None",
+        );
+
+        use sqlparser::dialect::*;
+        let planner = SqlToRel::new(Rc::new(MockSchemaProvider {}));
+        for sql in &[
+            "SELECT string_agg(last_name) FROM person",
+            "SELECT string_agg(last_name, state) FROM person",
+            "SELECT string_agg(age, ',') FROM person",
+        ] {
+            let ast = Parser::parse_sql(&GenericSqlDialect {}, sql.to_string()).unwrap();
+            assert!(planner.sql_to_rel(&ast).is_err());
+        }
+
+        // the DFParser passes the standard ORDER BY as the last argument
+        use crate::dfparser::{DFASTNode, DFParser};
+        let plan = |sql: &str| match DFParser::parse_sql(sql.to_string()).unwrap() {
+            DFASTNode::ANSI(ast) => planner.sql_to_rel(&ast),
+            _ => panic!(),
+        };
+        let sql = "SELECT state, string_agg(last_name, ', ' ORDER BY first_name), \
+                   listagg(last_name, ', ') WITHIN GROUP (ORDER BY first_name), \
+                   array_agg(id ORDER BY age) FROM person GROUP BY state";
+        let expected = "Aggregate: groupBy=[[#4]], aggr=[[string_agg(#2, Utf8(\", \"), #1), \
+                        string_agg(#2, Utf8(\", \"), #1), array_agg(#0, #3)]]\
+                        \n  TableScan: person projection=None";
+        assert_eq!(expected, format!("{:?}", plan(sql).unwrap()));
+        assert_eq!(
+            "NotImplemented(\"Aggregates cannot be ordered in descending order\")",
+            format!(
+                "{:?}",
+                plan("SELECT array_agg(id ORDER BY age DESC) FROM person").unwrap_err()
+            )
+        );
+    }
+
+    #[test]
+    fn select_aggregate_filter() {
+        use crate::dfparser::{DFASTNode, DFParser};
+        let planner = SqlToRel::new(Rc::new(MockSchemaProvider {}));
+        let plan = |sql: &str| match DFParser::parse_sql(sql.to_string()).unwrap() {
+            DFASTNode::ANSI(ast) => planner.sql_to_rel(&ast),
+            _ => panic!(),
+        };
+
+        let sql = "SELECT state, SUM(age) FILTER (WHERE salary > 1000.0), COUNT(id) \
+                   filter (where (state = 'CO' OR age < 21)) FROM person GROUP BY state";
+        let expected = "Aggregate: groupBy=[[#4]], aggr=[[SUM(#3) FILTER (WHERE #5 Gt Float64(1000.0)), \
+                        COUNT(#0) FILTER (WHERE #4 Eq Utf8(\"CO\") Or CAST(#3 AS Int64) Lt Int64(21))]]\
+                        \n  TableScan: person projection=None";
+        assert_eq!(expected, format!("{:?}", plan(sql).unwrap()));
+
+        // the condition must be boolean and can only be applied to aggregate functions
+        assert!(plan("SELECT SUM(age) FILTER (WHERE state) FROM person").is_err());
+        assert!(plan("SELECT abs(age) FILTER (WHERE age > 1) FROM person").is_err());
+    }
+
+    #[test]
+    fn select_window_functions() {
+        use crate::dfparser::{DFASTNode, DFParser};
+        let planner = SqlToRel::new(Rc::new(MockSchemaProvider {}));
+        let plan = |sql: &str| match DFParser::parse_sql(sql.to_string()).unwrap() {
+            DFASTNode::ANSI(ast) => planner.sql_to_rel(&ast),
+            _ => panic!(),
+        };
+
+        let sql = "SELECT id, ROW_NUMBER() OVER (PARTITION BY state ORDER BY salary DESC), \
+                   SUM(age) OVER (ORDER BY id ROWS BETWEEN 1 PRECEDING AND CURRENT ROW) \
+                   FROM person WHERE age > 21";
+        let expected = "Projection: #0, #6, #7\
+                        \n  Window: ROW_NUMBER() OVER (PARTITION BY #4 ORDER BY #5 DESC), \
+                        SUM(#3) OVER (ORDER BY #0 ASC ROWS BETWEEN 1 PRECEDING AND CURRENT ROW)\
+                        \n    Selection: CAST(#3 AS Int64) Gt Int64(21)\
+                        \n      TableScan: person projection=None";
+        assert_eq!(expected, format!("{:?}", plan(sql).unwrap()));
+
+        // a frame with only a start bound ends at the current row
+        let sql = "SELECT LAG(age, 2, 0) OVER (ORDER BY id ROWS UNBOUNDED PRECEDING) FROM person";
+        let expected = "Projection: #6\
+                        \n  Window: LAG(CAST(#3 AS Int64), Int64(2), Int64(0)) OVER \
+                        (ORDER BY #0 ASC ROWS BETWEEN UNBOUNDED PRECEDING AND CURRENT ROW)\
+                        \n    TableScan: person projection=None";
+        assert_eq!(expected, format!("{:?}", plan(sql).unwrap()));
+
+        assert!(plan("SELECT NTILE(0) OVER (ORDER BY id) FROM person").is_err());
+        assert!(plan("SELECT abs(age) OVER (ORDER BY id) FROM person").is_err());
+        assert!(plan("SELECT SUM(age) OVER (ORDER BY id RANGE 1 PRECEDING) FROM person").is_err());
+        assert!(plan("SELECT COUNT(id), ROW_NUMBER() OVER () FROM person").is_err());
+    }
+
+    #[test]
+    fn select_grouping_sets() {
+        use crate::dfparser::{DFASTNode, DFParser};
+        let planner = SqlToRel::new(Rc::new(MockSchemaProvider {}));
+        let plan = |sql: &str| match DFParser::parse_sql(sql.to_string()).unwrap() {
+            DFASTNode::ANSI(ast) => planner.sql_to_rel(&ast),
+            _ => panic!(),
+        };
+
+        let sql = "SELECT state, age, SUM(salary), GROUPING(state, age) FROM person \
+                   GROUP BY ROLLUP(state, age)";
+        let expected = "Aggregate: groupBy=[[#4, #3]], groupingSets=[[[0, 1], [0], []]], \
+                        aggr=[[SUM(#5), GROUPING(#4, #3)]]\
+                        \n  TableScan: person projection=None";
+        let rollup = plan(sql).unwrap();
+        assert_eq!(expected, format!("{:?}", rollup));
+        // rolled up columns are NULL in subtotal rows
+        assert!(rollup.schema().field(0).is_nullable());
+
+        let sql = "SELECT state, age, first_name, COUNT(id) FROM person \
+                   GROUP BY state, CUBE(age, first_name)";
+        let expected = "Aggregate: groupBy=[[#4, #3, #1]], \
+                        groupingSets=[[[0, 1, 2], [0, 1], [0, 2], [0]]], aggr=[[COUNT(#0)]]\
+                        \n  TableScan: person projection=None";
+        let cube = plan(sql).unwrap();
+        assert_eq!(expected, format!("{:?}", cube));
+        assert!(!cube.schema().field(0).is_nullable());
+
+        let sql = "SELECT state, age, COUNT(id) FROM person \
+                   GROUP BY GROUPING SETS ((state, age), state, ())";
+        let expected = "Aggregate: groupBy=[[#4, #3]], groupingSets=[[[0, 1], [0], []]], \
+                        aggr=[[COUNT(#0)]]\
+                        \n  TableScan: person projection=None";
+        assert_eq!(expected, format!("{:?}", plan(sql).unwrap()));
+
+        // GROUPING() arguments must be grouping expressions
+        assert!(plan("SELECT GROUPING(age) FROM person GROUP BY ROLLUP(state)").is_err());
+        assert!(plan("SELECT GROUPING(state) FROM person").is_err());
+    }
+
     #[test]
     fn test_collect_expr() {
         let mut accum: HashSet<usize> = HashSet::new();
@@ -749,6 +3358,301 @@ mod tests {
     //    }
 
     /// Create logical plan, write with formatter, compare to expected output
+    #[test]
+    fn select_from_derived_table() {
+        use crate::dfparser::{DFASTNode, DFParser};
+        let planner = SqlToRel::new(Rc::new(MockSchemaProvider {}));
+        let plan = |sql: &str| match DFParser::parse_sql(sql.to_string()).unwrap() {
+            DFASTNode::ANSI(ast) => planner.sql_to_rel(&ast),
+            _ => panic!(),
+        };
+
+        let sql = "SELECT t.a, b FROM (SELECT id, age FROM person WHERE age > 21) AS t (a, b) \
+                   WHERE t.b < 65";
+        let expected = "Projection: #0, #1\
+                        \n  Selection: CAST(#1 AS Int64) Lt Int64(65)\
+                        \n    Projection: #0, #1\
+                        \n      Projection: #0, #3\
+                        \n        Selection: CAST(#3 AS Int64) Gt Int64(21)\
+                        \n          TableScan: person projection=None";
+        let p = plan(sql).unwrap();
+        assert_eq!(expected, format!("{:?}", p));
+        assert_eq!("a", p.schema().field(0).name());
+        assert_eq!("b", p.schema().field(1).name());
+
+        // aggregating the result of an aggregate query
+        let sql = "SELECT MAX(total) FROM \
+                   (SELECT state, SUM(salary) FROM person GROUP BY state) totals (state, total)";
+        let expected = "Aggregate: groupBy=[[]], aggr=[[MAX(#1)]]\
+                        \n  Projection: #0, #1\
+                        \n    Aggregate: groupBy=[[#4]], aggr=[[SUM(#5)]]\
+                        \n      TableScan: person projection=None";
+        assert_eq!(expected, format!("{:?}", plan(sql).unwrap()));
+
+        // without column names the alias only qualifies the columns
+        let sql = "SELECT p.id FROM person p WHERE person.id > 1";
+        assert!(plan(sql).is_err());
+        let sql = "SELECT p.id FROM person p";
+        let expected = "Projection: #0\
+                        \n  TableScan: person projection=None";
+        assert_eq!(expected, format!("{:?}", plan(sql).unwrap()));
+        let sql = "SELECT person.first_name FROM person";
+        let expected = "Projection: #1\
+                        \n  TableScan: person projection=None";
+        assert_eq!(expected, format!("{:?}", plan(sql).unwrap()));
+
+        assert!(plan("SELECT a FROM (SELECT id FROM person) t (a, b)").is_err());
+
+        // the other relations of the FROM clause are aliased too, but joins cannot be planned
+        assert_eq!(
+            "NotImplemented(\"Joins are not supported\")",
+            format!(
+                "{:?}",
+                plan("SELECT p.id FROM person p, customer c").unwrap_err()
+            )
+        );
+    }
+
+    #[test]
+    fn select_with_clause() {
+        use crate::dfparser::{DFASTNode, DFParser};
+        let planner = SqlToRel::new(Rc::new(MockSchemaProvider {}));
+        let plan = |sql: &str| match DFParser::parse_sql(sql.to_string()).unwrap() {
+            DFASTNode::ANSI(ast) => planner.sql_to_rel(&ast),
+            _ => panic!(),
+        };
+
+        // each CTE can read the ones before it, and a CTE shadows a table of the same name
+        let sql = "WITH person AS (SELECT id, age FROM person WHERE age > 21), \
+                   adults (a, b) AS (SELECT id, age FROM person) \
+                   SELECT a FROM adults WHERE adults.b < 65";
+        let expected = "Projection: #0\
+                        \n  Selection: CAST(#1 AS Int64) Lt Int64(65)\
+                        \n    Projection: #0, #1\
+                        \n      Projection: #0, #1\
+                        \n        Projection: #0, #3\
+                        \n          Selection: CAST(#3 AS Int64) Gt Int64(21)\
+                        \n            TableScan: person projection=None";
+        assert_eq!(expected, format!("{:?}", plan(sql).unwrap()));
+
+        assert!(plan(
+            "WITH t AS (SELECT id FROM person), t AS (SELECT age FROM person) SELECT id FROM t"
+        )
+        .is_err());
+
+        // the recursive term is cast to the column types of the static term
+        let sql = "WITH RECURSIVE t (n) AS (SELECT age FROM person WHERE age > 21 \
+                   UNION ALL SELECT n + 1 FROM t WHERE n < 65) \
+                   SELECT n FROM t";
+        let expected = "Projection: #0\
+                        \n  RecursiveQuery: t distinct=false\
+                        \n    Projection: #0\
+                        \n      Projection: #3\
+                        \n        Selection: CAST(#3 AS Int64) Gt Int64(21)\
+                        \n          TableScan: person projection=None\
+                        \n    Projection: CAST(#0 AS Int32)\
+                        \n      Projection: CAST(#0 AS Int64) Plus Int64(1)\
+                        \n        Selection: CAST(#0 AS Int64) Lt Int64(65)\
+                        \n          TableScan: t projection=None";
+        let p = plan(sql).unwrap();
+        assert_eq!(expected, format!("{:?}", p));
+        assert_eq!("n", p.schema().field(0).name());
+
+        let sql = "WITH RECURSIVE t (n) AS (SELECT age FROM person \
+                   UNION SELECT n, n FROM t) SELECT n FROM t";
+        assert!(plan(sql).is_err());
+    }
+
+    #[test]
+    fn select_subquery_predicates() {
+        use crate::dfparser::{DFASTNode, DFParser};
+        let planner = SqlToRel::new(Rc::new(MockSchemaProvider {}));
+        let plan = |sql: &str| match DFParser::parse_sql(sql.to_string()).unwrap() {
+            DFASTNode::ANSI(ast) => planner.sql_to_rel(&ast),
+            _ => panic!(),
+        };
+
+        let sql = "SELECT id FROM person \
+                   WHERE state IN (SELECT state FROM person WHERE age < 65) AND age > 21";
+        let expected = "Projection: #0\
+                        \n  SemiJoin: #4 = #0\
+                        \n    Selection: CAST(#3 AS Int64) Gt Int64(21)\
+                        \n      TableScan: person projection=None\
+                        \n    Projection: #4\
+                        \n      Selection: CAST(#3 AS Int64) Lt Int64(65)\
+                        \n        TableScan: person projection=None";
+        assert_eq!(expected, format!("{:?}", plan(sql).unwrap()));
+
+        let sql = "SELECT id FROM person WHERE id NOT IN (SELECT id FROM person WHERE age < 65)";
+        let expected = "Projection: #0\
+                        \n  NullAwareAntiJoin: #0 = #0\
+                        \n    TableScan: person projection=None\
+                        \n    Projection: #0\
+                        \n      Selection: CAST(#3 AS Int64) Lt Int64(65)\
+                        \n        TableScan: person projection=None";
+        assert_eq!(expected, format!("{:?}", plan(sql).unwrap()));
+
+        // the correlated equality becomes a join key
+        let sql = "SELECT id FROM person p \
+                   WHERE NOT EXISTS (SELECT id FROM person q WHERE q.age = p.age + 1)";
+        let expected = "Projection: #0\
+                        \n  AntiJoin: CAST(#3 AS Int64) Plus Int64(1) = CAST(#0 AS Int64)\
+                        \n    TableScan: person projection=None\
+                        \n    Projection: #3\
+                        \n      TableScan: person projection=None";
+        assert_eq!(expected, format!("{:?}", plan(sql).unwrap()));
+
+        let sql = "SELECT id FROM person WHERE id IN (SELECT id FROM person) OR age > 21";
+        assert!(plan(sql).is_err());
+        let sql = "SELECT id FROM person WHERE id IN (SELECT id, age FROM person)";
+        assert!(plan(sql).is_err());
+        let sql = "SELECT id FROM person p \
+                   WHERE EXISTS (SELECT MAX(age) FROM person q WHERE q.id = p.id)";
+        assert!(plan(sql).is_err());
+    }
+
+    #[test]
+    fn select_scalar_subqueries() {
+        use crate::dfparser::{DFASTNode, DFParser};
+        let planner = SqlToRel::new(Rc::new(MockSchemaProvider {}));
+        let plan = |sql: &str| match DFParser::parse_sql(sql.to_string()).unwrap() {
+            DFASTNode::ANSI(ast) => planner.sql_to_rel(&ast),
+            _ => panic!(),
+        };
+
+        // an uncorrelated subquery is planned within the expression
+        let sql = "SELECT id FROM person WHERE salary > (SELECT AVG(salary) FROM person)";
+        let expected = "Projection: #0\
+                        \n  Selection: #5 Gt (Aggregate: groupBy=[[]], aggr=[[AVG(#5)]]\
+                        \n  TableScan: person projection=None)\
+                        \n    TableScan: person projection=None";
+        assert_eq!(expected, format!("{:?}", plan(sql).unwrap()));
+
+        // a correlated subquery is grouped by its correlated columns and joined to the input
+        let sql = "SELECT id, (SELECT MAX(age) FROM person q WHERE q.state = p.state) \
+                   FROM person p";
+        let expected = "Projection: #0, #7\
+                        \n  SingleJoin: #4 = #0\
+                        \n    TableScan: person projection=None\
+                        \n    Aggregate: groupBy=[[#4]], aggr=[[MAX(#3)]]\
+                        \n      TableScan: person projection=None";
+        assert_eq!(expected, format!("{:?}", plan(sql).unwrap()));
+
+        let sql = "SELECT id, (SELECT COUNT(id) FROM person q WHERE q.state = p.state) \
+                   FROM person p";
+        let expected = "Projection: #0, COALESCE(#7, UInt64(0))\
+                        \n  SingleJoin: #4 = #0\
+                        \n    TableScan: person projection=None\
+                        \n    Aggregate: groupBy=[[#4]], aggr=[[COUNT(#0)]]\
+                        \n      TableScan: person projection=None";
+        assert_eq!(expected, format!("{:?}", plan(sql).unwrap()));
+
+        let sql = "SELECT id FROM person WHERE age > (SELECT age, id FROM person)";
+        assert!(plan(sql).is_err());
+        let sql = "SELECT id, (SELECT MAX(age) FROM person q WHERE q.state = p.state \
+                   GROUP BY q.id) FROM person p";
+        assert!(plan(sql).is_err());
+    }
+
+    #[test]
+    fn select_set_operations() {
+        use crate::dfparser::{DFASTNode, DFParser};
+        let planner = SqlToRel::new(Rc::new(MockSchemaProvider {}));
+        let plan = |sql: &str| match DFParser::parse_sql(sql.to_string()).unwrap() {
+            DFASTNode::ANSI(ast) => planner.sql_to_rel(&ast),
+            _ => panic!(),
+        };
+
+        // the columns are cast to a common type
+        let sql = "SELECT id, age FROM person UNION ALL SELECT id, salary FROM person";
+        let expected = "Union: all=true\
+                        \n  Projection: #0, CAST(#1 AS Float64)\
+                        \n    Projection: #0, #3\
+                        \n      TableScan: person projection=None\
+                        \n  Projection: #0, #5\
+                        \n    TableScan: person projection=None";
+        assert_eq!(expected, format!("{:?}", plan(sql).unwrap()));
+
+        // INTERSECT binds more tightly than UNION and EXCEPT
+        let sql = "SELECT id FROM person EXCEPT SELECT id FROM customer \
+                   UNION SELECT id FROM person INTERSECT ALL SELECT id FROM customer";
+        let expected = "Union: all=false\
+                        \n  Except: all=false\
+                        \n    Projection: #0\
+                        \n      TableScan: person projection=None\
+                        \n    Projection: #0\
+                        \n      TableScan: customer projection=None\
+                        \n  Intersect: all=true\
+                        \n    Projection: #0\
+                        \n      TableScan: person projection=None\
+                        \n    Projection: #0\
+                        \n      TableScan: customer projection=None";
+        assert_eq!(expected, format!("{:?}", plan(sql).unwrap()));
+
+        // a trailing ORDER BY and LIMIT apply to the result
+        let sql = "SELECT id FROM person UNION SELECT id FROM customer ORDER BY id DESC LIMIT 2";
+        let expected = "Limit: 2\
+                        \n  Sort: #0 DESC\
+                        \n    Projection: #0\
+                        \n      Union: all=false\
+                        \n        Projection: #0\
+                        \n          TableScan: person projection=None\
+                        \n        Projection: #0\
+                        \n          TableScan: customer projection=None";
+        assert_eq!(expected, format!("{:?}", plan(sql).unwrap()));
+
+        let sql = "SELECT id FROM person UNION SELECT id, age FROM person";
+        assert!(plan(sql).is_err());
+        let sql = "SELECT first_name FROM person UNION SELECT age FROM person";
+        assert!(plan(sql).is_err());
+        // an unknown ORDER BY column is an error rather than a panic
+        let sql = "SELECT id FROM person UNION SELECT id FROM customer ORDER BY no_such_col";
+        assert!(plan(sql).is_err());
+    }
+
+    #[test]
+    fn select_function_named_like_a_rewrite() {
+        // the DFParser rewrites to names starting with `$`, which SQL text cannot contain, so
+        // the bare names are ordinary functions
+        let sql = "SELECT window_over(salary) FROM person";
+        let expected = "Projection: window_over(#5)\
+                        \n  TableScan: person projection=None";
+        df_quick_test(sql, expected);
+
+        use crate::dfparser::{DFASTNode, DFParser};
+        let planner = SqlToRel::new(Rc::new(MockSchemaProvider {}));
+        for name in &[
+            "aggregate_filter",
+            "grouping_sets",
+            "grouping_set",
+            "window_partition",
+            "window_order",
+            "window_frame",
+            "sort_desc",
+            "table_alias",
+            "with_query",
+            "cte",
+            "cte_columns",
+            "in_subquery",
+            "not_in_subquery",
+            "exists_subquery",
+            "set_operation",
+            "simple_case",
+            "in_list",
+            "between",
+        ] {
+            let sql = format!("SELECT {}(age) FROM person", name);
+            let err = match DFParser::parse_sql(sql).unwrap() {
+                DFASTNode::ANSI(ast) => planner.sql_to_rel(&ast).unwrap_err(),
+                _ => panic!(),
+            };
+            assert_eq!(
+                format!("General(\"Invalid function '{}'\")", name),
+                format!("{:?}", err)
+            );
+        }
+    }
+
     fn quick_test(sql: &str, expected: &str) {
         use sqlparser::dialect::*;
         let dialect = GenericSqlDialect {};
@@ -758,6 +3662,19 @@ mod tests {
         assert_eq!(expected, format!("{:?}", plan));
     }
 
+    /// Like `quick_test`, but parses with the DFParser so that its rewrites apply
+    fn df_quick_test(sql: &str, expected: &str) {
+        use crate::dfparser::{DFASTNode, DFParser};
+        let planner = SqlToRel::new(Rc::new(MockSchemaProvider {}));
+        match DFParser::parse_sql(sql.to_string()).unwrap() {
+            DFASTNode::ANSI(ast) => {
+                let plan = planner.sql_to_rel(&ast).unwrap();
+                assert_eq!(expected, format!("{:?}", plan));
+            }
+            _ => panic!(),
+        }
+    }
+
     struct MockSchemaProvider {}
 
     impl SchemaProvider for MockSchemaProvider {
@@ -771,6 +3688,18 @@ mod tests {
                     Field::new("state", DataType::Utf8, false),
                     Field::new("salary", DataType::Float64, false),
                 ]))),
+                "customer" => Some(Arc::new(Schema::new(vec![
+                    Field::new("id", DataType::UInt32, false),
+                    Field::new(
+                        "address",
+                        DataType::Struct(vec![
+                            Field::new("street", DataType::Utf8, false),
+                            Field::new("zip", DataType::UInt16, false),
+                        ]),
+                        true,
+                    ),
+                    Field::new("tags", DataType::List(Box::new(DataType::Utf8)), true),
+                ]))),
                 _ => None,
             }
         }
@@ -783,6 +3712,12 @@ mod tests {
                     DataType::Float64,
                     FunctionType::Scalar,
                 ))),
+                "window_over" => Some(Arc::new(FunctionMeta::new(
+                    "window_over".to_string(),
+                    vec![Field::new("n", DataType::Float64, false)],
+                    DataType::Float64,
+                    FunctionType::Scalar,
+                ))),
                 _ => None,
             }
         }
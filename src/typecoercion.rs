@@ -0,0 +1,403 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Type coercion rules, shared by the SQL query planner (which casts the inputs of an
+//! expression to a common type) and the execution engine (which derives result types)
+
+use arrow::datatypes::{DataType, DateUnit, IntervalUnit, TimeUnit};
+
+use super::logicalplan::Operator;
+
+/// Determine whether a data type is an integer or floating point type
+pub fn is_numeric(dt: &DataType) -> bool {
+    is_signed_int(dt) || is_unsigned_int(dt) || is_float(dt)
+}
+
+fn is_signed_int(dt: &DataType) -> bool {
+    match dt {
+        DataType::Int8 | DataType::Int16 | DataType::Int32 | DataType::Int64 => true,
+        _ => false,
+    }
+}
+
+fn is_unsigned_int(dt: &DataType) -> bool {
+    match dt {
+        DataType::UInt8 | DataType::UInt16 | DataType::UInt32 | DataType::UInt64 => true,
+        _ => false,
+    }
+}
+
+fn is_float(dt: &DataType) -> bool {
+    match dt {
+        DataType::Float32 | DataType::Float64 => true,
+        _ => false,
+    }
+}
+
+/// Determine whether a data type is a date, time or timestamp
+pub fn is_temporal(dt: &DataType) -> bool {
+    match dt {
+        DataType::Date(_) | DataType::Time64(_) | DataType::Timestamp(_) => true,
+        _ => false,
+    }
+}
+
+fn is_date_or_timestamp(dt: &DataType) -> bool {
+    match dt {
+        DataType::Date(_) | DataType::Timestamp(_) => true,
+        _ => false,
+    }
+}
+
+fn is_interval(dt: &DataType) -> bool {
+    match dt {
+        DataType::Interval(_) => true,
+        _ => false,
+    }
+}
+
+fn is_integer(dt: &DataType) -> bool {
+    is_signed_int(dt) || is_unsigned_int(dt)
+}
+
+/// Width in bits of an integer type
+fn int_width(dt: &DataType) -> usize {
+    match dt {
+        DataType::Int8 | DataType::UInt8 => 8,
+        DataType::Int16 | DataType::UInt16 => 16,
+        DataType::Int32 | DataType::UInt32 => 32,
+        _ => 64,
+    }
+}
+
+fn signed_int(width: usize) -> DataType {
+    match width {
+        8 => DataType::Int8,
+        16 => DataType::Int16,
+        32 => DataType::Int32,
+        _ => DataType::Int64,
+    }
+}
+
+fn unsigned_int(width: usize) -> DataType {
+    match width {
+        8 => DataType::UInt8,
+        16 => DataType::UInt16,
+        32 => DataType::UInt32,
+        _ => DataType::UInt64,
+    }
+}
+
+/// Get the smallest type that both types can be implicitly converted to.
+///
+/// Mixing signed and unsigned integers promotes to a signed type wide enough for both, so
+/// UInt32 and Int32 become Int64. UInt64 mixed with a signed type becomes Int64, which can
+/// overflow for very large values. Integers mixed with floats become the float type, which rounds
+/// integers that are too wide for its mantissa (see `can_coerce_from` for the lossless casts).
+///
+/// Strings can be converted to dates, times and timestamps, and dates to timestamps.
+pub fn get_supertype(l: &DataType, r: &DataType) -> Option<DataType> {
+    if l == r {
+        return Some(l.clone());
+    }
+    match (l, r) {
+        (DataType::Date(_), DataType::Timestamp(_))
+        | (DataType::Timestamp(_), DataType::Date(_)) => {
+            return Some(DataType::Timestamp(TimeUnit::Microsecond));
+        }
+        (t, DataType::Utf8) | (DataType::Utf8, t) if is_temporal(t) => return Some(t.clone()),
+        _ => {}
+    }
+    if !is_numeric(l) || !is_numeric(r) {
+        return None;
+    }
+    if *l == DataType::Float64 || *r == DataType::Float64 {
+        Some(DataType::Float64)
+    } else if is_float(l) || is_float(r) {
+        Some(DataType::Float32)
+    } else if is_signed_int(l) && is_signed_int(r) {
+        Some(signed_int(int_width(l).max(int_width(r))))
+    } else if is_unsigned_int(l) && is_unsigned_int(r) {
+        Some(unsigned_int(int_width(l).max(int_width(r))))
+    } else {
+        let (signed, unsigned) = if is_signed_int(l) { (l, r) } else { (r, l) };
+        Some(signed_int(int_width(signed).max(int_width(unsigned) * 2)))
+    }
+}
+
+/// Determine whether a value of type `other` can be cast to type `left` without losing
+/// information. Integers only widen, and only convert to a float whose mantissa can hold every
+/// value, so Int32 converts to Float64 but not to Float32.
+pub fn can_coerce_from(left: &DataType, other: &DataType) -> bool {
+    if left == other {
+        return true;
+    }
+    match (left, other) {
+        (DataType::Timestamp(_), DataType::Date(_)) => true,
+        (DataType::Float64, DataType::Float32) => true,
+        (DataType::Float32, t) if is_integer(t) => int_width(t) <= 16,
+        (DataType::Float64, t) if is_integer(t) => int_width(t) <= 32,
+        (l, r) if is_signed_int(l) && is_integer(r) => int_width(l) > int_width(r),
+        (l, r) if is_unsigned_int(l) && is_unsigned_int(r) => int_width(l) > int_width(r),
+        _ => false,
+    }
+}
+
+/// Get the type that both inputs of a binary operator must be cast to before evaluating it
+pub fn binary_operator_input_type(
+    l: &DataType,
+    op: &Operator,
+    r: &DataType,
+) -> Result<DataType, String> {
+    let error = || {
+        format!(
+            "Operator {:?} cannot be applied to types {:?} and {:?}",
+            op, l, r
+        )
+    };
+    match op {
+        Operator::Eq
+        | Operator::NotEq
+        | Operator::Lt
+        | Operator::LtEq
+        | Operator::Gt
+        | Operator::GtEq => get_supertype(l, r).ok_or_else(error),
+        Operator::And | Operator::Or | Operator::Not => {
+            if *l == DataType::Boolean && *r == DataType::Boolean {
+                Ok(DataType::Boolean)
+            } else {
+                Err(error())
+            }
+        }
+        Operator::Like | Operator::NotLike => {
+            if *l == DataType::Utf8 && *r == DataType::Utf8 {
+                Ok(DataType::Utf8)
+            } else {
+                Err(error())
+            }
+        }
+        Operator::Plus
+        | Operator::Minus
+        | Operator::Multiply
+        | Operator::Divide
+        | Operator::Modulus => {
+            if is_numeric(l) && is_numeric(r) {
+                get_supertype(l, r).ok_or_else(error)
+            } else {
+                Err(error())
+            }
+        }
+    }
+}
+
+/// Get the types that the left and right inputs of date and interval arithmetic are cast to,
+/// and the result type, or None if neither input is a date, time, timestamp or interval.
+///
+/// Intervals can be added to or subtracted from dates and timestamps, producing a timestamp.
+/// A number of days can be added to or subtracted from a date, and subtracting two dates gives
+/// the number of days between them.
+pub fn temporal_arithmetic_types(
+    l: &DataType,
+    op: &Operator,
+    r: &DataType,
+) -> Option<Result<(DataType, DataType, DataType), String>> {
+    if !is_arithmetic(op) || !(is_temporal(l) || is_temporal(r) || is_interval(l) || is_interval(r))
+    {
+        return None;
+    }
+    let date = DataType::Date(DateUnit::Day);
+    let timestamp = DataType::Timestamp(TimeUnit::Microsecond);
+    let interval = DataType::Interval(IntervalUnit::DayTime);
+    let types = match op {
+        Operator::Plus | Operator::Minus if is_date_or_timestamp(l) && is_interval(r) => {
+            Some((timestamp.clone(), interval, timestamp))
+        }
+        Operator::Plus if is_interval(l) && is_date_or_timestamp(r) => {
+            Some((interval, timestamp.clone(), timestamp))
+        }
+        Operator::Plus | Operator::Minus if *l == date && is_integer(r) => {
+            Some((date.clone(), DataType::Int32, date))
+        }
+        Operator::Plus if is_integer(l) && *r == date => {
+            Some((DataType::Int32, date.clone(), date))
+        }
+        Operator::Minus if *l == date && *r == date => Some((date.clone(), date, DataType::Int32)),
+        _ => None,
+    };
+    Some(types.ok_or_else(|| {
+        format!(
+            "Operator {:?} cannot be applied to types {:?} and {:?}",
+            op, l, r
+        )
+    }))
+}
+
+/// Get the result type of a binary operator. Comparisons and boolean logic produce a Boolean.
+/// Arithmetic produces the common input type, so integer division is integer division.
+pub fn binary_operator_data_type(
+    l: &DataType,
+    op: &Operator,
+    r: &DataType,
+) -> Result<DataType, String> {
+    if let Some(types) = temporal_arithmetic_types(l, op, r) {
+        return types.map(|(_, _, result_type)| result_type);
+    }
+    let input_type = binary_operator_input_type(l, op, r)?;
+    if is_arithmetic(op) {
+        Ok(input_type)
+    } else {
+        Ok(DataType::Boolean)
+    }
+}
+
+/// Determine whether an operator is an arithmetic operator
+pub fn is_arithmetic(op: &Operator) -> bool {
+    match op {
+        Operator::Plus
+        | Operator::Minus
+        | Operator::Multiply
+        | Operator::Divide
+        | Operator::Modulus => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::datatypes::DataType::*;
+
+    #[test]
+    fn supertype_of_same_type() {
+        assert_eq!(Some(Utf8), get_supertype(&Utf8, &Utf8));
+        assert_eq!(Some(Boolean), get_supertype(&Boolean, &Boolean));
+        assert_eq!(Some(UInt16), get_supertype(&UInt16, &UInt16));
+    }
+
+    #[test]
+    fn supertype_widens_integers() {
+        assert_eq!(Some(Int64), get_supertype(&Int32, &Int64));
+        assert_eq!(Some(Int16), get_supertype(&Int16, &Int8));
+        assert_eq!(Some(UInt32), get_supertype(&UInt8, &UInt32));
+    }
+
+    #[test]
+    fn supertype_mixing_signed_and_unsigned() {
+        assert_eq!(Some(Int16), get_supertype(&UInt8, &Int8));
+        assert_eq!(Some(Int64), get_supertype(&Int32, &UInt32));
+        assert_eq!(Some(Int32), get_supertype(&UInt16, &Int8));
+        assert_eq!(Some(Int64), get_supertype(&Int8, &UInt32));
+        assert_eq!(Some(Int64), get_supertype(&UInt64, &Int64));
+    }
+
+    #[test]
+    fn supertype_with_floats() {
+        assert_eq!(Some(Float32), get_supertype(&Int64, &Float32));
+        assert_eq!(Some(Float64), get_supertype(&Float32, &Float64));
+        assert_eq!(Some(Float64), get_supertype(&UInt8, &Float64));
+    }
+
+    #[test]
+    fn no_supertype() {
+        assert_eq!(None, get_supertype(&Utf8, &Int32));
+        assert_eq!(None, get_supertype(&Boolean, &Float64));
+    }
+
+    #[test]
+    fn coerce_from() {
+        assert!(can_coerce_from(&Int64, &Int32));
+        assert!(can_coerce_from(&Int64, &UInt32));
+        assert!(can_coerce_from(&Float64, &Int32));
+        assert!(can_coerce_from(&Float32, &Int16));
+        assert!(!can_coerce_from(&Float64, &UInt64));
+        assert!(!can_coerce_from(&Float32, &Int32));
+        assert!(!can_coerce_from(&Float32, &Int64));
+        assert!(!can_coerce_from(&Int32, &Int64));
+        assert!(!can_coerce_from(&Int32, &UInt32));
+        assert!(!can_coerce_from(&UInt64, &Int8));
+        assert!(!can_coerce_from(&Utf8, &Int32));
+    }
+
+    #[test]
+    fn comparison_types() {
+        assert_eq!(
+            Ok(Boolean),
+            binary_operator_data_type(&Int32, &Operator::Lt, &Int64)
+        );
+        assert_eq!(
+            Ok(Int64),
+            binary_operator_input_type(&Int32, &Operator::Lt, &Int64)
+        );
+        assert_eq!(
+            Ok(Boolean),
+            binary_operator_data_type(&Utf8, &Operator::Eq, &Utf8)
+        );
+        assert!(binary_operator_data_type(&Utf8, &Operator::Eq, &Int32).is_err());
+    }
+
+    #[test]
+    fn arithmetic_types() {
+        assert_eq!(
+            Ok(Int64),
+            binary_operator_data_type(&Int32, &Operator::Divide, &Int64)
+        );
+        assert_eq!(
+            Ok(Float64),
+            binary_operator_data_type(&Int32, &Operator::Divide, &Float64)
+        );
+        assert_eq!(
+            Ok(Int64),
+            binary_operator_data_type(&UInt32, &Operator::Plus, &Int32)
+        );
+        assert!(binary_operator_data_type(&Utf8, &Operator::Plus, &Utf8).is_err());
+        assert!(binary_operator_data_type(&Boolean, &Operator::Minus, &Boolean).is_err());
+    }
+
+    #[test]
+    fn temporal_types() {
+        let date = Date(DateUnit::Day);
+        let timestamp = Timestamp(TimeUnit::Microsecond);
+        let interval = Interval(IntervalUnit::DayTime);
+        assert_eq!(Some(date.clone()), get_supertype(&date, &Utf8));
+        assert_eq!(Some(timestamp.clone()), get_supertype(&date, &timestamp));
+        assert_eq!(
+            Ok(timestamp.clone()),
+            binary_operator_data_type(&date, &Operator::Plus, &interval)
+        );
+        assert_eq!(
+            Ok(date.clone()),
+            binary_operator_data_type(&date, &Operator::Minus, &Int64)
+        );
+        assert_eq!(
+            Ok(Int32),
+            binary_operator_data_type(&date, &Operator::Minus, &date)
+        );
+        assert!(binary_operator_data_type(&interval, &Operator::Minus, &date).is_err());
+        assert!(binary_operator_data_type(&timestamp, &Operator::Multiply, &Int32).is_err());
+        assert_eq!(
+            None,
+            temporal_arithmetic_types(&Int32, &Operator::Plus, &Int64)
+        );
+    }
+
+    #[test]
+    fn boolean_types() {
+        assert_eq!(
+            Ok(Boolean),
+            binary_operator_data_type(&Boolean, &Operator::And, &Boolean)
+        );
+        assert!(binary_operator_data_type(&Boolean, &Operator::Or, &Int32).is_err());
+    }
+
+}
@@ -13,6 +13,7 @@
 // limitations under the License.
 
 use std::cell::RefCell;
+use std::fs;
 use std::rc::Rc;
 use std::sync::Arc;
 
@@ -20,11 +21,15 @@ extern crate arrow;
 extern crate datafusion;
 
 use arrow::array::*;
-use arrow::datatypes::{DataType, Field, Schema};
+use arrow::builder::{BinaryBuilder, ListBuilder};
+use arrow::datatypes::{DataType, DateUnit, Field, Schema, TimeUnit};
+use arrow::record_batch::RecordBatch;
 
-use datafusion::execution::context::ExecutionContext;
-use datafusion::execution::datasource::CsvDataSource;
+use datafusion::execution::context::{DivideByZero, ExecutionContext};
+use datafusion::execution::datasource::{CsvDataSource, MemoryDataSource};
 use datafusion::execution::relation::Relation;
+use datafusion::execution::temporal;
+use datafusion::logicalplan::{Expr, LogicalPlan, ScalarValue};
 
 #[test]
 fn csv_query_with_predicate() {
@@ -47,33 +52,1375 @@ fn csv_query_group_by_int_min_max() {
     //TODO add ORDER BY once supported, to make this test determistic
     let sql = "SELECT a, MIN(b), MAX(b) FROM t1 GROUP BY a";
     let actual = execute(&mut ctx, sql);
-    let expected = "2\t3.3\t5.5\n3\t1.0\t2.0\n1\t1.1\t2.2\n".to_string();
+    let mut rows: Vec<&str> = actual.lines().collect();
+    rows.sort();
+    assert_eq!(vec!["1\t1.1\t2.2", "2\t3.3\t5.5", "3\t1.0\t2.0"], rows);
+}
+
+#[test]
+fn csv_query_group_by_string_min_max() {
+    let mut ctx = ExecutionContext::new();
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("a", DataType::Utf8, false),
+        Field::new("b", DataType::Float64, false),
+    ]));
+    register_csv(&mut ctx, "t1", "test/data/aggregate_test_2.csv", &schema);
+    //TODO add ORDER BY once supported, to make this test determistic
+    let sql = "SELECT a, MIN(b), MAX(b) FROM t1 GROUP BY a";
+    let actual = execute(&mut ctx, sql);
+    let expected = "\"three\"\t1.0\t2.0\n\"two\"\t3.3\t5.5\n\"one\"\t1.1\t2.2\n".to_string();
+    assert_eq!(expected, actual);
+}
+
+#[test]
+fn csv_query_cast() {
+    let mut ctx = ExecutionContext::new();
+    register_cities_csv(&mut ctx);
+    let sql = "SELECT CAST(lat AS int) FROM cities";
+    let actual = execute(&mut ctx, sql);
+    let expected= "57\n53\n52\n51\n50\n51\n51\n51\n51\n52\n52\n52\n51\n57\n51\n53\n55\n51\n50\n52\n53\n50\n53\n55\n50\n52\n51\n51\n54\n50\n50\n53\n54\n50\n52\n52\n57\n".to_string();
+    assert_eq!(expected, actual);
+}
+
+#[test]
+fn csv_query_is_null() {
+    let mut ctx = ExecutionContext::new();
+    register_null_test_csv(&mut ctx);
+    let sql = "SELECT c_int FROM null_test WHERE c_float IS NULL";
+    let actual = execute(&mut ctx, sql);
+    assert_eq!(load_expected("is_null_csv.csv"), actual);
+}
+
+#[test]
+fn csv_query_is_not_null() {
+    let mut ctx = ExecutionContext::new();
+    register_null_test_csv(&mut ctx);
+    let sql = "SELECT c_int FROM null_test WHERE c_float IS NOT NULL";
+    let actual = execute(&mut ctx, sql);
+    assert_eq!(load_expected("is_not_null_csv.csv"), actual);
+}
+
+#[test]
+fn csv_query_filter_all_types() {
+    let mut ctx = ExecutionContext::new();
+    register_null_test_csv(&mut ctx);
+    let sql = "SELECT c_int, c_float, c_string, c_bool FROM null_test WHERE c_float > 2.0";
+    let actual = execute(&mut ctx, sql);
+    let expected =
+        "2\t2.2\t\"2.22\"\ttrue\n4\t4.4\t\"\"\tfalse\n5\t6.6\t\"\"\tfalse\n".to_string();
+    assert_eq!(expected, actual);
+}
+
+#[test]
+fn csv_query_null_comparison_is_not_true() {
+    let mut ctx = ExecutionContext::new();
+    register_null_test_csv(&mut ctx);
+    // the row with a NULL c_float must be removed by both predicates
+    let sql = "SELECT c_int FROM null_test WHERE c_float > 2.0 OR c_float < 2.0";
+    let actual = execute(&mut ctx, sql);
+    let expected = "1\n2\n4\n5\n".to_string();
+    assert_eq!(expected, actual);
+}
+
+#[test]
+fn csv_query_three_valued_and() {
+    let mut ctx = ExecutionContext::new();
+    register_null_test_csv(&mut ctx);
+    // NULL AND FALSE is FALSE
+    let sql = "SELECT c_int, c_float > 5.0 AND c_float IS NOT NULL FROM null_test";
+    let actual = execute(&mut ctx, sql);
+    let expected = "1\tfalse\n2\tfalse\n3\tfalse\n4\tfalse\n5\ttrue\n".to_string();
+    assert_eq!(expected, actual);
+}
+
+#[test]
+fn csv_query_three_valued_or() {
+    let mut ctx = ExecutionContext::new();
+    register_null_test_csv(&mut ctx);
+    // NULL OR FALSE is NULL
+    let sql = "SELECT c_int, c_float > 5.0 OR c_float < 2.0 FROM null_test";
+    let actual = execute(&mut ctx, sql);
+    let expected = "1\ttrue\n2\tfalse\n3\tNULL\n4\tfalse\n5\ttrue\n".to_string();
+    assert_eq!(expected, actual);
+}
+
+#[test]
+fn csv_query_coalesce() {
+    let mut ctx = ExecutionContext::new();
+    register_null_test_csv(&mut ctx);
+    let sql = "SELECT COALESCE(c_float, 0.0) FROM null_test";
+    let actual = execute(&mut ctx, sql);
+    let expected = "1.1\n2.2\n0.0\n4.4\n6.6\n".to_string();
+    assert_eq!(expected, actual);
+}
+
+#[test]
+fn csv_query_nullif() {
+    let mut ctx = ExecutionContext::new();
+    register_null_test_csv(&mut ctx);
+    let sql = "SELECT NULLIF(c_float, 2.2) FROM null_test";
+    let actual = execute(&mut ctx, sql);
+    let expected = "1.1\nNULL\nNULL\n4.4\n6.6\n".to_string();
+    assert_eq!(expected, actual);
+}
+
+#[test]
+fn csv_query_case_when() {
+    let mut ctx = ExecutionContext::new();
+    register_null_test_csv(&mut ctx);
+    let sql = "SELECT c_int, CASE WHEN c_float > 2.0 THEN 'big' ELSE 'small' END FROM null_test";
+    let actual = execute(&mut ctx, sql);
+    let expected = "1\t\"small\"\n2\t\"big\"\n3\t\"small\"\n4\t\"big\"\n5\t\"big\"\n".to_string();
+    assert_eq!(expected, actual);
+}
+
+#[test]
+fn csv_query_case_when_without_else() {
+    let mut ctx = ExecutionContext::new();
+    register_null_test_csv(&mut ctx);
+    let sql = "SELECT CASE WHEN c_float < 2.0 THEN c_float WHEN c_float > 5.0 THEN 5.0 END \
+               FROM null_test";
+    let actual = execute(&mut ctx, sql);
+    let expected = "1.1\nNULL\nNULL\nNULL\n5.0\n".to_string();
+    assert_eq!(expected, actual);
+}
+
+#[test]
+fn csv_query_simple_case() {
+    let mut ctx = ExecutionContext::new();
+    register_null_test_csv(&mut ctx);
+    // the operand and the WHEN values are coerced to a common type
+    let sql = "SELECT c_int, CASE c_int WHEN 1 THEN 'one' WHEN 2.0 THEN 'two' ELSE 'many' END \
+               FROM null_test";
+    let actual = execute(&mut ctx, sql);
+    let expected = "1\t\"one\"\n2\t\"two\"\n3\t\"many\"\n4\t\"many\"\n5\t\"many\"\n".to_string();
+    assert_eq!(expected, actual);
+
+    // a NULL operand matches no WHEN value
+    let sql = "SELECT CASE c_float WHEN 1.1 THEN 1 WHEN 2.2 THEN 2 END FROM null_test";
+    let actual = execute(&mut ctx, sql);
+    assert_eq!("1\n2\nNULL\nNULL\nNULL\n".to_string(), actual);
+}
+
+#[test]
+fn csv_query_in_list() {
+    let mut ctx = ExecutionContext::new();
+    register_null_test_csv(&mut ctx);
+    let list = vec![
+        Expr::Literal(ScalarValue::Utf8(Rc::new("1.11".to_string()))),
+        Expr::Literal(ScalarValue::Utf8(Rc::new("".to_string()))),
+    ];
+    let actual = execute_null_test_selection(&mut ctx, Expr::Column(2).in_list(list, false));
+    let expected = "1\t1.1\t\"1.11\"\ttrue\n4\t4.4\t\"\"\tfalse\n5\t6.6\t\"\"\tfalse\n".to_string();
+    assert_eq!(expected, actual);
+
+    let sql =
+        "SELECT c_int, c_float, c_string, c_bool FROM null_test WHERE c_string IN ('1.11', '')";
+    assert_eq!(expected, execute(&mut ctx, sql));
+}
+
+#[test]
+fn csv_query_not_in_list() {
+    let mut ctx = ExecutionContext::new();
+    register_null_test_csv(&mut ctx);
+    let list = vec![
+        Expr::Literal(ScalarValue::Float64(1.1)),
+        Expr::Literal(ScalarValue::Float64(2.2)),
+    ];
+    // the NULL c_float is neither in nor not in the list
+    let actual = execute_null_test_selection(&mut ctx, Expr::Column(1).in_list(list, true));
+    let expected = "4\t4.4\t\"\"\tfalse\n5\t6.6\t\"\"\tfalse\n".to_string();
+    assert_eq!(expected, actual);
+
+    // the list values are coerced to the type of c_float
+    let sql =
+        "SELECT c_int, c_float, c_string, c_bool FROM null_test WHERE c_float NOT IN (1.1, 2.2, 3)";
+    assert_eq!(expected, execute(&mut ctx, sql));
+}
+
+#[test]
+fn csv_query_between() {
+    let mut ctx = ExecutionContext::new();
+    register_null_test_csv(&mut ctx);
+    let low = Expr::Literal(ScalarValue::Float64(2.0));
+    let high = Expr::Literal(ScalarValue::Float64(5.0));
+    let actual = execute_null_test_selection(&mut ctx, Expr::Column(1).between(&low, &high));
+    let expected = "2\t2.2\t\"2.22\"\ttrue\n4\t4.4\t\"\"\tfalse\n".to_string();
+    assert_eq!(expected, actual);
+
+    let sql = "SELECT c_int, c_float, c_string, c_bool FROM null_test WHERE c_float BETWEEN 2 AND 5.0 AND c_int > 1";
+    assert_eq!(expected, execute(&mut ctx, sql));
+
+    // the upper bound can be a function call
+    let sql = "SELECT c_int, c_float, c_string, c_bool FROM null_test WHERE c_float BETWEEN 2 AND CAST(c_int AS double) + 1.0";
+    assert_eq!(expected, execute(&mut ctx, sql));
+}
+
+#[test]
+fn csv_query_not_between() {
+    let mut ctx = ExecutionContext::new();
+    register_null_test_csv(&mut ctx);
+    let low = Expr::Literal(ScalarValue::Float64(2.0));
+    let high = Expr::Literal(ScalarValue::Float64(5.0));
+    let actual = execute_null_test_selection(&mut ctx, Expr::Column(1).not_between(&low, &high));
+    let expected = "1\t1.1\t\"1.11\"\ttrue\n5\t6.6\t\"\"\tfalse\n".to_string();
+    assert_eq!(expected, actual);
+
+    let sql = "SELECT c_int, c_float, c_string, c_bool FROM null_test WHERE c_float NOT BETWEEN 1.0 + 1.0 AND 5.0";
+    assert_eq!(expected, execute(&mut ctx, sql));
+}
+
+#[test]
+fn csv_query_not() {
+    let mut ctx = ExecutionContext::new();
+    register_null_test_csv(&mut ctx);
+    // NOT NULL is NULL so the row with a NULL c_float is removed
+    let sql = "SELECT c_int FROM null_test WHERE NOT (c_float > 2.0)";
+    let actual = execute(&mut ctx, sql);
+    assert_eq!("1\n".to_string(), actual);
+}
+
+#[test]
+fn csv_query_not_boolean_column() {
+    let mut ctx = ExecutionContext::new();
+    register_null_test_csv(&mut ctx);
+    let sql = "SELECT c_int FROM null_test WHERE NOT c_bool";
+    let actual = execute(&mut ctx, sql);
+    assert_eq!("4\n5\n".to_string(), actual);
+}
+
+#[test]
+fn csv_query_negative() {
+    let mut ctx = ExecutionContext::new();
+    register_null_test_csv(&mut ctx);
+    let sql = "SELECT -c_float FROM null_test";
+    let actual = execute(&mut ctx, sql);
+    let expected = "-1.1\n-2.2\nNULL\n-4.4\n-6.6\n".to_string();
+    assert_eq!(expected, actual);
+}
+
+#[test]
+fn csv_query_negative_overflow() {
+    let mut ctx = ExecutionContext::new();
+    register_null_test_csv(&mut ctx);
+    let results = ctx
+        .sql("SELECT -CAST(-2147483648 AS INT) FROM null_test")
+        .unwrap();
+    let mut relation = results.borrow_mut();
+    assert!(relation.next().is_err());
+}
+
+#[test]
+fn csv_query_numerics_arithmetic() {
+    for (op, name) in &[
+        ("+", "plus"),
+        ("-", "minus"),
+        ("*", "multiply"),
+        ("/", "divide"),
+        ("%", "modulo"),
+    ] {
+        for (float_type, suffix) in &[(DataType::Float32, ""), (DataType::Float64, "_f64")] {
+            let mut ctx = ExecutionContext::new();
+            register_numerics_csv(&mut ctx, float_type.clone());
+            let sql = format!(
+                "SELECT a {op} b, a {op} 2, a {op} 2.5, a_f {op} b_f, a_f {op} 2, a_f {op} 2.5 \
+                 FROM numerics",
+                op = op
+            );
+            let results = ctx.sql(&sql).unwrap();
+            let expected = load_expected(&format!("numerics_{}{}.csv", name, suffix));
+            assert_eq!(expected, result_csv(&results), "{}", sql);
+        }
+    }
+}
+
+#[test]
+fn csv_query_mixed_type_comparison() {
+    let mut ctx = ExecutionContext::new();
+    register_numerics_csv(&mut ctx, DataType::Float32);
+    // Int32 and Float32 columns are compared with Int64 and Float64 literals
+    let sql = "SELECT a, b FROM numerics WHERE a > 1 AND a_f < 3.1";
+    let actual = execute(&mut ctx, sql);
+    assert_eq!("5\t5\n".to_string(), actual);
+}
+
+#[test]
+fn csv_query_cast_numerics() {
+    let mut ctx = ExecutionContext::new();
+    register_numerics_csv(&mut ctx, DataType::Float64);
+    let sql = "SELECT CAST(a_f AS INT), CAST(a AS FLOAT), CAST(b AS VARCHAR(10)) FROM numerics";
+    let actual = execute(&mut ctx, sql);
+    let expected = "3\t2.0\t\"3\"\n3\t5.0\t\"5\"\n2\t0.0\t\"2\"\n".to_string();
+    assert_eq!(expected, actual);
+}
+
+#[test]
+fn csv_query_invalid_operand_types() {
+    let mut ctx = ExecutionContext::new();
+    register_null_test_csv(&mut ctx);
+    assert!(ctx.sql("SELECT c_string + c_int FROM null_test").is_err());
+    assert!(ctx
+        .sql("SELECT c_int FROM null_test WHERE c_int AND c_bool")
+        .is_err());
+}
+
+#[test]
+fn csv_query_divide_and_modulus() {
+    let mut ctx = ExecutionContext::new();
+    register_numerics_csv(&mut ctx, DataType::Float64);
+    let sql = "SELECT a / b, a % b, a_f / b_f, a_f % b_f FROM numerics";
+    let actual = execute(&mut ctx, sql);
+    let expected = "0\t2\t-1.4741784037558687\t1.0100000000000002\n\
+                    1\t0\t1.0\t0.0\n\
+                    0\t0\t0.525\t2.1\n"
+        .to_string();
+    assert_eq!(expected, actual);
+}
+
+#[test]
+fn csv_query_divide_by_zero_error() {
+    let mut ctx = ExecutionContext::new();
+    register_numerics_csv(&mut ctx, DataType::Float64);
+    let results = ctx.sql("SELECT b / a FROM numerics").unwrap();
+    let mut relation = results.borrow_mut();
+    assert!(relation.next().is_err());
+}
+
+#[test]
+fn csv_query_divide_by_zero_null() {
+    let mut ctx = ExecutionContext::new();
+    ctx.set_divide_by_zero(DivideByZero::Null);
+    register_numerics_csv(&mut ctx, DataType::Float64);
+    let sql = "SELECT b / a, b % a, b_f / (a_f - a_f) FROM numerics";
+    let actual = execute(&mut ctx, sql);
+    let expected = "1\t1\tNULL\n1\t0\tNULL\nNULL\tNULL\tNULL\n".to_string();
+    assert_eq!(expected, actual);
+}
+
+#[test]
+fn csv_query_date_comparison() {
+    let mut ctx = ExecutionContext::new();
+    register_events_csv(&mut ctx);
+    let sql = "SELECT id FROM events WHERE event_date > '2019-02-01'";
+    let actual = execute(&mut ctx, sql);
+    assert_eq!("2\n3\n".to_string(), actual);
+}
+
+#[test]
+fn csv_query_time_comparison() {
+    let mut ctx = ExecutionContext::new();
+    register_events_csv(&mut ctx);
+    let sql = "SELECT id FROM events WHERE event_time < '12:00:00'";
+    let actual = execute(&mut ctx, sql);
+    assert_eq!("1\n3\n".to_string(), actual);
+}
+
+#[test]
+fn csv_query_timestamp_with_time_zone() {
+    let mut ctx = ExecutionContext::new();
+    register_events_csv(&mut ctx);
+    let sql = "SELECT CAST(event_ts AS VARCHAR(30)) FROM events";
+    let actual = execute(&mut ctx, sql);
+    let expected = "\"2019-01-01 10:15:30\"\n\
+                    \"2019-02-15 22:59:59.500\"\n\
+                    \"2019-02-28 00:00:00\"\n\
+                    NULL\n"
+        .to_string();
+    assert_eq!(expected, actual);
+}
+
+#[test]
+fn csv_query_date_part_and_date_trunc() {
+    let mut ctx = ExecutionContext::new();
+    register_events_csv(&mut ctx);
+    let sql = "SELECT date_part('month', event_ts), date_part('dow', event_date), \
+               CAST(date_trunc('month', event_ts) AS VARCHAR(30)) FROM events";
+    let actual = execute(&mut ctx, sql);
+    let expected = "1\t2\t\"2019-01-01 00:00:00\"\n\
+                    2\t5\t\"2019-02-01 00:00:00\"\n\
+                    2\t4\t\"2019-02-01 00:00:00\"\n\
+                    NULL\tNULL\tNULL\n"
+        .to_string();
+    assert_eq!(expected, actual);
+}
+
+#[test]
+fn csv_query_min_max_temporal() {
+    let mut ctx = ExecutionContext::new();
+    register_events_csv(&mut ctx);
+    let sql = "SELECT MIN(event_ts), MAX(event_ts), MIN(event_date), MAX(event_time) FROM events";
+    let actual = execute(&mut ctx, sql);
+    let expected =
+        "2019-01-01 10:15:30\t2019-02-28 00:00:00\t2019-01-01\t23:59:59.500\n".to_string();
+    assert_eq!(expected, actual);
+
+    let sql = "SELECT id > 1, MIN(event_ts), MAX(event_date), MIN(event_time) FROM events \
+               GROUP BY id > 1";
+    let actual = execute(&mut ctx, sql);
+    let mut rows: Vec<&str> = actual.lines().collect();
+    rows.sort();
+    assert_eq!(
+        vec![
+            "false\t2019-01-01 10:15:30\t2019-01-01\t10:15:00",
+            "true\t2019-02-15 22:59:59.500\t2019-02-28\t00:00:00",
+        ],
+        rows
+    );
+}
+
+#[test]
+fn csv_query_interval_arithmetic() {
+    let mut ctx = ExecutionContext::new();
+    register_events_csv(&mut ctx);
+    let sql = "SELECT CAST(event_ts + to_interval('1 day 2 hours') AS VARCHAR(30)), \
+               CAST(event_date - to_interval('30 minutes') AS VARCHAR(30)) \
+               FROM events WHERE id = 1";
+    let actual = execute(&mut ctx, sql);
+    let expected = "\"2019-01-02 12:15:30\"\t\"2018-12-31 23:30:00\"\n".to_string();
+    assert_eq!(expected, actual);
+}
+
+#[test]
+fn csv_query_extract_and_interval_syntax() {
+    let mut ctx = ExecutionContext::new();
+    register_events_csv(&mut ctx);
+    let sql = "SELECT EXTRACT(month FROM event_ts), EXTRACT(DOW FROM event_date), \
+               CAST(event_ts + INTERVAL '1 day 2 hours' AS VARCHAR(30)) FROM events";
+    let actual = execute(&mut ctx, sql);
+    let expected = "1\t2\t\"2019-01-02 12:15:30\"\n\
+                    2\t5\t\"2019-02-17 00:59:59.500\"\n\
+                    2\t4\t\"2019-03-01 02:00:00\"\n\
+                    NULL\tNULL\tNULL\n"
+        .to_string();
+    assert_eq!(expected, actual);
+}
+
+#[test]
+fn csv_query_cast_timestamp_with_time_zone() {
+    let mut ctx = ExecutionContext::new();
+    register_events_csv(&mut ctx);
+    let sql = "SELECT CAST(CAST('2019-01-01 03:00:00+02:00' AS TIMESTAMP WITH TIME ZONE) \
+               AS VARCHAR(30)) FROM events WHERE id = 1";
+    let actual = execute(&mut ctx, sql);
+    assert_eq!("\"2019-01-01 01:00:00\"\n".to_string(), actual);
+}
+
+#[test]
+fn csv_query_interval_overflow() {
+    let mut ctx = ExecutionContext::new();
+    register_events_csv(&mut ctx);
+    let results = ctx
+        .sql("SELECT event_ts + INTERVAL '106751991 days' FROM events")
+        .unwrap();
+    let mut relation = results.borrow_mut();
+    assert!(relation.next().is_err());
+}
+
+#[test]
+fn csv_query_date_arithmetic() {
+    let mut ctx = ExecutionContext::new();
+    register_events_csv(&mut ctx);
+    let sql = "SELECT event_date - CAST('2019-01-01' AS DATE), \
+               CAST(event_date + 1 AS VARCHAR(10)) FROM events";
+    let actual = execute(&mut ctx, sql);
+    let expected = "0\t\"2019-01-02\"\n\
+                    45\t\"2019-02-16\"\n\
+                    58\t\"2019-03-01\"\n\
+                    NULL\tNULL\n"
+        .to_string();
+    assert_eq!(expected, actual);
+}
+
+#[test]
+fn csv_query_now() {
+    let mut ctx = ExecutionContext::new();
+    register_events_csv(&mut ctx);
+    let sql = "SELECT now() > CAST('2019-01-01' AS TIMESTAMP) FROM events WHERE id = 1";
+    let actual = execute(&mut ctx, sql);
+    assert_eq!("true\n".to_string(), actual);
+}
+
+#[test]
+fn csv_query_invalid_date() {
+    let mut ctx = ExecutionContext::new();
+    register_events_csv(&mut ctx);
+    let results = ctx
+        .sql("SELECT CAST('2019-13-01' AS DATE) FROM events")
+        .unwrap();
+    let mut relation = results.borrow_mut();
+    assert!(relation.next().is_err());
+    assert!(ctx
+        .sql("SELECT date_trunc('fortnight', event_ts) FROM events")
+        .is_err());
+}
+
+#[test]
+fn query_struct_fields() {
+    let mut ctx = ExecutionContext::new();
+    register_customers(&mut ctx);
+    let sql = "SELECT id, address.street, address.zip FROM customers \
+               WHERE address.zip IS NULL OR address.zip > 80301";
+    let actual = execute(&mut ctx, sql);
+    let expected = "2\t\"High St\"\tNULL\n3\t\"Main St\"\t80303\n".to_string();
+    assert_eq!(expected, actual);
+}
+
+#[test]
+fn query_group_by_struct_field() {
+    let mut ctx = ExecutionContext::new();
+    register_customers(&mut ctx);
+    let sql = "SELECT address.street, COUNT(id) FROM customers GROUP BY address.street";
+    let actual = execute(&mut ctx, sql);
+    let mut rows: Vec<&str> = actual.lines().collect();
+    rows.sort();
+    assert_eq!(vec!["\"High St\"\t1", "\"Main St\"\t2"], rows);
+}
+
+#[test]
+fn csv_query_group_by_bool() {
+    let mut ctx = ExecutionContext::new();
+    register_null_test_csv(&mut ctx);
+    let sql = "SELECT c_bool, COUNT(c_int) FROM null_test GROUP BY c_bool";
+    let actual = execute(&mut ctx, sql);
+    let mut rows: Vec<&str> = actual.lines().collect();
+    rows.sort();
+    assert_eq!(vec!["false\t2", "true\t3"], rows);
+}
+
+#[test]
+fn csv_query_group_by_float_with_nulls() {
+    let mut ctx = ExecutionContext::new();
+    register_null_test_csv(&mut ctx);
+    let sql = "SELECT c_float, COUNT(c_int) FROM null_test GROUP BY c_float";
+    let actual = execute(&mut ctx, sql);
+    let mut rows: Vec<&str> = actual.lines().collect();
+    rows.sort();
+    assert_eq!(
+        vec!["1.1\t1", "2.2\t1", "4.4\t1", "6.6\t1", "NULL\t1"],
+        rows
+    );
+}
+
+#[test]
+fn csv_query_group_by_expression() {
+    let mut ctx = ExecutionContext::new();
+    register_cities_csv(&mut ctx);
+    let sql = "SELECT CAST(lat AS int), COUNT(city) FROM cities GROUP BY CAST(lat AS int)";
+    let actual = execute(&mut ctx, sql);
+    let mut rows: Vec<&str> = actual.lines().collect();
+    rows.sort();
+    assert_eq!(
+        vec!["50\t7", "51\t10", "52\t8", "53\t5", "54\t2", "55\t2", "57\t3"],
+        rows
+    );
+}
+
+#[test]
+fn csv_aggregate_by_c_bool() {
+    let mut ctx = ExecutionContext::new();
+    register_all_types_flat_csv(&mut ctx);
+    let aggregates: Vec<String> = all_types_flat_schema()
+        .fields()
+        .iter()
+        .skip(1)
+        .map(|f| format!("MIN({}), MAX({})", f.name(), f.name()))
+        .collect();
+    let sql = format!(
+        "SELECT c_bool, {} FROM all_types_flat GROUP BY c_bool",
+        aggregates.join(", ")
+    );
+    let actual = execute(&mut ctx, &sql);
+    let mut rows: Vec<String> = actual
+        .lines()
+        .map(|line| line.replace('\t', ",").replace('"', ""))
+        .collect();
+    rows.sort();
+    // strings are formatted with escapes in the results
+    let expected: Vec<String> = load_expected("csv_aggregate_by_c_bool.csv")
+        .lines()
+        .map(|line| {
+            let values: Vec<String> = line
+                .split(',')
+                .map(|v| format!("{:?}", v).replace('"', ""))
+                .collect();
+            values.join(",")
+        })
+        .collect();
+    assert_eq!(expected, rows);
+}
+
+#[test]
+fn csv_query_min_max_utf8() {
+    let mut ctx = ExecutionContext::new();
+    register_cities_csv(&mut ctx);
+    let sql = "SELECT MIN(city), MAX(city) FROM cities";
+    let actual = execute(&mut ctx, sql);
+    let expected = "\"Aberdeen, Aberdeen City, UK\"\t\"Worthing, West Sussex, UK\"\n".to_string();
+    assert_eq!(expected, actual);
+
+    let sql = "SELECT CAST(lat AS int), MIN(city), MAX(city) FROM cities WHERE lat > 55 \
+               GROUP BY CAST(lat AS int)";
+    let actual = execute(&mut ctx, sql);
+    let mut rows: Vec<&str> = actual.lines().collect();
+    rows.sort();
+    assert_eq!(
+        vec![
+            "55\t\"Ayr, South Ayrshire, UK\"\t\"Londonderry, Derry, UK\"",
+            "57\t\"Aberdeen, Aberdeen City, UK\"\t\"Inverness, the UK\"",
+        ],
+        rows
+    );
+}
+
+#[test]
+fn csv_query_min_max_bool() {
+    let mut ctx = ExecutionContext::new();
+    register_null_test_csv(&mut ctx);
+    let sql = "SELECT MIN(c_bool), MAX(c_bool), bool_and(c_int > 0), bool_or(c_int > 4), \
+               every(c_int < 5) FROM null_test";
+    let actual = execute(&mut ctx, sql);
+    assert_eq!("false\ttrue\ttrue\ttrue\tfalse\n".to_string(), actual);
+}
+
+#[test]
+fn csv_query_bool_and_bool_or_group_by() {
+    let mut ctx = ExecutionContext::new();
+    register_null_test_csv(&mut ctx);
+    let sql = "SELECT c_bool, bool_and(c_int > 1), bool_or(c_int > 4), every(c_int < 5) \
+               FROM null_test GROUP BY c_bool";
+    let actual = execute(&mut ctx, sql);
+    let mut rows: Vec<&str> = actual.lines().collect();
+    rows.sort();
+    assert_eq!(
+        vec!["false\ttrue\ttrue\tfalse", "true\tfalse\tfalse\ttrue"],
+        rows
+    );
+}
+
+#[test]
+fn csv_query_avg() {
+    let mut ctx = ExecutionContext::new();
+    register_null_test_csv(&mut ctx);
+    // NULL values are ignored and integers are averaged in double precision
+    let sql = "SELECT AVG(c_int), AVG(c_float) FROM null_test";
+    let actual = execute_floats(&mut ctx, sql);
+    assert_floats_eq(&[3.0, 3.575], &actual[0]);
+
+    let sql = "SELECT c_bool, AVG(c_int), AVG(c_float) FROM null_test GROUP BY c_bool";
+    let actual = execute(&mut ctx, sql);
+    let mut rows: Vec<&str> = actual.lines().collect();
+    rows.sort();
+    assert_eq!(
+        vec!["false\t4.5\t5.5", "true\t2.0\t1.6500000000000001"],
+        rows
+    );
+}
+
+#[test]
+fn csv_query_variance_and_stddev() {
+    let mut ctx = ExecutionContext::new();
+    register_null_test_csv(&mut ctx);
+    let sql = "SELECT var_samp(c_int), var_pop(c_int), stddev_samp(c_int), stddev_pop(c_int), \
+               stddev(c_float) FROM null_test";
+    let actual = execute_floats(&mut ctx, sql);
+    assert_floats_eq(
+        &[2.5, 2.0, 2.5_f64.sqrt(), 2.0_f64.sqrt(), 2.43909136],
+        &actual[0],
+    );
+}
+
+#[test]
+fn csv_query_covariance_and_regression() {
+    let mut ctx = ExecutionContext::new();
+    register_null_test_csv(&mut ctx);
+    // the row with a NULL c_float is ignored
+    let sql = "SELECT covar_samp(c_float, c_int), covar_pop(c_float, c_int), \
+               corr(c_float, c_int), regr_slope(c_float, c_int), \
+               regr_intercept(c_float, c_int) FROM null_test";
+    let actual = execute_floats(&mut ctx, sql);
+    assert_floats_eq(&[4.4, 3.3, 0.98806436, 1.32, -0.385], &actual[0]);
+}
+
+#[test]
+fn csv_query_statistics_group_by() {
+    let mut ctx = ExecutionContext::new();
+    register_null_test_csv(&mut ctx);
+    let sql = "SELECT c_bool, var_samp(c_int), var_pop(c_int) FROM null_test GROUP BY c_bool";
+    let actual = execute(&mut ctx, sql);
+    let mut rows: Vec<&str> = actual.lines().collect();
+    rows.sort();
+    assert_eq!(
+        vec!["false\t0.5\t0.25", "true\t1.0\t0.6666666666666666"],
+        rows
+    );
+
+    // a sample statistic of a single value is NULL
+    let sql = "SELECT var_samp(c_int), var_pop(c_int) FROM null_test WHERE c_int = 1";
+    assert_eq!("NULL\t0.0\n".to_string(), execute(&mut ctx, sql));
+}
+
+#[test]
+fn csv_query_approx_count_distinct() {
+    let mut ctx = ExecutionContext::new();
+    register_null_test_csv(&mut ctx);
+    let sql = "SELECT approx_count_distinct(c_int), approx_count_distinct(c_bool), \
+               approx_count_distinct(c_float) FROM null_test";
+    assert_eq!("5\t2\t4\n".to_string(), execute(&mut ctx, sql));
+
+    let mut ctx = ExecutionContext::new();
+    register_cities_csv(&mut ctx);
+    let sql = "SELECT CAST(lat AS int), approx_count_distinct(city) FROM cities \
+               WHERE lat > 54 GROUP BY CAST(lat AS int)";
+    let actual = execute(&mut ctx, sql);
+    let mut rows: Vec<&str> = actual.lines().collect();
+    rows.sort();
+    assert_eq!(vec!["54\t2", "55\t2", "57\t3"], rows);
+}
+
+#[test]
+fn csv_query_approx_percentile() {
+    let mut ctx = ExecutionContext::new();
+    register_null_test_csv(&mut ctx);
+    let sql = "SELECT approx_median(c_int), approx_percentile_cont(c_float, 0.5), \
+               approx_percentile_cont(c_int, 0), approx_percentile_cont(c_int, 1.0) \
+               FROM null_test";
+    let actual = execute_floats(&mut ctx, sql);
+    assert_floats_eq(&[3.0, 3.3, 1.0, 5.0], &actual[0]);
+}
+
+#[test]
+fn csv_query_median_percentile_and_mode() {
+    let mut ctx = ExecutionContext::new();
+    register_null_test_csv(&mut ctx);
+    let sql = "SELECT median(c_int), percentile_cont(c_float, 0.25) FROM null_test";
+    let actual = execute_floats(&mut ctx, sql);
+    assert_floats_eq(&[3.0, 1.925], &actual[0]);
+
+    let sql = "SELECT percentile_disc(c_int, 0.5), mode(c_bool) FROM null_test";
+    assert_eq!("3\ttrue\n".to_string(), execute(&mut ctx, sql));
+
+    let sql = "SELECT c_bool, median(c_int), percentile_disc(c_int, 1.0) FROM null_test \
+               GROUP BY c_bool";
+    let actual = execute(&mut ctx, sql);
+    let mut rows: Vec<&str> = actual.lines().collect();
+    rows.sort();
+    assert_eq!(vec!["false\t4.5\t5", "true\t2.0\t3"], rows);
+
+    // the standard syntax orders the values WITHIN GROUP
+    let sql = "SELECT percentile_disc(0.5) WITHIN GROUP (ORDER BY c_int), \
+               mode() WITHIN GROUP (ORDER BY c_bool) FROM null_test";
+    assert_eq!("3\ttrue\n".to_string(), execute(&mut ctx, sql));
+}
+
+#[test]
+fn csv_query_string_agg() {
+    let mut ctx = ExecutionContext::new();
+    register_cities_csv(&mut ctx);
+    let sql = "SELECT CAST(lat AS int), string_agg(city, ' / ', city) FROM cities \
+               WHERE lat > 55 GROUP BY CAST(lat AS int)";
+    let actual = execute(&mut ctx, sql);
+    let mut rows: Vec<&str> = actual.lines().collect();
+    rows.sort();
+    assert_eq!(
+        vec![
+            "55\t\"Ayr, South Ayrshire, UK / Londonderry, Derry, UK\"",
+            "57\t\"Aberdeen, Aberdeen City, UK / Elgin, Scotland, the UK / Inverness, the UK\"",
+        ],
+        rows
+    );
+
+    // without an ordering the values are concatenated in input order
+    let sql = "SELECT listagg(city, '|') FROM cities WHERE lat > 55.5";
+    let actual = execute(&mut ctx, sql);
+    assert_eq!(
+        "\"Elgin, Scotland, the UK|Aberdeen, Aberdeen City, UK|Inverness, the UK\"\n".to_string(),
+        actual
+    );
+
+    // the standard syntax orders the values with ORDER BY or WITHIN GROUP
+    let sql = "SELECT string_agg(city, '|' ORDER BY lat), \
+               listagg(city, '|') WITHIN GROUP (ORDER BY city) FROM cities WHERE lat > 55.5";
+    let actual = execute(&mut ctx, sql);
+    assert_eq!(
+        "\"Aberdeen, Aberdeen City, UK|Inverness, the UK|Elgin, Scotland, the UK\"\t\
+         \"Aberdeen, Aberdeen City, UK|Elgin, Scotland, the UK|Inverness, the UK\"\n"
+            .to_string(),
+        actual
+    );
+}
+
+#[test]
+fn query_array_agg_with_ordering() {
+    let mut ctx = ExecutionContext::new();
+    register_customers(&mut ctx);
+    // NULL keys are ordered last
+    let sql = "SELECT array_agg(id, address.zip), array_agg(address.street, id) FROM customers";
+    let actual = execute(&mut ctx, sql);
+    assert_eq!(
+        "[1, 3, 2]\t[\"Main St\", \"High St\", \"Main St\"]\n".to_string(),
+        actual
+    );
+
+    let sql = "SELECT array_agg(id ORDER BY address.zip) FROM customers";
+    assert_eq!("[1, 3, 2]\n".to_string(), execute(&mut ctx, sql));
+}
+
+#[test]
+fn query_unnest() {
+    let mut ctx = ExecutionContext::new();
+    register_customers(&mut ctx);
+    let sql = "SELECT id, unnest(tags) FROM customers";
+    let actual = execute(&mut ctx, sql);
+    let expected = "1\t\"vip\"\n1\t\"new\"\n".to_string();
     assert_eq!(expected, actual);
 }
 
 #[test]
-fn csv_query_group_by_string_min_max() {
+fn query_array_functions() {
     let mut ctx = ExecutionContext::new();
-    let schema = Arc::new(Schema::new(vec![
-        Field::new("a", DataType::Utf8, false),
-        Field::new("b", DataType::Float64, false),
-    ]));
-    register_csv(&mut ctx, "t1", "test/data/aggregate_test_2.csv", &schema);
-    //TODO add ORDER BY once supported, to make this test determistic
-    let sql = "SELECT a, MIN(b), MAX(b) FROM t1 GROUP BY a";
+    register_customers(&mut ctx);
+    let sql = "SELECT id, array_length(tags), array_element(tags, 2), \
+               array_contains(tags, 'vip') FROM customers";
     let actual = execute(&mut ctx, sql);
-    let expected = "\"three\"\t1.0\t2.0\n\"two\"\t3.3\t5.5\n\"one\"\t1.1\t2.2\n".to_string();
+    let expected = "1\t2\t\"new\"\ttrue\n2\t0\tNULL\tfalse\n3\tNULL\tNULL\tNULL\n".to_string();
+    assert_eq!(expected, actual);
+
+    // a subscript is the same as array_element()
+    let sql = "SELECT id, tags[1], tags[3] FROM customers";
+    let actual = execute(&mut ctx, sql);
+    assert_eq!(
+        "1\t\"vip\"\tNULL\n2\tNULL\tNULL\n3\tNULL\tNULL\n".to_string(),
+        actual
+    );
+}
+
+#[test]
+fn query_array_agg() {
+    let mut ctx = ExecutionContext::new();
+    register_customers(&mut ctx);
+    let sql = "SELECT array_agg(address.zip) FROM customers";
+    let actual = execute(&mut ctx, sql);
+    assert_eq!("[80301, NULL, 80303]\n".to_string(), actual);
+
+    let sql = "SELECT address.street, array_agg(id) FROM customers GROUP BY address.street";
+    let actual = execute(&mut ctx, sql);
+    let mut rows: Vec<&str> = actual.lines().collect();
+    rows.sort();
+    assert_eq!(vec!["\"High St\"\t[2]", "\"Main St\"\t[1, 3]"], rows);
+}
+
+#[test]
+fn csv_missing_file() {
+    let result = CsvDataSource::new("test/data/no_such_file.csv", null_test_schema(), true, 1024);
+    assert!(result.is_err());
+}
+
+#[test]
+fn csv_query_aggregates_skip_nulls() {
+    let mut ctx = ExecutionContext::new();
+    register_null_test_csv(&mut ctx);
+    let sql = "SELECT MIN(c_float), MAX(c_float), COUNT(c_float) FROM null_test";
+    let actual = execute(&mut ctx, sql);
+    let expected = "1.1\t6.6\t4\n".to_string();
     assert_eq!(expected, actual);
 }
 
 #[test]
-fn csv_query_cast() {
+fn csv_query_count_star_counts_rows() {
     let mut ctx = ExecutionContext::new();
-    register_cities_csv(&mut ctx);
-    let sql = "SELECT CAST(lat AS int) FROM cities";
+    register_null_test_csv(&mut ctx);
+    // the first column of the derived table has a NULL, which COUNT(*) still counts
+    let sql = "SELECT COUNT(*), COUNT(1), COUNT(c_float) FROM \
+               (SELECT c_float, c_bool FROM null_test)";
+    let actual = execute(&mut ctx, sql);
+    assert_eq!("5\t5\t4\n".to_string(), actual);
+
+    let sql =
+        "SELECT c_bool, COUNT(*) FROM (SELECT c_float, c_bool FROM null_test) GROUP BY c_bool";
+    let actual = execute(&mut ctx, sql);
+    let mut rows: Vec<&str> = actual.lines().collect();
+    rows.sort();
+    assert_eq!(vec!["false\t2", "true\t3"], rows);
+}
+
+#[test]
+fn csv_query_group_by_aggregates_skip_nulls() {
+    let mut ctx = ExecutionContext::new();
+    register_null_test_csv(&mut ctx);
+    let sql = "SELECT c_string, MIN(c_float), COUNT(c_float) FROM null_test GROUP BY c_string";
+    let actual = execute(&mut ctx, sql);
+    let mut rows: Vec<&str> = actual.lines().collect();
+    rows.sort();
+    assert_eq!(
+        vec![
+            "\"\"\t4.4\t2",
+            "\"1.11\"\t1.1\t1",
+            "\"2.22\"\t2.2\t1",
+            "\"3.33\"\tNULL\t0",
+        ],
+        rows
+    );
+}
+
+#[test]
+fn csv_query_aggregate_filter() {
+    let mut ctx = ExecutionContext::new();
+    register_null_test_csv(&mut ctx);
+    let sql = "SELECT COUNT(c_int) FILTER (WHERE c_bool), \
+               SUM(c_int) FILTER (WHERE c_float > 2.0), SUM(c_int) FROM null_test";
+    let actual = execute(&mut ctx, sql);
+    assert_eq!("3\t11\t15\n".to_string(), actual);
+}
+
+#[test]
+fn csv_query_group_by_aggregate_filter() {
+    let mut ctx = ExecutionContext::new();
+    register_null_test_csv(&mut ctx);
+    // rows where the condition is NULL are not aggregated
+    let sql = "SELECT c_bool, COUNT(c_float) filter (where c_int > 1), \
+               MAX(c_int) FILTER (WHERE c_float IS NULL), \
+               string_agg(c_string, ',') FILTER (WHERE c_float < 3.0) \
+               FROM null_test GROUP BY c_bool";
+    let actual = execute(&mut ctx, sql);
+    let mut rows: Vec<&str> = actual.lines().collect();
+    rows.sort();
+    assert_eq!(
+        vec!["false\t2\tNULL\tNULL", "true\t1\t3\t\"1.11,2.22\""],
+        rows
+    );
+}
+
+#[test]
+fn csv_query_group_by_rollup() {
+    let mut ctx = ExecutionContext::new();
+    register_null_test_csv(&mut ctx);
+    let sql = "SELECT c_bool, COUNT(c_int), SUM(c_int), GROUPING(c_bool) FROM null_test \
+               GROUP BY ROLLUP(c_bool)";
+    let actual = execute(&mut ctx, sql);
+    let mut rows: Vec<&str> = actual.lines().collect();
+    rows.sort();
+    let expected = vec!["NULL\t5\t15\t1", "false\t2\t9\t0", "true\t3\t6\t0"];
+    assert_eq!(expected, rows);
+
+    let sql = "SELECT c_bool, COUNT(c_int), SUM(c_int), GROUPING(c_bool) FROM null_test \
+               GROUP BY GROUPING SETS ((c_bool), ())";
+    let actual = execute(&mut ctx, sql);
+    let mut rows: Vec<&str> = actual.lines().collect();
+    rows.sort();
+    assert_eq!(expected, rows);
+
+    // GROUPING() tells a NULL group apart from the subtotal
+    let sql = "SELECT c_float, COUNT(c_int), GROUPING(c_float) FROM null_test WHERE c_int > 2 \
+               GROUP BY ROLLUP(c_float)";
+    let actual = execute(&mut ctx, sql);
+    let mut rows: Vec<&str> = actual.lines().collect();
+    rows.sort();
+    assert_eq!(
+        vec!["4.4\t1\t0", "6.6\t1\t0", "NULL\t1\t0", "NULL\t3\t1"],
+        rows
+    );
+}
+
+#[test]
+fn csv_query_group_by_cube() {
+    let mut ctx = ExecutionContext::new();
+    register_null_test_csv(&mut ctx);
+    let sql = "SELECT c_bool, c_int > 2, COUNT(c_float) FROM null_test \
+               GROUP BY CUBE(c_bool, c_int > 2)";
+    let actual = execute(&mut ctx, sql);
+    let mut rows: Vec<&str> = actual.lines().collect();
+    rows.sort();
+    assert_eq!(
+        vec![
+            "NULL\tNULL\t4",
+            "NULL\tfalse\t2",
+            "NULL\ttrue\t2",
+            "false\tNULL\t2",
+            "false\ttrue\t2",
+            "true\tNULL\t2",
+            "true\tfalse\t2",
+            "true\ttrue\t0",
+        ],
+        rows
+    );
+}
+
+#[test]
+fn csv_query_window_ranking() {
+    let mut ctx = ExecutionContext::new();
+    register_null_test_csv(&mut ctx);
+    let sql = "SELECT c_int, ROW_NUMBER() OVER (PARTITION BY c_bool ORDER BY c_int DESC), \
+               RANK() OVER (ORDER BY c_bool), DENSE_RANK() OVER (ORDER BY c_bool), \
+               NTILE(2) OVER (ORDER BY c_int) FROM null_test";
+    let actual = execute(&mut ctx, sql);
+    let expected = "1\t3\t3\t2\t1\n\
+                    2\t2\t3\t2\t1\n\
+                    3\t1\t3\t2\t1\n\
+                    4\t2\t1\t1\t2\n\
+                    5\t1\t1\t1\t2\n";
+    assert_eq!(expected.to_string(), actual);
+}
+
+#[test]
+fn csv_query_window_aggregates() {
+    let mut ctx = ExecutionContext::new();
+    register_null_test_csv(&mut ctx);
+    // without a frame, each row sees its peers in the window ordering
+    let sql = "SELECT c_int, SUM(c_int) OVER (ORDER BY c_int), SUM(c_int) OVER (ORDER BY c_bool), \
+               SUM(c_int) OVER (PARTITION BY c_bool), \
+               MIN(c_int) OVER (ORDER BY c_int DESC ROWS BETWEEN 1 PRECEDING AND 1 FOLLOWING), \
+               COUNT(c_float) OVER (ORDER BY c_int ROWS BETWEEN CURRENT ROW AND UNBOUNDED FOLLOWING) \
+               FROM null_test";
+    let actual = execute(&mut ctx, sql);
+    let expected = "1\t1\t15\t6\t1\t4\n\
+                    2\t3\t15\t6\t1\t3\n\
+                    3\t6\t15\t6\t2\t2\n\
+                    4\t10\t9\t9\t3\t2\n\
+                    5\t15\t9\t9\t4\t1\n";
+    assert_eq!(expected.to_string(), actual);
+}
+
+#[test]
+fn csv_query_window_values() {
+    let mut ctx = ExecutionContext::new();
+    register_null_test_csv(&mut ctx);
+    let sql =
+        "SELECT c_int, LAG(c_int) OVER (ORDER BY c_int), LEAD(c_int, 2, 0) OVER (ORDER BY c_int), \
+               FIRST_VALUE(c_string) OVER (PARTITION BY c_bool ORDER BY c_int), \
+               LAST_VALUE(c_float) OVER (ORDER BY c_int ROWS BETWEEN CURRENT ROW AND 1 FOLLOWING) \
+               FROM null_test WHERE c_int > 1";
+    let actual = execute(&mut ctx, sql);
+    let expected = "2\tNULL\t4\t\"2.22\"\tNULL\n\
+                    3\t2\t5\t\"2.22\"\t4.4\n\
+                    4\t3\t0\t\"\"\t6.6\n\
+                    5\t4\t0\t\"\"\t6.6\n";
+    assert_eq!(expected.to_string(), actual);
+}
+
+#[test]
+fn csv_query_derived_table() {
+    let mut ctx = ExecutionContext::new();
+    register_null_test_csv(&mut ctx);
+    let sql =
+        "SELECT t.c_int FROM (SELECT c_int, c_float FROM null_test WHERE c_float IS NOT NULL) t \
+               WHERE t.c_int > 2";
+    let actual = execute(&mut ctx, sql);
+    assert_eq!("4\n5\n".to_string(), actual);
+}
+
+#[test]
+fn csv_query_derived_table_aggregate() {
+    let mut ctx = ExecutionContext::new();
+    register_null_test_csv(&mut ctx);
+    let sql = "SELECT flag, total FROM \
+               (SELECT c_bool, SUM(c_int) FROM null_test GROUP BY c_bool) AS t (flag, total) \
+               WHERE total > 6";
+    let actual = execute(&mut ctx, sql);
+    assert_eq!("false\t9\n".to_string(), actual);
+
+    let sql = "SELECT COUNT(total), MAX(total) FROM \
+               (SELECT c_bool, SUM(c_int) FROM null_test GROUP BY c_bool) AS t (flag, total)";
+    let actual = execute(&mut ctx, sql);
+    assert_eq!("2\t9\n".to_string(), actual);
+}
+
+#[test]
+fn csv_query_with_clause() {
+    let mut ctx = ExecutionContext::new();
+    register_null_test_csv(&mut ctx);
+    let sql = "WITH t AS (SELECT c_int, c_float FROM null_test WHERE c_float IS NOT NULL), \
+               u (a) AS (SELECT c_int FROM t WHERE c_int > 2) \
+               SELECT a FROM u";
+    let actual = execute(&mut ctx, sql);
+    assert_eq!("4\n5\n".to_string(), actual);
+}
+
+#[test]
+fn csv_query_recursive_cte() {
+    let mut ctx = ExecutionContext::new();
+    register_null_test_csv(&mut ctx);
+    let sql = "WITH RECURSIVE t (n) AS (SELECT c_int FROM null_test WHERE c_int = 1 \
+               UNION ALL SELECT n + 1 FROM t WHERE n < 5) \
+               SELECT n FROM t";
+    let actual = execute(&mut ctx, sql);
+    assert_eq!("1\n2\n3\n4\n5\n".to_string(), actual);
+
+    // UNION stops once an iteration only produces rows that were seen before
+    let sql = "WITH RECURSIVE t (n) AS (SELECT c_int FROM null_test WHERE c_int = 1 \
+               UNION SELECT n % 3 + 1 FROM t) \
+               SELECT n FROM t";
+    let actual = execute(&mut ctx, sql);
+    assert_eq!("1\n2\n3\n".to_string(), actual);
+}
+
+#[test]
+fn csv_query_in_subquery() {
+    let mut ctx = ExecutionContext::new();
+    register_null_test_csv(&mut ctx);
+    let sql = "SELECT c_int FROM null_test \
+               WHERE c_int IN (SELECT c_int FROM null_test WHERE c_float > 2)";
+    let actual = execute(&mut ctx, sql);
+    assert_eq!("2\n4\n5\n".to_string(), actual);
+
+    let sql = "SELECT c_int FROM null_test \
+               WHERE c_int NOT IN (SELECT c_int FROM null_test WHERE c_float > 2)";
+    let actual = execute(&mut ctx, sql);
+    assert_eq!("1\n3\n".to_string(), actual);
+
+    // NOT IN is never true when the subquery returns a NULL
+    let sql = "SELECT c_int FROM null_test \
+               WHERE c_float IN (SELECT c_float FROM null_test WHERE c_int > 2)";
+    let actual = execute(&mut ctx, sql);
+    assert_eq!("4\n5\n".to_string(), actual);
+    let sql = "SELECT c_int FROM null_test \
+               WHERE c_float NOT IN (SELECT c_float FROM null_test WHERE c_int > 2)";
+    let actual = execute(&mut ctx, sql);
+    assert_eq!("".to_string(), actual);
+}
+
+#[test]
+fn csv_query_correlated_subquery_predicates() {
+    let mut ctx = ExecutionContext::new();
+    register_null_test_csv(&mut ctx);
+    let sql = "SELECT c_int FROM null_test t WHERE EXISTS \
+               (SELECT 1 FROM null_test u WHERE u.c_int = t.c_int + 1 AND u.c_float IS NOT NULL)";
+    let actual = execute(&mut ctx, sql);
+    assert_eq!("1\n3\n4\n".to_string(), actual);
+
+    let sql = "SELECT c_int FROM null_test t WHERE NOT EXISTS \
+               (SELECT 1 FROM null_test u WHERE u.c_int = t.c_int + 1 AND u.c_float IS NOT NULL)";
+    let actual = execute(&mut ctx, sql);
+    assert_eq!("2\n5\n".to_string(), actual);
+
+    let sql = "SELECT c_int FROM null_test t WHERE c_int NOT IN \
+               (SELECT c_int + 1 FROM null_test u WHERE u.c_bool = t.c_bool)";
+    let actual = execute(&mut ctx, sql);
+    assert_eq!("1\n4\n".to_string(), actual);
+}
+
+#[test]
+fn csv_query_scalar_subquery() {
+    let mut ctx = ExecutionContext::new();
+    register_null_test_csv(&mut ctx);
+    let sql = "SELECT c_int FROM null_test WHERE c_float > (SELECT AVG(c_float) FROM null_test)";
+    let actual = execute(&mut ctx, sql);
+    assert_eq!("4\n5\n".to_string(), actual);
+
+    // no rows is NULL
+    let sql = "SELECT c_int FROM null_test \
+               WHERE c_float > (SELECT c_float FROM null_test WHERE c_int > 5)";
+    let actual = execute(&mut ctx, sql);
+    assert_eq!("".to_string(), actual);
+
+    let sql = "SELECT c_int FROM null_test WHERE c_float > (SELECT c_float FROM null_test)";
+    assert!(ctx.sql(sql).is_err());
+}
+
+#[test]
+fn csv_query_correlated_scalar_subquery() {
+    let mut ctx = ExecutionContext::new();
+    register_null_test_csv(&mut ctx);
+    let sql = "SELECT c_int FROM null_test t \
+               WHERE c_float > (SELECT AVG(c_float) FROM null_test u WHERE u.c_bool = t.c_bool)";
+    let actual = execute(&mut ctx, sql);
+    assert_eq!("2\n5\n".to_string(), actual);
+
+    // COUNT is zero when no rows match
+    let sql = "SELECT c_int, \
+               (SELECT COUNT(c_int) FROM null_test u WHERE u.c_int = t.c_int + 1) \
+               FROM null_test t";
+    let actual = execute(&mut ctx, sql);
+    assert_eq!("1\t1\n2\t1\n3\t1\n4\t1\n5\t0\n".to_string(), actual);
+
+    let sql = "SELECT c_int, \
+               (SELECT c_string FROM null_test u WHERE u.c_int = t.c_int - 2) \
+               FROM null_test t";
+    let actual = execute(&mut ctx, sql);
+    assert_eq!(
+        "1\tNULL\n2\tNULL\n3\t\"1.11\"\n4\t\"2.22\"\n5\t\"3.33\"\n".to_string(),
+        actual
+    );
+}
+
+#[test]
+fn csv_query_union() {
+    let mut ctx = ExecutionContext::new();
+    register_null_test_csv(&mut ctx);
+    register_csv(
+        &mut ctx,
+        "null_test_copy",
+        "test/data/null_test.csv",
+        &null_test_schema(),
+    );
+    let sql = "SELECT c_int, c_string FROM null_test WHERE c_int < 3 \
+               UNION ALL SELECT c_int, c_string FROM null_test_copy WHERE c_int > 3";
     let actual = execute(&mut ctx, sql);
-    let expected= "53\n52\n51\n50\n51\n51\n51\n51\n52\n52\n52\n51\n57\n51\n53\n55\n51\n50\n52\n53\n50\n53\n55\n50\n52\n51\n51\n54\n50\n50\n53\n54\n50\n52\n52\n57\n".to_string();
+    let expected = "1\t\"1.11\"\n2\t\"2.22\"\n4\t\"\"\n5\t\"\"\n".to_string();
     assert_eq!(expected, actual);
+
+    let sql = "SELECT c_bool FROM null_test UNION SELECT c_bool FROM null_test_copy";
+    let actual = execute(&mut ctx, sql);
+    assert_eq!("true\nfalse\n".to_string(), actual);
+
+    // the columns are cast to a common type
+    let sql = "SELECT c_int FROM null_test WHERE c_int < 3 \
+               UNION ALL SELECT c_float FROM null_test WHERE c_int = 2";
+    let actual = execute(&mut ctx, sql);
+    assert_eq!("1.0\n2.0\n2.2\n".to_string(), actual);
+
+    assert!(ctx
+        .sql("SELECT c_int FROM null_test UNION SELECT c_int, c_float FROM null_test")
+        .is_err());
+    assert!(ctx
+        .sql("SELECT c_int FROM null_test UNION SELECT c_bool FROM null_test")
+        .is_err());
+}
+
+#[test]
+fn csv_query_intersect_and_except() {
+    let mut ctx = ExecutionContext::new();
+    register_null_test_csv(&mut ctx);
+    let sql = "SELECT c_int FROM null_test WHERE c_int < 4 \
+               INTERSECT SELECT c_int FROM null_test WHERE c_int > 1";
+    let actual = execute(&mut ctx, sql);
+    assert_eq!("2\n3\n".to_string(), actual);
+
+    let sql = "SELECT c_int FROM null_test EXCEPT SELECT c_int FROM null_test WHERE c_int < 4";
+    let actual = execute(&mut ctx, sql);
+    assert_eq!("4\n5\n".to_string(), actual);
+
+    // each row of the right input matches one row of the left input
+    let sql = "SELECT c_bool FROM null_test \
+               INTERSECT ALL SELECT c_bool FROM null_test WHERE c_int > 2";
+    let actual = execute(&mut ctx, sql);
+    assert_eq!("true\nfalse\nfalse\n".to_string(), actual);
+
+    let sql = "SELECT c_bool FROM null_test \
+               EXCEPT ALL SELECT c_bool FROM null_test WHERE c_int > 2";
+    let actual = execute(&mut ctx, sql);
+    assert_eq!("true\ntrue\n".to_string(), actual);
+
+    // NULLs are equal to each other
+    let sql = "SELECT c_float FROM null_test \
+               INTERSECT SELECT c_float FROM null_test WHERE c_int = 3";
+    let actual = execute(&mut ctx, sql);
+    assert_eq!("NULL\n".to_string(), actual);
+}
+
+fn null_test_schema() -> Arc<Schema> {
+    Arc::new(Schema::new(vec![
+        Field::new("c_int", DataType::Int32, false),
+        Field::new("c_float", DataType::Float64, true),
+        Field::new("c_string", DataType::Utf8, true),
+        Field::new("c_bool", DataType::Boolean, false),
+    ]))
+}
+
+fn register_null_test_csv(ctx: &mut ExecutionContext) {
+    register_csv(
+        ctx,
+        "null_test",
+        "test/data/null_test.csv",
+        &null_test_schema(),
+    );
+}
+
+fn register_numerics_csv(ctx: &mut ExecutionContext, float_type: DataType) {
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("a", DataType::Int32, false),
+        Field::new("b", DataType::Int32, false),
+        Field::new("a_f", float_type.clone(), false),
+        Field::new("b_f", float_type, false),
+    ]));
+
+    register_csv(ctx, "numerics", "test/data/numerics.csv", &schema);
+}
+
+fn register_events_csv(ctx: &mut ExecutionContext) {
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("id", DataType::Int32, false),
+        Field::new("event_date", DataType::Date(DateUnit::Day), true),
+        Field::new("event_time", DataType::Time64(TimeUnit::Microsecond), true),
+        Field::new("event_ts", DataType::Timestamp(TimeUnit::Microsecond), true),
+    ]));
+
+    register_csv(ctx, "events", "test/data/events.csv", &schema);
+}
+
+/// Registers an in-memory table with a struct column
+fn register_customers(ctx: &mut ExecutionContext) {
+    let address_fields = vec![
+        Field::new("street", DataType::Utf8, false),
+        Field::new("zip", DataType::Int32, true),
+    ];
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("id", DataType::Int32, false),
+        Field::new("address", DataType::Struct(address_fields.clone()), false),
+        Field::new("tags", DataType::List(Box::new(DataType::Utf8)), true),
+    ]));
+
+    let mut street = BinaryBuilder::new(3);
+    for s in &["Main St", "High St", "Main St"] {
+        street.append_string(s).unwrap();
+    }
+    let mut zip = Int32Array::builder(3);
+    zip.append_value(80301).unwrap();
+    zip.append_null().unwrap();
+    zip.append_value(80303).unwrap();
+    let address = StructArray::from(vec![
+        (
+            address_fields[0].clone(),
+            Arc::new(street.finish()) as ArrayRef,
+        ),
+        (
+            address_fields[1].clone(),
+            Arc::new(zip.finish()) as ArrayRef,
+        ),
+    ]);
+    // tags are ["vip", "new"], [] and NULL
+    let mut tags = ListBuilder::new(BinaryBuilder::new(2));
+    tags.values().append_string("vip").unwrap();
+    tags.values().append_string("new").unwrap();
+    tags.append(true).unwrap();
+    tags.append(true).unwrap();
+    tags.append(false).unwrap();
+    let batch = RecordBatch::new(
+        schema.clone(),
+        vec![
+            Arc::new(Int32Array::from(vec![1, 2, 3])),
+            Arc::new(address),
+            Arc::new(tags.finish()),
+        ],
+    );
+
+    let ds = MemoryDataSource::new(schema, vec![batch]);
+    ctx.register_datasource("customers", Rc::new(RefCell::new(ds)));
+}
+
+fn all_types_flat_schema() -> Arc<Schema> {
+    Arc::new(Schema::new(vec![
+        Field::new("c_bool", DataType::Boolean, false),
+        Field::new("c_uint8", DataType::UInt8, false),
+        Field::new("c_uint16", DataType::UInt16, false),
+        Field::new("c_uint32", DataType::UInt32, false),
+        Field::new("c_uint64", DataType::UInt64, false),
+        Field::new("c_int8", DataType::Int8, false),
+        Field::new("c_int16", DataType::Int16, false),
+        Field::new("c_int32", DataType::Int32, false),
+        Field::new("c_int64", DataType::Int64, false),
+        Field::new("c_float32", DataType::Float32, false),
+        Field::new("c_float64", DataType::Float64, false),
+        Field::new("c_utf8", DataType::Utf8, false),
+    ]))
+}
+
+fn register_all_types_flat_csv(ctx: &mut ExecutionContext) {
+    register_csv(
+        ctx,
+        "all_types_flat",
+        "test/data/all_types_flat.csv",
+        &all_types_flat_schema(),
+    );
 }
 
 fn register_cities_csv(ctx: &mut ExecutionContext) {
@@ -83,20 +1430,101 @@ fn register_cities_csv(ctx: &mut ExecutionContext) {
         Field::new("lng", DataType::Float64, false),
     ]));
 
-    register_csv(ctx, "cities", "test/data/uk_cities.csv", &schema);
+    // the file has no header row
+    let csv_datasource =
+        CsvDataSource::new("test/data/uk_cities.csv", schema, false, 1024).unwrap();
+    ctx.register_datasource("cities", Rc::new(RefCell::new(csv_datasource)));
 }
 
 fn register_csv(ctx: &mut ExecutionContext, name: &str, filename: &str, schema: &Arc<Schema>) {
-    let csv_datasource = CsvDataSource::new(filename, schema.clone(), 1024);
+    let csv_datasource = CsvDataSource::new(filename, schema.clone(), true, 1024).unwrap();
     ctx.register_datasource(name, Rc::new(RefCell::new(csv_datasource)));
 }
 
+/// Load the contents of a file from the expected results directory
+fn load_expected(filename: &str) -> String {
+    fs::read_to_string(format!("test/data/expected/{}", filename)).unwrap()
+}
+
+/// Execute query and return each row of the result set as floating point values
+fn execute_floats(ctx: &mut ExecutionContext, sql: &str) -> Vec<Vec<f64>> {
+    execute(ctx, sql)
+        .lines()
+        .map(|line| line.split('\t').map(|v| v.parse().unwrap()).collect())
+        .collect()
+}
+
+/// Compare floating point results that may differ in the last few digits
+fn assert_floats_eq(expected: &[f64], actual: &[f64]) {
+    assert_eq!(expected.len(), actual.len());
+    for (e, a) in expected.iter().zip(actual) {
+        assert!(
+            (e - a).abs() < 1e-6,
+            "expected {:?} but got {:?}",
+            expected,
+            actual
+        );
+    }
+}
+
 /// Execute query and return result set as tab delimited string
 fn execute(ctx: &mut ExecutionContext, sql: &str) -> String {
     let results = ctx.sql(&sql).unwrap();
     result_str(&results)
 }
 
+/// Execute a selection against the null_test table and return result set as tab delimited string
+fn execute_null_test_selection(ctx: &mut ExecutionContext, expr: Expr) -> String {
+    let plan = LogicalPlan::Selection {
+        expr,
+        input: Rc::new(LogicalPlan::TableScan {
+            schema_name: "".to_string(),
+            table_name: "null_test".to_string(),
+            schema: null_test_schema(),
+            projection: None,
+        }),
+    };
+    let results = ctx.execute(&plan).unwrap();
+    result_str(&results)
+}
+
+/// Format a result set as comma separated values, using the display format for numbers
+fn result_csv(results: &Rc<RefCell<Relation>>) -> String {
+    let mut relation = results.borrow_mut();
+    let mut str = String::new();
+    while let Some(batch) = relation.next().unwrap() {
+        for row_index in 0..batch.num_rows() {
+            let values: Vec<String> = (0..batch.num_columns())
+                .map(|column_index| {
+                    let column = batch.column(column_index);
+                    match column.data_type() {
+                        DataType::Int32 => {
+                            let array = column.as_any().downcast_ref::<Int32Array>().unwrap();
+                            format!("{}", array.value(row_index))
+                        }
+                        DataType::Int64 => {
+                            let array = column.as_any().downcast_ref::<Int64Array>().unwrap();
+                            format!("{}", array.value(row_index))
+                        }
+                        DataType::Float32 => {
+                            let array = column.as_any().downcast_ref::<Float32Array>().unwrap();
+                            format!("{}", array.value(row_index))
+                        }
+                        DataType::Float64 => {
+                            let array = column.as_any().downcast_ref::<Float64Array>().unwrap();
+                            format!("{}", array.value(row_index))
+                        }
+                        other => panic!("Unsupported type {:?}", other),
+                    }
+                })
+                .collect();
+            str.push_str(&values.join(","));
+            str.push_str("\n");
+        }
+    }
+    str
+}
+
 fn result_str(results: &Rc<RefCell<Relation>>) -> String {
     let mut relation = results.borrow_mut();
     let mut str = String::new();
@@ -106,32 +1534,90 @@ fn result_str(results: &Rc<RefCell<Relation>>) -> String {
                 if column_index > 0 {
                     str.push_str("\t");
                 }
-                let column = batch.column(column_index);
-
-                match column.data_type() {
-                    DataType::Int32 => {
-                        let array = column.as_any().downcast_ref::<Int32Array>().unwrap();
-                        str.push_str(&format!("{:?}", array.value(row_index)));
-                    }
-                    DataType::Float32 => {
-                        let array = column.as_any().downcast_ref::<Float32Array>().unwrap();
-                        str.push_str(&format!("{:?}", array.value(row_index)));
-                    }
-                    DataType::Float64 => {
-                        let array = column.as_any().downcast_ref::<Float64Array>().unwrap();
-                        str.push_str(&format!("{:?}", array.value(row_index)));
-                    }
-                    DataType::Utf8 => {
-                        let array = column.as_any().downcast_ref::<BinaryArray>().unwrap();
-                        let s = String::from_utf8(array.value(row_index).to_vec()).unwrap();
-
-                        str.push_str(&format!("{:?}", s));
-                    }
-                    _ => str.push_str("???"),
-                }
+                str.push_str(&value_str(batch.column(column_index), row_index));
             }
             str.push_str("\n");
         }
     }
     str
 }
+
+/// Format a single value, with lists formatted as `[a, b, ...]`
+fn value_str(column: &ArrayRef, row_index: usize) -> String {
+    if column.is_null(row_index) {
+        return "NULL".to_string();
+    }
+
+    match column.data_type() {
+        DataType::Boolean => {
+            let array = column.as_any().downcast_ref::<BooleanArray>().unwrap();
+            format!("{:?}", array.value(row_index))
+        }
+        DataType::Int32 => {
+            let array = column.as_any().downcast_ref::<Int32Array>().unwrap();
+            format!("{:?}", array.value(row_index))
+        }
+        DataType::Int64 => {
+            let array = column.as_any().downcast_ref::<Int64Array>().unwrap();
+            format!("{:?}", array.value(row_index))
+        }
+        DataType::Int8 => {
+            let array = column.as_any().downcast_ref::<Int8Array>().unwrap();
+            format!("{:?}", array.value(row_index))
+        }
+        DataType::Int16 => {
+            let array = column.as_any().downcast_ref::<Int16Array>().unwrap();
+            format!("{:?}", array.value(row_index))
+        }
+        DataType::UInt8 => {
+            let array = column.as_any().downcast_ref::<UInt8Array>().unwrap();
+            format!("{:?}", array.value(row_index))
+        }
+        DataType::UInt16 => {
+            let array = column.as_any().downcast_ref::<UInt16Array>().unwrap();
+            format!("{:?}", array.value(row_index))
+        }
+        DataType::UInt32 => {
+            let array = column.as_any().downcast_ref::<UInt32Array>().unwrap();
+            format!("{:?}", array.value(row_index))
+        }
+        DataType::UInt64 => {
+            let array = column.as_any().downcast_ref::<UInt64Array>().unwrap();
+            format!("{:?}", array.value(row_index))
+        }
+        DataType::Float32 => {
+            let array = column.as_any().downcast_ref::<Float32Array>().unwrap();
+            format!("{:?}", array.value(row_index))
+        }
+        DataType::Float64 => {
+            let array = column.as_any().downcast_ref::<Float64Array>().unwrap();
+            format!("{:?}", array.value(row_index))
+        }
+        DataType::Utf8 => {
+            let array = column.as_any().downcast_ref::<BinaryArray>().unwrap();
+            let s = String::from_utf8(array.value(row_index).to_vec()).unwrap();
+            format!("{:?}", s)
+        }
+        DataType::Date(_) => {
+            let array = column.as_any().downcast_ref::<Int32Array>().unwrap();
+            temporal::format_date(array.value(row_index))
+        }
+        DataType::Time64(_) => {
+            let array = column.as_any().downcast_ref::<Int64Array>().unwrap();
+            temporal::format_time(array.value(row_index))
+        }
+        DataType::Timestamp(_) => {
+            let array = column.as_any().downcast_ref::<Int64Array>().unwrap();
+            temporal::format_timestamp(array.value(row_index))
+        }
+        DataType::List(_) => {
+            let array = column.as_any().downcast_ref::<ListArray>().unwrap();
+            let values = array.values();
+            let start = array.value_offset(row_index) as usize;
+            let end = start + array.value_length(row_index) as usize;
+            let items: Vec<String> = (start..end).map(|i| value_str(&values, i)).collect();
+            format!("[{}]", items.join(", "))
+        }
+        _ => "???".to_string(),
+    }
+}